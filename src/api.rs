@@ -0,0 +1,3099 @@
+use std::collections::{BTreeSet, HashSet};
+use std::sync::PoisonError;
+
+use serde::{Deserialize, Serialize};
+
+use crate::budget::{BudgetExceeded, BudgetLimit, SearchBudget};
+use crate::config::{EngineConfig, CURRENT_SNAPSHOT};
+use crate::encoding::Encoding;
+use crate::engine::{Capabilities, Engine, SelfCheckReport};
+use crate::export::{response_to_features, FeatureCollection};
+use crate::graph::alternatives::alternative_routes;
+use crate::graph::closed_gates::ClosedGates;
+use crate::graph::cross::{cross_routes, MAX_CROSS_SYSTEMS};
+use crate::graph::graph::{NameError, StarGraph};
+use crate::graph::hub_labels::MAX_DISTANCE_PAIRS;
+use crate::graph::jump::{shortest_hybrid_path, shortest_jump_path, TravelMode};
+use crate::graph::landmarks::shortest_gate_path_alt;
+use crate::graph::legality::{check_route, RouteViolation, ShipProfile};
+use crate::graph::pareto::{pareto_routes, DEFAULT_MAX_LABELS};
+use crate::graph::pathfinder::{
+    gate_route, shortest_gate_path_with, shortest_gate_path_within, shortest_gate_paths_batch,
+    shortest_path, trivial_path, Path, PathError, PathStep, RoutePreference, StepMode,
+};
+use crate::graph::route::{chunk_route, evaluate_route, route_progress, RouteStability};
+use crate::graph::traversal::{nearest_hubs_by_jumps, nearest_unvisited_by_jumps};
+use crate::graph::waypoints::{plan_patrol_cycle, plan_waypoint_route};
+use crate::jobs::{JobId, JobState, JobStatus};
+use crate::poi::Poi;
+use crate::spatial::corridor::within_corridor;
+use crate::spatial::heatmap::{density, heatmap, Aggregate, Heatmap, Projection};
+use crate::spatial::neighbours::NEIGHBOUR_RANKS;
+use crate::spatial::shapes::Cone;
+use crate::stats::{self, QueryStats, StatsRecorder};
+use crate::sweep::sweep::{longest_leg, sweep_systems, sweep_with_ends, SweepEnds, SweepObjective};
+use crate::tags::TagFilter;
+use crate::units::{CoordinateFrame, DistanceUnit};
+use crate::validate::{check_point, check_radius, InputError};
+use crate::{Scalar, ScalarBits};
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum LocationInput {
+    Coordinates {
+        #[serde(alias = "origin", alias = "center")]
+        coords: [Scalar; 3],
+    },
+    System {
+        system_name: String,
+    },
+}
+
+/// A query plus the options that apply to every query kind. Options sit
+/// alongside the query fields in the same JSON object.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct EngineRequest {
+    #[serde(flatten)]
+    pub query: Query,
+    #[serde(flatten)]
+    pub options: RequestOptions,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct RequestOptions {
+    #[serde(default)]
+    pub format: ResponseFormat,
+    /// When set, each returned system carries its position converted into
+    /// this frame.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub frame: Option<CoordinateFrame>,
+    /// Split path and sweep results into `chunks` of at most this many
+    /// waypoints, for clients with a waypoint cap.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub waypoint_limit: Option<usize>,
+    /// Attach execution metrics (`debug_stats`) to the response.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub debug_stats: bool,
+    /// Wire encoding of the reply, applied by the Lambda handler.
+    #[serde(default, skip_serializing_if = "Encoding::is_json")]
+    pub encoding: Encoding,
+    /// Unit of every distance in the response; the dataset's own units when
+    /// absent. Request fields such as `radius` stay in dataset units.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub units: Option<DistanceUnit>,
+}
+
+/// Optional knobs of a `path` query.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct PathOptions {
+    #[serde(default)]
+    pub preference: RoutePreference,
+    /// Reject routes with more jumps than this.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_jumps: Option<usize>,
+    #[serde(default)]
+    pub mode: TravelMode,
+    /// Jump-drive range in light-years; required by the `jump_drive` and
+    /// `hybrid` modes.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub jump_range: Option<Scalar>,
+    /// Extra cost per light-year of drive hops in `hybrid` mode.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fuel_penalty: Option<Scalar>,
+    /// Limits on the search; not part of the cache key, since a search
+    /// that finishes within them finds the same route.
+    #[serde(flatten)]
+    pub budget: SearchBudget,
+}
+
+/// `PathOptions` in hashable form, used as the path cache key.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub(crate) struct PathOptionsKey {
+    preference: RoutePreference,
+    max_jumps: Option<usize>,
+    mode: TravelMode,
+    jump_range: Option<ScalarBits>,
+    fuel_penalty: Option<ScalarBits>,
+}
+
+impl From<&PathOptions> for PathOptionsKey {
+    fn from(options: &PathOptions) -> Self {
+        PathOptionsKey {
+            preference: options.preference,
+            max_jumps: options.max_jumps,
+            mode: options.mode,
+            jump_range: options.jump_range.map(Scalar::to_bits),
+            fuel_penalty: options.fuel_penalty.map(Scalar::to_bits),
+        }
+    }
+}
+
+/// Outcome of a route search, as kept in the path cache.
+pub(crate) type PathOutcome = Result<Path, PathError>;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ResponseFormat {
+    /// The response shape of the query kind.
+    #[default]
+    Native,
+    /// A GeoJSON-like feature collection (see `export`).
+    Features,
+}
+
+/// The `kind` of every `Query` variant, as listed in `info` capabilities.
+pub const QUERY_KINDS: &[&str] = &[
+    "nearest",
+    "path",
+    "sweep",
+    "sweep_systems",
+    "unvisited",
+    "hubs",
+    "alternatives",
+    "pareto",
+    "cross_routes",
+    "jump_distances",
+    "set_tags",
+    "waypoints",
+    "patrol",
+    "check_route",
+    "eta",
+    "corridor",
+    "nearest_poi",
+    "pois_along_route",
+    "midpoint",
+    "density",
+    "heatmap",
+    "compare",
+    "route_stability",
+    "submit",
+    "job_status",
+    "info",
+];
+
+/// Kinds whose cost grows with the size of the searched area or of a route
+/// matrix rather than the size of the answer; these are subject to the
+/// concurrency limiter.
+pub const HEAVY_KINDS: &[&str] = &[
+    "sweep",
+    "sweep_systems",
+    "alternatives",
+    "pareto",
+    "cross_routes",
+    "jump_distances",
+    "waypoints",
+    "patrol",
+    "corridor",
+    "pois_along_route",
+    "heatmap",
+    "compare",
+    "route_stability",
+];
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Query {
+    Nearest {
+        #[serde(flatten)]
+        location: LocationInput,
+        radius: Scalar,
+        count: usize,
+        /// Only systems matching these tag predicates.
+        #[serde(default, skip_serializing_if = "TagFilter::is_empty")]
+        tags: TagFilter,
+        /// Only systems with a gate route from this system.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        reachable_from_id: Option<u32>,
+        /// When fewer than `count` systems match, report the radius that
+        /// would have found `count`.
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+        suggest_radius: bool,
+        /// Only systems ahead of the origin, inside this cone.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        cone: Option<ConeFilter>,
+    },
+    Path {
+        start_id: u32,
+        end_id: u32,
+        #[serde(flatten)]
+        options: PathOptions,
+        /// Only pass through systems matching these tag predicates (gate
+        /// routes only; the goal is exempt).
+        #[serde(default, skip_serializing_if = "TagFilter::is_empty")]
+        tags: TagFilter,
+        /// Directed `[from_id, to_id]` gates not to take (gate routes only).
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        avoid_gates: Vec<[u32; 2]>,
+    },
+    Sweep {
+        #[serde(flatten)]
+        location: LocationInput,
+        radius: Scalar,
+        /// Systems to leave out of the tour.
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        avoid_ids: Vec<u32>,
+        #[serde(default)]
+        objective: SweepObjective,
+        /// Leave out systems not matching these tag predicates too.
+        #[serde(default, skip_serializing_if = "TagFilter::is_empty")]
+        tags: TagFilter,
+        /// System the tour must start at, e.g. the current location.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        first_id: Option<u32>,
+        /// System the tour must end at, e.g. a pickup point.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        last_id: Option<u32>,
+    },
+    /// Tour through exactly the systems in `system_ids`, ordered like a
+    /// sweep; starts at `first_id`, or else the first listed system.
+    SweepSystems {
+        system_ids: Vec<u32>,
+        #[serde(default)]
+        objective: SweepObjective,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        first_id: Option<u32>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        last_id: Option<u32>,
+    },
+    /// Closest systems not yet in an exploration log, by gate jumps and by
+    /// straight-line distance within `radius`.
+    Unvisited {
+        origin_id: u32,
+        #[serde(default)]
+        visited_ids: Vec<u32>,
+        radius: Scalar,
+        count: usize,
+    },
+    /// Closest hub systems (more than `degree_threshold` gates) by gate
+    /// jumps, e.g. for picking market or staging destinations.
+    Hubs {
+        origin_id: u32,
+        degree_threshold: usize,
+        count: usize,
+    },
+    /// A few meaningfully different gate routes, fewest jumps first.
+    Alternatives {
+        start_id: u32,
+        end_id: u32,
+        count: usize,
+        /// Limits on the searches, as for `path`.
+        #[serde(flatten)]
+        budget: SearchBudget,
+    },
+    /// Gate routes that trade jumps against travelled distance: no returned
+    /// route is beaten on both by another.
+    Pareto {
+        start_id: u32,
+        end_id: u32,
+        /// Cap on the routes returned; an evenly spread subset of the
+        /// frontier is kept beyond it.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        max_routes: Option<usize>,
+        /// Limits on the searches, as for `path`.
+        #[serde(flatten)]
+        budget: SearchBudget,
+    },
+    /// Fewest-jumps routes from every source to every target, e.g. to
+    /// compare candidate staging systems.
+    CrossRoutes {
+        source_ids: Vec<u32>,
+        target_ids: Vec<u32>,
+        /// Limits on the searches, as for `path`.
+        #[serde(flatten)]
+        budget: SearchBudget,
+    },
+    /// Replaces the tags of a system (needs `admin_requests`).
+    SetTags {
+        system_id: u32,
+        tags: BTreeSet<String>,
+    },
+    /// Fewest-jump counts for many `[from_id, to_id]` pairs, answered from
+    /// hub labels when the dataset ships them.
+    JumpDistances {
+        pairs: Vec<[u32; 2]>,
+    },
+    /// Gate route from `origin_id` through every system in `waypoint_ids`,
+    /// in the order with the fewest total jumps.
+    Waypoints {
+        origin_id: u32,
+        waypoint_ids: Vec<u32>,
+        /// Limits on the searches, as for `path`.
+        #[serde(flatten)]
+        budget: SearchBudget,
+    },
+    /// Closed gate loop from `origin_id` through every system in
+    /// `waypoint_ids` and back, with the fewest total jumps.
+    Patrol {
+        origin_id: u32,
+        waypoint_ids: Vec<u32>,
+        /// Limits on the searches, as for `path`.
+        #[serde(flatten)]
+        budget: SearchBudget,
+    },
+    /// Whether a caller-supplied route is flyable by a ship, and if not,
+    /// the first rule it breaks.
+    CheckRoute {
+        system_ids: Vec<u32>,
+        #[serde(default)]
+        mode: TravelMode,
+        /// Jump-drive range in light-years.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        jump_range: Option<Scalar>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        max_jumps: Option<usize>,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        avoid_ids: Vec<u32>,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        avoid_gates: Vec<[u32; 2]>,
+    },
+    /// What is left of a route for a pilot seen in `current_id` at
+    /// `timestamp`, with an updated arrival time.
+    Eta {
+        system_ids: Vec<u32>,
+        current_id: u32,
+        /// Unix seconds.
+        timestamp: u64,
+        /// Unix seconds at which the pilot left the first system; with the
+        /// jumps made since, it sets the pace.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        started_at: Option<u64>,
+        /// Pace to assume instead of the observed one.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        seconds_per_jump: Option<Scalar>,
+    },
+    /// Systems within `distance` (dataset units) of a route through
+    /// `system_ids`, in route order.
+    Corridor {
+        system_ids: Vec<u32>,
+        distance: Scalar,
+    },
+    /// Points of interest nearest to a location, closest first.
+    NearestPoi {
+        #[serde(flatten)]
+        location: LocationInput,
+        radius: Scalar,
+        count: usize,
+        /// Only POIs carrying this tag.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        tag: Option<String>,
+    },
+    /// Points of interest within `distance` (dataset units) of a route
+    /// through `system_ids`, in route order.
+    PoisAlongRoute {
+        system_ids: Vec<u32>,
+        distance: Scalar,
+    },
+    /// Systems nearest to the spatial midpoint of two named systems.
+    Midpoint {
+        first_name: String,
+        second_name: String,
+        count: usize,
+        /// Search radius around the midpoint; unlimited when omitted.
+        #[serde(default)]
+        radius: Option<Scalar>,
+    },
+    /// Distances from a system to its 1st, 5th and 10th nearest
+    /// neighbours, a measure of how crowded its surroundings are.
+    Density {
+        system_id: u32,
+    },
+    /// `metric` aggregated over a grid of `cell_size` cells (dataset
+    /// units), optionally projected onto a plane.
+    Heatmap {
+        cell_size: Scalar,
+        metric: HeatmapMetric,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        projection: Option<Projection>,
+        /// Kernel width of the `density` metric (dataset units); defaults
+        /// to `cell_size`.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        bandwidth: Option<Scalar>,
+        /// Only systems matching these tag predicates.
+        #[serde(default, skip_serializing_if = "TagFilter::is_empty")]
+        tags: TagFilter,
+    },
+    /// How a metric between two systems changed between two dataset
+    /// snapshots. `after` defaults to the live dataset.
+    Compare {
+        start_id: u32,
+        end_id: u32,
+        metric: CompareMetric,
+        before: String,
+        #[serde(default)]
+        after: Option<String>,
+    },
+    /// Whether a route of `system_ids`, saved against snapshot `before`,
+    /// still holds in `after` (default: the live dataset), and a repair if
+    /// not.
+    RouteStability {
+        system_ids: Vec<u32>,
+        before: String,
+        #[serde(default)]
+        after: Option<String>,
+    },
+    /// Queues `request` as a background job (see `jobs`).
+    Submit {
+        request: Box<EngineRequest>,
+    },
+    /// State of a submitted job, and its result once done.
+    JobStatus {
+        job_id: JobId,
+    },
+    Info,
+}
+
+impl Query {
+    /// The `kind` tag this query is sent with.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Query::Nearest { .. } => "nearest",
+            Query::Path { .. } => "path",
+            Query::Sweep { .. } => "sweep",
+            Query::SweepSystems { .. } => "sweep_systems",
+            Query::Unvisited { .. } => "unvisited",
+            Query::Hubs { .. } => "hubs",
+            Query::Alternatives { .. } => "alternatives",
+            Query::Pareto { .. } => "pareto",
+            Query::CrossRoutes { .. } => "cross_routes",
+            Query::SetTags { .. } => "set_tags",
+            Query::JumpDistances { .. } => "jump_distances",
+            Query::Waypoints { .. } => "waypoints",
+            Query::Patrol { .. } => "patrol",
+            Query::CheckRoute { .. } => "check_route",
+            Query::Eta { .. } => "eta",
+            Query::Corridor { .. } => "corridor",
+            Query::NearestPoi { .. } => "nearest_poi",
+            Query::PoisAlongRoute { .. } => "pois_along_route",
+            Query::Midpoint { .. } => "midpoint",
+            Query::Density { .. } => "density",
+            Query::Heatmap { .. } => "heatmap",
+            Query::Compare { .. } => "compare",
+            Query::RouteStability { .. } => "route_stability",
+            Query::Submit { .. } => "submit",
+            Query::JobStatus { .. } => "job_status",
+            Query::Info => "info",
+        }
+    }
+}
+
+/// Cone opening from a `nearest` origin, for "what is ahead of me" scans.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ConeFilter {
+    /// Axis of the cone; any length but zero.
+    pub direction: [Scalar; 3],
+    /// Angle between the axis and the cone's side, in degrees.
+    pub half_angle_deg: Scalar,
+}
+
+impl ConeFilter {
+    /// The cone with its apex at `origin` and no range limit.
+    fn to_cone(self, origin: [Scalar; 3]) -> Result<Cone, String> {
+        let [x, y, z] = self.direction;
+        if !(x * x + y * y + z * z).is_normal() {
+            return Err("cone direction must be finite and non-zero".to_string());
+        }
+        if !(self.half_angle_deg > 0.0 && self.half_angle_deg <= 180.0) {
+            return Err("cone half_angle_deg must be in (0, 180]".to_string());
+        }
+        Ok(Cone::new(
+            origin,
+            self.direction,
+            self.half_angle_deg.to_radians(),
+            Scalar::INFINITY,
+        ))
+    }
+}
+
+/// Per-cell values of a `heatmap`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HeatmapMetric {
+    /// Number of systems.
+    Count,
+    /// Mean number of outgoing gates.
+    Degree,
+    /// Mean distance to the nearest other system, in dataset units.
+    NeighbourDistance,
+    /// Gaussian kernel density estimate of systems per cubic dataset unit
+    /// (per square unit when projected) at each cell's centre.
+    Density,
+}
+
+/// Metrics a `compare` request can track across snapshots.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CompareMetric {
+    /// Fewest gate jumps between the systems.
+    Jumps,
+    /// Straight-line distance in light-years.
+    Distance,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum EngineResponse {
+    Nearest {
+        systems: Vec<NearestResult>,
+        /// Smallest radius holding `count` matching systems; with
+        /// `suggest_radius` when the results fell short. In `units` like
+        /// every response distance, while a retry's `radius` is in dataset
+        /// units.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        suggested_radius: Option<Scalar>,
+    },
+    Path {
+        systems: Vec<PathResult>,
+        /// System ids split by `waypoint_limit`, boundaries repeated.
+        #[serde(skip_serializing_if = "Vec::is_empty")]
+        chunks: Vec<Vec<u32>>,
+    },
+    Alternatives {
+        routes: Vec<AlternativeRoute>,
+    },
+    /// Fewest jumps first, so distance falls from route to route.
+    Pareto {
+        routes: Vec<ParetoRouteResult>,
+    },
+    CrossRoutes {
+        source_ids: Vec<u32>,
+        target_ids: Vec<u32>,
+        /// `jumps[i][j]` from source `i` to target `j`; null when unreachable.
+        jumps: Vec<Vec<Option<usize>>>,
+        /// Per source, every `[from_id, to_id]` gate of its routes once; the
+        /// route to a target is found by walking these back from it.
+        gates: Vec<Vec<[u32; 2]>>,
+    },
+    Tags {
+        id: u32,
+        tags: BTreeSet<String>,
+    },
+    /// In the order of the request's pairs; null when unreachable.
+    JumpDistances {
+        jumps: Vec<Option<usize>>,
+    },
+    Sweep {
+        systems: Vec<SweepResult>,
+        total_distance: Scalar,
+        longest_leg: Scalar,
+        #[serde(skip_serializing_if = "Vec::is_empty")]
+        chunks: Vec<Vec<u32>>,
+        /// Systems inside the radius that were skipped because of `avoid_ids`.
+        #[serde(skip_serializing_if = "Vec::is_empty")]
+        avoided: Vec<SweepResult>,
+    },
+    Unvisited {
+        by_jumps: Vec<JumpResult>,
+        by_distance: Vec<NearestResult>,
+    },
+    Hubs {
+        systems: Vec<HubResult>,
+    },
+    RouteCheck {
+        valid: bool,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        violation: Option<RouteViolation>,
+    },
+    Eta {
+        /// Position of the pilot's system in the route; absent once they
+        /// have left it.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        position: Option<usize>,
+        /// The remaining legs are a fresh gate path rather than the rest of
+        /// the route.
+        rerouted: bool,
+        remaining_jumps: usize,
+        /// Summed straight-line length of the remaining legs, in dataset
+        /// units.
+        remaining_distance: Scalar,
+        systems: Vec<PathResult>,
+        /// Pace the arrival time assumes; absent when none was given or
+        /// could be observed.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        seconds_per_jump: Option<Scalar>,
+        /// Expected arrival, in Unix seconds.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        eta: Option<u64>,
+    },
+    Corridor {
+        systems: Vec<CorridorResult>,
+    },
+    Pois {
+        pois: Vec<PoiResult>,
+    },
+    Midpoint {
+        /// In the dataset's frame.
+        midpoint: [Scalar; 3],
+        systems: Vec<NearestResult>,
+    },
+    Density {
+        id: u32,
+        name: String,
+        neighbours: Vec<NeighbourDistance>,
+    },
+    Heatmap {
+        metric: HeatmapMetric,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        projection: Option<Projection>,
+        #[serde(flatten)]
+        grid: Heatmap,
+    },
+    Compare {
+        metric: CompareMetric,
+        before: SnapshotValue,
+        after: SnapshotValue,
+        /// `after - before`, when both are known.
+        change: Option<Scalar>,
+    },
+    /// Route costs are in light-years, like `compare` distances.
+    RouteStability {
+        before: String,
+        after: String,
+        #[serde(flatten)]
+        stability: RouteStability,
+    },
+    Job {
+        job_id: JobId,
+        #[serde(flatten)]
+        state: JobState,
+    },
+    Info {
+        systems: usize,
+        directed_edges: usize,
+        release_tag: Option<String>,
+        frame: CoordinateFrame,
+        self_check: SelfCheckReport,
+        capabilities: Box<Capabilities>,
+        config: EngineConfig,
+    },
+    /// A `system_name` matched several systems; retry with one of the ids
+    /// or coordinates.
+    AmbiguousName {
+        name: String,
+        candidate_ids: Vec<u32>,
+    },
+    /// The route search gave up at the request's `budget`.
+    BudgetExceeded {
+        limit: BudgetLimit,
+        budget: u64,
+    },
+    /// The requested route needs more jumps than the request allowed.
+    JumpLimitExceeded {
+        jumps: usize,
+        max_jumps: usize,
+    },
+    Features {
+        collection: FeatureCollection,
+    },
+    Error {
+        message: String,
+    },
+}
+
+/// A response plus the optional execution metrics requested with
+/// `debug_stats`. Serializes exactly like the bare response otherwise.
+#[derive(Debug, Serialize)]
+pub struct EngineReply {
+    #[serde(flatten)]
+    pub response: EngineResponse,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub debug_stats: Option<QueryStats>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct NearestResult {
+    pub id: u32,
+    pub name: String,
+    pub distance: Scalar,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub position: Option<[Scalar; 3]>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PathResult {
+    pub id: u32,
+    pub name: String,
+    pub cumulative_cost: Scalar,
+    /// How this system was reached; absent for the start.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mode: Option<StepMode>,
+    /// Straight-line length of the hop into this system, in dataset units.
+    pub leg_distance: Scalar,
+    /// The system this hop starts from; absent for the start.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub from_id: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub from_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub position: Option<[Scalar; 3]>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AlternativeRoute {
+    pub jumps: usize,
+    pub systems: Vec<PathResult>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ParetoRouteResult {
+    pub jumps: usize,
+    /// Summed straight-line length of the gate hops, in dataset units.
+    pub distance: Scalar,
+    pub systems: Vec<PathResult>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SweepResult {
+    pub id: u32,
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub position: Option<[Scalar; 3]>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct JumpResult {
+    pub id: u32,
+    pub name: String,
+    pub jumps: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub position: Option<[Scalar; 3]>,
+}
+
+/// Distance, in dataset units, to the neighbour of the given rank (1 being
+/// the closest other system); `None` when the dataset is too small.
+#[derive(Debug, Serialize)]
+pub struct NeighbourDistance {
+    pub rank: usize,
+    pub distance: Option<Scalar>,
+}
+
+/// A metric's value in one snapshot; `None` when either system is missing
+/// from it or, for jumps, unreachable.
+#[derive(Debug, Serialize)]
+pub struct SnapshotValue {
+    pub snapshot: String,
+    pub value: Option<Scalar>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CorridorResult {
+    pub id: u32,
+    pub name: String,
+    /// Distance to the closest leg of the route.
+    pub distance: Scalar,
+    /// The closest leg, counted from 0 for the first two systems.
+    pub leg: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub position: Option<[Scalar; 3]>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PoiResult {
+    pub id: u64,
+    pub label: String,
+    #[serde(skip_serializing_if = "BTreeSet::is_empty")]
+    pub tags: BTreeSet<String>,
+    /// In the dataset's frame.
+    pub pos: [Scalar; 3],
+    /// Distance to the location, or to the closest leg of the route.
+    pub distance: Scalar,
+    /// The closest leg, for `pois_along_route`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub leg: Option<usize>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct HubResult {
+    pub id: u32,
+    pub name: String,
+    pub jumps: usize,
+    pub degree: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub position: Option<[Scalar; 3]>,
+}
+
+/// Result items that name a system and can carry its position.
+trait SystemResult {
+    fn id(&self) -> u32;
+    fn set_position(&mut self, position: Option<[Scalar; 3]>);
+}
+
+macro_rules! impl_system_result {
+    ($($ty:ty),*) => {
+        $(impl SystemResult for $ty {
+            fn id(&self) -> u32 {
+                self.id
+            }
+
+            fn set_position(&mut self, position: Option<[Scalar; 3]>) {
+                self.position = position;
+            }
+        })*
+    };
+}
+
+impl_system_result!(
+    NearestResult,
+    PathResult,
+    SweepResult,
+    JumpResult,
+    HubResult,
+    CorridorResult
+);
+
+impl Query {
+    /// Whether the route costs in the response are distances rather than
+    /// jump counts.
+    fn costs_are_distances(&self) -> bool {
+        matches!(
+            self,
+            Query::Path { options, .. } if options.preference == RoutePreference::ShortestDistance
+        )
+    }
+}
+
+impl EngineRequest {
+    /// Requests of one of the `HEAVY_KINDS`.
+    pub fn is_heavy(&self) -> bool {
+        HEAVY_KINDS.contains(&self.query.kind())
+    }
+}
+
+impl EngineResponse {
+    pub fn error(message: impl Into<String>) -> Self {
+        EngineResponse::Error {
+            message: message.into(),
+        }
+    }
+}
+
+impl From<JobStatus> for EngineResponse {
+    fn from(status: JobStatus) -> Self {
+        EngineResponse::Job {
+            job_id: status.job_id,
+            state: status.state,
+        }
+    }
+}
+
+impl From<NameError> for EngineResponse {
+    fn from(err: NameError) -> Self {
+        match err {
+            NameError::Unknown(_) => EngineResponse::error(err.to_string()),
+            NameError::Ambiguous {
+                name,
+                candidate_ids,
+            } => EngineResponse::AmbiguousName {
+                name,
+                candidate_ids,
+            },
+        }
+    }
+}
+
+impl From<BudgetExceeded> for EngineResponse {
+    fn from(exceeded: BudgetExceeded) -> Self {
+        EngineResponse::BudgetExceeded {
+            limit: exceeded.limit,
+            budget: exceeded.budget,
+        }
+    }
+}
+
+/// A route from a system to itself is not a failure here; `handle_path`
+/// answers it with the one system before converting errors.
+impl From<PathError> for EngineResponse {
+    fn from(err: PathError) -> Self {
+        match err {
+            PathError::NoRoute => EngineResponse::error("No path found"),
+            PathError::ExceedsLimit { jumps, max_jumps } => {
+                EngineResponse::JumpLimitExceeded { jumps, max_jumps }
+            }
+            PathError::BudgetExceeded(exceeded) => exceeded.into(),
+            PathError::SameSystem | PathError::IndexOutOfRange { .. } => {
+                EngineResponse::error(err.to_string())
+            }
+        }
+    }
+}
+
+impl From<InputError> for EngineResponse {
+    fn from(err: InputError) -> Self {
+        EngineResponse::error(err.to_string())
+    }
+}
+
+impl From<EngineResponse> for EngineReply {
+    fn from(response: EngineResponse) -> Self {
+        EngineReply {
+            response,
+            debug_stats: None,
+        }
+    }
+}
+
+/// Executes a single request against the engine. Failures are reported as
+/// `EngineResponse::Error` rather than propagated, so every request yields a
+/// response the caller can serialize.
+pub fn handle_request(engine: &Engine, request: EngineRequest) -> EngineReply {
+    let costs_are_distances = request.query.costs_are_distances();
+    if !request.options.debug_stats {
+        let response = handle_query(engine, request.query);
+        return apply_options(engine, &request.options, costs_are_distances, response).into();
+    }
+    let mut recorder = StatsRecorder::start();
+    let response = handle_query(engine, request.query);
+    recorder.phase("query");
+    let response = apply_options(engine, &request.options, costs_are_distances, response);
+    recorder.phase("format");
+    EngineReply {
+        response,
+        debug_stats: Some(recorder.finish()),
+    }
+}
+
+fn handle_query(engine: &Engine, query: Query) -> EngineResponse {
+    match query {
+        Query::Nearest {
+            location,
+            radius,
+            count,
+            tags,
+            reachable_from_id,
+            suggest_radius,
+            cone,
+        } => handle_nearest(
+            engine,
+            location,
+            radius,
+            count,
+            NearestFilters {
+                tags: &tags,
+                reachable_from_id,
+                cone,
+            },
+            suggest_radius,
+        ),
+        Query::Path {
+            start_id,
+            end_id,
+            options,
+            tags,
+            avoid_gates,
+        } => handle_path(
+            engine,
+            start_id,
+            end_id,
+            options,
+            &tags,
+            &ClosedGates::from_pairs(&avoid_gates),
+        ),
+        Query::Sweep {
+            location,
+            radius,
+            avoid_ids,
+            objective,
+            tags,
+            first_id,
+            last_id,
+        } => handle_sweep(
+            engine,
+            location,
+            radius,
+            &avoid_ids,
+            objective,
+            &tags,
+            [first_id, last_id],
+        ),
+        Query::SweepSystems {
+            system_ids,
+            objective,
+            first_id,
+            last_id,
+        } => handle_sweep_systems(engine, &system_ids, objective, [first_id, last_id]),
+        Query::Unvisited {
+            origin_id,
+            visited_ids,
+            radius,
+            count,
+        } => handle_unvisited(engine, origin_id, &visited_ids, radius, count),
+        Query::Hubs {
+            origin_id,
+            degree_threshold,
+            count,
+        } => handle_hubs(engine, origin_id, degree_threshold, count),
+        Query::Alternatives {
+            start_id,
+            end_id,
+            count,
+            budget,
+        } => handle_alternatives(engine, start_id, end_id, count, budget),
+        Query::Pareto {
+            start_id,
+            end_id,
+            max_routes,
+            budget,
+        } => handle_pareto(engine, start_id, end_id, max_routes, budget),
+        Query::CrossRoutes {
+            source_ids,
+            target_ids,
+            budget,
+        } => handle_cross_routes(engine, source_ids, target_ids, budget),
+        Query::JumpDistances { pairs } => handle_jump_distances(engine, &pairs),
+        Query::SetTags { system_id, tags } => handle_set_tags(engine, system_id, tags),
+        Query::Waypoints {
+            origin_id,
+            waypoint_ids,
+            budget,
+        } => handle_waypoints(engine, origin_id, &waypoint_ids, false, budget),
+        Query::Patrol {
+            origin_id,
+            waypoint_ids,
+            budget,
+        } => handle_waypoints(engine, origin_id, &waypoint_ids, true, budget),
+        Query::CheckRoute {
+            system_ids,
+            mode,
+            jump_range,
+            max_jumps,
+            avoid_ids,
+            avoid_gates,
+        } => {
+            let mut avoid_gates = ClosedGates::from_pairs(&avoid_gates);
+            avoid_gates.extend(&engine.closed_gates);
+            let ship = ShipProfile {
+                mode,
+                jump_range: jump_range
+                    .map(|r| DistanceUnit::LightYears.convert(r, engine.frame().units)),
+                max_jumps,
+                avoid_ids: avoid_ids.into_iter().collect(),
+                avoid_gates,
+            };
+            handle_check_route(engine, &system_ids, &ship)
+        }
+        Query::Eta {
+            system_ids,
+            current_id,
+            timestamp,
+            started_at,
+            seconds_per_jump,
+        } => handle_eta(
+            engine,
+            &system_ids,
+            current_id,
+            timestamp,
+            started_at,
+            seconds_per_jump,
+        ),
+        Query::Corridor {
+            system_ids,
+            distance,
+        } => handle_corridor(engine, &system_ids, distance),
+        Query::NearestPoi {
+            location,
+            radius,
+            count,
+            tag,
+        } => handle_nearest_poi(engine, location, radius, count, tag.as_deref()),
+        Query::PoisAlongRoute {
+            system_ids,
+            distance,
+        } => handle_pois_along_route(engine, &system_ids, distance),
+        Query::Midpoint {
+            first_name,
+            second_name,
+            count,
+            radius,
+        } => handle_midpoint(engine, &first_name, &second_name, count, radius),
+        Query::Density { system_id } => handle_density(engine, system_id),
+        Query::Heatmap {
+            cell_size,
+            metric,
+            projection,
+            bandwidth,
+            tags,
+        } => handle_heatmap(engine, cell_size, metric, projection, bandwidth, &tags),
+        Query::Compare {
+            start_id,
+            end_id,
+            metric,
+            before,
+            after,
+        } => handle_compare(engine, start_id, end_id, metric, &before, after.as_deref()),
+        Query::RouteStability {
+            system_ids,
+            before,
+            after,
+        } => handle_route_stability(engine, &system_ids, before, after),
+        Query::Submit { request } => handle_submit(engine, *request),
+        Query::JobStatus { job_id } => match engine.jobs.status(job_id) {
+            Some(status) => status.into(),
+            None => EngineResponse::error(format!("Unknown or expired job_id {job_id}")),
+        },
+        Query::Info => EngineResponse::Info {
+            systems: engine.graph.len(),
+            directed_edges: engine.graph.edge_count(),
+            release_tag: engine.metadata.as_ref().map(|m| m.release_tag.clone()),
+            frame: engine.frame(),
+            self_check: engine.startup_check().clone(),
+            capabilities: Box::new(engine.capabilities()),
+            config: engine.config.sanitized(),
+        },
+    }
+}
+
+fn apply_options(
+    engine: &Engine,
+    options: &RequestOptions,
+    costs_are_distances: bool,
+    mut response: EngineResponse,
+) -> EngineResponse {
+    if let Some(units) = options.units {
+        convert_distances(
+            engine.frame().units,
+            units,
+            costs_are_distances,
+            &mut response,
+        );
+    }
+    if let Some(frame) = &options.frame {
+        attach_positions(engine, frame, &mut response);
+    }
+    if let Some(limit) = options.waypoint_limit {
+        attach_chunks(limit, &mut response);
+    }
+    match options.format {
+        ResponseFormat::Native => response,
+        ResponseFormat::Features => match response_to_features(&engine.graph, &response) {
+            Some(collection) => EngineResponse::Features { collection },
+            None => response,
+        },
+    }
+}
+
+/// The optional restrictions of a `nearest` request.
+struct NearestFilters<'a> {
+    tags: &'a TagFilter,
+    reachable_from_id: Option<u32>,
+    cone: Option<ConeFilter>,
+}
+
+fn handle_nearest(
+    engine: &Engine,
+    location: LocationInput,
+    radius: Scalar,
+    count: usize,
+    filters: NearestFilters,
+    suggest_radius: bool,
+) -> EngineResponse {
+    let NearestFilters {
+        tags,
+        reachable_from_id,
+        cone,
+    } = filters;
+    let origin = match resolve_location(&engine.graph, location) {
+        Ok(point) => point,
+        Err(err) => return err.into(),
+    };
+    if let Err(err) = check_point("origin", origin).and(check_radius("radius", radius)) {
+        return err.into();
+    }
+    // The search radius bounds the cone, so it is left open-ended here and
+    // `suggest_radius` can look past `radius`.
+    let cone = match cone.map(|c| c.to_cone(origin)).transpose() {
+        Ok(cone) => cone,
+        Err(message) => return EngineResponse::error(message),
+    };
+    let reachable_from = match reachable_from_id {
+        None => None,
+        Some(id) => match engine.graph.index_of_id(id) {
+            Some(idx) => Some(idx),
+            None => return EngineResponse::error(format!("Unknown reachable_from_id {id}")),
+        },
+    };
+    let filtered = !tags.is_empty() || reachable_from.is_some() || cone.is_some();
+    let system_tags = engine.tags();
+    let keep = |idx: usize| {
+        reachable_from.is_none_or(|from| engine.may_reach(from, idx))
+            && cone.is_none_or(|cone| cone.contains(engine.graph.systems[idx].pos))
+            && system_tags.matches(engine.graph.systems[idx].id, tags)
+    };
+    let nn = if filtered {
+        engine
+            .kd
+            .nearest_n_within_radius_filtered(origin, radius, count, keep)
+    } else if count == 1 {
+        engine
+            .kd
+            .nearest_one(origin)
+            .filter(|&(_, d)| d <= radius)
+            .into_iter()
+            .collect()
+    } else {
+        engine.kd.nearest_n_within_radius(origin, radius, count)
+    };
+    // The distance of the `count`-th match, if there are that many at all.
+    let suggested_radius = if suggest_radius && nn.len() < count {
+        if filtered {
+            kth_nearest_distance(&engine.graph, origin, count, keep)
+        } else {
+            engine
+                .kd
+                .nearest_n(origin, count)
+                .get(count - 1)
+                .map(|&(_, d)| d)
+        }
+    } else {
+        None
+    };
+    let systems = nn
+        .into_iter()
+        .map(|(idx, d)| {
+            let s = &engine.graph.systems[idx];
+            NearestResult {
+                id: s.id,
+                name: s.name.clone(),
+                distance: d,
+                position: None,
+            }
+        })
+        .collect();
+    EngineResponse::Nearest {
+        systems,
+        suggested_radius,
+    }
+}
+
+/// Distance from `origin` to the `count`-th nearest system passing `keep`,
+/// from one batch pass over every position. Matches are sparse whenever a
+/// suggestion is needed, so an unbounded filtered k-d search would visit
+/// most of the tree anyway, one predicate call and branch per node.
+fn kth_nearest_distance(
+    graph: &StarGraph,
+    origin: [Scalar; 3],
+    count: usize,
+    keep: impl Fn(usize) -> bool,
+) -> Option<Scalar> {
+    let mut dist2 = Vec::new();
+    graph.positions().squared_distances_to(origin, &mut dist2);
+    let mut matching: Vec<Scalar> = dist2
+        .into_iter()
+        .enumerate()
+        .filter_map(|(idx, d2)| keep(idx).then_some(d2))
+        .collect();
+    let kth = count.checked_sub(1).filter(|&kth| kth < matching.len())?;
+    let (_, d2, _) = matching.select_nth_unstable_by(kth, Scalar::total_cmp);
+    Some(d2.sqrt())
+}
+
+fn handle_path(
+    engine: &Engine,
+    start_id: u32,
+    end_id: u32,
+    options: PathOptions,
+    tags: &TagFilter,
+    avoid_gates: &ClosedGates,
+) -> EngineResponse {
+    let g = &engine.graph;
+    let Some(start) = g.index_of_id(start_id) else {
+        return EngineResponse::error(format!("Unknown start_id {}", start_id));
+    };
+    let Some(goal) = g.index_of_id(end_id) else {
+        return EngineResponse::error(format!("Unknown end_id {}", end_id));
+    };
+    let key = PathOptionsKey::from(&options);
+    // Tags change at runtime, so tag-filtered routes bypass the cache, as
+    // do routes avoiding gates for this request only.
+    let cacheable = tags.is_empty() && avoid_gates.is_empty();
+    let cached = cacheable
+        .then(|| engine.path_cache.get(start, goal, &key))
+        .flatten();
+    if cacheable {
+        stats::record_cache_hit(cached.is_some());
+    }
+    let path = match cached {
+        Some(outcome) => outcome,
+        None => match options
+            .budget
+            .run(|| find_path(engine, start, goal, &options, tags, avoid_gates))
+        {
+            Ok(Ok(outcome)) => {
+                if cacheable {
+                    engine.path_cache.insert(start, goal, key, outcome.clone());
+                }
+                outcome
+            }
+            Ok(Err(msg)) => return EngineResponse::error(msg),
+            Err(exceeded) => return exceeded.into(),
+        },
+    };
+    let path = match (path, options.max_jumps) {
+        (Ok(path), Some(max_jumps)) if path.len() - 1 > max_jumps => Err(PathError::ExceedsLimit {
+            jumps: path.len() - 1,
+            max_jumps,
+        }),
+        (path, _) => path,
+    };
+    let path = match path {
+        Ok(path) => path,
+        Err(PathError::SameSystem) => trivial_path(g, start),
+        Err(err) => return err.into(),
+    };
+    EngineResponse::Path {
+        systems: path_results(g, path),
+        chunks: Vec::new(),
+    }
+}
+
+fn path_results(g: &StarGraph, steps: Vec<PathStep>) -> Vec<PathResult> {
+    let mut prev: Option<usize> = None;
+    steps
+        .into_iter()
+        .map(|step| {
+            let s = &g.systems[step.system_index];
+            let from = prev.map(|p| &g.systems[p]);
+            prev = Some(step.system_index);
+            PathResult {
+                id: s.id,
+                name: s.name.clone(),
+                cumulative_cost: step.cost,
+                mode: step.mode,
+                leg_distance: step.leg_distance,
+                from_id: from.map(|f| f.id),
+                from_name: from.map(|f| f.name.clone()),
+                position: None,
+            }
+        })
+        .collect()
+}
+
+fn handle_alternatives(
+    engine: &Engine,
+    start_id: u32,
+    end_id: u32,
+    count: usize,
+    budget: SearchBudget,
+) -> EngineResponse {
+    let g = &engine.graph;
+    let Some(start) = g.index_of_id(start_id) else {
+        return EngineResponse::error(format!("Unknown start_id {}", start_id));
+    };
+    let Some(goal) = g.index_of_id(end_id) else {
+        return EngineResponse::error(format!("Unknown end_id {}", end_id));
+    };
+    let routes = match budget.run(|| alternative_routes(g, start, goal, count)) {
+        Ok(Ok(routes)) => routes,
+        Ok(Err(exceeded)) | Err(exceeded) => return exceeded.into(),
+    };
+    if routes.is_empty() && count > 0 {
+        return EngineResponse::error("No path found");
+    }
+    let routes = routes
+        .into_iter()
+        .map(|route| AlternativeRoute {
+            jumps: route.len() - 1,
+            systems: path_results(g, route),
+        })
+        .collect();
+    EngineResponse::Alternatives { routes }
+}
+
+fn handle_pareto(
+    engine: &Engine,
+    start_id: u32,
+    end_id: u32,
+    max_routes: Option<usize>,
+    budget: SearchBudget,
+) -> EngineResponse {
+    let g = &engine.graph;
+    let Some(start) = g.index_of_id(start_id) else {
+        return EngineResponse::error(format!("Unknown start_id {}", start_id));
+    };
+    let Some(goal) = g.index_of_id(end_id) else {
+        return EngineResponse::error(format!("Unknown end_id {}", end_id));
+    };
+    let costs = |a: usize, b: usize| [1.0, g.systems[a].distance(&g.systems[b])];
+    let max_routes = max_routes.unwrap_or(DEFAULT_MAX_LABELS);
+    let frontier = match budget.run(|| pareto_routes(g, start, goal, costs, max_routes)) {
+        Ok(Ok(frontier)) => frontier,
+        Ok(Err(exceeded)) | Err(exceeded) => return exceeded.into(),
+    };
+    if frontier.is_empty() {
+        return EngineResponse::error("No path found");
+    }
+    let routes = frontier
+        .into_iter()
+        .map(|route| ParetoRouteResult {
+            jumps: route.systems.len() - 1,
+            distance: route.costs[1],
+            systems: path_results(g, gate_route(g, &route.systems)),
+        })
+        .collect();
+    EngineResponse::Pareto { routes }
+}
+
+fn handle_cross_routes(
+    engine: &Engine,
+    source_ids: Vec<u32>,
+    target_ids: Vec<u32>,
+    budget: SearchBudget,
+) -> EngineResponse {
+    let g = &engine.graph;
+    if source_ids.len().max(target_ids.len()) > MAX_CROSS_SYSTEMS {
+        return EngineResponse::error(format!(
+            "cross_routes takes at most {MAX_CROSS_SYSTEMS} sources and targets"
+        ));
+    }
+    let indices = |ids: &[u32]| -> Result<Vec<usize>, u32> {
+        ids.iter().map(|&id| g.index_of_id(id).ok_or(id)).collect()
+    };
+    let (sources, targets) = match (indices(&source_ids), indices(&target_ids)) {
+        (Ok(sources), Ok(targets)) => (sources, targets),
+        (Err(id), _) | (_, Err(id)) => {
+            return EngineResponse::error(format!("Unknown system id {id}"))
+        }
+    };
+    let routes = match budget.run(|| cross_routes(g, &sources, &targets)) {
+        Ok(Ok(routes)) => routes,
+        Ok(Err(err)) => return err.into(),
+        Err(exceeded) => return exceeded.into(),
+    };
+    let gates = routes
+        .gates
+        .into_iter()
+        .map(|tree| {
+            tree.into_iter()
+                .map(|(from, to)| [g.systems[from].id, g.systems[to].id])
+                .collect()
+        })
+        .collect();
+    EngineResponse::CrossRoutes {
+        source_ids,
+        target_ids,
+        jumps: routes.jumps,
+        gates,
+    }
+}
+
+fn handle_jump_distances(engine: &Engine, pairs: &[[u32; 2]]) -> EngineResponse {
+    let g = &engine.graph;
+    if pairs.len() > MAX_DISTANCE_PAIRS {
+        return EngineResponse::error(format!(
+            "jump_distances takes at most {MAX_DISTANCE_PAIRS} pairs"
+        ));
+    }
+    let mut indices = Vec::with_capacity(pairs.len());
+    for &[from_id, to_id] in pairs {
+        match (g.index_of_id(from_id), g.index_of_id(to_id)) {
+            (Some(from), Some(to)) => indices.push((from, to)),
+            (None, _) => return EngineResponse::error(format!("Unknown system id {from_id}")),
+            (_, None) => return EngineResponse::error(format!("Unknown system id {to_id}")),
+        }
+    }
+    // Labels and the matrix answer each pair directly; the matrix is only
+    // built for graphs under `jump_matrix_max_systems`.
+    let jumps = if engine.hub_labels.is_some() || engine.jump_matrix().is_some() {
+        indices
+            .into_iter()
+            .map(|(from, to)| engine.jumps(from, to))
+            .collect()
+    } else {
+        // One search per distinct source instead of one per pair.
+        let mut jumps = Vec::with_capacity(indices.len());
+        for path in shortest_gate_paths_batch(g, &indices) {
+            jumps.push(match path {
+                Ok(path) => Some(path.len() - 1),
+                Err(PathError::NoRoute) => None,
+                Err(PathError::SameSystem) => Some(0),
+                Err(err) => return err.into(),
+            });
+        }
+        jumps
+    };
+    EngineResponse::JumpDistances { jumps }
+}
+
+fn handle_set_tags(engine: &Engine, system_id: u32, tags: BTreeSet<String>) -> EngineResponse {
+    if !engine.config.admin_requests {
+        return EngineResponse::error("set_tags needs admin_requests enabled");
+    }
+    if engine.graph.index_of_id(system_id).is_none() {
+        return EngineResponse::error(format!("Unknown system_id {system_id}"));
+    }
+    engine
+        .tags
+        .write()
+        .unwrap_or_else(PoisonError::into_inner)
+        .set(system_id, tags.clone());
+    EngineResponse::Tags {
+        id: system_id,
+        tags,
+    }
+}
+
+fn handle_submit(engine: &Engine, request: EngineRequest) -> EngineResponse {
+    if matches!(
+        request.query,
+        Query::Submit { .. } | Query::JobStatus { .. }
+    ) {
+        return EngineResponse::error("Jobs cannot submit or poll other jobs");
+    }
+    match engine.jobs.submit(request) {
+        Ok(status) => status.into(),
+        Err(err) => EngineResponse::error(err.to_string()),
+    }
+}
+
+/// Open waypoint route, or a patrol loop back to the origin when `closed`.
+fn handle_waypoints(
+    engine: &Engine,
+    origin_id: u32,
+    waypoint_ids: &[u32],
+    closed: bool,
+    budget: SearchBudget,
+) -> EngineResponse {
+    let g = &engine.graph;
+    let Some(origin) = g.index_of_id(origin_id) else {
+        return EngineResponse::error(format!("Unknown origin_id {origin_id}"));
+    };
+    let mut waypoints = Vec::with_capacity(waypoint_ids.len());
+    for &id in waypoint_ids {
+        let Some(idx) = g.index_of_id(id) else {
+            return EngineResponse::error(format!("Unknown waypoint id {id}"));
+        };
+        waypoints.push(idx);
+    }
+    let plan = budget.run(|| {
+        if closed {
+            plan_patrol_cycle(g, origin, &waypoints)
+        } else {
+            plan_waypoint_route(g, origin, &waypoints)
+        }
+    });
+    match plan {
+        Ok(Ok(plan)) => EngineResponse::Path {
+            systems: path_results(g, plan.route),
+            chunks: Vec::new(),
+        },
+        Ok(Err(err)) => err.into(),
+        Err(exceeded) => exceeded.into(),
+    }
+}
+
+/// Runs the search selected by `options`. The outer error is a request
+/// problem; the inner result is the search outcome.
+fn find_path(
+    engine: &Engine,
+    start: usize,
+    goal: usize,
+    options: &PathOptions,
+    tags: &TagFilter,
+    avoid_gates: &ClosedGates,
+) -> Result<PathOutcome, String> {
+    let g = &engine.graph;
+    let preference = options.preference;
+    // One-way gates can make the goal unreachable; no need to search then.
+    if options.mode == TravelMode::Gates && !engine.may_reach(start, goal) {
+        return Ok(Err(PathError::NoRoute));
+    }
+    if (!tags.is_empty() || !avoid_gates.is_empty()) && options.mode != TravelMode::Gates {
+        return Err("tag filters and avoid_gates apply to gate routes only".into());
+    }
+    // The closed-gate overlay applies to gate routes of every request.
+    let closed = &engine.closed_gates;
+    if options.mode == TravelMode::Gates
+        && !(tags.is_empty() && avoid_gates.is_empty() && closed.is_empty())
+    {
+        let system_tags = engine.tags();
+        let cost = |from: usize, to: usize| {
+            let (from_id, to_id) = (g.systems[from].id, g.systems[to].id);
+            let excluded = to != goal && !system_tags.matches(to_id, tags);
+            if excluded || avoid_gates.contains(from_id, to_id) || closed.contains(from_id, to_id) {
+                Scalar::INFINITY
+            } else if preference == RoutePreference::ShortestDistance {
+                g.systems[from].distance(&g.systems[to])
+            } else {
+                1.0
+            }
+        };
+        return Ok(shortest_gate_path_with(g, start, goal, &cost));
+    }
+    match options.mode {
+        // No per-system risk attributes are loaded yet, so `Safest` currently
+        // resolves to the fewest-jumps route.
+        TravelMode::Gates => Ok(match (preference, options.max_jumps, engine.landmarks()) {
+            (RoutePreference::FewestJumps | RoutePreference::Safest, None, Some(landmarks)) => {
+                shortest_gate_path_alt(g, landmarks, start, goal)
+            }
+            (RoutePreference::ShortestDistance, _, _) | (_, None, _) => {
+                shortest_path(g, start, goal, preference, None)
+            }
+            (_, Some(max_jumps), _) => shortest_gate_path_within(g, start, goal, max_jumps),
+        }),
+        TravelMode::JumpDrive | TravelMode::Hybrid => {
+            let units = engine.frame().units;
+            let range_ly = options
+                .jump_range
+                .filter(|r| *r > 0.0)
+                .ok_or("jump_drive and hybrid modes need a positive jump_range")?;
+            let range = DistanceUnit::LightYears.convert(range_ly, units);
+            let hops = engine.jump_neighbours(range);
+            Ok(if options.mode == TravelMode::JumpDrive {
+                shortest_jump_path(g, &engine.kd, &hops, start, goal, preference)
+            } else {
+                // Per light-year into per dataset unit.
+                let penalty = options.fuel_penalty.unwrap_or(0.0)
+                    * units.convert(1.0, DistanceUnit::LightYears);
+                shortest_hybrid_path(g, &engine.kd, &hops, start, goal, preference, penalty)
+            })
+        }
+    }
+}
+
+/// Unknown ids in `avoid_ids` are ignored.
+fn handle_sweep(
+    engine: &Engine,
+    location: LocationInput,
+    radius: Scalar,
+    avoid_ids: &[u32],
+    objective: SweepObjective,
+    tags: &TagFilter,
+    [first_id, last_id]: [Option<u32>; 2],
+) -> EngineResponse {
+    let center = match resolve_location(&engine.graph, location) {
+        Ok(point) => point,
+        Err(err) => return err.into(),
+    };
+    if let Err(err) = check_point("center", center).and(check_radius("radius", radius)) {
+        return err.into();
+    }
+    let g = &engine.graph;
+    let mut avoid: HashSet<usize> = avoid_ids
+        .iter()
+        .filter_map(|&id| g.index_of_id(id))
+        .collect();
+    if !tags.is_empty() {
+        let system_tags = engine.tags();
+        avoid.extend((0..g.len()).filter(|&idx| !system_tags.matches(g.systems[idx].id, tags)));
+    }
+    let ends = match sweep_ends(g, [first_id, last_id]) {
+        Ok(ends) => ends,
+        Err(id) => return EngineResponse::error(format!("Unknown system id {id}")),
+    };
+    let (indices, total_distance, avoided) =
+        sweep_with_ends(g, center, radius, &avoid, objective, ends);
+    EngineResponse::Sweep {
+        longest_leg: longest_leg(g, &indices),
+        systems: sweep_results(g, indices),
+        total_distance,
+        chunks: Vec::new(),
+        avoided: sweep_results(g, avoided),
+    }
+}
+
+fn handle_sweep_systems(
+    engine: &Engine,
+    system_ids: &[u32],
+    objective: SweepObjective,
+    ends: [Option<u32>; 2],
+) -> EngineResponse {
+    let g = &engine.graph;
+    let mut systems = Vec::with_capacity(system_ids.len());
+    for &id in system_ids {
+        match g.index_of_id(id) {
+            Some(idx) => systems.push(idx),
+            None => return EngineResponse::error(format!("Unknown system id {id}")),
+        }
+    }
+    let ends = match sweep_ends(g, ends) {
+        Ok(ends) => ends,
+        Err(id) => return EngineResponse::error(format!("Unknown system id {id}")),
+    };
+    let (indices, total_distance) = sweep_systems(g, &systems, objective, ends);
+    EngineResponse::Sweep {
+        longest_leg: longest_leg(g, &indices),
+        systems: sweep_results(g, indices),
+        total_distance,
+        chunks: Vec::new(),
+        avoided: Vec::new(),
+    }
+}
+
+/// Resolves the pinned `[first_id, last_id]` of a sweep, or returns the
+/// first unknown id.
+fn sweep_ends(g: &StarGraph, ids: [Option<u32>; 2]) -> Result<SweepEnds, u32> {
+    let [first, last] = ids.map(|id| id.map(|id| g.index_of_id(id).ok_or(id)).transpose());
+    Ok(SweepEnds {
+        first: first?,
+        last: last?,
+    })
+}
+
+fn sweep_results(g: &StarGraph, indices: Vec<usize>) -> Vec<SweepResult> {
+    indices
+        .into_iter()
+        .map(|idx| {
+            let s = &g.systems[idx];
+            SweepResult {
+                id: s.id,
+                name: s.name.clone(),
+                position: None,
+            }
+        })
+        .collect()
+}
+
+fn attach_chunks(limit: usize, response: &mut EngineResponse) {
+    let ids = |items: &[u32]| chunk_route(items, limit);
+    match response {
+        EngineResponse::Path { systems, chunks } => {
+            *chunks = ids(&systems.iter().map(|s| s.id).collect::<Vec<_>>());
+        }
+        EngineResponse::Sweep {
+            systems, chunks, ..
+        } => {
+            *chunks = ids(&systems.iter().map(|s| s.id).collect::<Vec<_>>());
+        }
+        _ => {}
+    }
+}
+
+/// Converts every distance in `response` from dataset units `from` into
+/// `to`. Path costs are converted only when `costs_are_distances`, as they
+/// count jumps otherwise; `compare` distances are always light-years.
+fn convert_distances(
+    from: DistanceUnit,
+    to: DistanceUnit,
+    costs_are_distances: bool,
+    response: &mut EngineResponse,
+) {
+    let convert = |value: &mut Scalar| *value = from.convert(*value, to);
+    let convert_path = |systems: &mut [PathResult]| {
+        for system in systems {
+            convert(&mut system.leg_distance);
+            if costs_are_distances {
+                convert(&mut system.cumulative_cost);
+            }
+        }
+    };
+    let convert_nearest = |systems: &mut [NearestResult]| {
+        systems.iter_mut().for_each(|s| convert(&mut s.distance));
+    };
+    match response {
+        EngineResponse::Nearest {
+            systems,
+            suggested_radius,
+        } => {
+            convert_nearest(systems);
+            suggested_radius.iter_mut().for_each(convert);
+        }
+        EngineResponse::Path { systems, .. } => convert_path(systems),
+        EngineResponse::Eta {
+            systems,
+            remaining_distance,
+            ..
+        } => {
+            convert(remaining_distance);
+            convert_path(systems);
+        }
+        EngineResponse::Alternatives { routes } => {
+            routes.iter_mut().for_each(|r| convert_path(&mut r.systems));
+        }
+        EngineResponse::Pareto { routes } => {
+            for route in routes {
+                convert(&mut route.distance);
+                convert_path(&mut route.systems);
+            }
+        }
+        EngineResponse::Sweep {
+            total_distance,
+            longest_leg,
+            ..
+        } => {
+            convert(total_distance);
+            convert(longest_leg);
+        }
+        EngineResponse::Unvisited { by_distance, .. } => convert_nearest(by_distance),
+        EngineResponse::Midpoint { systems, .. } => convert_nearest(systems),
+        EngineResponse::Corridor { systems } => {
+            systems.iter_mut().for_each(|s| convert(&mut s.distance));
+        }
+        EngineResponse::Pois { pois } => {
+            pois.iter_mut().for_each(|p| convert(&mut p.distance));
+        }
+        EngineResponse::Density { neighbours, .. } => {
+            for neighbour in neighbours {
+                neighbour.distance.iter_mut().for_each(convert);
+            }
+        }
+        EngineResponse::Heatmap {
+            metric: HeatmapMetric::NeighbourDistance,
+            grid,
+            ..
+        } => grid.cells.iter_mut().for_each(|c| convert(&mut c.value)),
+        EngineResponse::Compare {
+            metric: CompareMetric::Distance,
+            before,
+            after,
+            change,
+        } => {
+            let ly = |value: &mut Scalar| *value = DistanceUnit::LightYears.convert(*value, to);
+            before
+                .value
+                .iter_mut()
+                .chain(&mut after.value)
+                .chain(change)
+                .for_each(ly);
+        }
+        EngineResponse::RouteStability { stability, .. } => {
+            let ly = |value: &mut Scalar| *value = DistanceUnit::LightYears.convert(*value, to);
+            for cost in stability
+                .old_cost
+                .iter_mut()
+                .chain(&mut stability.new_cost)
+                .chain(&mut stability.repaired_cost)
+            {
+                ly(&mut cost.distance);
+            }
+        }
+        EngineResponse::RouteCheck {
+            violation: Some(RouteViolation::OutOfRange { distance, .. }),
+            ..
+        } => convert(distance),
+        // Counts, ids, jumps and other metrics: no distances to convert.
+        EngineResponse::RouteCheck { .. }
+        | EngineResponse::Heatmap { .. }
+        | EngineResponse::Compare { .. }
+        | EngineResponse::CrossRoutes { .. }
+        | EngineResponse::Tags { .. }
+        | EngineResponse::JumpDistances { .. }
+        | EngineResponse::Hubs { .. }
+        | EngineResponse::Job { .. }
+        | EngineResponse::Info { .. }
+        | EngineResponse::AmbiguousName { .. }
+        | EngineResponse::BudgetExceeded { .. }
+        | EngineResponse::JumpLimitExceeded { .. }
+        | EngineResponse::Features { .. }
+        | EngineResponse::Error { .. } => {}
+    }
+}
+
+/// Fills in `position` on every returned system, converted from the
+/// dataset's frame into `target`.
+fn attach_positions(engine: &Engine, target: &CoordinateFrame, response: &mut EngineResponse) {
+    let source = engine.frame();
+    let position_of = |id: u32| {
+        engine
+            .graph
+            .index_of_id(id)
+            .map(|idx| source.convert_point(engine.graph.systems[idx].pos, target))
+    };
+    fn fill<T: SystemResult>(items: &mut [T], position_of: &dyn Fn(u32) -> Option<[Scalar; 3]>) {
+        for item in items {
+            item.set_position(position_of(item.id()));
+        }
+    }
+    match response {
+        EngineResponse::Nearest { systems, .. } => fill(systems, &position_of),
+        EngineResponse::Path { systems, .. } => fill(systems, &position_of),
+        EngineResponse::Eta { systems, .. } => fill(systems, &position_of),
+        EngineResponse::Alternatives { routes } => {
+            for route in routes {
+                fill(&mut route.systems, &position_of);
+            }
+        }
+        EngineResponse::Pareto { routes } => {
+            for route in routes {
+                fill(&mut route.systems, &position_of);
+            }
+        }
+        EngineResponse::Sweep {
+            systems, avoided, ..
+        } => {
+            fill(systems, &position_of);
+            fill(avoided, &position_of);
+        }
+        EngineResponse::Hubs { systems } => fill(systems, &position_of),
+        EngineResponse::Midpoint { systems, .. } => fill(systems, &position_of),
+        EngineResponse::Corridor { systems } => fill(systems, &position_of),
+        EngineResponse::Unvisited {
+            by_jumps,
+            by_distance,
+        } => {
+            fill(by_jumps, &position_of);
+            fill(by_distance, &position_of);
+        }
+        _ => {}
+    }
+}
+
+/// Unknown ids in the exploration log are ignored, since logs often span
+/// dataset versions.
+fn handle_unvisited(
+    engine: &Engine,
+    origin_id: u32,
+    visited_ids: &[u32],
+    radius: Scalar,
+    count: usize,
+) -> EngineResponse {
+    let g = &engine.graph;
+    let Some(origin) = g.index_of_id(origin_id) else {
+        return EngineResponse::error(format!("Unknown origin_id {origin_id}"));
+    };
+    if let Err(err) = check_radius("radius", radius) {
+        return err.into();
+    }
+    let mut visited: HashSet<usize> = visited_ids
+        .iter()
+        .filter_map(|&id| g.index_of_id(id))
+        .collect();
+    visited.insert(origin);
+
+    let by_jumps = nearest_unvisited_by_jumps(g, origin, &visited, count)
+        .into_iter()
+        .map(|(idx, jumps)| JumpResult {
+            id: g.systems[idx].id,
+            name: g.systems[idx].name.clone(),
+            jumps,
+            position: None,
+        })
+        .collect();
+    let by_distance = engine
+        .kd
+        .nearest_n_within_radius_filtered(g.systems[origin].pos, radius, count, |idx| {
+            !visited.contains(&idx)
+        })
+        .into_iter()
+        .map(|(idx, distance)| NearestResult {
+            id: g.systems[idx].id,
+            name: g.systems[idx].name.clone(),
+            distance,
+            position: None,
+        })
+        .collect();
+    EngineResponse::Unvisited {
+        by_jumps,
+        by_distance,
+    }
+}
+
+fn handle_hubs(
+    engine: &Engine,
+    origin_id: u32,
+    degree_threshold: usize,
+    count: usize,
+) -> EngineResponse {
+    let g = &engine.graph;
+    let Some(origin) = g.index_of_id(origin_id) else {
+        return EngineResponse::error(format!("Unknown origin_id {origin_id}"));
+    };
+    let systems = nearest_hubs_by_jumps(g, origin, degree_threshold, count)
+        .into_iter()
+        .map(|(idx, jumps)| HubResult {
+            id: g.systems[idx].id,
+            name: g.systems[idx].name.clone(),
+            jumps,
+            degree: g.adjacency[idx].len(),
+            position: None,
+        })
+        .collect();
+    EngineResponse::Hubs { systems }
+}
+
+fn handle_check_route(engine: &Engine, system_ids: &[u32], ship: &ShipProfile) -> EngineResponse {
+    let violation = check_route(&engine.graph, system_ids, ship).err();
+    EngineResponse::RouteCheck {
+        valid: violation.is_none(),
+        violation,
+    }
+}
+
+fn handle_eta(
+    engine: &Engine,
+    system_ids: &[u32],
+    current_id: u32,
+    timestamp: u64,
+    started_at: Option<u64>,
+    seconds_per_jump: Option<Scalar>,
+) -> EngineResponse {
+    let g = &engine.graph;
+    let Some(current) = g.index_of_id(current_id) else {
+        return EngineResponse::error(format!("Unknown current_id {current_id}"));
+    };
+    if seconds_per_jump.is_some_and(|pace| !(pace.is_finite() && pace >= 0.0)) {
+        return EngineResponse::error("seconds_per_jump must be non-negative and finite");
+    }
+    let progress = match route_progress(g, system_ids, current) {
+        Ok(progress) => progress,
+        Err(err) => return err.into(),
+    };
+    // Observed pace: time since the start over the jumps made on the route.
+    let observed = match (started_at, progress.position) {
+        (Some(start), Some(done)) if done > 0 && timestamp >= start => {
+            Some((timestamp - start) as Scalar / done as Scalar)
+        }
+        _ => None,
+    };
+    let seconds_per_jump = seconds_per_jump.or(observed);
+    let remaining_jumps = progress.remaining.len() - 1;
+    let remaining_distance = progress.remaining.iter().map(|s| s.leg_distance).sum();
+    EngineResponse::Eta {
+        position: progress.position,
+        rerouted: progress.rerouted,
+        remaining_jumps,
+        remaining_distance,
+        systems: path_results(g, progress.remaining),
+        seconds_per_jump,
+        eta: seconds_per_jump
+            .map(|pace| timestamp + (pace * remaining_jumps as Scalar).round() as u64),
+    }
+}
+
+/// Positions of the systems along a route, for the corridor queries.
+fn route_waypoints(graph: &StarGraph, system_ids: &[u32]) -> Result<Vec<[Scalar; 3]>, String> {
+    system_ids
+        .iter()
+        .map(|&id| match graph.index_of_id(id) {
+            Some(idx) => Ok(graph.systems[idx].pos),
+            None => Err(format!("Unknown system id {id}")),
+        })
+        .collect()
+}
+
+fn handle_corridor(engine: &Engine, system_ids: &[u32], distance: Scalar) -> EngineResponse {
+    if let Err(err) = check_radius("distance", distance) {
+        return err.into();
+    }
+    let g = &engine.graph;
+    let waypoints = match route_waypoints(g, system_ids) {
+        Ok(waypoints) => waypoints,
+        Err(message) => return EngineResponse::error(message),
+    };
+    let points: Vec<[Scalar; 3]> = g.systems.iter().map(|s| s.pos).collect();
+    let systems = within_corridor(engine.morton(), &points, &waypoints, distance)
+        .into_iter()
+        .map(|hit| {
+            let s = &g.systems[hit.index];
+            CorridorResult {
+                id: s.id,
+                name: s.name.clone(),
+                distance: hit.distance,
+                leg: hit.segment,
+                position: None,
+            }
+        })
+        .collect();
+    EngineResponse::Corridor { systems }
+}
+
+fn handle_nearest_poi(
+    engine: &Engine,
+    location: LocationInput,
+    radius: Scalar,
+    count: usize,
+    tag: Option<&str>,
+) -> EngineResponse {
+    let origin = match resolve_location(&engine.graph, location) {
+        Ok(point) => point,
+        Err(err) => return err.into(),
+    };
+    if let Err(err) = check_point("origin", origin).and(check_radius("radius", radius)) {
+        return err.into();
+    }
+    let layer = engine.pois();
+    let pois = layer
+        .nearest(origin, radius, count, tag)
+        .into_iter()
+        .map(|(poi, distance)| poi_result(poi, distance, None))
+        .collect();
+    EngineResponse::Pois { pois }
+}
+
+fn handle_pois_along_route(
+    engine: &Engine,
+    system_ids: &[u32],
+    distance: Scalar,
+) -> EngineResponse {
+    if let Err(err) = check_radius("distance", distance) {
+        return err.into();
+    }
+    let waypoints = match route_waypoints(&engine.graph, system_ids) {
+        Ok(waypoints) => waypoints,
+        Err(message) => return EngineResponse::error(message),
+    };
+    let layer = engine.pois();
+    let pois = layer
+        .along_route(&waypoints, distance)
+        .into_iter()
+        .map(|(poi, distance, leg)| poi_result(poi, distance, Some(leg)))
+        .collect();
+    EngineResponse::Pois { pois }
+}
+
+fn poi_result(poi: &Poi, distance: Scalar, leg: Option<usize>) -> PoiResult {
+    PoiResult {
+        id: poi.id,
+        label: poi.label.clone(),
+        tags: poi.tags.clone(),
+        pos: poi.pos,
+        distance,
+        leg,
+    }
+}
+
+fn handle_midpoint(
+    engine: &Engine,
+    first_name: &str,
+    second_name: &str,
+    count: usize,
+    radius: Option<Scalar>,
+) -> EngineResponse {
+    let position = |name: &str| {
+        resolve_location(
+            &engine.graph,
+            LocationInput::System {
+                system_name: name.to_string(),
+            },
+        )
+    };
+    let (a, b) = match (position(first_name), position(second_name)) {
+        (Ok(a), Ok(b)) => (a, b),
+        (Err(err), _) | (_, Err(err)) => return err.into(),
+    };
+    let midpoint = [0, 1, 2].map(|axis| (a[axis] + b[axis]) / 2.0);
+    let radius = match check_radius("radius", radius.unwrap_or(Scalar::INFINITY)) {
+        Ok(radius) => radius,
+        Err(err) => return err.into(),
+    };
+    let systems = engine
+        .kd
+        .nearest_n_within_radius(midpoint, radius, count)
+        .into_iter()
+        .map(|(idx, distance)| NearestResult {
+            id: engine.graph.systems[idx].id,
+            name: engine.graph.systems[idx].name.clone(),
+            distance,
+            position: None,
+        })
+        .collect();
+    EngineResponse::Midpoint { midpoint, systems }
+}
+
+fn handle_density(engine: &Engine, system_id: u32) -> EngineResponse {
+    let Some(idx) = engine.graph.index_of_id(system_id) else {
+        return EngineResponse::error(format!("Unknown system_id {system_id}"));
+    };
+    let system = &engine.graph.systems[idx];
+    let neighbours = NEIGHBOUR_RANKS
+        .iter()
+        .zip(engine.neighbour_distances(idx))
+        .map(|(&rank, distance)| NeighbourDistance { rank, distance })
+        .collect();
+    EngineResponse::Density {
+        id: system.id,
+        name: system.name.clone(),
+        neighbours,
+    }
+}
+
+/// Systems without a neighbour are left out of `neighbour_distance` maps.
+fn handle_heatmap(
+    engine: &Engine,
+    cell_size: Scalar,
+    metric: HeatmapMetric,
+    projection: Option<Projection>,
+    bandwidth: Option<Scalar>,
+    tags: &TagFilter,
+) -> EngineResponse {
+    if bandwidth.is_some() && metric != HeatmapMetric::Density {
+        return EngineResponse::error("bandwidth only applies to the density metric");
+    }
+    let g = &engine.graph;
+    let system_tags = engine.tags();
+    let matching = (0..g.len()).filter(|&idx| system_tags.matches(g.systems[idx].id, tags));
+    let grid = if metric == HeatmapMetric::Density {
+        let points: Vec<[Scalar; 3]> = matching.map(|idx| g.systems[idx].pos).collect();
+        density(
+            &points,
+            cell_size,
+            projection,
+            bandwidth.unwrap_or(cell_size),
+        )
+    } else {
+        let samples: Vec<([Scalar; 3], Scalar)> = matching
+            .filter_map(|idx| {
+                let value = match metric {
+                    HeatmapMetric::Degree => g.adjacency[idx].len() as Scalar,
+                    HeatmapMetric::NeighbourDistance => engine.neighbour_distances(idx)[0]?,
+                    HeatmapMetric::Count | HeatmapMetric::Density => 1.0,
+                };
+                Some((g.systems[idx].pos, value))
+            })
+            .collect();
+        let aggregate = match metric {
+            HeatmapMetric::Degree | HeatmapMetric::NeighbourDistance => Aggregate::Mean,
+            HeatmapMetric::Count | HeatmapMetric::Density => Aggregate::Sum,
+        };
+        heatmap(&samples, cell_size, projection, aggregate)
+    };
+    match grid {
+        Ok(grid) => EngineResponse::Heatmap {
+            metric,
+            projection,
+            grid,
+        },
+        Err(err) => EngineResponse::error(err.to_string()),
+    }
+}
+
+fn handle_compare(
+    engine: &Engine,
+    start_id: u32,
+    end_id: u32,
+    metric: CompareMetric,
+    before: &str,
+    after: Option<&str>,
+) -> EngineResponse {
+    let after = after.unwrap_or(CURRENT_SNAPSHOT);
+    let measure = |label: &str| -> Result<SnapshotValue, String> {
+        let snapshot = engine
+            .snapshot(Some(label))
+            .ok_or_else(|| format!("Unknown snapshot {label}"))?;
+        let g = &snapshot.graph;
+        let value = g
+            .index_of_id(start_id)
+            .zip(g.index_of_id(end_id))
+            .and_then(|(start, goal)| match metric {
+                CompareMetric::Jumps => snapshot.jumps(start, goal).map(|j| j as Scalar),
+                CompareMetric::Distance => {
+                    let d = g.systems[start].distance(&g.systems[goal]);
+                    Some(snapshot.frame().units.convert(d, DistanceUnit::LightYears))
+                }
+            });
+        Ok(SnapshotValue {
+            snapshot: label.to_string(),
+            value,
+        })
+    };
+    let (before, after) = match (measure(before), measure(after)) {
+        (Ok(before), Ok(after)) => (before, after),
+        (Err(msg), _) | (_, Err(msg)) => return EngineResponse::error(msg),
+    };
+    let change = before.value.zip(after.value).map(|(b, a)| a - b);
+    EngineResponse::Compare {
+        metric,
+        before,
+        after,
+        change,
+    }
+}
+
+fn handle_route_stability(
+    engine: &Engine,
+    system_ids: &[u32],
+    before: String,
+    after: Option<String>,
+) -> EngineResponse {
+    let after = after.unwrap_or_else(|| CURRENT_SNAPSHOT.to_string());
+    let (Some(old), Some(new)) = (
+        engine.snapshot(Some(&before)),
+        engine.snapshot(Some(&after)),
+    ) else {
+        let unknown = if engine.snapshot(Some(&before)).is_none() {
+            &before
+        } else {
+            &after
+        };
+        return EngineResponse::error(format!("Unknown snapshot {unknown}"));
+    };
+    let mut stability = evaluate_route(&old.graph, &new.graph, system_ids);
+    // Each snapshot may use its own units, so costs are reported in
+    // light-years.
+    let ly = |snapshot: &Engine, cost: &mut Scalar| {
+        *cost = snapshot
+            .frame()
+            .units
+            .convert(*cost, DistanceUnit::LightYears)
+    };
+    if let Some(cost) = &mut stability.old_cost {
+        ly(old, &mut cost.distance);
+    }
+    for cost in stability
+        .new_cost
+        .iter_mut()
+        .chain(&mut stability.repaired_cost)
+    {
+        ly(new, &mut cost.distance);
+    }
+    EngineResponse::RouteStability {
+        before,
+        after,
+        stability,
+    }
+}
+
+pub fn resolve_location(
+    graph: &StarGraph,
+    location: LocationInput,
+) -> Result<[Scalar; 3], NameError> {
+    match location {
+        LocationInput::Coordinates { coords } => Ok(coords),
+        LocationInput::System { system_name } => {
+            Ok(graph.systems[graph.resolve_name(&system_name)?].pos)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::{demo_graph, graph_from};
+    use crate::graph::hub_labels::HubLabels;
+    use crate::graph::reachability::MAX_REACHABILITY_COMPONENTS;
+    use crate::spatial::index::scattered_points;
+
+    #[test]
+    fn location_from_coordinates() {
+        let json = r#"{"kind":"nearest","origin":[1.0,2.0,3.0],"radius":1.0,"count":1}"#;
+        let req: EngineRequest = serde_json::from_str(json).expect("parse");
+        match req.query {
+            Query::Nearest { location, .. } => {
+                let coords = resolve_location(&demo_graph(), location).expect("coords");
+                assert_eq!(coords, [1.0, 2.0, 3.0]);
+            }
+            _ => panic!("expected nearest"),
+        }
+    }
+
+    #[test]
+    fn location_from_system_name() {
+        let json = r#"{"kind":"sweep","system_name":"B","radius":10.0}"#;
+        let req: EngineRequest = serde_json::from_str(json).expect("parse");
+        match req.query {
+            Query::Sweep { location, .. } => {
+                let coords = resolve_location(&demo_graph(), location).expect("coords");
+                assert_eq!(coords, [1.0, 0.0, 0.0]);
+            }
+            _ => panic!("expected sweep"),
+        }
+    }
+
+    #[test]
+    fn location_unknown_system_errors() {
+        let json = r#"{"kind":"nearest","system_name":"Z","radius":5.0,"count":1}"#;
+        let req: EngineRequest = serde_json::from_str(json).expect("parse");
+        match req.query {
+            Query::Nearest { location, .. } => {
+                let err = resolve_location(&demo_graph(), location).expect_err("missing");
+                assert!(err.to_string().contains("Unknown system_name"));
+            }
+            _ => panic!("expected nearest"),
+        }
+    }
+
+    #[test]
+    fn request_round_trips_through_json() {
+        let json = r#"{"kind":"sweep","center":[1.0,0.0,0.0],"radius":2.0}"#;
+        let req: EngineRequest = serde_json::from_str(json).expect("parse");
+        let encoded = serde_json::to_string(&req).expect("encode");
+        let again: EngineRequest = serde_json::from_str(&encoded).expect("reparse");
+        let engine = Engine::new(demo_graph(), None);
+        let a = serde_json::to_value(handle_request(&engine, req)).unwrap();
+        let b = serde_json::to_value(handle_request(&engine, again)).unwrap();
+        assert_eq!(a, b);
+    }
+
+    #[test]
+    fn frame_option_attaches_converted_positions() {
+        let json = r#"{"kind":"path","start_id":1,"end_id":3,"frame":{"units":"meters","origin":[1.0,0.0,0.0]}}"#;
+        let req: EngineRequest = serde_json::from_str(json).expect("parse");
+        let engine = Engine::new(demo_graph(), None);
+        match handle_request(&engine, req).response {
+            EngineResponse::Path { systems, .. } => {
+                assert_eq!(systems[0].position, Some([-1.0, 0.0, 0.0]));
+                assert_eq!(systems[2].position, Some([1.0, 0.0, 0.0]));
+            }
+            other => panic!("expected path, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn unvisited_skips_logged_systems() {
+        let json =
+            r#"{"kind":"unvisited","origin_id":2,"visited_ids":[1,99],"radius":10.0,"count":2}"#;
+        let req: EngineRequest = serde_json::from_str(json).expect("parse");
+        let engine = Engine::new(demo_graph(), None);
+        match handle_request(&engine, req).response {
+            EngineResponse::Unvisited {
+                by_jumps,
+                by_distance,
+            } => {
+                let jump_ids: Vec<u32> = by_jumps.iter().map(|r| r.id).collect();
+                let dist_ids: Vec<u32> = by_distance.iter().map(|r| r.id).collect();
+                assert_eq!(jump_ids, vec![3, 4]);
+                assert_eq!(by_jumps[1].jumps, 2);
+                assert_eq!(dist_ids, vec![3, 4]);
+            }
+            other => panic!("expected unvisited, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn features_format_wraps_response() {
+        let json =
+            r#"{"kind":"nearest","system_name":"A","radius":1.5,"count":3,"format":"features"}"#;
+        let req: EngineRequest = serde_json::from_str(json).expect("parse");
+        assert_eq!(req.options.format, ResponseFormat::Features);
+        let engine = Engine::new(demo_graph(), None);
+        match handle_request(&engine, req).response {
+            EngineResponse::Features { collection } => assert_eq!(collection.features.len(), 2),
+            other => panic!("expected features, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn debug_stats_are_opt_in() {
+        let engine = Engine::new(demo_graph(), None);
+        let plain: EngineRequest =
+            serde_json::from_str(r#"{"kind":"path","start_id":1,"end_id":3}"#).unwrap();
+        let value = serde_json::to_value(handle_request(&engine, plain)).unwrap();
+        assert!(value.get("debug_stats").is_none());
+
+        let json = r#"{"kind":"path","start_id":1,"end_id":3,"debug_stats":true}"#;
+        let reply = handle_request(&engine, serde_json::from_str(json).unwrap());
+        let stats = reply.debug_stats.expect("stats");
+        // The plain request above already put this route in the path cache.
+        assert_eq!(stats.cache_hit, Some(true));
+        assert_eq!(stats.nodes_expanded, 0);
+        assert_eq!(stats.phases.len(), 2);
+        let value = serde_json::to_value(&reply.response).unwrap();
+        assert_eq!(value["kind"], "path");
+
+        // D to C misses the cache: A* expands D, A, B and C along its route.
+        let json = r#"{"kind":"path","start_id":4,"end_id":3,"debug_stats":true}"#;
+        let stats = handle_request(&engine, serde_json::from_str(json).unwrap())
+            .debug_stats
+            .expect("stats");
+        assert_eq!(stats.cache_hit, Some(false));
+        assert!(stats.nodes_expanded >= 4);
+        assert!(stats.frontier_peak >= 1);
+        let tightness = stats.heuristic_tightness.expect("heuristic search");
+        assert!(tightness > 0.0 && tightness <= 1.0, "{tightness}");
+    }
+
+    #[test]
+    fn jump_drive_mode_ignores_gates() {
+        let engine = Engine::new(demo_graph(), None);
+        // C and D share no gate; one light-year covers the whole demo map.
+        let json =
+            r#"{"kind":"path","start_id":3,"end_id":4,"mode":"jump_drive","jump_range":1.0}"#;
+        match handle_request(&engine, serde_json::from_str(json).unwrap()).response {
+            EngineResponse::Path { systems, .. } => assert_eq!(systems.len(), 2),
+            other => panic!("expected path, got {other:?}"),
+        }
+        let json = r#"{"kind":"path","start_id":3,"end_id":4,"mode":"jump_drive"}"#;
+        let reply = handle_request(&engine, serde_json::from_str(json).unwrap());
+        assert!(matches!(reply.response, EngineResponse::Error { .. }));
+    }
+
+    #[test]
+    fn compare_reports_change_between_snapshots() {
+        let mut engine = Engine::new(demo_graph(), None);
+        let mut older = demo_graph();
+        older.adjacency[0] = vec![1];
+        older.adjacency[3] = vec![];
+        older.rebuild_indices();
+        engine
+            .snapshots
+            .insert("v1".into(), Engine::new(older, None));
+
+        let json = r#"{"kind":"compare","start_id":3,"end_id":4,"metric":"jumps","before":"v1"}"#;
+        match handle_request(&engine, serde_json::from_str(json).unwrap()).response {
+            EngineResponse::Compare {
+                before,
+                after,
+                change,
+                ..
+            } => {
+                assert_eq!(before.value, None);
+                assert_eq!(after.value, Some(3.0));
+                assert_eq!(change, None);
+            }
+            other => panic!("expected compare, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn route_stability_repairs_a_route_broken_since_a_snapshot() {
+        let mut engine = Engine::new(demo_graph(), None);
+        // v1 also had a D -> C gate, which the live dataset lost.
+        let mut older = demo_graph();
+        older.adjacency[3].push(2);
+        older.rebuild_indices();
+        engine
+            .snapshots
+            .insert("v1".into(), Engine::new(older, None));
+
+        let json = r#"{"kind":"route_stability","system_ids":[4,3],"before":"v1"}"#;
+        match handle_request(&engine, serde_json::from_str(json).unwrap()).response {
+            EngineResponse::RouteStability {
+                after, stability, ..
+            } => {
+                assert_eq!(after, "current");
+                assert!(!stability.valid);
+                assert_eq!(stability.old_cost.map(|c| c.jumps), Some(1));
+                assert_eq!(stability.first_break, Some(1));
+                assert_eq!(stability.repaired, Some(vec![4, 1, 2, 3]));
+                assert_eq!(stability.repaired_cost.map(|c| c.jumps), Some(3));
+            }
+            other => panic!("expected route_stability, got {other:?}"),
+        }
+        let json = r#"{"kind":"route_stability","system_ids":[4,3],"before":"v0"}"#;
+        let reply = handle_request(&engine, serde_json::from_str(json).unwrap());
+        assert!(matches!(reply.response, EngineResponse::Error { .. }));
+    }
+
+    #[test]
+    fn midpoint_finds_systems_halfway() {
+        let engine = Engine::new(demo_graph(), None);
+        let json = r#"{"kind":"midpoint","first_name":"A","second_name":"C","count":1}"#;
+        match handle_request(&engine, serde_json::from_str(json).unwrap()).response {
+            EngineResponse::Midpoint { midpoint, systems } => {
+                assert_eq!(midpoint, [1.0, 0.0, 0.0]);
+                assert_eq!(systems[0].name, "B");
+            }
+            other => panic!("expected midpoint, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn pareto_returns_non_dominated_routes() {
+        let engine = Engine::new(demo_graph(), None);
+        let json = r#"{"kind":"pareto","start_id":1,"end_id":3}"#;
+        match handle_request(&engine, serde_json::from_str(json).unwrap()).response {
+            EngineResponse::Pareto { routes } => {
+                assert!(!routes.is_empty());
+                assert_eq!(routes[0].jumps, 2);
+                for pair in routes.windows(2) {
+                    assert!(pair[0].jumps < pair[1].jumps);
+                    assert!(pair[0].distance > pair[1].distance);
+                }
+            }
+            other => panic!("expected pareto, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn path_budget_is_reported_and_not_cached() {
+        let engine = Engine::new(demo_graph(), None);
+        let json = r#"{"kind":"path","start_id":4,"end_id":3,"max_expansions":1}"#;
+        match handle_request(&engine, serde_json::from_str(json).unwrap()).response {
+            EngineResponse::BudgetExceeded { limit, budget } => {
+                assert_eq!(limit, BudgetLimit::Expansions);
+                assert_eq!(budget, 1);
+            }
+            other => panic!("expected budget_exceeded, got {other:?}"),
+        }
+        let json = r#"{"kind":"path","start_id":4,"end_id":3,"time_budget_ms":1000}"#;
+        match handle_request(&engine, serde_json::from_str(json).unwrap()).response {
+            EngineResponse::Path { systems, .. } => assert_eq!(systems.len(), 4),
+            other => panic!("expected path, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn multi_route_searches_stop_at_their_budget() {
+        let engine = Engine::new(demo_graph(), None);
+        for json in [
+            r#"{"kind":"alternatives","start_id":4,"end_id":3,"count":2,"max_expansions":1}"#,
+            r#"{"kind":"pareto","start_id":4,"end_id":3,"max_expansions":1}"#,
+            r#"{"kind":"cross_routes","source_ids":[4],"target_ids":[3],"max_expansions":1}"#,
+            r#"{"kind":"waypoints","origin_id":4,"waypoint_ids":[3],"max_expansions":1}"#,
+            r#"{"kind":"patrol","origin_id":4,"waypoint_ids":[3],"max_expansions":1}"#,
+        ] {
+            let response = handle_request(&engine, serde_json::from_str(json).unwrap()).response;
+            assert!(
+                matches!(response, EngineResponse::BudgetExceeded { budget: 1, .. }),
+                "{json}: {response:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn jump_distances_agree_across_hub_labels_matrix_and_search() {
+        let json = r#"{"kind":"jump_distances","pairs":[[1,3],[4,3],[2,2]]}"#;
+        let expected = vec![Some(2), Some(3), Some(0)];
+        for (labelled, matrix) in [(false, false), (false, true), (true, false)] {
+            let mut engine = Engine::new(demo_graph(), None);
+            if !matrix {
+                engine.config.jump_matrix_max_systems = 0;
+            }
+            if labelled {
+                engine.hub_labels = Some(HubLabels::build(&engine.graph));
+            }
+            match handle_request(&engine, serde_json::from_str(json).unwrap()).response {
+                EngineResponse::JumpDistances { jumps } => assert_eq!(jumps, expected),
+                other => panic!("expected jump_distances, got {other:?}"),
+            }
+            let capabilities = engine.capabilities();
+            assert_eq!(
+                (capabilities.hub_labels, capabilities.jump_matrix),
+                (labelled, matrix)
+            );
+        }
+    }
+
+    #[test]
+    fn tag_filters_exclude_systems_once_tagged() {
+        let mut engine = Engine::new(demo_graph(), None);
+        let set = r#"{"kind":"set_tags","system_id":2,"tags":["hostile"]}"#;
+        let refused = handle_request(&engine, serde_json::from_str(set).unwrap());
+        assert!(matches!(refused.response, EngineResponse::Error { .. }));
+
+        engine.config.admin_requests = true;
+        match handle_request(&engine, serde_json::from_str(set).unwrap()).response {
+            EngineResponse::Tags { id, tags } => assert_eq!((id, tags.len()), (2, 1)),
+            other => panic!("expected tags, got {other:?}"),
+        }
+        let nearest =
+            r#"{"kind":"nearest","system_name":"A","radius":100.0,"count":4,"tags":["!hostile"]}"#;
+        match handle_request(&engine, serde_json::from_str(nearest).unwrap()).response {
+            EngineResponse::Nearest { systems, .. } => {
+                assert_eq!(systems.len(), 3);
+                assert!(systems.iter().all(|s| s.id != 2));
+            }
+            other => panic!("expected nearest, got {other:?}"),
+        }
+        // D -> C runs through B, so avoiding hostile systems leaves no route.
+        let path = r#"{"kind":"path","start_id":4,"end_id":3,"tags":["!hostile"]}"#;
+        let reply = handle_request(&engine, serde_json::from_str(path).unwrap());
+        assert!(matches!(reply.response, EngineResponse::Error { .. }));
+        let path = r#"{"kind":"path","start_id":4,"end_id":2,"tags":["!hostile"]}"#;
+        match handle_request(&engine, serde_json::from_str(path).unwrap()).response {
+            EngineResponse::Path { systems, .. } => assert_eq!(systems.len(), 3),
+            other => panic!("expected path, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn one_way_gates_limit_reachable_systems() {
+        // A -> D becomes one-way, stranding D.
+        let demo = demo_graph();
+        let mut adjacency = demo.adjacency.clone();
+        adjacency[3].clear();
+        let engine = Engine::new(StarGraph::new(demo.systems.clone(), adjacency), None);
+        let json = r#"{"kind":"nearest","system_name":"A","radius":100.0,"count":4,"reachable_from_id":4}"#;
+        match handle_request(&engine, serde_json::from_str(json).unwrap()).response {
+            EngineResponse::Nearest { systems, .. } => {
+                assert_eq!(systems.iter().map(|s| s.id).collect::<Vec<_>>(), vec![4]);
+            }
+            other => panic!("expected nearest, got {other:?}"),
+        }
+        assert!(engine.reachability().is_some());
+        assert!(engine.may_reach(0, 3));
+        assert!(!engine.may_reach(3, 0));
+        let json = r#"{"kind":"path","start_id":4,"end_id":3}"#;
+        let reply = handle_request(&engine, serde_json::from_str(json).unwrap());
+        assert!(matches!(reply.response, EngineResponse::Error { .. }));
+    }
+
+    #[test]
+    fn isolated_systems_are_unreachable_beyond_the_component_limit() {
+        // A gate pair 0 <-> 1 among more gateless systems than the bitmaps
+        // allow components, all within the search radius.
+        let n = MAX_REACHABILITY_COMPONENTS + 10;
+        let positions: Vec<[Scalar; 3]> = (0..n).map(|i| [i as Scalar * 1e-3, 0.0, 0.0]).collect();
+        let mut adjacency = vec![Vec::new(); n];
+        adjacency[0].push(1);
+        adjacency[1].push(0);
+        let engine = Engine::new(graph_from(&positions, adjacency), None);
+        let json = r#"{"kind":"nearest","system_name":"S0","radius":100.0,"count":5,"reachable_from_id":1}"#;
+        match handle_request(&engine, serde_json::from_str(json).unwrap()).response {
+            EngineResponse::Nearest { systems, .. } => {
+                assert_eq!(systems.iter().map(|s| s.id).collect::<Vec<_>>(), vec![1, 2]);
+            }
+            other => panic!("expected nearest, got {other:?}"),
+        }
+        assert!(!engine.may_reach(2, 3));
+        assert!(engine.may_reach(2, 2));
+    }
+
+    #[test]
+    fn short_nearest_results_suggest_a_radius() {
+        let engine = Engine::new(demo_graph(), None);
+        let json =
+            r#"{"kind":"nearest","system_name":"A","radius":1.5,"count":3,"suggest_radius":true}"#;
+        match handle_request(&engine, serde_json::from_str(json).unwrap()).response {
+            EngineResponse::Nearest {
+                systems,
+                suggested_radius,
+            } => {
+                assert_eq!(systems.len(), 2);
+                assert_eq!(suggested_radius, Some(2.0));
+            }
+            other => panic!("expected nearest, got {other:?}"),
+        }
+        // More systems than the map holds: nothing to suggest.
+        let json =
+            r#"{"kind":"nearest","system_name":"A","radius":1.5,"count":5,"suggest_radius":true}"#;
+        match handle_request(&engine, serde_json::from_str(json).unwrap()).response {
+            EngineResponse::Nearest {
+                suggested_radius, ..
+            } => assert_eq!(suggested_radius, None),
+            other => panic!("expected nearest, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn filtered_radius_suggestions_match_the_kd_tree() {
+        let points = scattered_points(500, 100.0);
+        let engine = Engine::new(graph_from(&points, vec![Vec::new(); points.len()]), None);
+        let filter = ConeFilter {
+            direction: [1.0, 0.5, 0.0],
+            half_angle_deg: 20.0,
+        };
+        let cone = filter.to_cone(points[0]).unwrap();
+        let json = r#"{"kind":"nearest","system_name":"S0","radius":1.0,"count":7,"suggest_radius":true,
+            "cone":{"direction":[1.0,0.5,0.0],"half_angle_deg":20.0}}"#;
+        match handle_request(&engine, serde_json::from_str(json).unwrap()).response {
+            EngineResponse::Nearest {
+                systems,
+                suggested_radius,
+            } => {
+                let expected = engine.kd.nearest_n_within_radius_filtered(
+                    points[0],
+                    Scalar::INFINITY,
+                    7,
+                    |idx| cone.contains(points[idx]),
+                );
+                assert!(systems.len() < 7);
+                assert_eq!(suggested_radius, Some(expected[6].1));
+            }
+            other => panic!("expected nearest, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn submitted_jobs_report_their_result() {
+        let engine = Engine::new(demo_graph(), None);
+        let json = r#"{"kind":"submit","request":{"kind":"path","start_id":1,"end_id":3}}"#;
+        let reply = handle_request(&engine, serde_json::from_str(json).unwrap());
+        let status = serde_json::to_value(&reply).unwrap();
+        assert_eq!(status["kind"], "job");
+        assert_eq!(status["state"], "queued");
+        assert!(engine.jobs.run_next(&engine));
+        let json = format!(r#"{{"kind":"job_status","job_id":{}}}"#, status["job_id"]);
+        let reply = handle_request(&engine, serde_json::from_str(&json).unwrap());
+        let status = serde_json::to_value(&reply).unwrap();
+        assert_eq!(status["state"], "done");
+        assert_eq!(status["result"]["kind"], "path");
+        let json = r#"{"kind":"job_status","job_id":99}"#;
+        let reply = handle_request(&engine, serde_json::from_str(json).unwrap());
+        assert!(matches!(reply.response, EngineResponse::Error { .. }));
+    }
+
+    #[test]
+    fn avoided_gates_are_closed_in_one_direction() {
+        let engine = Engine::new(demo_graph(), None);
+        let json = r#"{"kind":"path","start_id":2,"end_id":4,"avoid_gates":[[2,1]]}"#;
+        let reply = handle_request(&engine, serde_json::from_str(json).unwrap());
+        assert!(matches!(reply.response, EngineResponse::Error { .. }));
+        // The reverse direction, and A itself, are still open.
+        let json = r#"{"kind":"path","start_id":4,"end_id":2,"avoid_gates":[[2,1]]}"#;
+        match handle_request(&engine, serde_json::from_str(json).unwrap()).response {
+            EngineResponse::Path { systems, .. } => assert_eq!(systems.len(), 3),
+            other => panic!("expected path, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn sweep_systems_tours_an_explicit_list() {
+        let engine = Engine::new(demo_graph(), None);
+        let json = r#"{"kind":"sweep_systems","system_ids":[3,4],"first_id":2}"#;
+        let request: EngineRequest = serde_json::from_str(json).unwrap();
+        assert!(request.is_heavy());
+        match handle_request(&engine, request).response {
+            EngineResponse::Sweep { systems, .. } => {
+                assert_eq!(systems.iter().map(|s| s.id).collect::<Vec<_>>(), [2, 3, 4]);
+            }
+            other => panic!("expected sweep, got {other:?}"),
+        }
+        let json = r#"{"kind":"sweep_systems","system_ids":[3,99]}"#;
+        let reply = handle_request(&engine, serde_json::from_str(json).unwrap());
+        assert!(matches!(reply.response, EngineResponse::Error { .. }));
+    }
+
+    #[test]
+    fn heatmap_aggregates_a_metric_per_cell() {
+        let engine = Engine::new(demo_graph(), None);
+        let json = r#"{"kind":"heatmap","cell_size":1.5,"metric":"degree","projection":"xy"}"#;
+        let request: EngineRequest = serde_json::from_str(json).unwrap();
+        assert!(request.is_heavy());
+        let reply = handle_request(&engine, request);
+        let value = serde_json::to_value(&reply.response).unwrap();
+        assert_eq!(value["dims"], serde_json::json!([2, 2]));
+        match reply.response {
+            EngineResponse::Heatmap { grid, .. } => {
+                // A and B share the corner cell; both have two gates.
+                let cells: Vec<_> = grid
+                    .cells
+                    .iter()
+                    .map(|c| (c.index.clone(), c.systems, c.value))
+                    .collect();
+                assert_eq!(
+                    cells,
+                    [
+                        (vec![0, 0], 2, 2.0),
+                        (vec![0, 1], 1, 1.0),
+                        (vec![1, 0], 1, 1.0)
+                    ]
+                );
+            }
+            other => panic!("expected heatmap, got {other:?}"),
+        }
+        let json = r#"{"kind":"heatmap","cell_size":-1.0,"metric":"count"}"#;
+        let reply = handle_request(&engine, serde_json::from_str(json).unwrap());
+        assert!(matches!(reply.response, EngineResponse::Error { .. }));
+
+        // Densities cover empty cells next to systems too: all four cells.
+        let json = r#"{"kind":"heatmap","cell_size":1.5,"metric":"density","projection":"xy","bandwidth":1.0}"#;
+        match handle_request(&engine, serde_json::from_str(json).unwrap()).response {
+            EngineResponse::Heatmap { grid, .. } => {
+                assert_eq!(grid.cells.len(), 4);
+                assert_eq!(grid.cells[3].systems, 0);
+                assert!(grid.cells[0].value > grid.cells[3].value);
+            }
+            other => panic!("expected heatmap, got {other:?}"),
+        }
+        let json = r#"{"kind":"heatmap","cell_size":1.5,"metric":"count","bandwidth":1.0}"#;
+        let reply = handle_request(&engine, serde_json::from_str(json).unwrap());
+        assert!(matches!(reply.response, EngineResponse::Error { .. }));
+    }
+
+    #[test]
+    fn corridor_lists_systems_along_the_route() {
+        let engine = Engine::new(demo_graph(), None);
+        // A -> C along the x axis; D sits 2 units off it.
+        let json = r#"{"kind":"corridor","system_ids":[1,2,3],"distance":0.5}"#;
+        let request: EngineRequest = serde_json::from_str(json).unwrap();
+        assert!(request.is_heavy());
+        match handle_request(&engine, request).response {
+            EngineResponse::Corridor { systems } => {
+                let hits: Vec<(u32, usize)> = systems.iter().map(|s| (s.id, s.leg)).collect();
+                assert_eq!(hits, vec![(1, 0), (2, 0), (3, 1)]);
+            }
+            other => panic!("expected corridor, got {other:?}"),
+        }
+        let json = r#"{"kind":"corridor","system_ids":[1,3],"distance":2.0}"#;
+        let reply = handle_request(&engine, serde_json::from_str(json).unwrap());
+        let value = serde_json::to_value(&reply.response).unwrap();
+        assert_eq!(value["systems"].as_array().map(Vec::len), Some(4));
+    }
+
+    #[test]
+    fn poi_queries_search_the_loaded_layer() {
+        let engine = Engine::new(demo_graph(), None);
+        {
+            let mut layer = engine.pois.write().unwrap();
+            layer.insert(
+                "Depot",
+                BTreeSet::from(["friendly".to_string()]),
+                [1.0, 0.5, 0.0],
+            );
+            layer.insert("Wreck", BTreeSet::new(), [0.0, 1.5, 0.0]);
+        }
+        let json = r#"{"kind":"nearest_poi","system_name":"A","radius":5.0,"count":5}"#;
+        let reply = handle_request(&engine, serde_json::from_str(json).unwrap());
+        let value = serde_json::to_value(&reply.response).unwrap();
+        assert_eq!(value["pois"][0]["label"], "Depot");
+        assert_eq!(value["pois"][1]["label"], "Wreck");
+        let json = r#"{"kind":"nearest_poi","coords":[0.0,0.0,0.0],"radius":5.0,"count":5,
+            "tag":"friendly"}"#;
+        let reply = handle_request(&engine, serde_json::from_str(json).unwrap());
+        let value = serde_json::to_value(&reply.response).unwrap();
+        assert_eq!(value["pois"].as_array().map(Vec::len), Some(1));
+
+        // A -> C along the x axis; the wreck is 1.5 units off it.
+        let json = r#"{"kind":"pois_along_route","system_ids":[1,3],"distance":1.0}"#;
+        let request: EngineRequest = serde_json::from_str(json).unwrap();
+        assert!(request.is_heavy());
+        match handle_request(&engine, request).response {
+            EngineResponse::Pois { pois } => {
+                let hits: Vec<(&str, Option<usize>)> =
+                    pois.iter().map(|p| (p.label.as_str(), p.leg)).collect();
+                assert_eq!(hits, vec![("Depot", Some(0))]);
+                assert_eq!(pois[0].distance, 0.5);
+            }
+            other => panic!("expected pois, got {other:?}"),
+        }
+        let json = r#"{"kind":"pois_along_route","system_ids":[3,1,4],"distance":1.0}"#;
+        let reply = handle_request(&engine, serde_json::from_str(json).unwrap());
+        let value = serde_json::to_value(&reply.response).unwrap();
+        assert_eq!(value["pois"][0]["label"], "Depot");
+        assert_eq!(value["pois"][1]["leg"], 1);
+        let json = r#"{"kind":"pois_along_route","system_ids":[1,9],"distance":1.0}"#;
+        let reply = handle_request(&engine, serde_json::from_str(json).unwrap());
+        assert!(matches!(reply.response, EngineResponse::Error { .. }));
+    }
+
+    #[test]
+    fn nearest_cone_keeps_systems_ahead() {
+        let engine = Engine::new(demo_graph(), None);
+        // From B looking along +x: B itself and C; A is behind, D off axis.
+        let json = r#"{"kind":"nearest","coords":[1.0,0.0,0.0],"radius":5.0,"count":10,
+            "cone":{"direction":[1.0,0.0,0.0],"half_angle_deg":30.0}}"#;
+        let reply = handle_request(&engine, serde_json::from_str(json).unwrap());
+        match reply.response {
+            EngineResponse::Nearest { systems, .. } => {
+                let ids: Vec<u32> = systems.iter().map(|s| s.id).collect();
+                assert_eq!(ids, vec![2, 3]);
+            }
+            other => panic!("expected nearest, got {other:?}"),
+        }
+        let json = r#"{"kind":"nearest","coords":[1.0,0.0,0.0],"radius":5.0,"count":10,
+            "cone":{"direction":[0.0,0.0,0.0],"half_angle_deg":30.0}}"#;
+        let reply = handle_request(&engine, serde_json::from_str(json).unwrap());
+        assert!(matches!(reply.response, EngineResponse::Error { .. }));
+    }
+
+    #[test]
+    fn eta_counts_the_rest_of_the_route_at_the_observed_pace() {
+        let engine = Engine::new(demo_graph(), None);
+        // Two jumps in 600 s, one to go.
+        let json = r#"{"kind":"eta","system_ids":[4,1,2,3],"current_id":2,
+            "timestamp":1600,"started_at":1000}"#;
+        let reply = handle_request(&engine, serde_json::from_str(json).unwrap());
+        match reply.response {
+            EngineResponse::Eta {
+                position,
+                rerouted,
+                remaining_jumps,
+                systems,
+                seconds_per_jump,
+                eta,
+                ..
+            } => {
+                assert_eq!((position, rerouted, remaining_jumps), (Some(2), false, 1));
+                assert_eq!(systems.last().map(|s| s.id), Some(3));
+                assert_eq!((seconds_per_jump, eta), (Some(300.0), Some(1900)));
+            }
+            other => panic!("expected eta, got {other:?}"),
+        }
+
+        // Off the route: rerouted from D, at the given pace.
+        let json = r#"{"kind":"eta","system_ids":[1,2,3],"current_id":4,
+            "timestamp":0,"seconds_per_jump":60}"#;
+        let reply = handle_request(&engine, serde_json::from_str(json).unwrap());
+        let value = serde_json::to_value(&reply.response).unwrap();
+        assert_eq!(value["rerouted"], true);
+        assert_eq!(value["remaining_jumps"], 3);
+        assert_eq!(value["eta"], 180);
+        assert!(value.get("position").is_none());
+    }
+
+    #[test]
+    fn units_option_converts_response_distances() {
+        let engine = Engine::new(demo_graph(), None);
+        let meters_per_au = DistanceUnit::Au.meters_per_unit() as Scalar;
+        let json = r#"{"kind":"path","start_id":1,"end_id":3,"preference":"shortest_distance","units":"au"}"#;
+        match handle_request(&engine, serde_json::from_str(json).unwrap()).response {
+            EngineResponse::Path { systems, .. } => {
+                assert!((systems[2].cumulative_cost * meters_per_au - 2.0).abs() < 1e-4);
+                assert!((systems[2].leg_distance * meters_per_au - 1.0).abs() < 1e-4);
+            }
+            other => panic!("expected path, got {other:?}"),
+        }
+        // Fewest-jumps costs count jumps and stay as they are.
+        let json = r#"{"kind":"path","start_id":1,"end_id":3,"units":"au"}"#;
+        match handle_request(&engine, serde_json::from_str(json).unwrap()).response {
+            EngineResponse::Path { systems, .. } => assert_eq!(systems[2].cumulative_cost, 2.0),
+            other => panic!("expected path, got {other:?}"),
+        }
+        let json = r#"{"kind":"sweep","center":[0.0,0.0,0.0],"radius":3.0,"units":"au"}"#;
+        match handle_request(&engine, serde_json::from_str(json).unwrap()).response {
+            EngineResponse::Sweep { longest_leg, .. } => assert!(longest_leg * meters_per_au < 3.0),
+            other => panic!("expected sweep, got {other:?}"),
+        }
+        let json = r#"{"kind":"nearest","system_name":"A","radius":1.5,"count":3,"suggest_radius":true,"units":"au"}"#;
+        match handle_request(&engine, serde_json::from_str(json).unwrap()).response {
+            EngineResponse::Nearest {
+                suggested_radius: Some(radius),
+                ..
+            } => assert!((radius * meters_per_au - 2.0).abs() < 1e-4),
+            other => panic!("expected a suggested radius, got {other:?}"),
+        }
+        // A drive range of about 0.1 dataset units cannot reach B.
+        let json = r#"{"kind":"check_route","system_ids":[1,2],"mode":"jump_drive","jump_range":1e-17,"units":"au"}"#;
+        match handle_request(&engine, serde_json::from_str(json).unwrap()).response {
+            EngineResponse::RouteCheck {
+                violation: Some(RouteViolation::OutOfRange { distance, .. }),
+                ..
+            } => assert!((distance * meters_per_au - 1.0).abs() < 1e-4),
+            other => panic!("expected an out-of-range violation, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn info_lists_every_query_kind() {
+        let engine = Engine::new(demo_graph(), None);
+        let json = r#"{"kind":"info"}"#;
+        let EngineResponse::Info { capabilities, .. } =
+            handle_request(&engine, serde_json::from_str(json).unwrap()).response
+        else {
+            panic!("expected info");
+        };
+        assert_eq!(capabilities.datasets, vec!["current"]);
+        for kind in capabilities.query_kinds {
+            let err = serde_json::from_str::<Query>(&format!(r#"{{"kind":"{kind}"}}"#))
+                .err()
+                .map(|e| e.to_string())
+                .unwrap_or_default();
+            assert!(!err.contains("unknown variant"), "{kind}: {err}");
+        }
+    }
+
+    #[test]
+    fn every_query_kind_is_classified_for_the_limiter() {
+        // Answered from indices or by a single bounded search. A new kind
+        // must be added here or to `HEAVY_KINDS`.
+        let light = [
+            "nearest",
+            "path",
+            "unvisited",
+            "hubs",
+            "set_tags",
+            "check_route",
+            "eta",
+            "midpoint",
+            "nearest_poi",
+            "density",
+            "submit",
+            "job_status",
+            "info",
+        ];
+        for kind in QUERY_KINDS {
+            assert!(
+                HEAVY_KINDS.contains(kind) != light.contains(kind),
+                "{kind} must be either heavy or light"
+            );
+        }
+        assert!(HEAVY_KINDS.iter().all(|kind| QUERY_KINDS.contains(kind)));
+    }
+
+    #[test]
+    fn route_matrices_and_multi_route_searches_are_heavy() {
+        for json in [
+            r#"{"kind":"alternatives","start_id":1,"end_id":3,"count":2}"#,
+            r#"{"kind":"pareto","start_id":1,"end_id":3}"#,
+            r#"{"kind":"cross_routes","source_ids":[1],"target_ids":[3]}"#,
+            r#"{"kind":"jump_distances","pairs":[[1,3]]}"#,
+            r#"{"kind":"compare","start_id":1,"end_id":3,"metric":"jumps","before":"old"}"#,
+        ] {
+            let request: EngineRequest = serde_json::from_str(json).unwrap();
+            assert!(request.is_heavy(), "{json}");
+        }
+        let request: EngineRequest =
+            serde_json::from_str(r#"{"kind":"path","start_id":1,"end_id":3}"#).unwrap();
+        assert_eq!(request.query.kind(), "path");
+        assert!(!request.is_heavy());
+    }
+}
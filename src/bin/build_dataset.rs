@@ -1,21 +1,23 @@
-use std::collections::HashMap;
 use std::fs;
-use std::path::{Path, PathBuf};
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::path::PathBuf;
 
 use anyhow::{anyhow, Context, Result};
-use log::{info, warn};
+use log::info;
 use reqwest::blocking::Client;
-use rusqlite::Connection;
-use serde::{Deserialize, Serialize};
-use starmap_engine::data::write_graph_to_file;
-use starmap_engine::graph::graph::StarGraph;
-use starmap_engine::System;
+use serde::Deserialize;
+use starmap_engine::data::build::{build_bundle, build_graph_from_sqlite, ReleaseSource};
+use starmap_engine::data::bundle::write_bundle_to_file;
+use starmap_engine::data::{
+    hub_labels_path_for, metadata_path_for, write_graph_to_file, write_hub_labels_to_file,
+    write_metadata_to_file,
+};
 use tempfile::NamedTempFile;
 
 #[derive(Debug, Deserialize)]
 struct Release {
     tag_name: String,
+    /// RFC 3339 UTC timestamp, e.g. `2024-05-01T12:00:00Z`.
+    published_at: Option<String>,
     assets: Vec<Asset>,
 }
 
@@ -25,16 +27,6 @@ struct Asset {
     browser_download_url: String,
 }
 
-#[derive(Debug, Serialize)]
-struct DatasetMetadata {
-    release_tag: String,
-    asset_name: String,
-    asset_url: String,
-    systems: usize,
-    directed_edges: usize,
-    generated_at_epoch: u64,
-}
-
 fn main() -> Result<()> {
     env_logger::init();
 
@@ -60,18 +52,34 @@ fn main() -> Result<()> {
     write_graph_to_file(&graph, &dataset_path)
         .with_context(|| format!("failed to write dataset to {}", dataset_path.display()))?;
 
-    let metadata = DatasetMetadata {
+    let source = ReleaseSource {
         release_tag: release.tag_name.clone(),
         asset_name: asset.name.clone(),
         asset_url: asset.browser_download_url.clone(),
-        systems: graph.len(),
-        directed_edges: edge_count,
-        generated_at_epoch: current_epoch_seconds(),
+        published_at: release.published_at.clone(),
     };
+    let bundle = build_bundle(graph, edge_count, &source)
+        .with_context(|| format!("failed to build indexes for release {}", release.tag_name))?;
+    let metadata = bundle
+        .metadata
+        .as_ref()
+        .expect("build_bundle records metadata");
+    let labels = bundle
+        .hub_labels
+        .as_ref()
+        .expect("build_bundle builds hub labels");
+
+    let labels_path = hub_labels_path_for(&dataset_path);
+    write_hub_labels_to_file(labels, &labels_path)
+        .with_context(|| format!("failed to write hub labels to {}", labels_path.display()))?;
+    info!(
+        "Wrote hub labels to {} (mean label size {:.1})",
+        labels_path.display(),
+        labels.mean_label_size()
+    );
 
-    let metadata_path = output_dir.join("starmap.meta.json");
-    let metadata_json = serde_json::to_vec_pretty(&metadata)?;
-    fs::write(&metadata_path, metadata_json)
+    let metadata_path = metadata_path_for(&dataset_path);
+    write_metadata_to_file(metadata, &metadata_path)
         .with_context(|| format!("failed to write metadata to {}", metadata_path.display()))?;
 
     info!(
@@ -81,6 +89,11 @@ fn main() -> Result<()> {
         metadata.directed_edges
     );
 
+    let bundle_path = dataset_path.with_extension("bundle");
+    write_bundle_to_file(&bundle, &bundle_path)
+        .with_context(|| format!("failed to write bundle to {}", bundle_path.display()))?;
+    info!("Wrote engine bundle to {}", bundle_path.display());
+
     Ok(())
 }
 
@@ -115,68 +128,3 @@ fn download_asset(client: &Client, url: &str) -> Result<NamedTempFile> {
     response.copy_to(&mut file)?;
     Ok(file)
 }
-
-fn build_graph_from_sqlite(path: &Path) -> Result<(StarGraph, usize)> {
-    let conn = Connection::open(path)
-        .with_context(|| format!("failed to open SQLite database at {}", path.display()))?;
-
-    let mut systems = Vec::new();
-    let mut id_to_index = HashMap::new();
-    {
-        let mut stmt = conn.prepare(
-            "SELECT solarSystemId, name, centerX, centerY, centerZ FROM SolarSystems ORDER BY solarSystemId",
-        )?;
-        let rows = stmt.query_map([], |row| {
-            let id: i64 = row.get(0)?;
-            let name: String = row.get(1)?;
-            let x: f64 = row.get(2)?;
-            let y: f64 = row.get(3)?;
-            let z: f64 = row.get(4)?;
-            Ok((id as u32, name, [x as f32, y as f32, z as f32]))
-        })?;
-        for (idx, row) in rows.enumerate() {
-            let (id, name, pos) = row?;
-            id_to_index.insert(id, idx);
-            systems.push(System { id, name, pos });
-        }
-    }
-
-    let mut adjacency = vec![Vec::new(); systems.len()];
-    {
-        let mut stmt = conn.prepare("SELECT fromSystemId, toSystemId FROM Jumps")?;
-        let rows = stmt.query_map([], |row| {
-            let from: i64 = row.get(0)?;
-            let to: i64 = row.get(1)?;
-            Ok((from as u32, to as u32))
-        })?;
-        for row in rows {
-            let (from, to) = row?;
-            let Some(&from_idx) = id_to_index.get(&from) else {
-                warn!("Jumps entry references missing fromSystemId {from}");
-                continue;
-            };
-            let Some(&to_idx) = id_to_index.get(&to) else {
-                warn!("Jumps entry references missing toSystemId {to}");
-                continue;
-            };
-            adjacency[from_idx].push(to_idx);
-        }
-    }
-
-    let mut edge_count = 0usize;
-    for neighbours in &mut adjacency {
-        neighbours.sort_unstable();
-        neighbours.dedup();
-        edge_count += neighbours.len();
-    }
-
-    let graph = StarGraph::new(systems, adjacency);
-    Ok((graph, edge_count))
-}
-
-fn current_epoch_seconds() -> u64 {
-    SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .map(|d| d.as_secs())
-        .unwrap_or(0)
-}
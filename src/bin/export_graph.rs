@@ -0,0 +1,80 @@
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+
+use anyhow::{anyhow, Context, Result};
+use log::info;
+use starmap_engine::data::read_graph_from_file;
+use starmap_engine::export::spanning_tree_features;
+use starmap_engine::graph::embedding::{random_walks, write_matrix_market, WalkConfig};
+use starmap_engine::spatial::kd_tree::KDTree;
+use starmap_engine::spatial::mst::{euclidean_mst, DEFAULT_MST_NEIGHBOURS};
+use starmap_engine::Scalar;
+
+/// Exports a dataset bundle for embedding work:
+///
+/// `export_graph <dataset> <output_dir> [walks_per_node] [walk_length]`
+///
+/// writes `adjacency.mtx` (Matrix Market), `walks.txt` (one random walk of
+/// system ids per line) and `mst.geojson` (the Euclidean minimum spanning
+/// tree of the systems, one line feature per edge) into `output_dir`.
+fn main() -> Result<()> {
+    env_logger::init();
+
+    let mut args = std::env::args().skip(1);
+    let usage =
+        || anyhow!("usage: export_graph <dataset> <output_dir> [walks_per_node] [walk_length]");
+    let dataset = args.next().ok_or_else(usage)?;
+    let output_dir = PathBuf::from(args.next().ok_or_else(usage)?);
+    let mut config = WalkConfig::default();
+    if let Some(walks) = args.next() {
+        config.walks_per_node = walks.parse().context("invalid walks_per_node")?;
+    }
+    if let Some(length) = args.next() {
+        config.walk_length = length.parse().context("invalid walk_length")?;
+    }
+
+    let graph = read_graph_from_file(&dataset)
+        .with_context(|| format!("failed to read dataset from {dataset}"))?;
+    fs::create_dir_all(&output_dir).context("failed to create output directory")?;
+
+    let matrix_path = output_dir.join("adjacency.mtx");
+    let mut matrix = BufWriter::new(File::create(&matrix_path)?);
+    write_matrix_market(&graph, &mut matrix)?;
+    matrix.flush()?;
+
+    let walks_path = output_dir.join("walks.txt");
+    let mut walks_out = BufWriter::new(File::create(&walks_path)?);
+    let walks = random_walks(&graph, &config);
+    for walk in &walks {
+        let ids: Vec<String> = walk
+            .iter()
+            .map(|&i| graph.systems[i].id.to_string())
+            .collect();
+        writeln!(walks_out, "{}", ids.join(" "))?;
+    }
+    walks_out.flush()?;
+
+    let points: Vec<[Scalar; 3]> = graph.systems.iter().map(|s| s.pos).collect();
+    let tree = euclidean_mst(&points, &KDTree::build(&points), DEFAULT_MST_NEIGHBOURS);
+    let mst_path = output_dir.join("mst.geojson");
+    fs::write(
+        &mst_path,
+        serde_json::to_vec(&spanning_tree_features(&graph, &tree))?,
+    )?;
+    info!(
+        "Wrote {} ({} edges, total length {:.1}, {} components)",
+        mst_path.display(),
+        tree.edges.len(),
+        tree.total_length,
+        tree.components
+    );
+
+    info!(
+        "Wrote {} and {} walks to {}",
+        matrix_path.display(),
+        walks.len(),
+        walks_path.display()
+    );
+    Ok(())
+}
@@ -0,0 +1,53 @@
+use std::fs;
+use std::path::PathBuf;
+
+use anyhow::{anyhow, Context, Result};
+use log::info;
+use starmap_engine::data::{read_graph_from_file, write_graph_to_file};
+use starmap_engine::graph::partition::partition_graph;
+
+/// Splits a dataset bundle for a sharded deployment:
+///
+/// `partition_dataset <dataset> <output_dir> <parts>`
+///
+/// writes `part_<n>.bin` (one dataset per part, holding only its own gates)
+/// and `boundary.json` (the part of every system plus all gates between
+/// parts) into `output_dir`.
+fn main() -> Result<()> {
+    env_logger::init();
+
+    let mut args = std::env::args().skip(1);
+    let usage = || anyhow!("usage: partition_dataset <dataset> <output_dir> <parts>");
+    let dataset = args.next().ok_or_else(usage)?;
+    let output_dir = PathBuf::from(args.next().ok_or_else(usage)?);
+    let parts: usize = args
+        .next()
+        .ok_or_else(usage)?
+        .parse()
+        .context("invalid parts")?;
+
+    let graph = read_graph_from_file(&dataset)
+        .with_context(|| format!("failed to read dataset from {dataset}"))?;
+    fs::create_dir_all(&output_dir).context("failed to create output directory")?;
+
+    let partitioning = partition_graph(&graph, parts);
+    for part in 0..partitioning.parts as u32 {
+        let path = output_dir.join(format!("part_{part}.bin"));
+        write_graph_to_file(&partitioning.part_graph(&graph, part), &path)
+            .with_context(|| format!("failed to write {}", path.display()))?;
+    }
+    let table = partitioning.boundary_table(&graph);
+    fs::write(
+        output_dir.join("boundary.json"),
+        serde_json::to_vec_pretty(&table)?,
+    )?;
+
+    info!(
+        "Wrote {} parts (sizes {:?}, {} cut gates) to {}",
+        partitioning.parts,
+        partitioning.part_sizes(),
+        table.edges.len(),
+        output_dir.display()
+    );
+    Ok(())
+}
@@ -0,0 +1,59 @@
+use std::env;
+use std::process::ExitCode;
+use std::time::Instant;
+
+use anyhow::{anyhow, Context, Result};
+use starmap_engine::api::{handle_request, EngineRequest};
+use starmap_engine::engine::{demo_graph, Engine};
+use starmap_engine::recording::read_recordings;
+
+/// Re-runs recorded traffic against this build and reports every response
+/// that differs from the recorded one.
+fn main() -> Result<ExitCode> {
+    env_logger::init();
+
+    let path = env::args()
+        .nth(1)
+        .ok_or_else(|| anyhow!("usage: replay <recording.jsonl>"))?;
+    let recordings =
+        read_recordings(&path).with_context(|| format!("failed to read recordings from {path}"))?;
+
+    let engine = match env::var("STARMAP_DATASET") {
+        Ok(dataset) => Engine::load(&dataset)
+            .with_context(|| format!("failed to load dataset from {dataset}"))?,
+        Err(_) => Engine::new(demo_graph(), None),
+    };
+
+    let mut mismatches = 0usize;
+    let mut recorded_micros = 0u64;
+    let mut replayed_micros = 0u64;
+    for (idx, recording) in recordings.iter().enumerate() {
+        let request: EngineRequest = serde_json::from_value(recording.request.clone())
+            .with_context(|| format!("recording {} holds an unknown request", idx + 1))?;
+        let started = Instant::now();
+        let response = serde_json::to_value(handle_request(&engine, request).response)?;
+        replayed_micros += started.elapsed().as_micros() as u64;
+        recorded_micros += recording.elapsed_micros;
+
+        if response != recording.response {
+            mismatches += 1;
+            println!("recording {} differs", idx + 1);
+            println!("  request:  {}", recording.request);
+            println!("  recorded: {}", recording.response);
+            println!("  replayed: {response}");
+        }
+    }
+
+    println!(
+        "{} recordings, {} mismatches, recorded {}us total, replayed {}us total",
+        recordings.len(),
+        mismatches,
+        recorded_micros,
+        replayed_micros
+    );
+    Ok(if mismatches == 0 {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    })
+}
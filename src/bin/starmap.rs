@@ -0,0 +1,61 @@
+use anyhow::{anyhow, Context, Result};
+use starmap_engine::config::EngineConfig;
+use starmap_engine::engine::Engine;
+use starmap_engine::graph::pathfinder::{shortest_path, trivial_path, PathError, RoutePreference};
+use starmap_engine::render::{render_route, RenderSize};
+
+/// Interactive command line for quick lookups against the configured
+/// dataset (`STARMAP_DATASET`, or the demo graph):
+///
+/// `starmap path <from> <to> [--render]`
+///
+/// prints the fewest-jumps gate route between two systems, given by name or
+/// id, and with `--render` a terminal map of it.
+fn main() -> Result<()> {
+    env_logger::init();
+
+    let usage = || anyhow!("usage: starmap path <from> <to> [--render]");
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let render = args.iter().any(|arg| arg == "--render");
+    let positional: Vec<&str> = args
+        .iter()
+        .map(String::as_str)
+        .filter(|arg| *arg != "--render")
+        .collect();
+    let [command, from, to] = positional[..] else {
+        return Err(usage());
+    };
+    if command != "path" {
+        return Err(usage());
+    }
+
+    let config = EngineConfig::from_env().context("invalid configuration")?;
+    let engine = Engine::from_config(config).context("failed to load the dataset")?;
+    let graph = &engine.graph;
+    let resolve = |system: &str| -> Result<usize> {
+        match system
+            .parse::<u32>()
+            .ok()
+            .and_then(|id| graph.index_of_id(id))
+        {
+            Some(idx) => Ok(idx),
+            None => Ok(graph.resolve_name(system)?),
+        }
+    };
+    let (start, goal) = (resolve(from)?, resolve(to)?);
+    let path = match shortest_path(graph, start, goal, RoutePreference::FewestJumps, None) {
+        Ok(path) => path,
+        Err(PathError::SameSystem) => trivial_path(graph, start),
+        Err(err) => return Err(anyhow!("{from} -> {to}: {err}")),
+    };
+    let route: Vec<usize> = path.iter().map(|step| step.system_index).collect();
+
+    for (jump, &idx) in route.iter().enumerate() {
+        let system = &graph.systems[idx];
+        println!("{jump:>3}  {} ({})", system.name, system.id);
+    }
+    if render {
+        print!("{}", render_route(graph, &route, RenderSize::default()));
+    }
+    Ok(())
+}
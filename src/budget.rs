@@ -0,0 +1,181 @@
+use std::cell::Cell;
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+/// Limits on the work a route search may do before giving up, so a
+/// pathological request fails fast instead of running into the Lambda
+/// timeout. Unset limits do not apply.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SearchBudget {
+    /// Systems the search may take off its frontier.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_expansions: Option<u64>,
+    /// Wall time in milliseconds.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub time_budget_ms: Option<u64>,
+}
+
+/// Which limit of a `SearchBudget` ran out.
+#[derive(Clone, Copy, Debug, Error, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BudgetLimit {
+    #[error("expansions")]
+    Expansions,
+    #[error("ms")]
+    TimeMs,
+}
+
+#[derive(Clone, Copy, Debug, Error, PartialEq, Eq, Serialize)]
+#[error("search stopped at its budget of {budget} {limit}")]
+pub struct BudgetExceeded {
+    pub limit: BudgetLimit,
+    pub budget: u64,
+}
+
+/// Expansions between two clock reads.
+const CLOCK_INTERVAL: u64 = 256;
+
+#[derive(Clone, Copy)]
+struct Active {
+    budget: SearchBudget,
+    started: Instant,
+    spent: u64,
+    exceeded: Option<BudgetExceeded>,
+}
+
+thread_local! {
+    static ACTIVE: Cell<Option<Active>> = const { Cell::new(None) };
+}
+
+impl SearchBudget {
+    pub fn is_unlimited(&self) -> bool {
+        self.max_expansions.is_none() && self.time_budget_ms.is_none()
+    }
+
+    /// Runs `search` under this budget. Searches on the current thread stop
+    /// once it is spent, in which case their result is discarded and the
+    /// exceeded limit returned instead. Like the `stats` counters, the
+    /// budget is thread-local, which matches requests being handled
+    /// synchronously on one thread.
+    pub fn run<T>(&self, search: impl FnOnce() -> T) -> Result<T, BudgetExceeded> {
+        struct Reset(Option<Active>);
+        impl Drop for Reset {
+            fn drop(&mut self) {
+                ACTIVE.with(|a| a.set(self.0));
+            }
+        }
+
+        let outer = ACTIVE.with(|a| {
+            a.replace(Some(Active {
+                budget: *self,
+                started: Instant::now(),
+                spent: 0,
+                exceeded: None,
+            }))
+        });
+        let reset = Reset(outer);
+        let result = search();
+        let exceeded = ACTIVE.with(|a| a.get()).and_then(|active| active.exceeded);
+        drop(reset);
+        match exceeded {
+            Some(exceeded) => Err(exceeded),
+            None => Ok(result),
+        }
+    }
+}
+
+/// Charges one expansion to the running budget. Fails once the budget is
+/// spent, telling the search to stop; always succeeds outside
+/// `SearchBudget::run`.
+pub(crate) fn spend() -> Result<(), BudgetExceeded> {
+    ACTIVE.with(|cell| {
+        let Some(mut active) = cell.get() else {
+            return Ok(());
+        };
+        if let Some(exceeded) = active.exceeded {
+            return Err(exceeded);
+        }
+        active.spent += 1;
+        let budget = active.budget;
+        if budget.max_expansions.is_some_and(|max| active.spent > max) {
+            active.exceeded = Some(BudgetExceeded {
+                limit: BudgetLimit::Expansions,
+                budget: budget.max_expansions.unwrap_or_default(),
+            });
+        } else if let Some(ms) = budget.time_budget_ms {
+            if active.spent % CLOCK_INTERVAL == 0
+                && active.started.elapsed() > Duration::from_millis(ms)
+            {
+                active.exceeded = Some(BudgetExceeded {
+                    limit: BudgetLimit::TimeMs,
+                    budget: ms,
+                });
+            }
+        }
+        cell.set(Some(active));
+        active.exceeded.map_or(Ok(()), Err)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::graph_from;
+    use crate::graph::graph::StarGraph;
+    use crate::graph::pathfinder::shortest_gate_path;
+    use crate::Scalar;
+
+    /// A line of 100 systems.
+    fn line() -> StarGraph {
+        let positions: Vec<[Scalar; 3]> = (0..100).map(|i| [i as Scalar, 0.0, 0.0]).collect();
+        let adjacency = (0..100usize)
+            .map(|i| [i.checked_sub(1), (i < 99).then_some(i + 1)])
+            .map(|n| n.into_iter().flatten().collect())
+            .collect();
+        graph_from(&positions, adjacency)
+    }
+
+    fn expansions(max: u64) -> SearchBudget {
+        SearchBudget {
+            max_expansions: Some(max),
+            time_budget_ms: None,
+        }
+    }
+
+    #[test]
+    fn searches_stop_when_the_budget_is_spent() {
+        let graph = line();
+        let exceeded = BudgetExceeded {
+            limit: BudgetLimit::Expansions,
+            budget: 10,
+        };
+        assert_eq!(
+            expansions(10).run(|| shortest_gate_path(&graph, 0, 99)),
+            Err(exceeded)
+        );
+    }
+
+    #[test]
+    fn searches_within_the_budget_finish() {
+        let graph = line();
+        let roomy = SearchBudget {
+            max_expansions: Some(1000),
+            time_budget_ms: Some(60_000),
+        };
+        let path = roomy.run(|| shortest_gate_path(&graph, 0, 99)).unwrap();
+        assert_eq!(path.map(|p| p.len()), Ok(100));
+    }
+
+    #[test]
+    fn nested_budgets_restore_the_outer_one() {
+        let outer = expansions(5).run(|| {
+            assert!(expansions(1).run(|| (spend(), spend())).is_err());
+            (0..5).map(|_| spend()).collect::<Result<Vec<_>, _>>()
+        });
+        assert_eq!(outer, Ok(Ok(vec![(); 5])));
+        // Outside `run` nothing is limited.
+        assert_eq!(spend(), Ok(()));
+    }
+}
@@ -0,0 +1,297 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::graph::cache::DEFAULT_PATH_CACHE_SIZE;
+use crate::graph::landmarks::DEFAULT_LANDMARK_COUNT;
+use crate::graph::matrix::DEFAULT_MATRIX_MAX_SYSTEMS;
+use crate::jobs::DEFAULT_MAX_JOBS;
+use crate::limiter::{OverflowPolicy, DEFAULT_MAX_HEAVY_QUERIES};
+
+/// Environment variable naming a JSON file with the base configuration.
+pub const CONFIG_PATH_VAR: &str = "STARMAP_CONFIG";
+
+/// Snapshot label that always refers to the live dataset.
+pub const CURRENT_SNAPSHOT: &str = "current";
+
+#[derive(Debug, Error)]
+pub enum ConfigError {
+    #[error("failed to read config file {path}: {source}")]
+    Io {
+        path: String,
+        source: std::io::Error,
+    },
+    #[error("invalid config JSON: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("invalid value for {name}: {message}")]
+    Invalid { name: &'static str, message: String },
+}
+
+/// Every runtime knob of the engine in one place. Loaded from an optional
+/// JSON file named by `STARMAP_CONFIG`, then overridden field by field from
+/// the individual `STARMAP_*` variables.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EngineConfig {
+    /// Dataset bundle to load, a local path or an HTTP(S) URL; the built-in
+    /// demo graph is used when unset.
+    pub dataset: Option<String>,
+    /// Concurrent heavy queries allowed.
+    pub max_heavy_queries: usize,
+    /// What to do with heavy queries once all slots are taken.
+    pub heavy_overflow: OverflowPolicy,
+    /// JSONL file to record traffic to (`recording` feature only).
+    pub record_path: Option<String>,
+    /// Routes kept in the LRU path cache; 0 disables it.
+    pub path_cache_size: usize,
+    /// Largest dataset for which an all-pairs jump matrix is built on first
+    /// use; 0 disables it.
+    pub jump_matrix_max_systems: usize,
+    /// Landmarks used for the fewest-jumps gate heuristic; 0 falls back to
+    /// straight-line distance.
+    pub landmark_count: usize,
+    /// Older dataset snapshots loaded next to the live one, by label, for
+    /// historical comparisons.
+    pub snapshots: BTreeMap<String, String>,
+    /// Seconds between checks of `dataset` for a newer version, which is
+    /// then loaded and swapped in; 0 disables the check.
+    pub refresh_interval_secs: u64,
+    /// JSON overlay of per-system tags (`tags::SystemTags::load`).
+    pub tags_path: Option<String>,
+    /// JSON overlay of gates closed to every gate route
+    /// (`graph::closed_gates::ClosedGates::load`).
+    pub closed_gates_path: Option<String>,
+    /// JSON list of points of interest (`poi::PoiLayer::load`).
+    pub pois_path: Option<String>,
+    /// Accept administrative requests such as `set_tags`.
+    pub admin_requests: bool,
+    /// Background jobs kept at once, finished ones included; 0 disables
+    /// `submit`.
+    pub max_jobs: usize,
+    /// Treat every loading anomaly (a missing or mismatched side file,
+    /// duplicate names, a legacy layout, a failed self-check, a missing
+    /// dataset) as an error instead of logging it.
+    pub strict: bool,
+}
+
+impl Default for EngineConfig {
+    fn default() -> Self {
+        EngineConfig {
+            dataset: None,
+            max_heavy_queries: DEFAULT_MAX_HEAVY_QUERIES,
+            heavy_overflow: OverflowPolicy::default(),
+            record_path: None,
+            path_cache_size: DEFAULT_PATH_CACHE_SIZE,
+            jump_matrix_max_systems: DEFAULT_MATRIX_MAX_SYSTEMS,
+            landmark_count: DEFAULT_LANDMARK_COUNT,
+            snapshots: BTreeMap::new(),
+            refresh_interval_secs: 0,
+            tags_path: None,
+            closed_gates_path: None,
+            pois_path: None,
+            admin_requests: false,
+            max_jobs: DEFAULT_MAX_JOBS,
+            strict: false,
+        }
+    }
+}
+
+impl EngineConfig {
+    pub fn from_json(json: &str) -> Result<Self, ConfigError> {
+        Ok(serde_json::from_str(json)?)
+    }
+
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, ConfigError> {
+        let path = path.as_ref();
+        let json = fs::read_to_string(path).map_err(|source| ConfigError::Io {
+            path: path.display().to_string(),
+            source,
+        })?;
+        Self::from_json(&json)
+    }
+
+    /// Builds and validates the configuration from the process environment.
+    pub fn from_env() -> Result<Self, ConfigError> {
+        Self::from_vars(|name| env::var(name).ok())
+    }
+
+    /// Same as `from_env`, reading variables through `lookup`.
+    pub fn from_vars(lookup: impl Fn(&str) -> Option<String>) -> Result<Self, ConfigError> {
+        let mut config = match lookup(CONFIG_PATH_VAR) {
+            Some(path) => Self::from_file(path)?,
+            None => EngineConfig::default(),
+        };
+        if let Some(dataset) = lookup("STARMAP_DATASET") {
+            config.dataset = Some(dataset);
+        }
+        if let Some(value) = lookup("STARMAP_MAX_HEAVY_QUERIES") {
+            config.max_heavy_queries = value.parse().map_err(|err| ConfigError::Invalid {
+                name: "STARMAP_MAX_HEAVY_QUERIES",
+                message: format!("{err}"),
+            })?;
+        }
+        if let Some(value) = lookup("STARMAP_HEAVY_OVERFLOW") {
+            config.heavy_overflow = value.parse().map_err(|message| ConfigError::Invalid {
+                name: "STARMAP_HEAVY_OVERFLOW",
+                message,
+            })?;
+        }
+        if let Some(path) = lookup("STARMAP_RECORD_PATH") {
+            config.record_path = Some(path);
+        }
+        if let Some(value) = lookup("STARMAP_PATH_CACHE_SIZE") {
+            config.path_cache_size = value.parse().map_err(|err| ConfigError::Invalid {
+                name: "STARMAP_PATH_CACHE_SIZE",
+                message: format!("{err}"),
+            })?;
+        }
+        if let Some(value) = lookup("STARMAP_JUMP_MATRIX_MAX_SYSTEMS") {
+            config.jump_matrix_max_systems = value.parse().map_err(|err| ConfigError::Invalid {
+                name: "STARMAP_JUMP_MATRIX_MAX_SYSTEMS",
+                message: format!("{err}"),
+            })?;
+        }
+        if let Some(value) = lookup("STARMAP_LANDMARK_COUNT") {
+            config.landmark_count = value.parse().map_err(|err| ConfigError::Invalid {
+                name: "STARMAP_LANDMARK_COUNT",
+                message: format!("{err}"),
+            })?;
+        }
+        if let Some(value) = lookup("STARMAP_REFRESH_INTERVAL_SECS") {
+            config.refresh_interval_secs = value.parse().map_err(|err| ConfigError::Invalid {
+                name: "STARMAP_REFRESH_INTERVAL_SECS",
+                message: format!("{err}"),
+            })?;
+        }
+        if let Some(path) = lookup("STARMAP_TAGS") {
+            config.tags_path = Some(path);
+        }
+        if let Some(path) = lookup("STARMAP_CLOSED_GATES") {
+            config.closed_gates_path = Some(path);
+        }
+        if let Some(path) = lookup("STARMAP_POIS") {
+            config.pois_path = Some(path);
+        }
+        if let Some(value) = lookup("STARMAP_ADMIN_REQUESTS") {
+            config.admin_requests = value.parse().map_err(|err| ConfigError::Invalid {
+                name: "STARMAP_ADMIN_REQUESTS",
+                message: format!("{err}"),
+            })?;
+        }
+        if let Some(value) = lookup("STARMAP_MAX_JOBS") {
+            config.max_jobs = value.parse().map_err(|err| ConfigError::Invalid {
+                name: "STARMAP_MAX_JOBS",
+                message: format!("{err}"),
+            })?;
+        }
+        if let Some(value) = lookup("STARMAP_STRICT") {
+            config.strict = value.parse().map_err(|err| ConfigError::Invalid {
+                name: "STARMAP_STRICT",
+                message: format!("{err}"),
+            })?;
+        }
+        if let Some(value) = lookup("STARMAP_SNAPSHOTS") {
+            config.snapshots = parse_snapshots(&value)?;
+        }
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if self.max_heavy_queries == 0 {
+            return Err(ConfigError::Invalid {
+                name: "max_heavy_queries",
+                message: "must be at least 1".to_string(),
+            });
+        }
+        if self.dataset.as_deref().is_some_and(|d| d.trim().is_empty()) {
+            return Err(ConfigError::Invalid {
+                name: "dataset",
+                message: "must not be empty".to_string(),
+            });
+        }
+        if let Some(label) = self
+            .snapshots
+            .keys()
+            .find(|l| l.as_str() == CURRENT_SNAPSHOT)
+        {
+            return Err(ConfigError::Invalid {
+                name: "snapshots",
+                message: format!("label {label} is reserved for the live dataset"),
+            });
+        }
+        Ok(())
+    }
+
+    /// Copy that is safe to return to clients: credentials and query strings
+    /// are stripped from the dataset URI.
+    pub fn sanitized(&self) -> Self {
+        EngineConfig {
+            dataset: self.dataset.as_deref().map(sanitize_uri),
+            snapshots: self
+                .snapshots
+                .iter()
+                .map(|(label, uri)| (label.clone(), sanitize_uri(uri)))
+                .collect(),
+            ..self.clone()
+        }
+    }
+}
+
+/// Parses `label=path` pairs separated by commas.
+fn parse_snapshots(value: &str) -> Result<BTreeMap<String, String>, ConfigError> {
+    value
+        .split(',')
+        .filter(|entry| !entry.trim().is_empty())
+        .map(|entry| {
+            let (label, path) = entry.split_once('=').ok_or_else(|| ConfigError::Invalid {
+                name: "STARMAP_SNAPSHOTS",
+                message: format!("expected label=path, got {entry}"),
+            })?;
+            Ok((label.trim().to_string(), path.trim().to_string()))
+        })
+        .collect()
+}
+
+fn sanitize_uri(uri: &str) -> String {
+    let without_query = uri.split(['?', '#']).next().unwrap_or_default();
+    match without_query.split_once("://") {
+        Some((scheme, rest)) => {
+            let rest = rest.rsplit_once('@').map_or(rest, |(_, host)| host);
+            format!("{scheme}://{rest}")
+        }
+        None => without_query.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn env_overrides_and_validation() {
+        let vars = |name: &str| match name {
+            "STARMAP_DATASET" => Some("s3://key:secret@bucket/starmap.bin?X-Sig=abc".to_string()),
+            "STARMAP_HEAVY_OVERFLOW" => Some("shed".to_string()),
+            _ => None,
+        };
+        let config = EngineConfig::from_vars(vars).unwrap();
+        assert_eq!(config.heavy_overflow, OverflowPolicy::Shed);
+        assert_eq!(config.max_heavy_queries, DEFAULT_MAX_HEAVY_QUERIES);
+        assert_eq!(
+            config.sanitized().dataset.as_deref(),
+            Some("s3://bucket/starmap.bin")
+        );
+
+        let zero = |name: &str| (name == "STARMAP_MAX_HEAVY_QUERIES").then(|| "0".to_string());
+        assert!(matches!(
+            EngineConfig::from_vars(zero),
+            Err(ConfigError::Invalid { .. })
+        ));
+        assert!(EngineConfig::from_json(r#"{"max_heavy_queries": 4, "bogus": 1}"#).is_err());
+    }
+}
@@ -0,0 +1,218 @@
+//! Dataset builds from the `evefrontier_datasets` SQLite releases, shared by
+//! `build_dataset` and its reproducibility tests. Nothing here reads the
+//! wall clock or depends on row or hash order, so the same release always
+//! gives byte-identical output.
+
+use std::collections::HashMap;
+use std::path::Path;
+
+use log::warn;
+use rusqlite::Connection;
+
+use crate::data::bundle::EngineBundle;
+use crate::data::{Canary, DataError, DatasetMetadata};
+use crate::graph::graph::StarGraph;
+use crate::graph::hub_labels::HubLabels;
+use crate::graph::pathfinder::shortest_gate_path;
+use crate::spatial::kd_tree::KDTree;
+use crate::spatial::morton::MortonIndex;
+use crate::spatial::neighbours::{NeighbourDistances, NEIGHBOUR_RANKS};
+use crate::units::CoordinateFrame;
+use crate::{Scalar, System};
+
+/// Number of neighbours recorded by the nearest-query canary.
+const CANARY_NEAREST_COUNT: usize = 5;
+
+/// Where a dataset came from, recorded in its metadata.
+#[derive(Clone, Debug)]
+pub struct ReleaseSource {
+    pub release_tag: String,
+    pub asset_name: String,
+    pub asset_url: String,
+    /// `YYYY-MM-DDTHH:MM:SSZ` publication time of the release.
+    pub published_at: Option<String>,
+}
+
+/// Reads systems and gates from a release database, ordered by id whatever
+/// the row order on disk. Returns the graph and its number of directed
+/// gates; duplicate gates and gates to unknown systems are dropped.
+pub fn build_graph_from_sqlite(path: &Path) -> Result<(StarGraph, usize), DataError> {
+    let conn = Connection::open(path)?;
+
+    let mut systems = Vec::new();
+    let mut id_to_index = HashMap::new();
+    {
+        let mut stmt = conn.prepare(
+            "SELECT solarSystemId, name, centerX, centerY, centerZ FROM SolarSystems ORDER BY solarSystemId",
+        )?;
+        let rows = stmt.query_map([], |row| {
+            let id: i64 = row.get(0)?;
+            let name: String = row.get(1)?;
+            let x: f64 = row.get(2)?;
+            let y: f64 = row.get(3)?;
+            let z: f64 = row.get(4)?;
+            Ok((id as u32, name, [x as Scalar, y as Scalar, z as Scalar]))
+        })?;
+        for (idx, row) in rows.enumerate() {
+            let (id, name, pos) = row?;
+            id_to_index.insert(id, idx);
+            systems.push(System { id, name, pos });
+        }
+    }
+
+    let mut adjacency = vec![Vec::new(); systems.len()];
+    {
+        let mut stmt = conn.prepare(
+            "SELECT fromSystemId, toSystemId FROM Jumps ORDER BY fromSystemId, toSystemId",
+        )?;
+        let rows = stmt.query_map([], |row| {
+            let from: i64 = row.get(0)?;
+            let to: i64 = row.get(1)?;
+            Ok((from as u32, to as u32))
+        })?;
+        for row in rows {
+            let (from, to) = row?;
+            let Some(&from_idx) = id_to_index.get(&from) else {
+                warn!("Jumps entry references missing fromSystemId {from}");
+                continue;
+            };
+            let Some(&to_idx) = id_to_index.get(&to) else {
+                warn!("Jumps entry references missing toSystemId {to}");
+                continue;
+            };
+            adjacency[from_idx].push(to_idx);
+        }
+    }
+
+    let mut edge_count = 0usize;
+    for neighbours in &mut adjacency {
+        neighbours.sort_unstable();
+        neighbours.dedup();
+        edge_count += neighbours.len();
+    }
+
+    let graph = StarGraph::new(systems, adjacency);
+    Ok((graph, edge_count))
+}
+
+/// Everything `build_dataset` publishes for `graph`: the metadata, with its
+/// canaries and neighbour distances, and the k-d tree, Morton index and hub
+/// labels.
+pub fn build_bundle(
+    graph: StarGraph,
+    directed_edges: usize,
+    source: &ReleaseSource,
+) -> Result<EngineBundle, DataError> {
+    let points: Vec<[Scalar; 3]> = graph.systems.iter().map(|s| s.pos).collect();
+    let kd = KDTree::build(&points);
+    let metadata = DatasetMetadata {
+        release_tag: source.release_tag.clone(),
+        asset_name: source.asset_name.clone(),
+        asset_url: source.asset_url.clone(),
+        systems: graph.len(),
+        directed_edges,
+        generated_at_epoch: generated_at_epoch(source.published_at.as_deref())?,
+        // Positions are copied verbatim from the SQLite centre columns, which
+        // are raw meters in the game's own frame.
+        frame: CoordinateFrame::default(),
+        canaries: build_canaries(&graph, &kd),
+        neighbour_distances: Some(NeighbourDistances::compute(&points, &kd, &NEIGHBOUR_RANKS)),
+    };
+    Ok(EngineBundle {
+        metadata: Some(metadata),
+        morton_index: Some(MortonIndex::build(&points)),
+        hub_labels: Some(HubLabels::build(&graph)),
+        kd_tree: Some(kd),
+        ..EngineBundle::new(graph)
+    })
+}
+
+/// Records a known path and a known nearest-neighbour result so the engine
+/// can verify the dataset with `Engine::self_check` after loading it.
+fn build_canaries(graph: &StarGraph, kd: &KDTree) -> Vec<Canary> {
+    let mut canaries = Vec::new();
+    let Some(origin) = graph.adjacency.iter().position(|n| !n.is_empty()) else {
+        return canaries;
+    };
+    let origin_system = &graph.systems[origin];
+
+    // The system two gates away that lies furthest in space keeps the
+    // canary path non-trivial while staying cheap to recompute at startup.
+    let target = graph.adjacency[origin]
+        .iter()
+        .flat_map(|&n| graph.adjacency[n].iter().copied())
+        .filter(|&n| n != origin)
+        .max_by(|&a, &b| {
+            let da = graph.systems[a].distance(origin_system);
+            let db = graph.systems[b].distance(origin_system);
+            da.total_cmp(&db)
+        });
+    if let Some(target) = target {
+        if let Ok(path) = shortest_gate_path(graph, origin, target) {
+            canaries.push(Canary::Path {
+                start_id: origin_system.id,
+                end_id: graph.systems[target].id,
+                jumps: path.len() - 1,
+            });
+        }
+    }
+
+    let nearest =
+        kd.nearest_n_within_radius(origin_system.pos, Scalar::INFINITY, CANARY_NEAREST_COUNT);
+    if let Some(&(_, furthest)) = nearest.last() {
+        canaries.push(Canary::Nearest {
+            system_id: origin_system.id,
+            radius: furthest,
+            expected_ids: nearest.iter().map(|&(i, _)| graph.systems[i].id).collect(),
+        });
+    }
+
+    canaries
+}
+
+/// Timestamp recorded in the metadata: `SOURCE_DATE_EPOCH` when set,
+/// otherwise the release's publication time. The wall clock is never used.
+fn generated_at_epoch(published_at: Option<&str>) -> Result<u64, DataError> {
+    if let Ok(epoch) = std::env::var("SOURCE_DATE_EPOCH") {
+        return epoch
+            .parse()
+            .map_err(|_| DataError::Timestamp(format!("invalid SOURCE_DATE_EPOCH {epoch}")));
+    }
+    let published = published_at
+        .ok_or_else(|| DataError::Timestamp("release has no publication time".to_string()))?;
+    parse_utc_timestamp(published)
+        .ok_or_else(|| DataError::Timestamp(format!("unrecognised publication time {published}")))
+}
+
+/// Seconds since the Unix epoch of a `YYYY-MM-DDTHH:MM:SSZ` timestamp.
+fn parse_utc_timestamp(text: &str) -> Option<u64> {
+    let field = |range: std::ops::Range<usize>| text.get(range)?.parse::<i64>().ok();
+    if text.len() != 20 || !text.ends_with('Z') {
+        return None;
+    }
+    let (year, month, day) = (field(0..4)?, field(5..7)?, field(8..10)?);
+    let (hour, minute, second) = (field(11..13)?, field(14..16)?, field(17..19)?);
+    // Days from civil date (Howard Hinnant's algorithm).
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let doy = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    let days = era * 146_097 + doe - 719_468;
+    u64::try_from(days * 86_400 + hour * 3_600 + minute * 60 + second).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn utc_timestamps_parse_to_unix_seconds() {
+        assert_eq!(parse_utc_timestamp("1970-01-01T00:00:00Z"), Some(0));
+        assert_eq!(
+            parse_utc_timestamp("2024-02-29T12:30:05Z"),
+            Some(1_709_209_805)
+        );
+        assert_eq!(parse_utc_timestamp("2024-02-29 12:30:05"), None);
+    }
+}
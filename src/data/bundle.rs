@@ -0,0 +1,245 @@
+//! Engine bundles: one file holding a dataset and everything built over it,
+//! in place of the graph file plus side files found by naming convention
+//! (`.meta.json`, `.hubs.bin`, ...).
+//!
+//! Layout: `BUNDLE_MAGIC`, the length of the table of contents as a
+//! little-endian `u32`, the bincode-encoded table of contents, then the
+//! sections back to back. Each section is encoded like its standalone file,
+//! so readers decode only what they use and skip sections they do not know.
+
+use std::fs;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use super::{compress, decompress, deserialize_graph, serialize_graph, DataError, DatasetMetadata};
+use crate::graph::closed_gates::ClosedGates;
+use crate::graph::graph::StarGraph;
+use crate::graph::hub_labels::HubLabels;
+use crate::graph::matrix::JumpMatrix;
+use crate::spatial::kd_tree::KDTree;
+use crate::spatial::morton::MortonIndex;
+use crate::tags::SystemTags;
+
+/// Prefix of engine bundles.
+pub const BUNDLE_MAGIC: &[u8; 4] = b"SMB1";
+
+const GRAPH: &str = "graph";
+const METADATA: &str = "metadata";
+const KD_TREE: &str = "kd_tree";
+const MORTON_INDEX: &str = "morton_index";
+const HUB_LABELS: &str = "hub_labels";
+const JUMP_MATRIX: &str = "jump_matrix";
+const TAGS: &str = "tags";
+const CLOSED_GATES: &str = "closed_gates";
+
+/// One entry of a bundle's table of contents. `offset` counts from the
+/// first byte after the table.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BundleSection {
+    pub name: String,
+    pub offset: u64,
+    pub length: u64,
+}
+
+/// A dataset with its precomputed analyses and overlays. Only the graph is
+/// required; absent parts are built on demand or left empty by the engine.
+#[derive(Clone, Debug)]
+pub struct EngineBundle {
+    pub graph: StarGraph,
+    pub metadata: Option<DatasetMetadata>,
+    pub kd_tree: Option<KDTree>,
+    /// Stored uncompressed in its flat layout, so loading is a copy.
+    pub morton_index: Option<MortonIndex>,
+    pub hub_labels: Option<HubLabels>,
+    pub jump_matrix: Option<JumpMatrix>,
+    pub tags: Option<SystemTags>,
+    pub closed_gates: Option<ClosedGates>,
+}
+
+impl EngineBundle {
+    pub fn new(graph: StarGraph) -> Self {
+        EngineBundle {
+            graph,
+            metadata: None,
+            kd_tree: None,
+            morton_index: None,
+            hub_labels: None,
+            jump_matrix: None,
+            tags: None,
+            closed_gates: None,
+        }
+    }
+}
+
+/// Whether `bytes` start like an engine bundle.
+pub fn is_bundle(bytes: &[u8]) -> bool {
+    bytes.starts_with(BUNDLE_MAGIC)
+}
+
+pub fn serialize_bundle(bundle: &EngineBundle) -> Result<Vec<u8>, DataError> {
+    let mut sections: Vec<(&str, Vec<u8>)> = vec![(GRAPH, serialize_graph(&bundle.graph)?)];
+    if let Some(metadata) = &bundle.metadata {
+        sections.push((METADATA, serde_json::to_vec(metadata)?));
+    }
+    if let Some(tree) = &bundle.kd_tree {
+        sections.push((KD_TREE, compress(tree)?));
+    }
+    if let Some(index) = &bundle.morton_index {
+        sections.push((MORTON_INDEX, index.to_bytes()));
+    }
+    if let Some(labels) = &bundle.hub_labels {
+        sections.push((HUB_LABELS, compress(labels)?));
+    }
+    if let Some(matrix) = &bundle.jump_matrix {
+        sections.push((JUMP_MATRIX, compress(matrix)?));
+    }
+    if let Some(tags) = &bundle.tags {
+        sections.push((TAGS, serde_json::to_vec(tags)?));
+    }
+    if let Some(closed) = &bundle.closed_gates {
+        sections.push((CLOSED_GATES, serde_json::to_vec(closed)?));
+    }
+
+    let mut offset = 0;
+    let contents: Vec<BundleSection> = sections
+        .iter()
+        .map(|(name, bytes)| {
+            let section = BundleSection {
+                name: name.to_string(),
+                offset,
+                length: bytes.len() as u64,
+            };
+            offset += section.length;
+            section
+        })
+        .collect();
+    let table = bincode::serialize(&contents)?;
+    let mut bytes = BUNDLE_MAGIC.to_vec();
+    bytes.extend((table.len() as u32).to_le_bytes());
+    bytes.extend(table);
+    for (_, section) in sections {
+        bytes.extend(section);
+    }
+    Ok(bytes)
+}
+
+/// The table of contents of a bundle and the bytes its offsets refer to.
+pub fn bundle_contents(bytes: &[u8]) -> Result<(Vec<BundleSection>, &[u8]), DataError> {
+    let rest = bytes
+        .strip_prefix(BUNDLE_MAGIC)
+        .ok_or_else(|| DataError::Corrupt("not an engine bundle".to_string()))?;
+    let truncated = || DataError::Corrupt("truncated engine bundle".to_string());
+    let (length, rest) = rest.split_first_chunk::<4>().ok_or_else(truncated)?;
+    let length = u32::from_le_bytes(*length) as usize;
+    if rest.len() < length {
+        return Err(truncated());
+    }
+    let (table, body) = rest.split_at(length);
+    let contents: Vec<BundleSection> = bincode::deserialize(table)?;
+    for section in &contents {
+        if section.offset.saturating_add(section.length) > body.len() as u64 {
+            return Err(DataError::Corrupt(format!(
+                "bundle section {} runs past the end of the file",
+                section.name
+            )));
+        }
+    }
+    Ok((contents, body))
+}
+
+pub fn deserialize_bundle(bytes: &[u8]) -> Result<EngineBundle, DataError> {
+    let (contents, body) = bundle_contents(bytes)?;
+    let section = |name: &str| {
+        contents
+            .iter()
+            .find(|s| s.name == name)
+            .map(|s| &body[s.offset as usize..(s.offset + s.length) as usize])
+    };
+    let graph = section(GRAPH)
+        .ok_or_else(|| DataError::Corrupt("engine bundle has no graph".to_string()))?;
+    Ok(EngineBundle {
+        graph: deserialize_graph(graph)?,
+        metadata: section(METADATA).map(serde_json::from_slice).transpose()?,
+        kd_tree: section(KD_TREE).map(decompress).transpose()?,
+        morton_index: section(MORTON_INDEX)
+            .map(MortonIndex::from_bytes)
+            .transpose()
+            .map_err(|err| DataError::Corrupt(err.to_string()))?,
+        hub_labels: section(HUB_LABELS).map(decompress).transpose()?,
+        jump_matrix: section(JUMP_MATRIX).map(decompress).transpose()?,
+        tags: section(TAGS).map(serde_json::from_slice).transpose()?,
+        closed_gates: section(CLOSED_GATES)
+            .map(serde_json::from_slice)
+            .transpose()?,
+    })
+}
+
+pub fn write_bundle_to_file<P: AsRef<Path>>(
+    bundle: &EngineBundle,
+    path: P,
+) -> Result<(), DataError> {
+    fs::write(path, serialize_bundle(bundle)?)?;
+    Ok(())
+}
+
+pub fn read_bundle_from_file<P: AsRef<Path>>(path: P) -> Result<EngineBundle, DataError> {
+    deserialize_bundle(&fs::read(path)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::read_graph_from_file;
+    use crate::engine::demo_graph;
+    use crate::Scalar;
+
+    #[test]
+    fn bundles_round_trip_and_reject_truncation() {
+        let graph = demo_graph();
+        let points: Vec<[Scalar; 3]> = graph.systems.iter().map(|s| s.pos).collect();
+        let bundle = EngineBundle {
+            kd_tree: Some(KDTree::build(&points)),
+            morton_index: Some(MortonIndex::build(&points)),
+            hub_labels: Some(HubLabels::build(&graph)),
+            closed_gates: Some(ClosedGates::from_pairs(&[[1, 2]])),
+            ..EngineBundle::new(graph.clone())
+        };
+        let bytes = serialize_bundle(&bundle).unwrap();
+        assert!(is_bundle(&bytes));
+        let names: Vec<String> = bundle_contents(&bytes)
+            .unwrap()
+            .0
+            .into_iter()
+            .map(|s| s.name)
+            .collect();
+        assert_eq!(
+            names,
+            [
+                "graph",
+                "kd_tree",
+                "morton_index",
+                "hub_labels",
+                "closed_gates"
+            ]
+        );
+
+        let read = deserialize_bundle(&bytes).unwrap();
+        assert_eq!(read.graph.systems, graph.systems);
+        assert_eq!(read.graph.adjacency, graph.adjacency);
+        assert_eq!(read.hub_labels, bundle.hub_labels);
+        assert_eq!(read.closed_gates, bundle.closed_gates);
+        assert_eq!(read.morton_index, bundle.morton_index);
+        assert!(read.kd_tree.is_some() && read.metadata.is_none() && read.tags.is_none());
+        let file = tempfile::NamedTempFile::new().expect("temp file");
+        write_bundle_to_file(&bundle, file.path()).unwrap();
+        let engine = crate::engine::Engine::load(file.path()).unwrap();
+        assert!(engine.hub_labels.is_some() && engine.closed_gates.contains(1, 2));
+        assert_eq!(read_graph_from_file(file.path()).unwrap().len(), 4);
+
+        assert!(matches!(
+            deserialize_bundle(&bytes[..bytes.len() - 1]),
+            Err(DataError::Corrupt(_))
+        ));
+    }
+}
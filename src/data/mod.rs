@@ -1,11 +1,25 @@
 use std::fs;
-use std::io::Cursor;
-use std::path::Path;
+use std::io::{Cursor, Write};
+use std::path::{Path, PathBuf};
 
 use bincode::ErrorKind;
+use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
 use crate::graph::graph::StarGraph;
+use crate::graph::hub_labels::HubLabels;
+use crate::graph::matrix::JumpMatrix;
+use crate::spatial::kd_tree::KDTree;
+use crate::spatial::neighbours::NeighbourDistances;
+use crate::units::CoordinateFrame;
+use crate::Scalar;
+
+pub mod build;
+pub mod bundle;
+mod packed;
+pub mod remote;
+
+use packed::PackedGraph;
 
 /// Compression level used when encoding serialized graph data.
 ///
@@ -14,6 +28,24 @@ use crate::graph::graph::StarGraph;
 /// size.
 const GRAPH_COMPRESSION_LEVEL: i32 = 19;
 
+/// Compression window, pinned rather than derived from the input size so
+/// the encoder parameters never vary between builds.
+const GRAPH_WINDOW_LOG: u32 = 23;
+
+/// Prefix of graph bundles in the packed layout. Older bundles are a bare
+/// Zstandard frame of the `StarGraph` and are still read. Builds with the
+/// `f64` feature write `f64` positions under their own prefix.
+#[cfg(not(feature = "f64"))]
+const PACKED_MAGIC: &[u8; 4] = b"SMP1";
+#[cfg(feature = "f64")]
+const PACKED_MAGIC: &[u8; 4] = b"SMD1";
+
+/// `PACKED_MAGIC` of builds with the other position width.
+#[cfg(not(feature = "f64"))]
+const OTHER_WIDTH_MAGIC: &[u8; 4] = b"SMD1";
+#[cfg(feature = "f64")]
+const OTHER_WIDTH_MAGIC: &[u8; 4] = b"SMP1";
+
 #[derive(Debug, Error)]
 pub enum DataError {
     #[error("I/O error: {0}")]
@@ -22,19 +54,113 @@ pub enum DataError {
     Serialize(#[from] Box<ErrorKind>),
     #[error("Compression error: {0}")]
     Compression(#[source] std::io::Error),
+    #[error("Metadata error: {0}")]
+    Metadata(#[from] serde_json::Error),
+    #[error("Download error: {0}")]
+    Download(#[from] reqwest::Error),
+    #[error("SQLite error: {0}")]
+    Sqlite(#[from] rusqlite::Error),
+    #[error("Timestamp error: {0}")]
+    Timestamp(String),
+    #[error("Corrupt dataset: {0}")]
+    Corrupt(String),
+    /// An anomaly that lenient loading only logs.
+    #[error("Rejected in strict mode: {0}")]
+    Strict(String),
 }
 
+/// Build metadata written next to a dataset bundle by `build_dataset`.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct DatasetMetadata {
+    pub release_tag: String,
+    pub asset_name: String,
+    pub asset_url: String,
+    pub systems: usize,
+    pub directed_edges: usize,
+    /// Publication time of the source release (or `SOURCE_DATE_EPOCH`), not
+    /// the build time, so rebuilding the same release gives identical files.
+    pub generated_at_epoch: u64,
+    /// Frame and units the system positions are stored in.
+    #[serde(default)]
+    pub frame: CoordinateFrame,
+    /// Known query results captured at build time and replayed by
+    /// `Engine::self_check` after loading.
+    #[serde(default)]
+    pub canaries: Vec<Canary>,
+    /// Nearest-neighbour distances per system, computed at build time.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub neighbour_distances: Option<NeighbourDistances>,
+}
+
+/// A query whose result was recorded when the dataset was built.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Canary {
+    Path {
+        start_id: u32,
+        end_id: u32,
+        jumps: usize,
+    },
+    Nearest {
+        system_id: u32,
+        radius: Scalar,
+        expected_ids: Vec<u32>,
+    },
+}
+
+/// Encodes a graph in the packed layout (see `PackedGraph`), which
+/// compresses markedly better than the plain serialized graph.
 pub fn serialize_graph(graph: &StarGraph) -> Result<Vec<u8>, DataError> {
-    let encoded = bincode::serialize(graph)?;
-    let mut cursor = Cursor::new(encoded);
-    zstd::stream::encode_all(&mut cursor, GRAPH_COMPRESSION_LEVEL).map_err(DataError::Compression)
+    let mut bytes = PACKED_MAGIC.to_vec();
+    bytes.extend(compress(&PackedGraph::pack(graph))?);
+    Ok(bytes)
+}
+
+/// Whether `bytes` hold a graph in the current packed layout rather than
+/// the unpacked one of older versions.
+pub fn is_packed_graph(bytes: &[u8]) -> bool {
+    bytes.starts_with(PACKED_MAGIC) || bytes.starts_with(OTHER_WIDTH_MAGIC)
 }
 
+/// Decodes a graph written by `serialize_graph`, or by older versions that
+/// stored it unpacked.
 pub fn deserialize_graph(bytes: &[u8]) -> Result<StarGraph, DataError> {
+    if bytes.starts_with(OTHER_WIDTH_MAGIC) {
+        return Err(DataError::Corrupt(
+            "positions are stored at the other width; rebuild the dataset \
+             with or without the f64 feature to match the engine"
+                .to_string(),
+        ));
+    }
+    match bytes.strip_prefix(PACKED_MAGIC) {
+        Some(packed) => decompress::<PackedGraph>(packed)?
+            .unpack()
+            .map_err(DataError::Corrupt),
+        None => decompress(bytes),
+    }
+}
+
+/// Serializes and compresses `value` with every encoder parameter fixed and
+/// a single thread, so identical values give byte-identical output.
+fn compress<T: Serialize>(value: &T) -> Result<Vec<u8>, DataError> {
+    let encoded = bincode::serialize(value)?;
+    let encode = || -> std::io::Result<Vec<u8>> {
+        let mut encoder = zstd::stream::Encoder::new(Vec::new(), GRAPH_COMPRESSION_LEVEL)?;
+        encoder.window_log(GRAPH_WINDOW_LOG)?;
+        encoder.long_distance_matching(false)?;
+        encoder.include_checksum(true)?;
+        encoder.include_contentsize(true)?;
+        encoder.set_pledged_src_size(Some(encoded.len() as u64))?;
+        encoder.write_all(&encoded)?;
+        encoder.finish()
+    };
+    encode().map_err(DataError::Compression)
+}
+
+fn decompress<T: serde::de::DeserializeOwned>(bytes: &[u8]) -> Result<T, DataError> {
     let mut cursor = Cursor::new(bytes);
     let decoded = zstd::stream::decode_all(&mut cursor).map_err(DataError::Compression)?;
-    let graph = bincode::deserialize(&decoded)?;
-    Ok(graph)
+    Ok(bincode::deserialize(&decoded)?)
 }
 
 pub fn write_graph_to_file<P: AsRef<Path>>(graph: &StarGraph, path: P) -> Result<(), DataError> {
@@ -43,7 +169,73 @@ pub fn write_graph_to_file<P: AsRef<Path>>(graph: &StarGraph, path: P) -> Result
     Ok(())
 }
 
+/// Reads the graph of a graph file or of an engine bundle.
 pub fn read_graph_from_file<P: AsRef<Path>>(path: P) -> Result<StarGraph, DataError> {
     let bytes = fs::read(path)?;
+    if bundle::is_bundle(&bytes) {
+        return Ok(bundle::deserialize_bundle(&bytes)?.graph);
+    }
     deserialize_graph(&bytes)
 }
+
+/// Writes a precomputed jump matrix, Zstandard-compressed like the graph.
+pub fn write_matrix_to_file<P: AsRef<Path>>(matrix: &JumpMatrix, path: P) -> Result<(), DataError> {
+    fs::write(path, compress(matrix)?)?;
+    Ok(())
+}
+
+pub fn read_matrix_from_file<P: AsRef<Path>>(path: P) -> Result<JumpMatrix, DataError> {
+    decompress(&fs::read(path)?)
+}
+
+/// Writes a k-d tree in its portable form (`PortableKDTree`),
+/// Zstandard-compressed like the graph.
+pub fn write_kd_tree_to_file<P: AsRef<Path>>(tree: &KDTree, path: P) -> Result<(), DataError> {
+    fs::write(path, compress(tree)?)?;
+    Ok(())
+}
+
+/// Reads a k-d tree written by any layout version; trees from another
+/// layout are rebuilt on load.
+pub fn read_kd_tree_from_file<P: AsRef<Path>>(path: P) -> Result<KDTree, DataError> {
+    decompress(&fs::read(path)?)
+}
+
+/// Path of the hub labels that accompany a dataset bundle, e.g.
+/// `data/starmap.bin` -> `data/starmap.hubs.bin`.
+pub fn hub_labels_path_for<P: AsRef<Path>>(dataset_path: P) -> PathBuf {
+    dataset_path.as_ref().with_extension("hubs.bin")
+}
+
+/// Writes hub labels, Zstandard-compressed like the graph.
+pub fn write_hub_labels_to_file<P: AsRef<Path>>(
+    labels: &HubLabels,
+    path: P,
+) -> Result<(), DataError> {
+    fs::write(path, compress(labels)?)?;
+    Ok(())
+}
+
+pub fn read_hub_labels_from_file<P: AsRef<Path>>(path: P) -> Result<HubLabels, DataError> {
+    decompress(&fs::read(path)?)
+}
+
+/// Path of the metadata file that accompanies a dataset bundle, e.g.
+/// `data/starmap.bin` -> `data/starmap.meta.json`.
+pub fn metadata_path_for<P: AsRef<Path>>(dataset_path: P) -> PathBuf {
+    dataset_path.as_ref().with_extension("meta.json")
+}
+
+pub fn write_metadata_to_file<P: AsRef<Path>>(
+    metadata: &DatasetMetadata,
+    path: P,
+) -> Result<(), DataError> {
+    let json = serde_json::to_vec_pretty(metadata)?;
+    fs::write(path, json)?;
+    Ok(())
+}
+
+pub fn read_metadata_from_file<P: AsRef<Path>>(path: P) -> Result<DatasetMetadata, DataError> {
+    let bytes = fs::read(path)?;
+    Ok(serde_json::from_slice(&bytes)?)
+}
@@ -0,0 +1,207 @@
+use serde::{Deserialize, Serialize};
+
+use crate::graph::graph::StarGraph;
+use crate::spatial::morton::{bounds, morton_code};
+use crate::System;
+use crate::{Scalar, ScalarBits};
+
+/// Column-oriented dataset layout that Zstandard compresses well: systems
+/// are stored in Morton order so neighbours in space (and, mostly, along
+/// gates) sit next to each other, and every column holds small deltas
+/// instead of absolute values. `order` maps back to the original system
+/// order, so indices, ids and anything keyed on them survive a round trip
+/// unchanged.
+#[derive(Serialize, Deserialize)]
+pub(super) struct PackedGraph {
+    /// Original index of each system, in Morton order, as the difference
+    /// from the previous one.
+    order: Vec<i32>,
+    /// Each id minus the previous one, wrapping.
+    id_deltas: Vec<u32>,
+    names: Vec<String>,
+    /// Bit pattern of each coordinate minus the previous system's, per axis,
+    /// wrapping. Nearby floats of the same sign have nearby bit patterns.
+    coords: [Vec<ScalarBits>; 3],
+    degrees: Vec<u32>,
+    /// Morton-order index of each gate target minus the previous target of
+    /// the same system, or minus the system itself for the first.
+    neighbours: Vec<i32>,
+}
+
+impl PackedGraph {
+    pub(super) fn pack(graph: &StarGraph) -> Self {
+        let n = graph.len();
+        let (min, max) = bounds(graph.systems.iter().map(|s| s.pos)).unwrap_or_default();
+        let mut order: Vec<usize> = (0..n).collect();
+        order.sort_by_key(|&i| (morton_code(graph.systems[i].pos, min, max), i));
+        let mut rank = vec![0; n];
+        for (new, &old) in order.iter().enumerate() {
+            rank[old] = new;
+        }
+
+        let mut packed = PackedGraph {
+            order: order
+                .iter()
+                .scan(0i64, |prev, &i| {
+                    let delta = i as i64 - *prev;
+                    *prev = i as i64;
+                    Some(delta as i32)
+                })
+                .collect(),
+            id_deltas: Vec::with_capacity(n),
+            names: Vec::with_capacity(n),
+            coords: Default::default(),
+            degrees: Vec::with_capacity(n),
+            neighbours: Vec::with_capacity(graph.edge_count()),
+        };
+        let mut prev_id = 0u32;
+        let mut prev_bits: [ScalarBits; 3] = [0; 3];
+        for (new, &old) in order.iter().enumerate() {
+            let system = &graph.systems[old];
+            packed.id_deltas.push(system.id.wrapping_sub(prev_id));
+            prev_id = system.id;
+            packed.names.push(system.name.clone());
+            for ((column, prev), coord) in
+                packed.coords.iter_mut().zip(&mut prev_bits).zip(system.pos)
+            {
+                let bits = coord.to_bits();
+                column.push(bits.wrapping_sub(*prev));
+                *prev = bits;
+            }
+            packed.degrees.push(graph.adjacency[old].len() as u32);
+            let mut prev = new as i64;
+            for &target in &graph.adjacency[old] {
+                let target = rank[target] as i64;
+                packed.neighbours.push((target - prev) as i32);
+                prev = target;
+            }
+        }
+        packed
+    }
+
+    pub(super) fn unpack(self) -> Result<StarGraph, String> {
+        let n = self.order.len();
+        let order: Vec<i64> = self
+            .order
+            .iter()
+            .scan(0i64, |prev, &delta| {
+                *prev += delta as i64;
+                Some(*prev)
+            })
+            .collect();
+        if [
+            self.id_deltas.len(),
+            self.names.len(),
+            self.coords[0].len(),
+            self.coords[1].len(),
+            self.coords[2].len(),
+            self.degrees.len(),
+        ]
+        .iter()
+        .any(|&len| len != n)
+        {
+            return Err(format!("packed columns do not all hold {n} systems"));
+        }
+
+        let mut systems: Vec<Option<System>> = vec![None; n];
+        let mut adjacency = vec![Vec::new(); n];
+        let mut prev_id = 0u32;
+        let mut prev_bits: [ScalarBits; 3] = [0; 3];
+        let mut neighbours = self.neighbours.into_iter();
+        for (new, (&old, name)) in order.iter().zip(self.names).enumerate() {
+            let old = match usize::try_from(old) {
+                Ok(old) if old < n && systems[old].is_none() => old,
+                _ => return Err(format!("invalid system order entry {old}")),
+            };
+            prev_id = prev_id.wrapping_add(self.id_deltas[new]);
+            let mut pos = [0.0; 3];
+            for axis in 0..3 {
+                prev_bits[axis] = prev_bits[axis].wrapping_add(self.coords[axis][new]);
+                pos[axis] = Scalar::from_bits(prev_bits[axis]);
+            }
+            systems[old] = Some(System {
+                id: prev_id,
+                name,
+                pos,
+            });
+
+            let mut prev = new as i64;
+            for _ in 0..self.degrees[new] {
+                let delta = neighbours.next().ok_or("packed gate list is truncated")?;
+                let target = prev + delta as i64;
+                let original = usize::try_from(target)
+                    .ok()
+                    .and_then(|t| order.get(t))
+                    .ok_or_else(|| format!("gate references missing system {target}"))?;
+                adjacency[old].push(*original as usize);
+                prev = target;
+            }
+        }
+        if neighbours.next().is_some() {
+            return Err("packed gate list has trailing entries".into());
+        }
+        // Every slot was filled exactly once above.
+        let systems = systems.into_iter().flatten().collect();
+        Ok(StarGraph::new(systems, adjacency))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::{deserialize_graph, serialize_graph, DataError};
+
+    #[test]
+    fn packing_round_trips_and_compresses_better() {
+        // Clusters of systems scattered through a large volume, with ids
+        // assigned per cluster and gates inside each cluster plus a chain
+        // between clusters, roughly like the real map.
+        let mut systems = Vec::new();
+        let mut adjacency = Vec::new();
+        for cluster in 0..40u32 {
+            let base = [
+                (cluster * 7919 % 1000) as Scalar * 1.0e15,
+                (cluster * 104_729 % 1000) as Scalar * 1.0e15,
+                (cluster * 1_299_709 % 1000) as Scalar * 1.0e15,
+            ];
+            let first = systems.len();
+            for k in 0..25u32 {
+                let jitter = k.wrapping_mul(2_654_435_761) as Scalar * 1.0e3;
+                systems.push(System {
+                    id: 30_000_000 + cluster * 100 + k,
+                    name: format!("C{cluster}-S{k}"),
+                    pos: [
+                        base[0] + jitter,
+                        base[1] - jitter * 0.5,
+                        base[2] + k as Scalar,
+                    ],
+                });
+                let mut gates: Vec<usize> =
+                    (0..3).map(|d| first + (k as usize + d + 1) % 25).collect();
+                if k == 0 && cluster > 0 {
+                    gates.push(first - 25);
+                }
+                adjacency.push(gates);
+            }
+        }
+        let graph = StarGraph::new(systems, adjacency);
+
+        let packed = serialize_graph(&graph).unwrap();
+        let restored = deserialize_graph(&packed).unwrap();
+        assert_eq!(restored.systems, graph.systems);
+        assert_eq!(restored.adjacency, graph.adjacency);
+
+        let legacy = super::super::compress(&graph).unwrap();
+        assert!(packed.len() < legacy.len());
+        let restored = deserialize_graph(&legacy).unwrap();
+        assert_eq!(restored.systems, graph.systems);
+
+        // Builds of the other position width refuse the dataset.
+        let mut other = super::super::OTHER_WIDTH_MAGIC.to_vec();
+        other.extend(&packed[4..]);
+        assert!(matches!(
+            deserialize_graph(&other),
+            Err(DataError::Corrupt(_))
+        ));
+    }
+}
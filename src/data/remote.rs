@@ -0,0 +1,159 @@
+//! Datasets served over HTTP(S). A download lands in a temporary directory
+//! together with the side files published next to it, so the file loaders
+//! read it like a local dataset.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use reqwest::blocking::{Client, Response};
+use reqwest::header::{ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED};
+use reqwest::StatusCode;
+use tempfile::TempDir;
+
+use crate::data::{hub_labels_path_for, metadata_path_for, DataError};
+
+/// Whether `dataset` is an HTTP(S) URL rather than a local path.
+pub fn is_remote(dataset: &str) -> bool {
+    dataset.starts_with("http://") || dataset.starts_with("https://")
+}
+
+/// Identifies one version of a remote dataset: its `ETag`, or its
+/// `Last-Modified` time when the server sends no ETag.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RemoteVersion {
+    ETag(String),
+    LastModified(String),
+}
+
+impl RemoteVersion {
+    fn of(response: &Response) -> Option<Self> {
+        let header = |name| {
+            response
+                .headers()
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_string)
+        };
+        header(ETAG)
+            .map(RemoteVersion::ETag)
+            .or_else(|| header(LAST_MODIFIED).map(RemoteVersion::LastModified))
+    }
+}
+
+/// A downloaded dataset and its side files, deleted when dropped.
+#[derive(Debug)]
+pub struct FetchedDataset {
+    _dir: TempDir,
+    /// Local copy of the dataset, with the side files next to it.
+    pub path: PathBuf,
+    /// `None` when the server sent neither an ETag nor a modification time.
+    pub version: Option<RemoteVersion>,
+}
+
+/// Version of the dataset at `url`, from a `HEAD` request.
+pub fn remote_version(url: &str) -> Result<Option<RemoteVersion>, DataError> {
+    let response = Client::new().head(url).send()?.error_for_status()?;
+    Ok(RemoteVersion::of(&response))
+}
+
+/// Downloads the dataset at `url` into a temporary directory, along with the
+/// metadata and hub label files published next to it (skipped when
+/// missing). With the `seen` version the request is conditional, and `None`
+/// means the server reports no change. The dataset's own version covers the
+/// side files; republish the dataset when only they change.
+pub fn fetch_dataset(
+    url: &str,
+    seen: Option<&RemoteVersion>,
+) -> Result<Option<FetchedDataset>, DataError> {
+    let client = Client::new();
+    let request = match seen {
+        Some(RemoteVersion::ETag(tag)) => client.get(url).header(IF_NONE_MATCH, tag),
+        Some(RemoteVersion::LastModified(time)) => client.get(url).header(IF_MODIFIED_SINCE, time),
+        None => client.get(url),
+    };
+    let response = request.send()?;
+    if response.status() == StatusCode::NOT_MODIFIED {
+        return Ok(None);
+    }
+    let response = response.error_for_status()?;
+    let version = RemoteVersion::of(&response);
+    let dir = tempfile::tempdir()?;
+    let remote_path = Path::new(url);
+    let path = dir
+        .path()
+        .join(remote_path.file_name().unwrap_or("dataset".as_ref()));
+    fs::write(&path, response.bytes()?)?;
+    for side in [
+        metadata_path_for(remote_path),
+        hub_labels_path_for(remote_path),
+    ] {
+        let response = client.get(side.to_string_lossy().as_ref()).send()?;
+        if response.status() == StatusCode::NOT_FOUND {
+            continue;
+        }
+        let bytes = response.error_for_status()?.bytes()?;
+        let name = side
+            .file_name()
+            .expect("side files are named after the dataset");
+        fs::write(dir.path().join(name), bytes)?;
+    }
+    Ok(Some(FetchedDataset {
+        _dir: dir,
+        path,
+        version,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use std::thread;
+
+    /// Serves `/starmap.bin` with ETag `"v1"` for `requests` requests,
+    /// answering 304 to requests that already know it and 404 to any other
+    /// path. Returns the dataset URL.
+    fn serve(requests: usize) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/starmap.bin", listener.local_addr().unwrap());
+        thread::spawn(move || {
+            for stream in listener.incoming().take(requests) {
+                let mut stream = stream.unwrap();
+                let head: Vec<String> = BufReader::new(&stream)
+                    .lines()
+                    .map(Result::unwrap)
+                    .take_while(|line| !line.is_empty())
+                    .collect();
+                let known = head
+                    .iter()
+                    .any(|line| line.eq_ignore_ascii_case("if-none-match: \"v1\""));
+                let (status, body) = match head[0].split(' ').nth(1) {
+                    Some("/starmap.bin") if known => ("304 Not Modified", ""),
+                    Some("/starmap.bin") => ("200 OK", "dataset"),
+                    _ => ("404 Not Found", ""),
+                };
+                let reply = format!(
+                    "HTTP/1.1 {status}\r\nETag: \"v1\"\r\nContent-Length: {}\r\n\
+                     Connection: close\r\n\r\n{body}",
+                    body.len()
+                );
+                stream.write_all(reply.as_bytes()).unwrap();
+            }
+        });
+        url
+    }
+
+    #[test]
+    fn downloads_are_conditional_on_the_seen_version() {
+        // The dataset and its two missing side files, then a 304.
+        let url = serve(4);
+        assert!(is_remote(&url) && !is_remote("data/starmap.bin"));
+        let fetched = fetch_dataset(&url, None).unwrap().expect("a download");
+        assert_eq!(fs::read(&fetched.path).unwrap(), b"dataset");
+        assert_eq!(fetched.path.file_name().unwrap(), "starmap.bin");
+        let version = fetched.version.clone().expect("an ETag");
+        assert_eq!(version, RemoteVersion::ETag("\"v1\"".to_string()));
+        assert!(fetch_dataset(&url, Some(&version)).unwrap().is_none());
+    }
+}
@@ -0,0 +1,75 @@
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine as _;
+use serde::{Deserialize, Serialize};
+
+use crate::api::{EngineReply, EngineResponse};
+
+/// Wire encoding of a reply, chosen per request with `encoding`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Encoding {
+    /// The reply as a plain JSON object.
+    #[default]
+    Json,
+    /// The reply encoded as MessagePack, which is far smaller and cheaper to
+    /// produce for large numeric results such as matrices and sweeps.
+    Msgpack,
+}
+
+impl Encoding {
+    pub fn is_json(&self) -> bool {
+        *self == Encoding::Json
+    }
+}
+
+/// A reply as returned to the caller. Lambda payloads are JSON, so binary
+/// encodings travel in `body` as standard, padded base64.
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+pub enum WireReply {
+    Json(Box<EngineReply>),
+    Binary { encoding: Encoding, body: String },
+}
+
+/// Encodes `reply` as requested. A reply that cannot be encoded is answered
+/// with a JSON error instead.
+pub fn encode_reply(reply: EngineReply, encoding: Encoding) -> WireReply {
+    match encoding {
+        Encoding::Json => WireReply::Json(Box::new(reply)),
+        // Named fields, so the MessagePack maps mirror the JSON objects.
+        Encoding::Msgpack => match rmp_serde::to_vec_named(&reply) {
+            Ok(bytes) => WireReply::Binary {
+                encoding,
+                body: STANDARD.encode(bytes),
+            },
+            Err(err) => WireReply::Json(Box::new(EngineResponse::error(err.to_string()).into())),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::{handle_request, EngineRequest};
+    use crate::engine::{demo_graph, Engine};
+
+    #[test]
+    fn msgpack_replies_decode_to_the_json_reply() {
+        let engine = Engine::new(demo_graph(), None);
+        let json = r#"{"kind":"path","start_id":1,"end_id":3,"encoding":"msgpack"}"#;
+        let request: EngineRequest = serde_json::from_str(json).unwrap();
+        assert_eq!(request.options.encoding, Encoding::Msgpack);
+        let wire = encode_reply(handle_request(&engine, request), Encoding::Msgpack);
+        let value = serde_json::to_value(&wire).unwrap();
+        assert_eq!(value["encoding"], "msgpack");
+
+        let bytes = STANDARD.decode(value["body"].as_str().unwrap()).unwrap();
+        // A map of "kind" and "systems" at the top, like the JSON reply.
+        assert_eq!(bytes[..6], [0x82, 0xa4, b'k', b'i', b'n', b'd']);
+        let decoded: serde_json::Value = rmp_serde::from_slice(&bytes).unwrap();
+        let plain: EngineRequest =
+            serde_json::from_str(r#"{"kind":"path","start_id":1,"end_id":3}"#).unwrap();
+        let json = serde_json::to_value(handle_request(&engine, plain)).unwrap();
+        assert_eq!(decoded, json);
+    }
+}
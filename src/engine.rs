@@ -0,0 +1,808 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use std::sync::{Arc, PoisonError, RwLock, RwLockReadGuard};
+
+use once_cell::sync::OnceCell;
+use serde::Serialize;
+
+use crate::api::{PathOptionsKey, PathOutcome, QUERY_KINDS};
+use crate::config::{EngineConfig, CURRENT_SNAPSHOT};
+use crate::data::bundle::{deserialize_bundle, is_bundle, EngineBundle};
+use crate::data::remote::{fetch_dataset, is_remote};
+use crate::data::{
+    deserialize_graph, hub_labels_path_for, is_packed_graph, metadata_path_for,
+    read_hub_labels_from_file, read_metadata_from_file, Canary, DataError, DatasetMetadata,
+};
+use crate::graph::cache::PathCache;
+use crate::graph::closed_gates::ClosedGates;
+use crate::graph::components::StronglyConnected;
+use crate::graph::graph::StarGraph;
+use crate::graph::hub_labels::HubLabels;
+use crate::graph::jump::{JumpNeighbourCache, JumpNeighbours, TravelMode};
+use crate::graph::landmarks::Landmarks;
+use crate::graph::matrix::JumpMatrix;
+use crate::graph::pathfinder::{
+    shortest_gate_path, shortest_gate_path_within, PathError, RoutePreference,
+};
+use crate::graph::reachability::Reachability;
+use crate::jobs::JobQueue;
+use crate::poi::PoiLayer;
+use crate::spatial::kd_tree::KDTree;
+use crate::spatial::morton::MortonIndex;
+use crate::spatial::neighbours::{neighbour_distances, NEIGHBOUR_RANKS};
+use crate::tags::SystemTags;
+use crate::units::CoordinateFrame;
+use crate::Scalar;
+use crate::System;
+
+/// A loaded dataset together with the indices built over it.
+#[derive(Clone, Debug)]
+pub struct Engine {
+    pub graph: StarGraph,
+    pub kd: KDTree,
+    pub metadata: Option<DatasetMetadata>,
+    /// Precomputed hub labels, when shipped next to the dataset.
+    pub hub_labels: Option<HubLabels>,
+    /// Operator tags; shared with engines swapped in by a dataset refresh.
+    pub tags: Arc<RwLock<SystemTags>>,
+    /// Operator overlay of gates no gate route may take.
+    pub closed_gates: ClosedGates,
+    /// User-defined points of interest; shared like `tags`.
+    pub pois: Arc<RwLock<PoiLayer>>,
+    /// Background jobs; shared like `tags`.
+    pub jobs: Arc<JobQueue>,
+    pub config: EngineConfig,
+    /// Older dataset versions by label, for historical comparisons.
+    pub snapshots: BTreeMap<String, Engine>,
+    pub(crate) path_cache: PathCache<PathOptionsKey, PathOutcome>,
+    jump_neighbours: JumpNeighbourCache,
+    jump_matrix: OnceCell<Option<JumpMatrix>>,
+    landmarks: OnceCell<Option<Landmarks>>,
+    morton: OnceCell<MortonIndex>,
+    components: OnceCell<StronglyConnected>,
+    reachability: OnceCell<Option<Reachability>>,
+    startup_check: OnceCell<SelfCheckReport>,
+}
+
+/// What this deployment supports, reported by `info` so clients of other
+/// versions can adapt instead of failing on unsupported requests.
+#[derive(Clone, Debug, Serialize, PartialEq)]
+pub struct Capabilities {
+    pub query_kinds: Vec<&'static str>,
+    pub travel_modes: Vec<TravelMode>,
+    pub route_preferences: Vec<RoutePreference>,
+    /// Per-system risk data, without which `safest` equals `fewest_jumps`.
+    pub risk_overlay: bool,
+    /// Region border tables seeding long searches.
+    pub regions: bool,
+    /// Constant-time jump counts from the all-pairs matrix.
+    pub jump_matrix: bool,
+    /// Landmark heuristic for fewest-jumps routes.
+    pub landmarks: bool,
+    /// Hub labels answering `jump_distances` without graph searches.
+    pub hub_labels: bool,
+    /// Traffic recording (`recording` feature).
+    pub recording: bool,
+    /// Labels of the loaded datasets usable in `compare`, live one first.
+    pub datasets: Vec<String>,
+}
+
+/// Outcome of a single self-check.
+#[derive(Clone, Debug, Serialize, PartialEq)]
+pub struct CheckResult {
+    pub name: String,
+    pub passed: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+}
+
+/// Aggregated result of `Engine::self_check`.
+#[derive(Clone, Debug, Serialize, PartialEq)]
+pub struct SelfCheckReport {
+    pub passed: bool,
+    pub checks: Vec<CheckResult>,
+}
+
+impl Engine {
+    pub fn new(graph: StarGraph, metadata: Option<DatasetMetadata>) -> Self {
+        Engine::with_kd(graph, metadata, None)
+    }
+
+    /// Like `new`, but with a prebuilt k-d tree over `graph`'s positions,
+    /// e.g. from a bundle, instead of building one.
+    pub fn with_kd(
+        graph: StarGraph,
+        metadata: Option<DatasetMetadata>,
+        kd: Option<KDTree>,
+    ) -> Self {
+        let duplicates = graph.duplicate_names().len();
+        if duplicates > 0 {
+            log::warn!("{duplicates} system names are shared by several systems");
+        }
+        let kd = kd.unwrap_or_else(|| {
+            let pts: Vec<[Scalar; 3]> = graph.systems.iter().map(|s| s.pos).collect();
+            KDTree::build(&pts)
+        });
+        let config = EngineConfig::default();
+        let pois = PoiLayer::new(poi_cell_size(&graph));
+        Engine {
+            graph,
+            kd,
+            metadata,
+            hub_labels: None,
+            tags: Arc::default(),
+            closed_gates: ClosedGates::default(),
+            pois: Arc::new(RwLock::new(pois)),
+            jobs: Arc::new(JobQueue::new(config.max_jobs)),
+            path_cache: PathCache::new(config.path_cache_size),
+            config,
+            snapshots: BTreeMap::new(),
+            jump_neighbours: JumpNeighbourCache::default(),
+            jump_matrix: OnceCell::new(),
+            landmarks: OnceCell::new(),
+            morton: OnceCell::new(),
+            components: OnceCell::new(),
+            reachability: OnceCell::new(),
+            startup_check: OnceCell::new(),
+        }
+    }
+
+    /// Loads the dataset named by `config`. Leniently, a missing or
+    /// unreadable dataset falls back to the demo graph and unreadable
+    /// snapshots and overlays are logged and skipped, so this never fails;
+    /// with `config.strict` each of those is an error.
+    pub fn from_config(config: EngineConfig) -> Result<Self, DataError> {
+        let strict = config.strict;
+        let mut engine = match &config.dataset {
+            None => {
+                anomaly(strict, "No dataset configured".to_string())?;
+                log::info!("No dataset configured; using built-in demo graph");
+                Engine::new(demo_graph(), None)
+            }
+            Some(path) => {
+                log::info!("Loading dataset from {path}");
+                match Engine::load_from(path, strict) {
+                    Ok(engine) => engine,
+                    Err(err) if strict => return Err(err),
+                    Err(err) => {
+                        log::warn!("Failed to load dataset {path}: {err}");
+                        Engine::new(demo_graph(), None)
+                    }
+                }
+            }
+        };
+        for (label, path) in &config.snapshots {
+            match Engine::load_from(path, strict) {
+                Ok(snapshot) => {
+                    engine.snapshots.insert(label.clone(), snapshot);
+                }
+                Err(err) => anomaly(
+                    strict,
+                    format!("Failed to load snapshot {label} from {path}: {err}"),
+                )?,
+            }
+        }
+        if let Some(path) = &config.tags_path {
+            match SystemTags::load(path) {
+                Ok(tags) => engine.tags = Arc::new(RwLock::new(tags)),
+                Err(err) => anomaly(
+                    strict,
+                    format!("Failed to load system tags from {path}: {err}"),
+                )?,
+            }
+        }
+        if let Some(path) = &config.closed_gates_path {
+            match ClosedGates::load(path) {
+                Ok(closed) => engine.closed_gates = closed,
+                Err(err) => anomaly(
+                    strict,
+                    format!("Failed to load closed gates from {path}: {err}"),
+                )?,
+            }
+        }
+        if let Some(path) = &config.pois_path {
+            match PoiLayer::load(path, poi_cell_size(&engine.graph)) {
+                Ok(pois) => engine.pois = Arc::new(RwLock::new(pois)),
+                Err(err) => anomaly(
+                    strict,
+                    format!("Failed to load points of interest from {path}: {err}"),
+                )?,
+            }
+        }
+        engine.path_cache = PathCache::new(config.path_cache_size);
+        engine.jobs = Arc::new(JobQueue::new(config.max_jobs));
+        engine.config = config;
+        Ok(engine)
+    }
+
+    /// Neighbour lists for jump routes at `range` (dataset units), kept
+    /// across requests for the most recently used ranges.
+    pub fn jump_neighbours(&self, range: Scalar) -> Arc<JumpNeighbours> {
+        self.jump_neighbours.get(self.graph.len(), range)
+    }
+
+    /// Read access to the system tags.
+    pub fn tags(&self) -> RwLockReadGuard<'_, SystemTags> {
+        self.tags.read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Read access to the points of interest.
+    pub fn pois(&self) -> RwLockReadGuard<'_, PoiLayer> {
+        self.pois.read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// All-pairs jump matrix, built on first use when the dataset has at
+    /// most `config.jump_matrix_max_systems` systems.
+    pub fn jump_matrix(&self) -> Option<&JumpMatrix> {
+        self.jump_matrix
+            .get_or_init(|| {
+                JumpMatrix::build(&self.graph, self.config.jump_matrix_max_systems).ok()
+            })
+            .as_ref()
+    }
+
+    /// Landmark distances for the gate heuristic, selected on first use;
+    /// `None` when `config.landmark_count` is 0.
+    pub fn landmarks(&self) -> Option<&Landmarks> {
+        self.landmarks
+            .get_or_init(|| {
+                (self.config.landmark_count > 0)
+                    .then(|| Landmarks::select(&self.graph, self.config.landmark_count))
+            })
+            .as_ref()
+    }
+
+    /// Morton index over the system positions, built on first use unless
+    /// the bundle carried one.
+    pub fn morton(&self) -> &MortonIndex {
+        self.morton.get_or_init(|| {
+            let points: Vec<[Scalar; 3]> = self.graph.systems.iter().map(|s| s.pos).collect();
+            MortonIndex::build(&points)
+        })
+    }
+
+    /// Strongly connected components of the gate graph, built on first use.
+    pub fn components(&self) -> &StronglyConnected {
+        self.components
+            .get_or_init(|| StronglyConnected::build(&self.graph))
+    }
+
+    /// Component reachability bitmaps, built on first use; `None` when the
+    /// gate graph has too many components.
+    pub fn reachability(&self) -> Option<&Reachability> {
+        self.reachability
+            .get_or_init(|| Reachability::build(&self.graph, self.components()))
+            .as_ref()
+    }
+
+    /// Whether a gate route from `from` to `to` can exist. Exact when the
+    /// reachability bitmaps are available; otherwise `false` only rules out
+    /// routes to or from systems without gates and routes against the order
+    /// of one-way gates.
+    pub fn may_reach(&self, from: usize, to: usize) -> bool {
+        let components = self.components();
+        let (a, b) = (components.component_of(from), components.component_of(to));
+        match self.reachability() {
+            Some(reachability) => reachability.reaches(a, b),
+            None => {
+                let gated = |v: usize| {
+                    !self.graph.adjacency[v].is_empty() || !self.graph.incoming(v).is_empty()
+                };
+                from == to || (a >= b && gated(from) && gated(to))
+            }
+        }
+    }
+
+    /// Distances from `systems[index]` to its neighbours of each rank in
+    /// `NEIGHBOUR_RANKS`, from the metadata when it holds them and computed
+    /// on the spot otherwise.
+    pub fn neighbour_distances(&self, index: usize) -> Vec<Option<Scalar>> {
+        let stored = self
+            .metadata
+            .as_ref()
+            .and_then(|m| m.neighbour_distances.as_ref())
+            .filter(|table| {
+                table.ranks == NEIGHBOUR_RANKS && table.systems.len() == self.graph.len()
+            })
+            .and_then(|table| table.get(index));
+        match stored {
+            Some(distances) => distances.to_vec(),
+            None => {
+                let points: Vec<[Scalar; 3]> = self.graph.systems.iter().map(|s| s.pos).collect();
+                neighbour_distances(&points, &self.kd, index, &NEIGHBOUR_RANKS)
+            }
+        }
+    }
+
+    /// The dataset version called `label`; `current` (or `None`) is this one.
+    pub fn snapshot(&self, label: Option<&str>) -> Option<&Engine> {
+        match label {
+            None | Some(CURRENT_SNAPSHOT) => Some(self),
+            Some(label) => self.snapshots.get(label),
+        }
+    }
+
+    /// Loads an engine bundle, or a graph file and, if present, the metadata
+    /// and hub labels next to it. Missing or unreadable side files are
+    /// logged and otherwise ignored.
+    pub fn load<P: AsRef<Path>>(dataset_path: P) -> Result<Self, DataError> {
+        Engine::load_with(dataset_path, false)
+    }
+
+    /// `load_with` for a local path or an HTTP(S) URL, which is downloaded
+    /// with its side files first.
+    pub fn load_from(dataset: &str, strict: bool) -> Result<Self, DataError> {
+        if !is_remote(dataset) {
+            return Engine::load_with(dataset, strict);
+        }
+        match fetch_dataset(dataset, None)? {
+            Some(fetched) => Engine::load_with(&fetched.path, strict),
+            None => Err(DataError::Corrupt(format!("{dataset} sent no dataset"))),
+        }
+    }
+
+    /// Like `load`, but when `strict` every anomaly `load` would log, and a
+    /// failed self-check, rejects the dataset with `DataError::Strict`.
+    pub fn load_with<P: AsRef<Path>>(dataset_path: P, strict: bool) -> Result<Self, DataError> {
+        let path = dataset_path.as_ref();
+        let bytes = fs::read(path)?;
+        let engine = if is_bundle(&bytes) {
+            Engine::adopt_bundle(deserialize_bundle(&bytes)?, strict)?
+        } else {
+            Engine::load_graph_file(path, &bytes, strict)?
+        };
+        if strict {
+            let duplicates = engine.graph.duplicate_names().len();
+            if duplicates > 0 {
+                return Err(DataError::Strict(format!(
+                    "{duplicates} system names are shared by several systems"
+                )));
+            }
+            let report = engine.self_check();
+            if let Some(failed) = report.checks.iter().find(|c| !c.passed) {
+                return Err(DataError::Strict(format!(
+                    "self-check {} failed: {}",
+                    failed.name,
+                    failed.detail.as_deref().unwrap_or("no detail")
+                )));
+            }
+        }
+        Ok(engine)
+    }
+
+    fn load_graph_file(path: &Path, bytes: &[u8], strict: bool) -> Result<Self, DataError> {
+        if !is_packed_graph(bytes) {
+            anomaly(
+                strict,
+                format!("{} uses the legacy unpacked layout", path.display()),
+            )?;
+        }
+        let graph = deserialize_graph(bytes)?;
+        let metadata = match read_metadata_from_file(metadata_path_for(path)) {
+            Ok(metadata) => Some(metadata),
+            Err(err) => {
+                anomaly(
+                    strict,
+                    format!(
+                        "No usable dataset metadata next to {}: {err}",
+                        path.display()
+                    ),
+                )?;
+                None
+            }
+        };
+        let labels_path = hub_labels_path_for(path);
+        let hub_labels = if labels_path.exists() {
+            match read_hub_labels_from_file(&labels_path) {
+                Ok(labels) if labels.len() == graph.len() => Some(labels),
+                Ok(labels) => {
+                    anomaly(
+                        strict,
+                        format!(
+                            "Ignoring hub labels for {} systems; the dataset has {}",
+                            labels.len(),
+                            graph.len()
+                        ),
+                    )?;
+                    None
+                }
+                Err(err) => {
+                    anomaly(
+                        strict,
+                        format!("Unreadable hub labels {}: {err}", labels_path.display()),
+                    )?;
+                    None
+                }
+            }
+        } else {
+            None
+        };
+        let mut engine = Engine::new(graph, metadata);
+        engine.hub_labels = hub_labels;
+        Ok(engine)
+    }
+
+    /// An engine over a bundle's dataset, adopting the bundled analyses that
+    /// match it and the bundled overlays.
+    pub fn from_bundle(bundle: EngineBundle) -> Self {
+        Engine::adopt_bundle(bundle, false).expect("lenient loading does not fail")
+    }
+
+    fn adopt_bundle(bundle: EngineBundle, strict: bool) -> Result<Self, DataError> {
+        let systems = bundle.graph.len();
+        if bundle.metadata.is_none() {
+            anomaly(strict, "Bundle carries no dataset metadata".to_string())?;
+        }
+        let kd = match bundle.kd_tree {
+            Some(tree) if tree.len() == systems => Some(tree),
+            Some(tree) => {
+                anomaly(
+                    strict,
+                    format!("Ignoring bundled k-d tree over {} points", tree.len()),
+                )?;
+                None
+            }
+            None => None,
+        };
+        let mut engine = Engine::with_kd(bundle.graph, bundle.metadata, kd);
+        match bundle.morton_index {
+            Some(index) if index.len() == systems => {
+                let _ = engine.morton.set(index);
+            }
+            Some(index) => anomaly(
+                strict,
+                format!("Ignoring bundled Morton index over {} points", index.len()),
+            )?,
+            None => {}
+        }
+        match bundle.hub_labels {
+            Some(labels) if labels.len() == systems => engine.hub_labels = Some(labels),
+            Some(labels) => anomaly(
+                strict,
+                format!("Ignoring bundled hub labels for {} systems", labels.len()),
+            )?,
+            None => {}
+        }
+        match bundle.jump_matrix {
+            Some(matrix) if matrix.len() == systems => {
+                let _ = engine.jump_matrix.set(Some(matrix));
+            }
+            Some(matrix) => anomaly(
+                strict,
+                format!("Ignoring bundled jump matrix for {} systems", matrix.len()),
+            )?,
+            None => {}
+        }
+        if let Some(tags) = bundle.tags {
+            engine.tags = Arc::new(RwLock::new(tags));
+        }
+        if let Some(closed) = bundle.closed_gates {
+            engine.closed_gates = closed;
+        }
+        Ok(engine)
+    }
+
+    /// Fewest gate jumps from `from` to `to` using the cheapest source
+    /// available: loaded hub labels, the jump matrix (built on first use),
+    /// or a breadth-first search.
+    pub fn jumps(&self, from: usize, to: usize) -> Option<usize> {
+        if !self.may_reach(from, to) {
+            return None;
+        }
+        if let Some(labels) = &self.hub_labels {
+            return labels.jumps(from, to).map(usize::from);
+        }
+        if let Some(matrix) = self.jump_matrix() {
+            return matrix.jumps(from, to).map(usize::from);
+        }
+        match shortest_gate_path_within(&self.graph, from, to, usize::MAX) {
+            Ok(path) => Some(path.len() - 1),
+            Err(PathError::SameSystem) => Some(0),
+            Err(_) => None,
+        }
+    }
+
+    /// Frame the loaded positions are expressed in. Datasets without
+    /// metadata are assumed to use the default raw frame.
+    pub fn frame(&self) -> CoordinateFrame {
+        self.metadata.as_ref().map(|m| m.frame).unwrap_or_default()
+    }
+
+    /// Capabilities of this engine. Cheap: lazily built indices are reported
+    /// as available without being built.
+    pub fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            query_kinds: QUERY_KINDS.to_vec(),
+            travel_modes: vec![TravelMode::Gates, TravelMode::JumpDrive, TravelMode::Hybrid],
+            route_preferences: vec![
+                RoutePreference::FewestJumps,
+                RoutePreference::ShortestDistance,
+                RoutePreference::Safest,
+            ],
+            risk_overlay: false,
+            regions: false,
+            jump_matrix: self.graph.len() <= self.config.jump_matrix_max_systems,
+            landmarks: self.config.landmark_count > 0,
+            hub_labels: self.hub_labels.is_some(),
+            recording: cfg!(feature = "recording"),
+            datasets: std::iter::once(CURRENT_SNAPSHOT.to_string())
+                .chain(self.snapshots.keys().cloned())
+                .collect(),
+        }
+    }
+
+    /// Result of the first `self_check` run, computed once and reused.
+    pub fn startup_check(&self) -> &SelfCheckReport {
+        self.startup_check.get_or_init(|| self.self_check())
+    }
+
+    /// Runs structural checks plus every canary recorded in the dataset
+    /// metadata, so a silently corrupt dataset is caught right after loading.
+    pub fn self_check(&self) -> SelfCheckReport {
+        let mut checks = vec![self.check_adjacency()];
+        if let Some(metadata) = &self.metadata {
+            checks.push(self.check_counts(metadata));
+            checks.extend(metadata.canaries.iter().map(|c| self.check_canary(c)));
+        }
+        SelfCheckReport {
+            passed: checks.iter().all(|c| c.passed),
+            checks,
+        }
+    }
+
+    fn check_adjacency(&self) -> CheckResult {
+        let n = self.graph.len();
+        let bad = self
+            .graph
+            .adjacency
+            .iter()
+            .flatten()
+            .filter(|&&idx| idx >= n)
+            .count();
+        check(
+            "adjacency_in_range",
+            bad == 0,
+            format!("{bad} edges point outside the system table"),
+        )
+    }
+
+    fn check_counts(&self, metadata: &DatasetMetadata) -> CheckResult {
+        let (systems, edges) = (self.graph.len(), self.graph.edge_count());
+        check(
+            "metadata_counts",
+            systems == metadata.systems && edges == metadata.directed_edges,
+            format!(
+                "expected {} systems / {} edges, found {systems} / {edges}",
+                metadata.systems, metadata.directed_edges
+            ),
+        )
+    }
+
+    fn check_canary(&self, canary: &Canary) -> CheckResult {
+        match canary {
+            Canary::Path {
+                start_id,
+                end_id,
+                jumps,
+            } => {
+                let name = format!("path_{start_id}_{end_id}");
+                let (Some(start), Some(goal)) = (
+                    self.graph.index_of_id(*start_id),
+                    self.graph.index_of_id(*end_id),
+                ) else {
+                    return check(&name, false, "canary systems missing".into());
+                };
+                let found = shortest_gate_path(&self.graph, start, goal)
+                    .ok()
+                    .map(|p| p.len() - 1);
+                check(
+                    &name,
+                    found == Some(*jumps),
+                    format!("expected {jumps} jumps, found {found:?}"),
+                )
+            }
+            Canary::Nearest {
+                system_id,
+                radius,
+                expected_ids,
+            } => {
+                let name = format!("nearest_{system_id}");
+                let Some(idx) = self.graph.index_of_id(*system_id) else {
+                    return check(&name, false, "canary system missing".into());
+                };
+                let origin = self.graph.systems[idx].pos;
+                let found: Vec<u32> = self
+                    .kd
+                    .nearest_n_within_radius(origin, *radius, expected_ids.len())
+                    .into_iter()
+                    .map(|(i, _)| self.graph.systems[i].id)
+                    .collect();
+                check(
+                    &name,
+                    &found == expected_ids,
+                    format!("expected {expected_ids:?}, found {found:?}"),
+                )
+            }
+        }
+    }
+}
+
+/// Tiny demo graph used when no dataset is configured.
+pub fn demo_graph() -> StarGraph {
+    let systems = vec![
+        System {
+            id: 1,
+            name: "A".into(),
+            pos: [0.0, 0.0, 0.0],
+        },
+        System {
+            id: 2,
+            name: "B".into(),
+            pos: [1.0, 0.0, 0.0],
+        },
+        System {
+            id: 3,
+            name: "C".into(),
+            pos: [2.0, 0.0, 0.0],
+        },
+        System {
+            id: 4,
+            name: "D".into(),
+            pos: [0.0, 2.0, 0.0],
+        },
+    ];
+    let adjacency = vec![
+        vec![1, 3], // A -> B, D
+        vec![0, 2], // B -> A, C
+        vec![1],    // C -> B
+        vec![0],    // D -> A
+    ];
+    StarGraph::new(systems, adjacency)
+}
+
+/// Test graph with a system at each of `positions`, named `S0`, `S1`, ...
+/// with ids counting from 1.
+#[cfg(test)]
+pub(crate) fn graph_from(positions: &[[Scalar; 3]], adjacency: Vec<Vec<usize>>) -> StarGraph {
+    let systems = positions
+        .iter()
+        .enumerate()
+        .map(|(i, &pos)| System {
+            id: i as u32 + 1,
+            name: format!("S{i}"),
+            pos,
+        })
+        .collect();
+    StarGraph::new(systems, adjacency)
+}
+
+fn check(name: &str, passed: bool, failure_detail: String) -> CheckResult {
+    CheckResult {
+        name: name.to_string(),
+        passed,
+        detail: (!passed).then_some(failure_detail),
+    }
+}
+
+/// POI grid cells about one gate long, the typical radius of POI queries.
+fn poi_cell_size(graph: &StarGraph) -> Scalar {
+    let longest = graph.max_gate_length();
+    if longest.is_normal() {
+        longest
+    } else {
+        1.0
+    }
+}
+
+/// Logs `message` when loading leniently; rejects the load with it when
+/// `strict`.
+fn anomaly(strict: bool, message: String) -> Result<(), DataError> {
+    if strict {
+        return Err(DataError::Strict(message));
+    }
+    log::warn!("{message}");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::{write_graph_to_file, write_metadata_to_file};
+
+    fn metadata_with(canaries: Vec<Canary>) -> DatasetMetadata {
+        DatasetMetadata {
+            release_tag: "test".into(),
+            asset_name: "test.db".into(),
+            asset_url: String::new(),
+            systems: 3,
+            directed_edges: 4,
+            generated_at_epoch: 0,
+            frame: CoordinateFrame::default(),
+            canaries,
+            neighbour_distances: None,
+        }
+    }
+
+    /// Systems 1 - 2 - 3 one unit apart.
+    fn line_of_three() -> StarGraph {
+        let positions = [1.0, 2.0, 3.0].map(|x| [x, 0.0, 0.0]);
+        graph_from(&positions, vec![vec![1], vec![0, 2], vec![1]])
+    }
+
+    #[test]
+    fn self_check_runs_canaries() {
+        let good = Engine::new(
+            line_of_three(),
+            Some(metadata_with(vec![
+                Canary::Path {
+                    start_id: 1,
+                    end_id: 3,
+                    jumps: 2,
+                },
+                Canary::Nearest {
+                    system_id: 1,
+                    radius: 5.0,
+                    expected_ids: vec![1, 2],
+                },
+            ])),
+        );
+        let report = good.self_check();
+        assert!(report.passed, "{report:?}");
+        assert_eq!(report.checks.len(), 4);
+    }
+
+    #[test]
+    fn self_check_reports_failing_canaries() {
+        let bad = Engine::new(
+            line_of_three(),
+            Some(metadata_with(vec![Canary::Path {
+                start_id: 1,
+                end_id: 3,
+                jumps: 1,
+            }])),
+        );
+        let report = bad.self_check();
+        assert!(!report.passed);
+        assert!(report.checks[2].detail.is_some());
+    }
+
+    #[test]
+    fn strict_loading_rejects_what_lenient_loading_logs() {
+        let dir = tempfile::tempdir().expect("temp dir");
+        let path = dir.path().join("starmap.bin");
+        write_graph_to_file(&demo_graph(), &path).unwrap();
+
+        // No metadata next to the graph.
+        assert!(Engine::load(&path).is_ok());
+        assert!(matches!(
+            Engine::load_with(&path, true),
+            Err(DataError::Strict(_))
+        ));
+
+        let mut metadata = metadata_with(Vec::new());
+        metadata.systems = 4;
+        metadata.directed_edges = 6;
+        write_metadata_to_file(&metadata, metadata_path_for(&path)).unwrap();
+        let config = EngineConfig {
+            dataset: Some(path.display().to_string()),
+            strict: true,
+            ..EngineConfig::default()
+        };
+        assert!(Engine::from_config(config.clone()).is_ok());
+
+        // Metadata from another version of the dataset fails the self-check.
+        metadata.systems = 5;
+        write_metadata_to_file(&metadata, metadata_path_for(&path)).unwrap();
+        assert!(Engine::load(&path).is_ok());
+        assert!(Engine::from_config(config).is_err());
+
+        let dev = Engine::from_config(EngineConfig::default()).unwrap();
+        assert_eq!(dev.graph.len(), 4);
+        let strict = EngineConfig {
+            strict: true,
+            ..EngineConfig::default()
+        };
+        assert!(Engine::from_config(strict).is_err());
+    }
+}
@@ -0,0 +1,224 @@
+//! GeoJSON-like 3D feature export for query results. The shape follows
+//! GeoJSON (RFC 7946) with three-element coordinates, which WebGL map
+//! frontends consume directly.
+
+use std::collections::HashSet;
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::api::{EngineResponse, PathResult};
+use crate::graph::graph::StarGraph;
+use crate::spatial::mst::SpanningTree;
+use crate::Scalar;
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "type", rename = "FeatureCollection")]
+pub struct FeatureCollection {
+    pub features: Vec<Feature>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "type", rename = "Feature")]
+pub struct Feature {
+    pub geometry: Geometry,
+    pub properties: Map<String, Value>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "type")]
+pub enum Geometry {
+    Point { coordinates: [Scalar; 3] },
+    LineString { coordinates: Vec<[Scalar; 3]> },
+}
+
+/// Builds one point feature per result item, using the item's serialized
+/// fields as properties. The item's `position` is used as the coordinates
+/// when present, otherwise its `id` is looked up in the graph. Items without
+/// a known id are skipped.
+pub fn point_features<T: Serialize>(graph: &StarGraph, items: &[T]) -> Vec<Feature> {
+    items
+        .iter()
+        .enumerate()
+        .filter_map(|(order, item)| {
+            let Ok(Value::Object(mut properties)) = serde_json::to_value(item) else {
+                return None;
+            };
+            let coordinates = match properties.remove("position") {
+                Some(position) => serde_json::from_value(position).ok()?,
+                None => {
+                    let id = properties.get("id")?.as_u64()? as u32;
+                    graph.systems[graph.index_of_id(id)?].pos
+                }
+            };
+            properties.insert("order".into(), order.into());
+            Some(Feature {
+                geometry: Geometry::Point { coordinates },
+                properties,
+            })
+        })
+        .collect()
+}
+
+/// Joins the point features, in order, into a single line feature.
+pub fn line_feature(points: &[Feature], properties: Map<String, Value>) -> Feature {
+    let coordinates = points
+        .iter()
+        .filter_map(|f| match f.geometry {
+            Geometry::Point { coordinates } => Some(coordinates),
+            Geometry::LineString { .. } => None,
+        })
+        .collect();
+    Feature {
+        geometry: Geometry::LineString { coordinates },
+        properties,
+    }
+}
+
+/// Converts a query response into a feature collection. Nearest results
+/// become points; path and sweep results become points plus the connecting
+/// line. Responses without systems convert to `None`.
+pub fn response_to_features(
+    graph: &StarGraph,
+    response: &EngineResponse,
+) -> Option<FeatureCollection> {
+    let mut line_properties = Map::new();
+    let (points, with_line) = match response {
+        EngineResponse::Nearest { systems, .. } => (point_features(graph, systems), false),
+        EngineResponse::Hubs { systems } => (point_features(graph, systems), false),
+        EngineResponse::Midpoint { systems, .. } => (point_features(graph, systems), false),
+        EngineResponse::Unvisited {
+            by_jumps,
+            by_distance,
+        } => {
+            let mut points = point_features(graph, by_jumps);
+            points.extend(point_features(graph, by_distance));
+            (points, false)
+        }
+        EngineResponse::Path { systems, .. } => {
+            if let Some(last) = systems.last() {
+                line_properties.insert("total_cost".into(), last.cumulative_cost.into());
+            }
+            (point_features(graph, systems), true)
+        }
+        EngineResponse::Alternatives { routes } => {
+            let routes = routes.iter().map(|route| {
+                let mut properties = Map::new();
+                properties.insert("jumps".into(), route.jumps.into());
+                (properties, route.systems.as_slice())
+            });
+            return Some(route_features(graph, routes));
+        }
+        EngineResponse::Pareto { routes } => {
+            let routes = routes.iter().map(|route| {
+                let mut properties = Map::new();
+                properties.insert("jumps".into(), route.jumps.into());
+                properties.insert("distance".into(), route.distance.into());
+                (properties, route.systems.as_slice())
+            });
+            return Some(route_features(graph, routes));
+        }
+        EngineResponse::Sweep {
+            systems,
+            total_distance,
+            longest_leg,
+            avoided,
+            ..
+        } => {
+            line_properties.insert("total_distance".into(), (*total_distance).into());
+            line_properties.insert("longest_leg".into(), (*longest_leg).into());
+            if !avoided.is_empty() {
+                let ids: Vec<u32> = avoided.iter().map(|s| s.id).collect();
+                line_properties.insert("avoided_ids".into(), ids.into());
+            }
+            (point_features(graph, systems), true)
+        }
+        _ => return None,
+    };
+
+    let mut features = Vec::with_capacity(points.len() + 1);
+    if with_line && points.len() > 1 {
+        features.push(line_feature(&points, line_properties));
+    }
+    features.extend(points);
+    Some(FeatureCollection { features })
+}
+
+/// One line per spanning-tree edge between systems, with its `length`, the
+/// system ids and whether a gate already joins them; edges without a gate
+/// are candidate expansions of the gate network.
+pub fn spanning_tree_features(graph: &StarGraph, tree: &SpanningTree) -> FeatureCollection {
+    let features = tree
+        .edges
+        .iter()
+        .map(|edge| {
+            let (a, b) = (&graph.systems[edge.a], &graph.systems[edge.b]);
+            let mut properties = Map::new();
+            properties.insert("from_id".into(), a.id.into());
+            properties.insert("to_id".into(), b.id.into());
+            properties.insert("length".into(), edge.length.into());
+            let gated = graph.adjacency[edge.a].contains(&edge.b)
+                || graph.adjacency[edge.b].contains(&edge.a);
+            properties.insert("existing_gate".into(), gated.into());
+            Feature {
+                geometry: Geometry::LineString {
+                    coordinates: vec![a.pos, b.pos],
+                },
+                properties,
+            }
+        })
+        .collect();
+    FeatureCollection { features }
+}
+
+/// One line per route, numbered in `route`, then each system once.
+fn route_features<'a>(
+    graph: &StarGraph,
+    routes: impl Iterator<Item = (Map<String, Value>, &'a [PathResult])>,
+) -> FeatureCollection {
+    let mut features = Vec::new();
+    let mut seen = HashSet::new();
+    let mut points = Vec::new();
+    for (i, (mut properties, systems)) in routes.enumerate() {
+        let route_points = point_features(graph, systems);
+        properties.insert("route".into(), i.into());
+        features.push(line_feature(&route_points, properties));
+        points.extend(
+            systems
+                .iter()
+                .zip(route_points)
+                .filter(|(s, _)| seen.insert(s.id))
+                .map(|(_, p)| p),
+        );
+    }
+    features.extend(points);
+    FeatureCollection { features }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::{handle_request, EngineRequest};
+    use crate::engine::{demo_graph, Engine};
+
+    #[test]
+    fn path_response_exports_points_and_line() {
+        let engine = Engine::new(demo_graph(), None);
+        let request: EngineRequest =
+            serde_json::from_str(r#"{"kind":"path","start_id":1,"end_id":3}"#).unwrap();
+        let response = handle_request(&engine, request).response;
+        let collection = response_to_features(&engine.graph, &response).expect("features");
+
+        assert_eq!(collection.features.len(), 4);
+        assert_eq!(
+            collection.features[0].geometry,
+            Geometry::LineString {
+                coordinates: vec![[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [2.0, 0.0, 0.0]]
+            }
+        );
+        let json = serde_json::to_value(&collection).unwrap();
+        assert_eq!(json["type"], "FeatureCollection");
+        assert_eq!(json["features"][1]["geometry"]["type"], "Point");
+        assert_eq!(json["features"][3]["properties"]["name"], "C");
+    }
+}
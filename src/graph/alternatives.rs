@@ -0,0 +1,120 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::budget::BudgetExceeded;
+use crate::graph::graph::StarGraph;
+use crate::graph::pathfinder::{astar, trivial_path, PathError, PathStep};
+use crate::progress;
+use crate::Scalar;
+
+/// Extra cost added to a gate each time an earlier alternative used it.
+const REUSE_PENALTY: Scalar = 1.0;
+/// Alternatives may take at most this factor of the fewest jumps.
+const MAX_STRETCH: Scalar = 1.5;
+/// Alternatives sharing more than this fraction of their gates with an
+/// already chosen route are dropped as too similar.
+const MAX_OVERLAP: Scalar = 0.7;
+/// Searches run per requested route before giving up on finding more.
+const ATTEMPTS_PER_ROUTE: usize = 3;
+
+/// Up to `count` meaningfully different gate routes from `start` to
+/// `goal`, the fewest-jumps route first. Each further route comes from
+/// rerunning the search with every gate used so far made more expensive,
+/// keeping only routes that are not much longer than the best and do not
+/// mostly retrace one already chosen. Step costs are jump counts. Fails
+/// only when a search runs out of the running budget.
+pub fn alternative_routes(
+    graph: &StarGraph,
+    start: usize,
+    goal: usize,
+    count: usize,
+) -> Result<Vec<Vec<PathStep>>, BudgetExceeded> {
+    let mut routes: Vec<Vec<PathStep>> = Vec::new();
+    let mut route_gates: Vec<HashSet<(usize, usize)>> = Vec::new();
+    let mut uses: HashMap<(usize, usize), u32> = HashMap::new();
+    let mut max_jumps = usize::MAX;
+    for _ in 0..count.saturating_mul(ATTEMPTS_PER_ROUTE) {
+        if routes.len() == count {
+            break;
+        }
+        progress::report(routes.len(), count);
+        let cost = |from: usize, to: usize| {
+            let reused = uses.get(&gate(from, to)).copied().unwrap_or(0);
+            Some(1.0 + REUSE_PENALTY * reused as Scalar)
+        };
+        let mut route = match astar(graph, start, goal, cost, |_| 0.0) {
+            Ok(route) => route,
+            Err(PathError::SameSystem) => trivial_path(graph, start),
+            Err(PathError::BudgetExceeded(exceeded)) => return Err(exceeded),
+            Err(_) => break,
+        };
+        let jumps = route.len() - 1;
+        if routes.is_empty() {
+            max_jumps = (jumps as Scalar * MAX_STRETCH).floor() as usize;
+        } else if jumps > max_jumps {
+            break;
+        }
+
+        let gates: HashSet<(usize, usize)> = route
+            .windows(2)
+            .map(|w| gate(w[0].system_index, w[1].system_index))
+            .collect();
+        for &g in &gates {
+            *uses.entry(g).or_default() += 1;
+        }
+        if gates.is_empty() && !routes.is_empty() {
+            break;
+        }
+        let similar = route_gates.iter().any(|chosen| {
+            let shared = chosen.intersection(&gates).count();
+            shared as Scalar > MAX_OVERLAP * chosen.len().min(gates.len()) as Scalar
+        });
+        if similar {
+            continue;
+        }
+        for (jumps, step) in route.iter_mut().enumerate() {
+            step.cost = jumps as Scalar;
+        }
+        routes.push(route);
+        route_gates.push(gates);
+    }
+    Ok(routes)
+}
+
+/// Gates are penalized in both directions, so an alternative does not just
+/// retrace an earlier route backwards.
+fn gate(a: usize, b: usize) -> (usize, usize) {
+    (a.min(b), a.max(b))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::graph_from;
+
+    #[test]
+    fn alternatives_take_different_gates() {
+        // Two 2-jump routes from 0 to 2 (via 1 or via 3) and a 3-jump one
+        // (via 4 and 5).
+        let adjacency = vec![
+            vec![1, 3, 4],
+            vec![0, 2],
+            vec![1, 3, 5],
+            vec![0, 2],
+            vec![0, 5],
+            vec![4, 2],
+        ];
+        let graph = graph_from(&[[0.0; 3]; 6], adjacency);
+
+        let routes = alternative_routes(&graph, 0, 2, 3).unwrap();
+        let indices: Vec<Vec<usize>> = routes
+            .iter()
+            .map(|r| r.iter().map(|s| s.system_index).collect())
+            .collect();
+        assert_eq!(indices.len(), 3);
+        assert_eq!(indices[0].len(), 3);
+        assert_eq!(indices[1].len(), 3);
+        assert_ne!(indices[0], indices[1]);
+        assert_eq!(indices[2], vec![0, 4, 5, 2]);
+        assert_eq!(routes[2].last().unwrap().cost, 3.0);
+    }
+}
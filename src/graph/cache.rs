@@ -0,0 +1,132 @@
+use std::collections::{BTreeMap, HashMap};
+use std::hash::Hash;
+use std::sync::Mutex;
+
+/// Default number of routes kept by `PathCache`.
+pub const DEFAULT_PATH_CACHE_SIZE: usize = 256;
+
+/// Memoizes recent `(start, goal, options)` → route results with least
+/// recently used eviction. Shared between concurrent requests, so all
+/// methods take `&self`.
+#[derive(Debug)]
+pub struct PathCache<O, V> {
+    capacity: usize,
+    inner: Mutex<Lru<(usize, usize, O), V>>,
+}
+
+#[derive(Clone, Debug)]
+struct Lru<K, V> {
+    entries: HashMap<K, (V, u64)>,
+    /// Last use tick → key, oldest first.
+    order: BTreeMap<u64, K>,
+    tick: u64,
+}
+
+impl<O, V> PathCache<O, V>
+where
+    O: Clone + Eq + Hash,
+    V: Clone,
+{
+    pub fn new(capacity: usize) -> Self {
+        PathCache {
+            capacity,
+            inner: Mutex::new(Lru {
+                entries: HashMap::new(),
+                order: BTreeMap::new(),
+                tick: 0,
+            }),
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn len(&self) -> usize {
+        self.lock().entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the cached result and marks it as recently used.
+    pub fn get(&self, start: usize, goal: usize, options: &O) -> Option<V> {
+        let mut lru = self.lock();
+        let key = (start, goal, options.clone());
+        lru.tick += 1;
+        let tick = lru.tick;
+        let (value, last_used) = lru.entries.get_mut(&key)?;
+        let value = value.clone();
+        let previous = std::mem::replace(last_used, tick);
+        lru.order.remove(&previous);
+        lru.order.insert(tick, key);
+        Some(value)
+    }
+
+    /// Stores a result, evicting the least recently used entry when full.
+    pub fn insert(&self, start: usize, goal: usize, options: O, value: V) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut lru = self.lock();
+        let key = (start, goal, options);
+        lru.tick += 1;
+        let tick = lru.tick;
+        if let Some((_, previous)) = lru.entries.insert(key.clone(), (value, tick)) {
+            lru.order.remove(&previous);
+        }
+        lru.order.insert(tick, key);
+        while lru.entries.len() > self.capacity {
+            let Some((_, oldest)) = lru.order.pop_first() else {
+                break;
+            };
+            lru.entries.remove(&oldest);
+        }
+    }
+
+    pub fn clear(&self) {
+        let mut lru = self.lock();
+        lru.entries.clear();
+        lru.order.clear();
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Lru<(usize, usize, O), V>> {
+        // A panic while holding the lock cannot leave the maps inconsistent
+        // in a way that matters for a cache, so poisoning is ignored.
+        self.inner
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl<O, V> Clone for PathCache<O, V>
+where
+    O: Clone + Eq + Hash,
+    V: Clone,
+{
+    fn clone(&self) -> Self {
+        PathCache {
+            capacity: self.capacity,
+            inner: Mutex::new(self.lock().clone()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn evicts_least_recently_used() {
+        let cache: PathCache<u8, &str> = PathCache::new(2);
+        cache.insert(0, 1, 0, "a");
+        cache.insert(0, 2, 0, "b");
+        assert_eq!(cache.get(0, 1, &0), Some("a"));
+        cache.insert(0, 3, 0, "c");
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.get(0, 2, &0), None);
+        assert_eq!(cache.get(0, 1, &0), Some("a"));
+        assert_eq!(cache.get(0, 1, &1), None);
+    }
+}
@@ -0,0 +1,58 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::data::DataError;
+
+/// Directed gates routes must not take, as `(from_id, to_id)` pairs: a
+/// camped gate can be closed in one direction while both of its systems stay
+/// usable through other gates. Kept outside the dataset like
+/// `tags::SystemTags`, either as an operator overlay or per request.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct ClosedGates(HashSet<(u32, u32)>);
+
+impl ClosedGates {
+    /// Reads a JSON overlay listing `[from_id, to_id]` pairs.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, DataError> {
+        Ok(serde_json::from_slice(&fs::read(path)?)?)
+    }
+
+    pub fn from_pairs(pairs: &[[u32; 2]]) -> Self {
+        ClosedGates(pairs.iter().map(|&[from, to]| (from, to)).collect())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Whether the gate from system `from_id` to system `to_id` is closed;
+    /// the opposite direction is independent.
+    pub fn contains(&self, from_id: u32, to_id: u32) -> bool {
+        self.0.contains(&(from_id, to_id))
+    }
+
+    /// Adds the gates closed in `other`.
+    pub fn extend(&mut self, other: &ClosedGates) {
+        self.0.extend(other.0.iter().copied());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gates_close_in_one_direction() {
+        let mut file = tempfile::NamedTempFile::new().expect("temp file");
+        std::io::Write::write_all(&mut file, b"[[1, 2], [3, 4]]").unwrap();
+        let mut closed = ClosedGates::load(file.path()).unwrap();
+        assert!(closed.contains(1, 2));
+        assert!(!closed.contains(2, 1));
+        closed.extend(&ClosedGates::from_pairs(&[[2, 1]]));
+        assert!(closed.contains(2, 1));
+        assert!(ClosedGates::default().is_empty());
+    }
+}
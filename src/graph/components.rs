@@ -0,0 +1,138 @@
+use crate::graph::graph::StarGraph;
+
+/// Strongly connected components of the directed gate graph: systems that
+/// can all reach each other. One-way gates split the map into several, and a
+/// route between components only exists in the direction of their gates.
+///
+/// Components are numbered in reverse topological order of the condensed
+/// graph, so a gate from component `a` into component `b` always has
+/// `a > b`. Systems in a lower-numbered component therefore never reach a
+/// higher-numbered one, which `may_reach` checks in constant time.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StronglyConnected {
+    component: Vec<u32>,
+    count: usize,
+}
+
+impl StronglyConnected {
+    /// Tarjan's algorithm with an explicit stack, so deep chains of systems
+    /// cannot overflow the call stack.
+    pub fn build(graph: &StarGraph) -> Self {
+        const UNVISITED: u32 = u32::MAX;
+        let n = graph.len();
+        let mut index = vec![UNVISITED; n];
+        let mut low = vec![0u32; n];
+        let mut on_stack = vec![false; n];
+        let mut stack = Vec::new();
+        let mut component = vec![UNVISITED; n];
+        let mut count = 0;
+        let mut next_index = 0u32;
+        // (system, position in its adjacency list)
+        let mut calls: Vec<(usize, usize)> = Vec::new();
+
+        for root in 0..n {
+            if index[root] != UNVISITED {
+                continue;
+            }
+            calls.push((root, 0));
+            while let Some(&mut (v, ref mut edge)) = calls.last_mut() {
+                if *edge == 0 {
+                    index[v] = next_index;
+                    low[v] = next_index;
+                    next_index += 1;
+                    stack.push(v);
+                    on_stack[v] = true;
+                }
+                if let Some(&w) = graph.adjacency[v].get(*edge) {
+                    *edge += 1;
+                    if index[w] == UNVISITED {
+                        calls.push((w, 0));
+                    } else if on_stack[w] {
+                        low[v] = low[v].min(index[w]);
+                    }
+                    continue;
+                }
+                calls.pop();
+                if let Some(&(parent, _)) = calls.last() {
+                    low[parent] = low[parent].min(low[v]);
+                }
+                if low[v] == index[v] {
+                    while let Some(w) = stack.pop() {
+                        on_stack[w] = false;
+                        component[w] = count as u32;
+                        if w == v {
+                            break;
+                        }
+                    }
+                    count += 1;
+                }
+            }
+        }
+        StronglyConnected { component, count }
+    }
+
+    /// Number of components.
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    /// Component id of system `index`.
+    pub fn component_of(&self, index: usize) -> u32 {
+        self.component[index]
+    }
+
+    /// Component id of every system, indexed like the graph's systems.
+    pub fn components(&self) -> &[u32] {
+        &self.component
+    }
+
+    /// Whether `from` and `to` can reach each other.
+    pub fn same_component(&self, from: usize, to: usize) -> bool {
+        self.component[from] == self.component[to]
+    }
+
+    /// `false` when no route from `from` to `to` can exist. `true` is not a
+    /// guarantee across components, only that the gate order allows one.
+    pub fn may_reach(&self, from: usize, to: usize) -> bool {
+        self.component[from] >= self.component[to]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::{demo_graph, graph_from};
+    use crate::Scalar;
+
+    #[test]
+    fn one_way_gates_split_components() {
+        // Demo gates A<->B<->C, with A -> D made one-way.
+        let mut graph = demo_graph();
+        graph.adjacency[3].clear();
+        graph.rebuild_indices();
+        let scc = StronglyConnected::build(&graph);
+        assert_eq!(scc.len(), 2);
+        assert!(scc.same_component(0, 2));
+        assert!(!scc.same_component(0, 3));
+        assert!(scc.may_reach(2, 3));
+        assert!(!scc.may_reach(3, 2));
+    }
+
+    #[test]
+    fn long_one_way_chains_do_not_overflow_the_stack() {
+        // Every system of the chain is its own component.
+        let n = 100_000;
+        let positions: Vec<[Scalar; 3]> = (0..n).map(|i| [i as Scalar, 0.0, 0.0]).collect();
+        let adjacency = (0..n)
+            .map(|i| if i + 1 < n { vec![i + 1] } else { vec![] })
+            .collect();
+        let chain = StronglyConnected::build(&graph_from(&positions, adjacency));
+        assert_eq!(chain.len(), n);
+        assert!(chain.may_reach(0, n - 1));
+        assert!(!chain.may_reach(n - 1, 0));
+    }
+}
@@ -0,0 +1,87 @@
+use std::collections::HashSet;
+
+use crate::graph::graph::StarGraph;
+use crate::graph::pathfinder::{shortest_gate_paths_batch, PathError};
+
+/// Largest source or target set a `cross_routes` request may name.
+pub const MAX_CROSS_SYSTEMS: usize = 64;
+
+/// Fewest-jumps routes between every source and every target.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CrossRoutes {
+    /// `jumps[i][j]` from `sources[i]` to `targets[j]`; `None` when unreachable.
+    pub jumps: Vec<Vec<Option<usize>>>,
+    /// Per source, the `(from, to)` gates of all its routes, each listed
+    /// once. They form a tree rooted at the source, so the route to any
+    /// target is found by walking back from it.
+    pub gates: Vec<Vec<(usize, usize)>>,
+}
+
+/// Routes for the cross product of `sources` and `targets`. Each distinct
+/// source needs a single breadth-first search, and routes from one source
+/// share their common prefixes instead of repeating them. A source that is
+/// also a target is 0 jumps from itself; any failure other than an
+/// unreachable target, such as a spent budget, fails the whole request.
+pub fn cross_routes(
+    graph: &StarGraph,
+    sources: &[usize],
+    targets: &[usize],
+) -> Result<CrossRoutes, PathError> {
+    let pairs: Vec<(usize, usize)> = sources
+        .iter()
+        .flat_map(|&s| targets.iter().map(move |&t| (s, t)))
+        .collect();
+    let mut paths = shortest_gate_paths_batch(graph, &pairs).into_iter();
+
+    let mut jumps = Vec::with_capacity(sources.len());
+    let mut gates = Vec::with_capacity(sources.len());
+    for _ in sources {
+        let mut row = Vec::with_capacity(targets.len());
+        let mut tree = Vec::new();
+        let mut seen = HashSet::new();
+        for path in paths.by_ref().take(targets.len()) {
+            let path = match path {
+                Ok(path) => Some(path),
+                Err(PathError::NoRoute) => None,
+                Err(PathError::SameSystem) => {
+                    row.push(Some(0));
+                    continue;
+                }
+                Err(err) => return Err(err),
+            };
+            row.push(path.as_ref().map(|p| p.len() - 1));
+            for w in path.iter().flat_map(|p| p.windows(2)) {
+                let gate = (w[0].system_index, w[1].system_index);
+                if seen.insert(gate) {
+                    tree.push(gate);
+                }
+            }
+        }
+        jumps.push(row);
+        gates.push(tree);
+    }
+    Ok(CrossRoutes { jumps, gates })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::demo_graph;
+
+    #[test]
+    fn shared_prefixes_are_listed_once() {
+        // Demo gates: A(0)-B(1)-C(2), A-D(3).
+        let graph = demo_graph();
+        let routes = cross_routes(&graph, &[0, 2], &[1, 2, 3]).unwrap();
+        assert_eq!(
+            routes.jumps,
+            vec![
+                vec![Some(1), Some(2), Some(1)],
+                vec![Some(1), Some(0), Some(3)]
+            ]
+        );
+        // From A, the route to C extends the one to B.
+        assert_eq!(routes.gates[0], vec![(0, 1), (1, 2), (0, 3)]);
+        assert_eq!(routes.gates[1], vec![(2, 1), (1, 0), (0, 3)]);
+    }
+}
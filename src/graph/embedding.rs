@@ -0,0 +1,166 @@
+//! Exports for building graph embeddings outside the engine: the gate
+//! adjacency as a Matrix Market sparse matrix and node2vec-style random walks.
+
+use std::io::{self, Write};
+
+use serde::{Deserialize, Serialize};
+
+use crate::graph::graph::StarGraph;
+use crate::rng::SplitMix64;
+
+/// Writes the adjacency as a Matrix Market `coordinate pattern general`
+/// matrix. Row and column `i + 1` correspond to `graph.systems[i]`; the
+/// header comments list the system ids in that order so the matrix can be
+/// joined back to the dataset.
+pub fn write_matrix_market<W: Write>(graph: &StarGraph, mut out: W) -> io::Result<()> {
+    writeln!(out, "%%MatrixMarket matrix coordinate pattern general")?;
+    writeln!(
+        out,
+        "% starmap gate adjacency; row/column i is system_ids[i]"
+    )?;
+    for chunk in graph.systems.chunks(16) {
+        let ids: Vec<String> = chunk.iter().map(|s| s.id.to_string()).collect();
+        writeln!(out, "% system_ids {}", ids.join(" "))?;
+    }
+    writeln!(
+        out,
+        "{} {} {}",
+        graph.len(),
+        graph.len(),
+        graph.edge_count()
+    )?;
+    for (from, neighbours) in graph.adjacency.iter().enumerate() {
+        for &to in neighbours {
+            writeln!(out, "{} {}", from + 1, to + 1)?;
+        }
+    }
+    Ok(())
+}
+
+/// Parameters for node2vec biased random walks (Grover & Leskovec, 2016).
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq)]
+pub struct WalkConfig {
+    pub walks_per_node: usize,
+    pub walk_length: usize,
+    /// Return parameter: higher values make stepping straight back less likely.
+    pub p: f64,
+    /// In-out parameter: higher values keep walks local, lower values explore.
+    pub q: f64,
+    pub seed: u64,
+}
+
+impl Default for WalkConfig {
+    fn default() -> Self {
+        WalkConfig {
+            walks_per_node: 10,
+            walk_length: 40,
+            p: 1.0,
+            q: 1.0,
+            seed: 0,
+        }
+    }
+}
+
+/// Generates `walks_per_node` walks from every system, each at most
+/// `walk_length` systems long (shorter if a dead end is reached). Walks hold
+/// system indices and are fully determined by the config's seed.
+pub fn random_walks(graph: &StarGraph, config: &WalkConfig) -> Vec<Vec<usize>> {
+    let mut rng = SplitMix64::new(config.seed);
+    let mut walks = Vec::with_capacity(graph.len() * config.walks_per_node);
+    for _ in 0..config.walks_per_node {
+        for start in 0..graph.len() {
+            walks.push(walk_from(graph, start, config, &mut rng));
+        }
+    }
+    walks
+}
+
+fn walk_from(
+    graph: &StarGraph,
+    start: usize,
+    config: &WalkConfig,
+    rng: &mut SplitMix64,
+) -> Vec<usize> {
+    let mut walk = Vec::with_capacity(config.walk_length);
+    if config.walk_length == 0 {
+        return walk;
+    }
+    walk.push(start);
+    while walk.len() < config.walk_length {
+        let current = walk[walk.len() - 1];
+        let neighbours = &graph.adjacency[current];
+        if neighbours.is_empty() {
+            break;
+        }
+        let next = match walk.len().checked_sub(2).map(|i| walk[i]) {
+            None => neighbours[rng.next_index(neighbours.len())],
+            Some(previous) => biased_step(graph, previous, neighbours, config, rng),
+        };
+        walk.push(next);
+    }
+    walk
+}
+
+/// Second-order step: weight 1/p to return to `previous`, 1 for systems also
+/// adjacent to `previous`, 1/q for everything further out.
+fn biased_step(
+    graph: &StarGraph,
+    previous: usize,
+    neighbours: &[usize],
+    config: &WalkConfig,
+    rng: &mut SplitMix64,
+) -> usize {
+    let weights: Vec<f64> = neighbours
+        .iter()
+        .map(|&candidate| {
+            if candidate == previous {
+                1.0 / config.p
+            } else if graph.adjacency[previous].contains(&candidate) {
+                1.0
+            } else {
+                1.0 / config.q
+            }
+        })
+        .collect();
+    let mut pick = rng.next_f64() * weights.iter().sum::<f64>();
+    for (&candidate, weight) in neighbours.iter().zip(weights) {
+        if pick < weight {
+            return candidate;
+        }
+        pick -= weight;
+    }
+    neighbours[neighbours.len() - 1]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::demo_graph;
+
+    #[test]
+    fn matrix_market_and_walks() {
+        let graph = demo_graph();
+        let mut buf = Vec::new();
+        write_matrix_market(&graph, &mut buf).expect("write");
+        let text = String::from_utf8(buf).unwrap();
+        let lines: Vec<&str> = text.lines().filter(|l| !l.starts_with('%')).collect();
+        assert_eq!(lines[0], "4 4 6");
+        assert_eq!(lines[1], "1 2");
+        assert!(text.contains("% system_ids 1 2 3 4"));
+
+        let config = WalkConfig {
+            walks_per_node: 2,
+            walk_length: 5,
+            ..WalkConfig::default()
+        };
+        let walks = random_walks(&graph, &config);
+        assert_eq!(walks.len(), 8);
+        assert!(walks.iter().all(|w| w.len() == 5));
+        for walk in &walks {
+            for pair in walk.windows(2) {
+                assert!(graph.adjacency[pair[0]].contains(&pair[1]));
+            }
+        }
+        assert_eq!(walks, random_walks(&graph, &config));
+    }
+}
@@ -1,13 +1,73 @@
+use std::collections::HashMap;
+
 use serde::{Deserialize, Serialize};
+use thiserror::Error;
 
+use crate::spatial::positions::Positions;
+use crate::Scalar;
 use crate::System;
 
+/// Why a system name did not resolve to a single system.
+#[derive(Clone, Debug, Error, PartialEq, Eq)]
+pub enum NameError {
+    #[error("Unknown system_name {0}")]
+    Unknown(String),
+    #[error("system_name {name} is shared by systems {candidate_ids:?}")]
+    Ambiguous {
+        name: String,
+        candidate_ids: Vec<u32>,
+    },
+}
+
 /// Simple adjacency-list graph over systems.
+///
+/// Derived lookup structures are rebuilt on construction and
+/// deserialization; call `rebuild_indices` after mutating `systems` or
+/// `adjacency` directly.
 #[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(try_from = "StarGraphData")]
 pub struct StarGraph {
     pub systems: Vec<System>,
     /// adjacency[i] lists neighbour indices of systems[i]
     pub adjacency: Vec<Vec<usize>>,
+    /// reverse_adjacency[i] lists systems with a gate into systems[i]
+    #[serde(skip)]
+    reverse_adjacency: Vec<Vec<usize>>,
+    /// Every system index by name; names are not guaranteed unique.
+    #[serde(skip)]
+    name_index: HashMap<String, Vec<usize>>,
+    /// Straight-line length of the longest gate.
+    #[serde(skip)]
+    max_gate_length: Scalar,
+    /// Copy of the system positions laid out for batch distance kernels.
+    #[serde(skip)]
+    positions: Positions,
+}
+
+/// Serialized form of `StarGraph`, validated before indices are built.
+#[derive(Deserialize)]
+struct StarGraphData {
+    systems: Vec<System>,
+    adjacency: Vec<Vec<usize>>,
+}
+
+impl TryFrom<StarGraphData> for StarGraph {
+    type Error = String;
+
+    fn try_from(data: StarGraphData) -> Result<Self, Self::Error> {
+        if data.systems.len() != data.adjacency.len() {
+            return Err(format!(
+                "adjacency has {} entries for {} systems",
+                data.adjacency.len(),
+                data.systems.len()
+            ));
+        }
+        let n = data.systems.len();
+        if let Some(bad) = data.adjacency.iter().flatten().find(|&&idx| idx >= n) {
+            return Err(format!("adjacency references missing system index {bad}"));
+        }
+        Ok(StarGraph::new(data.systems, data.adjacency))
+    }
 }
 
 impl StarGraph {
@@ -17,7 +77,42 @@ impl StarGraph {
             adjacency.len(),
             "adjacency must match systems"
         );
-        StarGraph { systems, adjacency }
+        let mut graph = StarGraph {
+            systems,
+            adjacency,
+            reverse_adjacency: Vec::new(),
+            name_index: HashMap::new(),
+            max_gate_length: 0.0,
+            positions: Positions::default(),
+        };
+        graph.rebuild_indices();
+        graph
+    }
+
+    /// Recomputes the derived lookup structures from `systems` and `adjacency`.
+    pub fn rebuild_indices(&mut self) {
+        let mut reverse = vec![Vec::new(); self.adjacency.len()];
+        for (from, neighbours) in self.adjacency.iter().enumerate() {
+            for &to in neighbours {
+                reverse[to].push(from);
+            }
+        }
+        self.reverse_adjacency = reverse;
+
+        let mut names: HashMap<String, Vec<usize>> = HashMap::new();
+        for (idx, system) in self.systems.iter().enumerate() {
+            names.entry(system.name.clone()).or_default().push(idx);
+        }
+        self.name_index = names;
+
+        self.max_gate_length = self
+            .adjacency
+            .iter()
+            .enumerate()
+            .flat_map(|(from, neighbours)| neighbours.iter().map(move |&to| (from, to)))
+            .map(|(from, to)| self.systems[from].distance(&self.systems[to]))
+            .fold(0.0, Scalar::max);
+        self.positions = self.systems.iter().map(|s| s.pos).collect();
     }
 
     pub fn len(&self) -> usize {
@@ -28,11 +123,99 @@ impl StarGraph {
         self.systems.is_empty()
     }
 
+    /// Straight-line length of the longest gate; 0 without gates. No single
+    /// jump covers more distance, which makes distance divided by it a lower
+    /// bound on the jumps still needed.
+    pub fn max_gate_length(&self) -> Scalar {
+        self.max_gate_length
+    }
+
+    /// System positions, indexed like `systems`, for computing distances
+    /// from one point to many at once.
+    pub fn positions(&self) -> &Positions {
+        &self.positions
+    }
+
+    /// Total number of directed gate edges.
+    pub fn edge_count(&self) -> usize {
+        self.adjacency.iter().map(Vec::len).sum()
+    }
+
+    /// Indices of systems with a gate leading into `systems[index]`.
+    pub fn incoming(&self, index: usize) -> &[usize] {
+        &self.reverse_adjacency[index]
+    }
+
     pub fn index_of_id(&self, id: u32) -> Option<usize> {
         self.systems.iter().position(|s| s.id == id)
     }
 
+    /// Index of the system called `name`, or `None` when no system or more
+    /// than one has that name (see `resolve_name`).
     pub fn index_of_name(&self, name: &str) -> Option<usize> {
-        self.systems.iter().position(|s| s.name == name)
+        self.resolve_name(name).ok()
+    }
+
+    /// Every system called `name`, in index order.
+    pub fn indices_of_name(&self, name: &str) -> &[usize] {
+        self.name_index.get(name).map_or(&[], Vec::as_slice)
+    }
+
+    /// The only system called `name`, or an error listing the ids of all
+    /// candidates when the name is shared.
+    pub fn resolve_name(&self, name: &str) -> Result<usize, NameError> {
+        match self.indices_of_name(name) {
+            [] => Err(NameError::Unknown(name.to_string())),
+            [idx] => Ok(*idx),
+            many => Err(NameError::Ambiguous {
+                name: name.to_string(),
+                candidate_ids: many.iter().map(|&i| self.systems[i].id).collect(),
+            }),
+        }
+    }
+
+    /// Names shared by more than one system with their indices, by name.
+    pub fn duplicate_names(&self) -> Vec<(&str, &[usize])> {
+        let mut duplicates: Vec<(&str, &[usize])> = self
+            .name_index
+            .iter()
+            .filter(|(_, indices)| indices.len() > 1)
+            .map(|(name, indices)| (name.as_str(), indices.as_slice()))
+            .collect();
+        duplicates.sort_unstable();
+        duplicates
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shared_names_are_reported_as_ambiguous() {
+        let system = |id: u32, name: &str| System {
+            id,
+            name: name.into(),
+            pos: [id as Scalar, 0.0, 0.0],
+        };
+        let mut graph = StarGraph::new(
+            vec![system(1, "A"), system(2, "B"), system(3, "A")],
+            vec![vec![], vec![], vec![]],
+        );
+        assert_eq!(graph.resolve_name("B"), Ok(1));
+        assert_eq!(
+            graph.resolve_name("A"),
+            Err(NameError::Ambiguous {
+                name: "A".into(),
+                candidate_ids: vec![1, 3],
+            })
+        );
+        assert_eq!(graph.index_of_name("A"), None);
+        assert_eq!(graph.duplicate_names(), vec![("A", &[0, 2][..])]);
+
+        graph.systems[2].name = "C".into();
+        graph.rebuild_indices();
+        assert_eq!(graph.resolve_name("A"), Ok(0));
+        assert_eq!(graph.resolve_name("Z"), Err(NameError::Unknown("Z".into())));
     }
 }
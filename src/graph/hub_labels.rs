@@ -0,0 +1,194 @@
+use std::cmp::Reverse;
+use std::collections::VecDeque;
+
+use serde::{Deserialize, Serialize};
+
+use crate::graph::graph::StarGraph;
+use crate::Scalar;
+
+/// Most pairs a `jump_distances` request may name.
+pub const MAX_DISTANCE_PAIRS: usize = 10_000;
+
+/// Label entry: a hub's rank and the jumps between the system and that hub.
+type Label = (u32, u16);
+
+/// Hub labels for exact fewest-jump counts between any pair of systems in
+/// time linear in the label sizes, without a graph search. Every system
+/// keeps the hubs it reaches (`out`) and the hubs reaching it (`into`), and
+/// any shortest route from `s` to `t` passes through a hub common to
+/// `out[s]` and `into[t]`.
+///
+/// Built by pruned landmark labeling: one breadth-first search per system
+/// in descending degree order, each stopping wherever earlier hubs already
+/// give the right answer, which keeps labels small on hub-heavy gate
+/// networks. Persist with `data::write_hub_labels_to_file`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct HubLabels {
+    /// Per system, `(hub rank, jumps to the hub)` sorted by rank.
+    out: Vec<Vec<Label>>,
+    /// Per system, `(hub rank, jumps from the hub)` sorted by rank.
+    into: Vec<Vec<Label>>,
+}
+
+impl HubLabels {
+    pub fn build(graph: &StarGraph) -> Self {
+        let n = graph.len();
+        let mut order: Vec<usize> = (0..n).collect();
+        order.sort_by_key(|&v| {
+            (
+                Reverse(graph.adjacency[v].len() + graph.incoming(v).len()),
+                v,
+            )
+        });
+
+        let mut labels = HubLabels {
+            out: vec![Vec::new(); n],
+            into: vec![Vec::new(); n],
+        };
+        // Jumps between the current root and each hub rank, from its own labels.
+        let mut root_hubs = vec![u16::MAX; n];
+        let mut seen = vec![false; n];
+        let mut queue = VecDeque::new();
+        for (rank, &root) in order.iter().enumerate() {
+            for forward in [true, false] {
+                let root_labels = if forward {
+                    &labels.out[root]
+                } else {
+                    &labels.into[root]
+                };
+                for &(hub, d) in root_labels {
+                    root_hubs[hub as usize] = d;
+                }
+
+                let mut reached = vec![root];
+                seen[root] = true;
+                queue.push_back((root, 0u16));
+                while let Some((v, d)) = queue.pop_front() {
+                    let target = if forward {
+                        &mut labels.into[v]
+                    } else {
+                        &mut labels.out[v]
+                    };
+                    let known = target
+                        .iter()
+                        .map(|&(hub, dv)| root_hubs[hub as usize].saturating_add(dv))
+                        .min();
+                    if known.is_some_and(|k| k <= d) {
+                        continue;
+                    }
+                    target.push((rank as u32, d));
+                    let next = if forward {
+                        &graph.adjacency[v]
+                    } else {
+                        graph.incoming(v)
+                    };
+                    for &w in next {
+                        if !seen[w] {
+                            seen[w] = true;
+                            reached.push(w);
+                            queue.push_back((w, d.saturating_add(1)));
+                        }
+                    }
+                }
+
+                for v in reached {
+                    seen[v] = false;
+                }
+                let root_labels = if forward {
+                    &labels.out[root]
+                } else {
+                    &labels.into[root]
+                };
+                for &(hub, _) in root_labels {
+                    root_hubs[hub as usize] = u16::MAX;
+                }
+            }
+        }
+        labels
+    }
+
+    /// Number of systems covered.
+    pub fn len(&self) -> usize {
+        self.out.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.out.is_empty()
+    }
+
+    /// Mean number of entries per system and direction.
+    pub fn mean_label_size(&self) -> Scalar {
+        let total: usize = self.out.iter().chain(&self.into).map(Vec::len).sum();
+        total as Scalar / (2 * self.len()).max(1) as Scalar
+    }
+
+    /// Fewest gate jumps from `from` to `to`, or `None` if unreachable.
+    pub fn jumps(&self, from: usize, to: usize) -> Option<u16> {
+        let (a, b) = (&self.out[from], &self.into[to]);
+        let (mut i, mut j) = (0, 0);
+        let mut best = None;
+        while i < a.len() && j < b.len() {
+            match a[i].0.cmp(&b[j].0) {
+                std::cmp::Ordering::Less => i += 1,
+                std::cmp::Ordering::Greater => j += 1,
+                std::cmp::Ordering::Equal => {
+                    let d = a[i].1.saturating_add(b[j].1);
+                    best = Some(best.map_or(d, |b: u16| b.min(d)));
+                    i += 1;
+                    j += 1;
+                }
+            }
+        }
+        best
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::graph_from;
+    use crate::graph::matrix::JumpMatrix;
+
+    #[test]
+    fn labels_match_breadth_first_search() {
+        // A 7x7 grid with every fourth gate one-way and a detached pair.
+        let width = 7;
+        let n = width * width + 2;
+        let positions: Vec<[Scalar; 3]> = (0..n)
+            .map(|i| [(i % width) as Scalar, (i / width) as Scalar, 0.0])
+            .collect();
+        let mut adjacency = vec![Vec::new(); n];
+        let mut gate = 0;
+        for v in 0..width * width {
+            let (x, y) = (v % width, v / width);
+            for w in [
+                (x + 1 < width).then(|| v + 1),
+                (y + 1 < width).then(|| v + width),
+            ]
+            .into_iter()
+            .flatten()
+            {
+                adjacency[v].push(w);
+                if gate % 4 != 0 {
+                    adjacency[w].push(v);
+                }
+                gate += 1;
+            }
+        }
+        adjacency[n - 2].push(n - 1);
+        let graph = graph_from(&positions, adjacency);
+
+        let labels = HubLabels::build(&graph);
+        let matrix = JumpMatrix::build(&graph, n).unwrap();
+        for from in 0..n {
+            for to in 0..n {
+                assert_eq!(
+                    labels.jumps(from, to),
+                    matrix.jumps(from, to),
+                    "{from} -> {to}"
+                );
+            }
+        }
+        assert!(labels.mean_label_size() < n as Scalar / 2.0);
+    }
+}
@@ -0,0 +1,271 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex, PoisonError};
+
+use once_cell::sync::OnceCell;
+use serde::{Deserialize, Serialize};
+
+use crate::graph::graph::StarGraph;
+use crate::graph::pathfinder::{
+    astar_over, check_endpoints, Path, PathError, RoutePreference, StepMode,
+};
+use crate::spatial::kd_tree::KDTree;
+use crate::Scalar;
+
+/// How a ship moves between systems.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TravelMode {
+    /// Only along the gate network.
+    #[default]
+    Gates,
+    /// Direct jumps to any system within the ship's jump range, ignoring gates.
+    JumpDrive,
+    /// Gates and drive jumps mixed, whichever is cheaper per leg.
+    Hybrid,
+}
+
+/// Ranges whose `JumpNeighbours` an engine keeps.
+pub const JUMP_NEIGHBOUR_RANGES: usize = 4;
+
+/// Systems within one jump range (dataset units) of each system, looked up
+/// in the k-d tree the first time a system is expanded and kept, so repeated
+/// routes at the same range do not redo the radius query on every
+/// expansion of every search.
+#[derive(Debug)]
+pub struct JumpNeighbours {
+    range: Scalar,
+    lists: Vec<OnceCell<Hops>>,
+}
+
+/// `(system, distance)` of the systems one jump away.
+type Hops = Box<[(u32, Scalar)]>;
+
+impl JumpNeighbours {
+    pub fn new(systems: usize, range: Scalar) -> Self {
+        JumpNeighbours {
+            range,
+            lists: (0..systems).map(|_| OnceCell::new()).collect(),
+        }
+    }
+
+    pub fn range(&self) -> Scalar {
+        self.range
+    }
+
+    /// The other systems within range of system `index` and their
+    /// distances, nearest first. `kd` must be built over the positions of
+    /// `graph.systems` in order.
+    pub fn of(&self, graph: &StarGraph, kd: &KDTree, index: usize) -> &[(u32, Scalar)] {
+        self.lists[index].get_or_init(|| {
+            kd.nearest_n_within_radius(graph.systems[index].pos, self.range, graph.len())
+                .into_iter()
+                .filter(|&(next, _)| next != index)
+                .map(|(next, d)| (next as u32, d))
+                .collect()
+        })
+    }
+}
+
+/// The `JumpNeighbours` of the `JUMP_NEIGHBOUR_RANGES` most recently used
+/// ranges, shared by concurrent requests.
+#[derive(Debug, Default)]
+pub struct JumpNeighbourCache {
+    /// Most recently used first.
+    ranges: Mutex<VecDeque<Arc<JumpNeighbours>>>,
+}
+
+impl JumpNeighbourCache {
+    /// The neighbour lists for `range` over `systems` systems, new or kept.
+    pub fn get(&self, systems: usize, range: Scalar) -> Arc<JumpNeighbours> {
+        let mut ranges = self.ranges.lock().unwrap_or_else(PoisonError::into_inner);
+        let found = ranges
+            .iter()
+            .position(|n| n.range.to_bits() == range.to_bits() && n.lists.len() == systems);
+        let neighbours = match found.and_then(|i| ranges.remove(i)) {
+            Some(neighbours) => neighbours,
+            None => Arc::new(JumpNeighbours::new(systems, range)),
+        };
+        ranges.push_front(Arc::clone(&neighbours));
+        ranges.truncate(JUMP_NEIGHBOUR_RANGES);
+        neighbours
+    }
+}
+
+impl Clone for JumpNeighbourCache {
+    fn clone(&self) -> Self {
+        let ranges = self.ranges.lock().unwrap_or_else(PoisonError::into_inner);
+        JumpNeighbourCache {
+            ranges: Mutex::new(ranges.clone()),
+        }
+    }
+}
+
+/// Gateless route for a jump-drive ship: from any system it may hop to every
+/// system within `hops.range()` (dataset units). Candidate hops come from
+/// `hops`, filled in from `kd`, which must be built over the positions of
+/// `graph.systems` in order.
+///
+/// `ShortestDistance` minimises travelled distance; the other preferences
+/// minimise the number of jumps.
+pub fn shortest_jump_path(
+    graph: &StarGraph,
+    kd: &KDTree,
+    hops: &JumpNeighbours,
+    start: usize,
+    goal: usize,
+    preference: RoutePreference,
+) -> Result<Path, PathError> {
+    check_endpoints(graph, start, goal)?;
+    let jump_range = hops.range();
+    if jump_range.is_nan() || jump_range <= 0.0 {
+        return Err(PathError::NoRoute);
+    }
+    let by_distance = preference == RoutePreference::ShortestDistance;
+    let goal_pos = graph.systems[goal].pos;
+    astar_over(
+        graph,
+        start,
+        goal,
+        |current, out| {
+            out.extend(hops.of(graph, kd, current).iter().map(|&(next, d)| {
+                let cost = if by_distance { d } else { 1.0 };
+                (next as usize, cost, StepMode::Jump)
+            }));
+        },
+        |idx| {
+            let remaining = graph.systems[idx].distance_to_point(goal_pos);
+            if by_distance {
+                remaining
+            } else {
+                // Each jump covers at most `jump_range`, so this never
+                // overestimates the jumps left.
+                (remaining / jump_range).ceil()
+            }
+        },
+    )
+}
+
+/// Route that may use both gates and jump-drive hops within `hops.range()`
+/// (dataset units), taking whichever is cheaper on each leg; every step's
+/// `mode` says which was used.
+///
+/// Both kinds of leg cost what `preference` measures (one per jump, or the
+/// distance for `ShortestDistance`). A drive hop additionally pays
+/// `fuel_penalty` per unit of distance covered. On equal cost the gate wins.
+pub fn shortest_hybrid_path(
+    graph: &StarGraph,
+    kd: &KDTree,
+    hops: &JumpNeighbours,
+    start: usize,
+    goal: usize,
+    preference: RoutePreference,
+    fuel_penalty: Scalar,
+) -> Result<Path, PathError> {
+    check_endpoints(graph, start, goal)?;
+    let by_distance = preference == RoutePreference::ShortestDistance;
+    let can_jump = hops.range() > 0.0;
+    let fuel_penalty = fuel_penalty.max(0.0);
+    let goal_pos = graph.systems[goal].pos;
+    astar_over(
+        graph,
+        start,
+        goal,
+        |current, out| {
+            let here = &graph.systems[current];
+            for &next in &graph.adjacency[current] {
+                let cost = if by_distance {
+                    here.distance(&graph.systems[next])
+                } else {
+                    1.0
+                };
+                out.push((next, cost, StepMode::Gate));
+            }
+            if !can_jump {
+                return;
+            }
+            for &(next, d) in hops.of(graph, kd, current) {
+                let base = if by_distance { d } else { 1.0 };
+                out.push((next as usize, base + fuel_penalty * d, StepMode::Jump));
+            }
+        },
+        |idx| {
+            if by_distance {
+                graph.systems[idx].distance_to_point(goal_pos)
+            } else {
+                // A single gate can span any distance, so there is no useful
+                // lower bound on the jumps left.
+                0.0
+            }
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::graph_from;
+    use crate::stats::StatsRecorder;
+
+    /// Systems along the x axis with the given gates, and their k-d tree.
+    fn on_line(xs: &[Scalar], adjacency: Vec<Vec<usize>>) -> (StarGraph, KDTree) {
+        let positions: Vec<[Scalar; 3]> = xs.iter().map(|&x| [x, 0.0, 0.0]).collect();
+        (graph_from(&positions, adjacency), KDTree::build(&positions))
+    }
+
+    /// Five systems at most one unit apart and a sixth far away, no gates.
+    fn gateless() -> (StarGraph, KDTree) {
+        on_line(&[0.0, 1.0, 2.0, 2.5, 3.0, 10.0], vec![Vec::new(); 6])
+    }
+
+    #[test]
+    fn jump_route_ignores_gates_and_respects_range() {
+        let (graph, kd) = gateless();
+        let hops = JumpNeighbours::new(graph.len(), 1.5);
+        let far = shortest_jump_path(&graph, &kd, &hops, 0, 5, RoutePreference::FewestJumps);
+        assert_eq!(far, Err(PathError::NoRoute));
+        let jumps = shortest_jump_path(&graph, &kd, &hops, 0, 4, RoutePreference::FewestJumps)
+            .expect("route");
+        assert_eq!(jumps.len(), 4);
+        assert_eq!(jumps.last().unwrap().cost, 3.0);
+    }
+
+    #[test]
+    fn searches_reuse_neighbour_lists() {
+        let (graph, kd) = gateless();
+        let hops = JumpNeighbours::new(graph.len(), 1.5);
+        // The failed search expands every reachable system; the next one
+        // reuses their neighbour lists.
+        let far = shortest_jump_path(&graph, &kd, &hops, 0, 5, RoutePreference::FewestJumps);
+        assert!(far.is_err());
+        let recorder = StatsRecorder::start();
+        shortest_jump_path(&graph, &kd, &hops, 0, 4, RoutePreference::FewestJumps).expect("route");
+        assert_eq!(recorder.finish().kd_nodes_visited, 0);
+    }
+
+    #[test]
+    fn neighbour_cache_shares_and_evicts_ranges() {
+        let cache = JumpNeighbourCache::default();
+        let shared = cache.get(6, 1.5);
+        assert!(Arc::ptr_eq(&shared, &cache.get(6, 1.5)));
+        for range in 2..=JUMP_NEIGHBOUR_RANGES + 1 {
+            cache.get(6, range as Scalar);
+        }
+        assert!(!Arc::ptr_eq(&shared, &cache.get(6, 1.5)));
+    }
+
+    #[test]
+    fn hybrid_route_mixes_gates_and_jumps() {
+        // A -gate- B, then C is only reachable by a 1-unit hop from B.
+        let (graph, kd) = on_line(&[0.0, 5.0, 6.0], vec![vec![1], vec![0], vec![]]);
+        let hops = JumpNeighbours::new(graph.len(), 2.0);
+        let route =
+            shortest_hybrid_path(&graph, &kd, &hops, 0, 2, RoutePreference::FewestJumps, 0.5)
+                .expect("route");
+        let modes: Vec<_> = route.iter().map(|s| s.mode).collect();
+        assert_eq!(
+            modes,
+            vec![None, Some(StepMode::Gate), Some(StepMode::Jump)]
+        );
+        assert_eq!(route.last().unwrap().cost, 2.5);
+    }
+}
@@ -0,0 +1,167 @@
+use std::collections::VecDeque;
+
+use crate::graph::graph::StarGraph;
+use crate::graph::pathfinder::{astar, Path, PathError};
+use crate::Scalar;
+
+/// Default number of landmarks picked by `Landmarks::select`.
+pub const DEFAULT_LANDMARK_COUNT: usize = 8;
+
+/// Precomputed jump counts to and from a few landmark systems, giving an
+/// admissible A* heuristic through the triangle inequality (ALT). Unlike
+/// straight-line distance it tracks the gate network, so it stays tight
+/// where spatial distance says little about the number of jumps.
+#[derive(Clone, Debug)]
+pub struct Landmarks {
+    /// Landmark system indices.
+    pub landmarks: Vec<usize>,
+    /// from[l][v]: jumps from landmark `l` to `v`.
+    from: Vec<Vec<Scalar>>,
+    /// to[l][v]: jumps from `v` to landmark `l`.
+    to: Vec<Vec<Scalar>>,
+}
+
+impl Landmarks {
+    /// Picks up to `count` landmarks by farthest-point selection: each new
+    /// landmark is the system farthest (in jumps) from those already chosen,
+    /// which spreads them around the edge of the map.
+    pub fn select(graph: &StarGraph, count: usize) -> Self {
+        let mut landmarks = Vec::new();
+        let mut nearest = vec![Scalar::INFINITY; graph.len()];
+        let mut candidate = 0;
+        let mut from = Vec::new();
+        let mut to = Vec::new();
+        while landmarks.len() < count.min(graph.len()) {
+            let forward = bfs_jumps(graph, candidate, false);
+            for (n, &d) in nearest.iter_mut().zip(&forward) {
+                *n = n.min(d);
+            }
+            landmarks.push(candidate);
+            to.push(bfs_jumps(graph, candidate, true));
+            from.push(forward);
+
+            // Unreachable systems count as farthest so every component gets
+            // a landmark before any is reused.
+            let Some(next) = (0..graph.len())
+                .filter(|i| !landmarks.contains(i))
+                .max_by(|&a, &b| nearest[a].total_cmp(&nearest[b]).then(b.cmp(&a)))
+            else {
+                break;
+            };
+            candidate = next;
+        }
+        Landmarks {
+            landmarks,
+            from,
+            to,
+        }
+    }
+
+    /// Lower bound on the jumps from `v` to `goal`.
+    pub fn estimate(&self, v: usize, goal: usize) -> Scalar {
+        let mut best: Scalar = 0.0;
+        for (from, to) in self.from.iter().zip(&self.to) {
+            // d(L, goal) <= d(L, v) + d(v, goal)
+            let forward = from[goal] - from[v];
+            // d(v, L) <= d(v, goal) + d(goal, L)
+            let backward = to[v] - to[goal];
+            for bound in [forward, backward] {
+                if bound.is_finite() {
+                    best = best.max(bound);
+                }
+            }
+        }
+        best
+    }
+}
+
+/// Fewest-jumps gate route using the landmark heuristic. Because the
+/// heuristic is admissible the jump count is always optimal.
+pub fn shortest_gate_path_alt(
+    graph: &StarGraph,
+    landmarks: &Landmarks,
+    start: usize,
+    goal: usize,
+) -> Result<Path, PathError> {
+    astar(
+        graph,
+        start,
+        goal,
+        |_, _| Some(1.0),
+        |idx| landmarks.estimate(idx, goal),
+    )
+}
+
+/// Jumps from `source` to every system, or to `source` from every system
+/// when `reverse` is set; unreachable systems are infinite.
+fn bfs_jumps(graph: &StarGraph, source: usize, reverse: bool) -> Vec<Scalar> {
+    let mut jumps = vec![Scalar::INFINITY; graph.len()];
+    jumps[source] = 0.0;
+    let mut queue = VecDeque::from([source]);
+    while let Some(current) = queue.pop_front() {
+        let next_jumps = jumps[current] + 1.0;
+        let neighbours = if reverse {
+            graph.incoming(current)
+        } else {
+            &graph.adjacency[current]
+        };
+        for &next in neighbours {
+            if jumps[next].is_infinite() {
+                jumps[next] = next_jumps;
+                queue.push_back(next);
+            }
+        }
+    }
+    jumps
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::graph_from;
+    use crate::graph::pathfinder::shortest_gate_path_within;
+
+    /// A ring of 12 systems whose positions are shuffled, so straight-line
+    /// distance says little about jumps.
+    fn shuffled_ring() -> StarGraph {
+        let n = 12;
+        let positions: Vec<[Scalar; 3]> = (0..n)
+            .map(|i| [((i * 7) % n) as Scalar, ((i * 5) % n) as Scalar, 0.0])
+            .collect();
+        let adjacency = (0..n).map(|i| vec![(i + 1) % n, (i + n - 1) % n]).collect();
+        graph_from(&positions, adjacency)
+    }
+
+    #[test]
+    fn alt_routes_have_optimal_jump_counts() {
+        let graph = shuffled_ring();
+        let landmarks = Landmarks::select(&graph, 3);
+        assert_eq!(landmarks.landmarks.len(), 3);
+        for (start, goal) in [(0, 6), (1, 10), (3, 4), (11, 5)] {
+            let alt = shortest_gate_path_alt(&graph, &landmarks, start, goal).unwrap();
+            let exact = shortest_gate_path_within(&graph, start, goal, usize::MAX).unwrap();
+            assert_eq!(alt.len(), exact.len());
+        }
+    }
+
+    #[test]
+    fn estimates_never_exceed_the_jump_count() {
+        let graph = shuffled_ring();
+        let landmarks = Landmarks::select(&graph, 3);
+        for start in 0..graph.len() {
+            for goal in 0..graph.len() {
+                let around = (goal + 12 - start) % 12;
+                let jumps = around.min(12 - around);
+                assert!(landmarks.estimate(start, goal) <= jumps as Scalar);
+            }
+        }
+    }
+
+    #[test]
+    fn every_component_gets_a_landmark_first() {
+        // Two disconnected pairs.
+        let graph = graph_from(&[[0.0; 3]; 4], vec![vec![1], vec![0], vec![3], vec![2]]);
+        assert_eq!(Landmarks::select(&graph, 2).landmarks, vec![0, 2]);
+        assert_eq!(Landmarks::select(&graph, 10).landmarks.len(), 4);
+    }
+}
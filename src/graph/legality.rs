@@ -0,0 +1,189 @@
+use std::collections::HashSet;
+
+use serde::Serialize;
+use thiserror::Error;
+
+use crate::graph::closed_gates::ClosedGates;
+use crate::graph::graph::StarGraph;
+use crate::graph::jump::TravelMode;
+use crate::Scalar;
+
+/// What a ship can do, as far as route legality is concerned.
+#[derive(Clone, Debug, Default)]
+pub struct ShipProfile {
+    pub mode: TravelMode,
+    /// Drive range in dataset units; needed for `JumpDrive` and `Hybrid`.
+    pub jump_range: Option<Scalar>,
+    pub max_jumps: Option<usize>,
+    /// Ids of systems the route must not enter.
+    pub avoid_ids: HashSet<u32>,
+    /// Gates the route must not take; drive jumps along them are fine.
+    pub avoid_gates: ClosedGates,
+}
+
+/// The first rule a route breaks. `step` is the position in the route of
+/// the offending system, counting the start as 0.
+#[derive(Clone, Debug, Error, PartialEq, Serialize)]
+#[serde(tag = "reason", rename_all = "snake_case")]
+pub enum RouteViolation {
+    #[error("the route lists no systems")]
+    Empty,
+    #[error("step {step}: unknown system id {id}")]
+    UnknownSystem { step: usize, id: u32 },
+    #[error("step {step}: no gate from {from_id} to {to_id}")]
+    NoGate {
+        step: usize,
+        from_id: u32,
+        to_id: u32,
+    },
+    #[error("step {step}: {from_id} to {to_id} is {distance} away, beyond the jump range")]
+    OutOfRange {
+        step: usize,
+        from_id: u32,
+        to_id: u32,
+        distance: Scalar,
+    },
+    #[error("step {step}: system {id} is avoided")]
+    AvoidedSystem { step: usize, id: u32 },
+    #[error("step {step}: the gate from {from_id} to {to_id} is avoided")]
+    AvoidedGate {
+        step: usize,
+        from_id: u32,
+        to_id: u32,
+    },
+    #[error("the route takes {jumps} jumps, over the limit of {max_jumps}")]
+    TooManyJumps { jumps: usize, max_jumps: usize },
+}
+
+/// Checks a route given as system ids against `graph` and `ship`, returning
+/// the first violation in route order. Every hop must be a gate in `Gates`
+/// mode, within `jump_range` in `JumpDrive` mode, and either in `Hybrid`
+/// mode; a drive mode without a positive range only allows gates. The start
+/// may be an avoided system, since the ship is already there.
+pub fn check_route(
+    graph: &StarGraph,
+    ids: &[u32],
+    ship: &ShipProfile,
+) -> Result<(), RouteViolation> {
+    if ids.is_empty() {
+        return Err(RouteViolation::Empty);
+    }
+    let range = ship
+        .jump_range
+        .filter(|r| *r > 0.0 && ship.mode != TravelMode::Gates);
+    let mut prev: Option<usize> = None;
+    for (step, &id) in ids.iter().enumerate() {
+        let idx = graph
+            .index_of_id(id)
+            .ok_or(RouteViolation::UnknownSystem { step, id })?;
+        if let Some(from) = prev {
+            let from_id = graph.systems[from].id;
+            let gate = graph.adjacency[from].contains(&idx);
+            let distance = graph.systems[from].distance(&graph.systems[idx]);
+            let in_range = range.is_some_and(|r| distance <= r);
+            let legal = match ship.mode {
+                TravelMode::Gates => gate,
+                TravelMode::JumpDrive => in_range,
+                TravelMode::Hybrid => gate || in_range,
+            };
+            if !legal && ship.mode == TravelMode::Gates {
+                return Err(RouteViolation::NoGate {
+                    step,
+                    from_id,
+                    to_id: id,
+                });
+            }
+            if !legal {
+                return Err(RouteViolation::OutOfRange {
+                    step,
+                    from_id,
+                    to_id: id,
+                    distance,
+                });
+            }
+            // Only hops that need the gate are blocked by closing it.
+            if !in_range && ship.avoid_gates.contains(from_id, id) {
+                return Err(RouteViolation::AvoidedGate {
+                    step,
+                    from_id,
+                    to_id: id,
+                });
+            }
+            if ship.avoid_ids.contains(&id) {
+                return Err(RouteViolation::AvoidedSystem { step, id });
+            }
+        }
+        prev = Some(idx);
+    }
+    let jumps = ids.len() - 1;
+    match ship.max_jumps {
+        Some(max_jumps) if jumps > max_jumps => {
+            Err(RouteViolation::TooManyJumps { jumps, max_jumps })
+        }
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::demo_graph;
+
+    #[test]
+    fn first_violation_is_reported() {
+        // Demo ids: A=1, B=2, C=3 along gates A-B-C; D=4 off A.
+        let graph = demo_graph();
+        let gates = ShipProfile::default();
+        assert_eq!(check_route(&graph, &[1, 2, 3], &gates), Ok(()));
+        assert_eq!(check_route(&graph, &[], &gates), Err(RouteViolation::Empty));
+        assert_eq!(
+            check_route(&graph, &[1, 9, 3], &gates),
+            Err(RouteViolation::UnknownSystem { step: 1, id: 9 })
+        );
+        assert_eq!(
+            check_route(&graph, &[1, 3], &gates),
+            Err(RouteViolation::NoGate {
+                step: 1,
+                from_id: 1,
+                to_id: 3
+            })
+        );
+
+        let drive = ShipProfile {
+            mode: TravelMode::JumpDrive,
+            jump_range: Some(1.5),
+            max_jumps: Some(1),
+            avoid_ids: HashSet::from([3]),
+            ..ShipProfile::default()
+        };
+        assert!(matches!(
+            check_route(&graph, &[1, 3], &drive),
+            Err(RouteViolation::OutOfRange { step: 1, .. })
+        ));
+        assert_eq!(
+            check_route(&graph, &[1, 2, 3], &drive),
+            Err(RouteViolation::AvoidedSystem { step: 2, id: 3 })
+        );
+        assert_eq!(
+            check_route(&graph, &[2, 1, 2], &drive),
+            Err(RouteViolation::TooManyJumps {
+                jumps: 2,
+                max_jumps: 1
+            })
+        );
+
+        let camped = ShipProfile {
+            avoid_gates: ClosedGates::from_pairs(&[[2, 3]]),
+            ..ShipProfile::default()
+        };
+        assert_eq!(check_route(&graph, &[3, 2, 1], &camped), Ok(()));
+        assert_eq!(
+            check_route(&graph, &[1, 2, 3], &camped),
+            Err(RouteViolation::AvoidedGate {
+                step: 2,
+                from_id: 2,
+                to_id: 3
+            })
+        );
+    }
+}
@@ -0,0 +1,96 @@
+use std::collections::VecDeque;
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::graph::graph::StarGraph;
+
+/// Largest graph `JumpMatrix::build` accepts by default (32 MiB of entries).
+pub const DEFAULT_MATRIX_MAX_SYSTEMS: usize = 4096;
+
+/// Entry for pairs with no gate route between them.
+pub const UNREACHABLE: u16 = u16::MAX;
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum MatrixError {
+    #[error("graph has {systems} systems, over the matrix limit of {limit}")]
+    TooLarge { systems: usize, limit: usize },
+}
+
+/// Precomputed fewest-jump counts between every pair of systems, for
+/// constant-time lookups. Stored row-major as `u16`; persist it with
+/// `data::write_matrix_to_file`, which compresses it.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct JumpMatrix {
+    len: usize,
+    jumps: Vec<u16>,
+}
+
+impl JumpMatrix {
+    /// Runs one breadth-first search per system. Refuses graphs with more
+    /// than `max_systems` systems, since the matrix grows quadratically.
+    pub fn build(graph: &StarGraph, max_systems: usize) -> Result<Self, MatrixError> {
+        let len = graph.len();
+        if len > max_systems {
+            return Err(MatrixError::TooLarge {
+                systems: len,
+                limit: max_systems,
+            });
+        }
+        let mut jumps = vec![UNREACHABLE; len * len];
+        let mut queue = VecDeque::new();
+        for start in 0..len {
+            let row = &mut jumps[start * len..(start + 1) * len];
+            row[start] = 0;
+            queue.clear();
+            queue.push_back(start);
+            while let Some(current) = queue.pop_front() {
+                let next_jumps = row[current].saturating_add(1).min(UNREACHABLE - 1);
+                for &next in &graph.adjacency[current] {
+                    if row[next] == UNREACHABLE {
+                        row[next] = next_jumps;
+                        queue.push_back(next);
+                    }
+                }
+            }
+        }
+        Ok(JumpMatrix { len, jumps })
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Fewest gate jumps from `from` to `to`, or `None` if unreachable.
+    pub fn jumps(&self, from: usize, to: usize) -> Option<u16> {
+        match self.jumps[from * self.len + to] {
+            UNREACHABLE => None,
+            jumps => Some(jumps),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::demo_graph;
+
+    #[test]
+    fn matrix_matches_bfs_and_respects_limit() {
+        let graph = demo_graph();
+        let matrix = JumpMatrix::build(&graph, 10).unwrap();
+        assert_eq!(matrix.jumps(2, 3), Some(3));
+        assert_eq!(matrix.jumps(1, 1), Some(0));
+        assert_eq!(
+            JumpMatrix::build(&graph, 3),
+            Err(MatrixError::TooLarge {
+                systems: 4,
+                limit: 3
+            })
+        );
+    }
+}
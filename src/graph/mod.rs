@@ -1,4 +1,22 @@
 #![allow(clippy::module_inception)]
 
+pub mod alternatives;
+pub mod cache;
+pub mod closed_gates;
+pub mod components;
+pub mod cross;
+pub mod embedding;
 pub mod graph;
+pub mod hub_labels;
+pub mod jump;
+pub mod landmarks;
+pub mod legality;
+pub mod matrix;
+pub mod pareto;
+pub mod partition;
 pub mod pathfinder;
+pub mod reachability;
+pub mod regions;
+pub mod route;
+pub mod traversal;
+pub mod waypoints;
@@ -0,0 +1,263 @@
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+
+use crate::budget::{self, BudgetExceeded};
+use crate::graph::graph::StarGraph;
+use crate::stats;
+use crate::Scalar;
+
+/// Default cap on the non-dominated labels kept per system.
+pub const DEFAULT_MAX_LABELS: usize = 16;
+
+/// One route on the Pareto frontier with its cost under both objectives.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParetoRoute {
+    pub systems: Vec<usize>,
+    pub costs: [Scalar; 2],
+}
+
+#[derive(Clone, Debug)]
+struct Label {
+    node: usize,
+    costs: [Scalar; 2],
+    parent: Option<usize>,
+    alive: bool,
+}
+
+/// Queue entry ordered so the label with the lowest first objective (then
+/// second) is popped first.
+#[derive(Clone, Copy, Debug)]
+struct Queued {
+    label: usize,
+    costs: [Scalar; 2],
+}
+
+impl PartialEq for Queued {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Queued {}
+
+impl Ord for Queued {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.costs[0]
+            .total_cmp(&self.costs[0])
+            .then(other.costs[1].total_cmp(&self.costs[1]))
+            .then(other.label.cmp(&self.label))
+    }
+}
+
+impl PartialOrd for Queued {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Label-correcting multi-objective search returning the Pareto-optimal
+/// routes from `start` to `goal` under two non-negative objectives given by
+/// `edge_costs(from, to)`, sorted by the first objective.
+///
+/// At most `max_labels` labels are kept per system. When a set overflows the
+/// interior label with the smallest crowding distance is dropped, so the
+/// extremes of each frontier always survive; the result is exact whenever no
+/// set overflows and an evenly spread approximation otherwise. Each label
+/// taken off the queue is charged to the running search budget.
+pub fn pareto_routes<C>(
+    graph: &StarGraph,
+    start: usize,
+    goal: usize,
+    edge_costs: C,
+    max_labels: usize,
+) -> Result<Vec<ParetoRoute>, BudgetExceeded>
+where
+    C: Fn(usize, usize) -> [Scalar; 2],
+{
+    let max_labels = max_labels.max(2);
+    let mut labels = vec![Label {
+        node: start,
+        costs: [0.0, 0.0],
+        parent: None,
+        alive: true,
+    }];
+    let mut at_node: Vec<Vec<usize>> = vec![Vec::new(); graph.len()];
+    at_node[start].push(0);
+    let mut queue = BinaryHeap::from([Queued {
+        label: 0,
+        costs: [0.0, 0.0],
+    }]);
+
+    while let Some(Queued { label, .. }) = queue.pop() {
+        if !labels[label].alive {
+            continue;
+        }
+        budget::spend()?;
+        stats::count_expanded();
+        let (node, costs) = (labels[label].node, labels[label].costs);
+        if node == goal {
+            continue;
+        }
+        for &next in &graph.adjacency[node] {
+            let step = edge_costs(node, next);
+            let candidate = [costs[0] + step[0], costs[1] + step[1]];
+            // Anything the goal frontier already dominates can never improve it.
+            if is_dominated(&labels, &at_node[goal], candidate)
+                || is_dominated(&labels, &at_node[next], candidate)
+            {
+                continue;
+            }
+            let id = labels.len();
+            labels.push(Label {
+                node: next,
+                costs: candidate,
+                parent: Some(label),
+                alive: true,
+            });
+            insert_label(&mut labels, &mut at_node[next], id, max_labels);
+            if labels[id].alive {
+                queue.push(Queued {
+                    label: id,
+                    costs: candidate,
+                });
+            }
+        }
+    }
+
+    let mut frontier: Vec<ParetoRoute> = at_node[goal]
+        .iter()
+        .map(|&id| ParetoRoute {
+            systems: reconstruct(&labels, id),
+            costs: labels[id].costs,
+        })
+        .collect();
+    frontier.sort_by(|a, b| {
+        a.costs[0]
+            .total_cmp(&b.costs[0])
+            .then(a.costs[1].total_cmp(&b.costs[1]))
+    });
+    Ok(frontier)
+}
+
+/// True if some label in `set` is at least as good as `costs` in both
+/// objectives (equal costs count as dominated to avoid duplicates).
+fn is_dominated(labels: &[Label], set: &[usize], costs: [Scalar; 2]) -> bool {
+    set.iter().any(|&id| {
+        let other = labels[id].costs;
+        other[0] <= costs[0] && other[1] <= costs[1]
+    })
+}
+
+fn insert_label(labels: &mut [Label], set: &mut Vec<usize>, id: usize, max_labels: usize) {
+    let costs = labels[id].costs;
+    set.retain(|&other| {
+        let c = labels[other].costs;
+        let dominated = costs[0] <= c[0] && costs[1] <= c[1];
+        if dominated {
+            labels[other].alive = false;
+        }
+        !dominated
+    });
+    set.push(id);
+    if set.len() > max_labels {
+        let position = most_crowded(labels, set);
+        let evicted = set.remove(position);
+        labels[evicted].alive = false;
+    }
+}
+
+/// Position in `set` of the interior label whose neighbours on the frontier
+/// are closest together (NSGA-II style crowding distance).
+fn most_crowded(labels: &[Label], set: &mut [usize]) -> usize {
+    set.sort_by(|&a, &b| labels[a].costs[0].total_cmp(&labels[b].costs[0]));
+    let first = labels[set[0]].costs;
+    let last = labels[set[set.len() - 1]].costs;
+    let span = [
+        (last[0] - first[0]).max(Scalar::EPSILON),
+        (first[1] - last[1]).max(Scalar::EPSILON),
+    ];
+    (1..set.len() - 1)
+        .min_by(|&i, &j| {
+            let crowding = |k: usize| {
+                let (prev, next) = (labels[set[k - 1]].costs, labels[set[k + 1]].costs);
+                (next[0] - prev[0]) / span[0] + (prev[1] - next[1]) / span[1]
+            };
+            crowding(i).total_cmp(&crowding(j))
+        })
+        .unwrap_or(set.len() - 1)
+}
+
+fn reconstruct(labels: &[Label], mut id: usize) -> Vec<usize> {
+    let mut systems = vec![labels[id].node];
+    while let Some(parent) = labels[id].parent {
+        systems.push(labels[parent].node);
+        id = parent;
+    }
+    systems.reverse();
+    systems
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::graph_from;
+
+    #[test]
+    fn frontier_trades_jumps_against_distance() {
+        // A -> B -> C is two jumps with a long detour; A -> D -> E -> C is
+        // three jumps in a straight line; A -> F -> G -> C is dominated.
+        let positions = [
+            [0.0, 0.0, 0.0],
+            [0.0, 50.0, 0.0],
+            [4.0, 0.0, 0.0],
+            [1.0, 0.0, 0.0],
+            [3.0, 0.0, 0.0],
+            [0.0, 60.0, 0.0],
+            [4.0, 60.0, 0.0],
+        ];
+        let adjacency = vec![
+            vec![1, 3, 5],
+            vec![2],
+            vec![],
+            vec![4],
+            vec![2],
+            vec![6],
+            vec![2],
+        ];
+        let graph = graph_from(&positions, adjacency);
+
+        let frontier = pareto_routes(
+            &graph,
+            0,
+            2,
+            |a, b| [1.0, graph.systems[a].distance(&graph.systems[b])],
+            DEFAULT_MAX_LABELS,
+        )
+        .unwrap();
+        assert_eq!(frontier.len(), 2);
+        assert_eq!(frontier[0].systems, vec![0, 1, 2]);
+        assert_eq!(frontier[1].systems, vec![0, 3, 4, 2]);
+        assert!((frontier[1].costs[1] - 4.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn overflowing_label_sets_keep_the_extremes() {
+        // Three non-dominated routes via 1, 2 and 3; two labels per system
+        // leave room for the extremes only.
+        let graph = graph_from(
+            &[[0.0; 3]; 5],
+            vec![vec![1, 2, 3], vec![4], vec![4], vec![4], vec![]],
+        );
+        let costs = |from: usize, to: usize| {
+            if from == 0 {
+                [to as Scalar, (4 - to) as Scalar]
+            } else {
+                [0.0, 0.0]
+            }
+        };
+        let frontier = pareto_routes(&graph, 0, 4, costs, 2).unwrap();
+        let vias: Vec<usize> = frontier.iter().map(|route| route.systems[1]).collect();
+        assert_eq!(vias, vec![1, 3]);
+        assert_eq!(pareto_routes(&graph, 0, 4, costs, 3).unwrap().len(), 3);
+    }
+}
@@ -0,0 +1,274 @@
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::graph::graph::StarGraph;
+use crate::Scalar;
+
+/// Parts may grow to this factor of the ideal size during refinement.
+const BALANCE_TOLERANCE: Scalar = 1.05;
+/// Upper bound on boundary refinement passes.
+const REFINEMENT_PASSES: usize = 8;
+
+/// Assignment of every system to one of `parts` balanced parts.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Partitioning {
+    /// part_of[i] is the part of graph.systems[i]; usable directly as the
+    /// region assignment of `RegionShortcuts::build`.
+    pub part_of: Vec<u32>,
+    pub parts: usize,
+}
+
+/// A gate that leaves its part.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BoundaryEdge {
+    pub from_id: u32,
+    pub to_id: u32,
+    pub from_part: u32,
+    pub to_part: u32,
+}
+
+/// What a sharded deployment needs to route between parts: which part
+/// serves each system and every gate that crosses between parts.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct BoundaryTable {
+    pub parts: usize,
+    pub part_of_id: BTreeMap<u32, u32>,
+    pub edges: Vec<BoundaryEdge>,
+}
+
+/// Splits the graph into `parts` parts of near-equal size with few cut
+/// gates: recursive coordinate bisection along the widest axis gives
+/// balanced, spatially compact parts, then greedy passes move boundary
+/// systems to the neighbouring part holding most of their gates while the
+/// parts stay within `BALANCE_TOLERANCE` of the ideal size.
+pub fn partition_graph(graph: &StarGraph, parts: usize) -> Partitioning {
+    let parts = parts.max(1);
+    let mut part_of = vec![0; graph.len()];
+    let mut members: Vec<usize> = (0..graph.len()).collect();
+    bisect(graph, &mut members, parts, 0, &mut part_of);
+    let mut partitioning = Partitioning { part_of, parts };
+    partitioning.refine(graph);
+    partitioning
+}
+
+fn bisect(graph: &StarGraph, members: &mut [usize], parts: usize, first: u32, part_of: &mut [u32]) {
+    if parts <= 1 || members.len() <= 1 {
+        for &m in members.iter() {
+            part_of[m] = first;
+        }
+        return;
+    }
+    let left_parts = parts / 2;
+    let split = members.len() * left_parts / parts;
+
+    let mut min = [Scalar::INFINITY; 3];
+    let mut max = [Scalar::NEG_INFINITY; 3];
+    for &m in members.iter() {
+        for axis in 0..3 {
+            min[axis] = min[axis].min(graph.systems[m].pos[axis]);
+            max[axis] = max[axis].max(graph.systems[m].pos[axis]);
+        }
+    }
+    let axis = (0..3)
+        .max_by(|&a, &b| {
+            (max[a] - min[a])
+                .partial_cmp(&(max[b] - min[b]))
+                .unwrap_or(Ordering::Equal)
+        })
+        .unwrap_or(0);
+    if split < members.len() {
+        members.select_nth_unstable_by(split, |&a, &b| {
+            graph.systems[a].pos[axis]
+                .partial_cmp(&graph.systems[b].pos[axis])
+                .unwrap_or(Ordering::Equal)
+        });
+    }
+    let (left, right) = members.split_at_mut(split);
+    bisect(graph, left, left_parts, first, part_of);
+    bisect(
+        graph,
+        right,
+        parts - left_parts,
+        first + left_parts as u32,
+        part_of,
+    );
+}
+
+impl Partitioning {
+    pub fn part_sizes(&self) -> Vec<usize> {
+        let mut sizes = vec![0; self.parts];
+        for &p in &self.part_of {
+            sizes[p as usize] += 1;
+        }
+        sizes
+    }
+
+    /// Number of directed gate edges between different parts.
+    pub fn cut_edges(&self, graph: &StarGraph) -> usize {
+        self.boundary_edges(graph).count()
+    }
+
+    /// The systems of `part`, in dataset order, with only the gates inside
+    /// the part.
+    pub fn part_graph(&self, graph: &StarGraph, part: u32) -> StarGraph {
+        let members: Vec<usize> = (0..graph.len())
+            .filter(|&i| self.part_of[i] == part)
+            .collect();
+        let mut local = vec![usize::MAX; graph.len()];
+        for (new, &old) in members.iter().enumerate() {
+            local[old] = new;
+        }
+        let systems = members.iter().map(|&i| graph.systems[i].clone()).collect();
+        let adjacency = members
+            .iter()
+            .map(|&i| {
+                graph.adjacency[i]
+                    .iter()
+                    .filter(|&&j| self.part_of[j] == part)
+                    .map(|&j| local[j])
+                    .collect()
+            })
+            .collect();
+        StarGraph::new(systems, adjacency)
+    }
+
+    pub fn boundary_table(&self, graph: &StarGraph) -> BoundaryTable {
+        BoundaryTable {
+            parts: self.parts,
+            part_of_id: graph
+                .systems
+                .iter()
+                .zip(&self.part_of)
+                .map(|(s, &p)| (s.id, p))
+                .collect(),
+            edges: self
+                .boundary_edges(graph)
+                .map(|(from, to)| BoundaryEdge {
+                    from_id: graph.systems[from].id,
+                    to_id: graph.systems[to].id,
+                    from_part: self.part_of[from],
+                    to_part: self.part_of[to],
+                })
+                .collect(),
+        }
+    }
+
+    fn boundary_edges<'a>(
+        &'a self,
+        graph: &'a StarGraph,
+    ) -> impl Iterator<Item = (usize, usize)> + 'a {
+        graph
+            .adjacency
+            .iter()
+            .enumerate()
+            .flat_map(|(from, targets)| targets.iter().map(move |&to| (from, to)))
+            .filter(|&(from, to)| self.part_of[from] != self.part_of[to])
+    }
+
+    fn refine(&mut self, graph: &StarGraph) {
+        let ideal = graph.len() as Scalar / self.parts as Scalar;
+        let max_size = (ideal * BALANCE_TOLERANCE).ceil() as usize;
+        let min_size = (ideal / BALANCE_TOLERANCE).floor() as usize;
+        let mut sizes = self.part_sizes();
+        let mut counts: Vec<(u32, usize)> = Vec::new();
+        for _ in 0..REFINEMENT_PASSES {
+            let mut moved = false;
+            for v in 0..graph.len() {
+                let current = self.part_of[v];
+                counts.clear();
+                for &n in graph.adjacency[v].iter().chain(graph.incoming(v)) {
+                    let p = self.part_of[n];
+                    match counts.iter_mut().find(|(q, _)| *q == p) {
+                        Some((_, c)) => *c += 1,
+                        None => counts.push((p, 1)),
+                    }
+                }
+                let here = counts
+                    .iter()
+                    .find(|(q, _)| *q == current)
+                    .map_or(0, |&(_, c)| c);
+                let best = counts
+                    .iter()
+                    .filter(|&&(q, c)| q != current && c > here && sizes[q as usize] < max_size)
+                    .max_by_key(|&&(q, c)| (c, std::cmp::Reverse(q)));
+                if let Some(&(target, _)) = best {
+                    if sizes[current as usize] > min_size {
+                        sizes[current as usize] -= 1;
+                        sizes[target as usize] += 1;
+                        self.part_of[v] = target;
+                        moved = true;
+                    }
+                }
+            }
+            if !moved {
+                break;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::graph_from;
+
+    /// Two 4-cliques far apart, joined by one gate between 3 and 4.
+    fn two_cliques() -> StarGraph {
+        let positions: Vec<[Scalar; 3]> = (0..8)
+            .map(|i| [if i < 4 { 0.0 } else { 100.0 } + i as Scalar, 0.0, 0.0])
+            .collect();
+        let mut adjacency: Vec<Vec<usize>> = (0..8)
+            .map(|i: usize| {
+                let base = i / 4 * 4;
+                (base..base + 4).filter(|&j| j != i).collect()
+            })
+            .collect();
+        adjacency[3].push(4);
+        adjacency[4].push(3);
+        graph_from(&positions, adjacency)
+    }
+
+    #[test]
+    fn two_clusters_split_on_their_bridge() {
+        let graph = two_cliques();
+        let partitioning = partition_graph(&graph, 2);
+        assert_eq!(partitioning.part_of, vec![0, 0, 0, 0, 1, 1, 1, 1]);
+        assert_eq!(partitioning.part_sizes(), vec![4, 4]);
+        assert_eq!(partitioning.cut_edges(&graph), 2);
+    }
+
+    #[test]
+    fn one_part_cuts_nothing() {
+        let graph = two_cliques();
+        let partitioning = partition_graph(&graph, 1);
+        assert_eq!(partitioning.part_sizes(), vec![8]);
+        assert_eq!(partitioning.cut_edges(&graph), 0);
+    }
+
+    #[test]
+    fn boundary_table_lists_the_bridge_both_ways() {
+        let graph = two_cliques();
+        let table = partition_graph(&graph, 2).boundary_table(&graph);
+        assert_eq!(table.parts, 2);
+        assert_eq!(table.part_of_id[&4], 0);
+        assert_eq!(table.part_of_id[&5], 1);
+        let edge = |from_id, to_id, from_part, to_part| BoundaryEdge {
+            from_id,
+            to_id,
+            from_part,
+            to_part,
+        };
+        assert_eq!(table.edges, vec![edge(4, 5, 0, 1), edge(5, 4, 1, 0)]);
+    }
+
+    #[test]
+    fn part_graph_keeps_only_the_gates_inside() {
+        let graph = two_cliques();
+        let part = partition_graph(&graph, 2).part_graph(&graph, 1);
+        let ids: Vec<u32> = part.systems.iter().map(|s| s.id).collect();
+        assert_eq!(ids, vec![5, 6, 7, 8]);
+        assert_eq!(part.edge_count(), 12);
+    }
+}
@@ -1,34 +1,446 @@
+use crate::budget::{self, BudgetExceeded};
 use crate::graph::graph::StarGraph;
+use crate::Scalar;
+use crate::{progress, stats};
+use serde::{Deserialize, Serialize};
 use std::cmp::Ordering;
-use std::collections::{BinaryHeap, HashMap};
+use std::collections::{BinaryHeap, HashMap, HashSet, VecDeque};
+use thiserror::Error;
 
-#[derive(Clone, Debug)]
+/// What a route search minimises.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RoutePreference {
+    /// Fewest gate jumps.
+    #[default]
+    FewestJumps,
+    /// Shortest travelled distance.
+    ShortestDistance,
+    /// Fewest jumps, with each jump into a system also paying that system's
+    /// risk. Behaves like `FewestJumps` when no risk data is available.
+    Safest,
+}
+
+/// How a route step was reached from the previous one.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StepMode {
+    /// Through a stargate.
+    Gate,
+    /// A direct jump-drive hop.
+    Jump,
+}
+
+#[derive(Clone, Debug, PartialEq)]
 pub struct PathStep {
     pub system_index: usize,
-    pub cost: f32,
+    pub cost: Scalar,
+    /// How this system was reached; `None` for the first step.
+    pub mode: Option<StepMode>,
+    /// Straight-line length of the hop into this system, in dataset units;
+    /// 0 for the first step.
+    pub leg_distance: Scalar,
 }
 
-/// A* pathfinding over the gate graph, using 3D Euclidean distance as a heuristic.
-/// Cost model: each gate jump has cost 1.0 (minimal fuel usage).
-pub fn shortest_gate_path(graph: &StarGraph, start: usize, goal: usize) -> Option<Vec<PathStep>> {
+impl PathStep {
+    /// Step into `system_index`, reached from `prev` unless it is the start.
+    pub(crate) fn new(
+        graph: &StarGraph,
+        prev: Option<usize>,
+        system_index: usize,
+        cost: Scalar,
+        mode: Option<StepMode>,
+    ) -> Self {
+        PathStep {
+            system_index,
+            cost,
+            mode,
+            leg_distance: prev.map_or(0.0, |p| {
+                graph.systems[p].distance(&graph.systems[system_index])
+            }),
+        }
+    }
+}
+
+/// A route as the steps it takes, start first.
+pub type Path = Vec<PathStep>;
+
+/// Why a route search produced no route.
+#[derive(Clone, Debug, Error, PartialEq, Eq)]
+pub enum PathError {
+    #[error("no route between the systems")]
+    NoRoute,
+    /// Searches do not answer trivial requests; callers that want a
+    /// zero-jump route use `trivial_path`.
+    #[error("start and goal are the same system")]
+    SameSystem,
+    #[error("system index {index} is out of range for {len} systems")]
+    IndexOutOfRange { index: usize, len: usize },
+    #[error(transparent)]
+    BudgetExceeded(#[from] BudgetExceeded),
+    #[error("shortest gate route needs {jumps} jumps, over the limit of {max_jumps}")]
+    ExceedsLimit { jumps: usize, max_jumps: usize },
+}
+
+/// Rejects endpoints a search cannot start from: indices outside `graph`,
+/// and a goal equal to the start.
+pub(crate) fn check_endpoints(
+    graph: &StarGraph,
+    start: usize,
+    goal: usize,
+) -> Result<(), PathError> {
+    let len = graph.len();
+    if let Some(index) = [start, goal].into_iter().find(|&i| i >= len) {
+        return Err(PathError::IndexOutOfRange { index, len });
+    }
     if start == goal {
-        return Some(vec![PathStep {
-            system_index: start,
-            cost: 0.0,
-        }]);
+        return Err(PathError::SameSystem);
+    }
+    Ok(())
+}
+
+/// The zero-jump route of a system to itself.
+pub fn trivial_path(graph: &StarGraph, system_index: usize) -> Path {
+    vec![PathStep::new(graph, None, system_index, 0.0, None)]
+}
+
+/// Shortest route under the given preference. `risk`, indexed like
+/// `graph.systems`, holds a non-negative risk per system and is only
+/// consulted by `RoutePreference::Safest`.
+pub fn shortest_path(
+    graph: &StarGraph,
+    start: usize,
+    goal: usize,
+    preference: RoutePreference,
+    risk: Option<&[Scalar]>,
+) -> Result<Path, PathError> {
+    match (preference, risk) {
+        (RoutePreference::FewestJumps, _) | (RoutePreference::Safest, None) => {
+            shortest_gate_path(graph, start, goal)
+        }
+        (RoutePreference::ShortestDistance, _) => shortest_distance_path(graph, start, goal),
+        (RoutePreference::Safest, Some(risk)) => {
+            shortest_gate_path_with(graph, start, goal, &RiskCost(risk))
+        }
+    }
+}
+
+/// Edge-cost model for gate routing, so custom costs (fuel, risk, tolls)
+/// plug into the pathfinder without changes to it. Any
+/// `Fn(from_idx, to_idx) -> Scalar` closure is a cost model; a non-finite cost
+/// forbids the gate.
+pub trait CostFn {
+    /// Cost of the gate jump from `from` to `to`.
+    fn cost(&self, from: usize, to: usize) -> Scalar;
+
+    /// Estimate of the remaining cost from `from` to `goal` guiding the
+    /// search. Overestimates can make routes suboptimal; the default of 0
+    /// never does.
+    fn estimate(&self, _graph: &StarGraph, _from: usize, _goal: usize) -> Scalar {
+        0.0
+    }
+}
+
+impl<F: Fn(usize, usize) -> Scalar> CostFn for F {
+    fn cost(&self, from: usize, to: usize) -> Scalar {
+        self(from, to)
+    }
+}
+
+/// Every gate jump costs 1 (minimal fuel usage), estimated by 3D Euclidean
+/// distance in units of the longest gate.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct JumpCost;
+
+impl CostFn for JumpCost {
+    fn cost(&self, _from: usize, _to: usize) -> Scalar {
+        1.0
+    }
+
+    fn estimate(&self, graph: &StarGraph, from: usize, goal: usize) -> Scalar {
+        jump_heuristic(graph, from, goal)
+    }
+}
+
+/// Every gate jump costs 1 plus the non-negative risk of the system it
+/// enters, indexed like `graph.systems`.
+#[derive(Clone, Copy, Debug)]
+pub struct RiskCost<'a>(pub &'a [Scalar]);
+
+impl CostFn for RiskCost<'_> {
+    fn cost(&self, _from: usize, to: usize) -> Scalar {
+        1.0 + self.0.get(to).copied().unwrap_or(0.0).max(0.0)
+    }
+
+    fn estimate(&self, graph: &StarGraph, from: usize, goal: usize) -> Scalar {
+        jump_heuristic(graph, from, goal)
+    }
+}
+
+/// A* pathfinding over the gate graph, using 3D Euclidean distance over the
+/// longest gate length as a heuristic.
+/// Cost model: each gate jump has cost 1.0 (minimal fuel usage).
+pub fn shortest_gate_path(graph: &StarGraph, start: usize, goal: usize) -> Result<Path, PathError> {
+    shortest_gate_path_with(graph, start, goal, &JumpCost)
+}
+
+/// A* pathfinding over the gate graph under a custom cost model, e.g.
+/// `&|from, to| toll[to]` or a `&dyn CostFn` chosen at runtime.
+pub fn shortest_gate_path_with<C: CostFn + ?Sized>(
+    graph: &StarGraph,
+    start: usize,
+    goal: usize,
+    cost: &C,
+) -> Result<Path, PathError> {
+    astar(
+        graph,
+        start,
+        goal,
+        |from, to| Some(cost.cost(from, to)).filter(|c| c.is_finite()),
+        |idx| cost.estimate(graph, idx, goal),
+    )
+}
+
+/// Fewest-jumps gate route that is rejected with `PathError::ExceedsLimit`
+/// when it needs more than `max_jumps` jumps. A breadth-first search gives
+/// the exact minimum jump count, which the error reports so callers can
+/// tell how far off they are.
+///
+/// Ties follow the `astar_over` rule: a system reached by several routes of
+/// the same length keeps the lowest-id predecessor.
+pub fn shortest_gate_path_within(
+    graph: &StarGraph,
+    start: usize,
+    goal: usize,
+    max_jumps: usize,
+) -> Result<Path, PathError> {
+    check_endpoints(graph, start, goal)?;
+    let mut parent: Vec<Option<usize>> = vec![None; graph.len()];
+    let mut depth = vec![usize::MAX; graph.len()];
+    depth[start] = 0;
+    let mut queue = VecDeque::from([start]);
+    while let Some(current) = queue.pop_front() {
+        budget::spend()?;
+        stats::count_expanded();
+        // Every system one jump short of the goal is dequeued before it, so
+        // its predecessor is final by now.
+        if current == goal {
+            break;
+        }
+        for &next in &graph.adjacency[current] {
+            if depth[next] == usize::MAX {
+                depth[next] = depth[current] + 1;
+                parent[next] = Some(current);
+                queue.push_back(next);
+            } else if depth[next] == depth[current] + 1 {
+                prefer_lower_id(graph, &mut parent[next], current);
+            }
+        }
+        stats::record_frontier(queue.len());
+    }
+    if depth[goal] == usize::MAX {
+        return Err(PathError::NoRoute);
+    }
+
+    let mut systems = vec![goal];
+    let mut current = goal;
+    while let Some(prev) = parent[current] {
+        systems.push(prev);
+        current = prev;
+    }
+    let jumps = systems.len() - 1;
+    if jumps > max_jumps {
+        return Err(PathError::ExceedsLimit { jumps, max_jumps });
+    }
+    systems.reverse();
+    Ok(gate_route(graph, &systems))
+}
+
+/// Fewest-jumps gate routes for many `(start, goal)` pairs in one call,
+/// returned in the order of `pairs`. Each pair fails like a single search
+/// would: `check_endpoints` errors for bad pairs, `PathError::NoRoute` for
+/// unreachable goals, and `PathError::BudgetExceeded` for every pair left
+/// unanswered once the running budget is spent. Pairs are grouped by start
+/// so each distinct start needs a single breadth-first search, stopped once
+/// all of its goals are reached, and the search buffers are reused between
+/// starts. Ties follow the same lowest-id predecessor rule as
+/// `shortest_gate_path_within`.
+pub fn shortest_gate_paths_batch(
+    graph: &StarGraph,
+    pairs: &[(usize, usize)],
+) -> Vec<Result<Path, PathError>> {
+    let mut results = vec![Err(PathError::NoRoute); pairs.len()];
+    let mut by_start: HashMap<usize, Vec<usize>> = HashMap::new();
+    for (i, &(start, goal)) in pairs.iter().enumerate() {
+        match check_endpoints(graph, start, goal) {
+            Ok(()) => by_start.entry(start).or_default().push(i),
+            Err(err) => results[i] = Err(err),
+        }
     }
 
+    let mut parent = vec![None; graph.len()];
+    let mut depth = vec![0usize; graph.len()];
+    // `seen[idx] == generation` marks systems reached from the current start,
+    // so the buffers never need clearing.
+    let mut seen = vec![0u32; graph.len()];
+    let mut generation = 0;
+    let mut queue = VecDeque::new();
+    let starts = by_start.len();
+    for (start, members) in by_start {
+        progress::report(generation as usize, starts);
+        generation += 1;
+        let mut pending: HashSet<usize> = members.iter().map(|&i| pairs[i].1).collect();
+        seen[start] = generation;
+        depth[start] = 0;
+        parent[start] = None;
+        queue.clear();
+        queue.push_back(start);
+        // Once every goal is found, the level before the deepest one still
+        // has to finish so those goals keep their lowest-id predecessors.
+        let mut deepest = 0;
+        let mut exceeded = None;
+        while let Some(&current) = queue.front() {
+            if pending.is_empty() && depth[current] >= deepest {
+                break;
+            }
+            queue.pop_front();
+            if let Err(err) = budget::spend() {
+                exceeded = Some(err);
+                break;
+            }
+            stats::count_expanded();
+            for &next in &graph.adjacency[current] {
+                if seen[next] != generation {
+                    seen[next] = generation;
+                    depth[next] = depth[current] + 1;
+                    parent[next] = Some(current);
+                    if pending.remove(&next) {
+                        deepest = depth[next];
+                    }
+                    queue.push_back(next);
+                } else if depth[next] == depth[current] + 1 {
+                    prefer_lower_id(graph, &mut parent[next], current);
+                }
+            }
+            stats::record_frontier(queue.len());
+        }
+
+        for i in members {
+            let goal = pairs[i].1;
+            if let Some(err) = exceeded {
+                results[i] = Err(err.into());
+                continue;
+            }
+            if seen[goal] != generation {
+                continue;
+            }
+            let mut systems = vec![goal];
+            while let Some(prev) = parent[*systems.last().unwrap()] {
+                systems.push(prev);
+            }
+            systems.reverse();
+            results[i] = Ok(gate_route(graph, &systems));
+        }
+    }
+    results
+}
+
+/// Replaces an equal-length route's predecessor with `candidate` when it has
+/// the lower system id.
+fn prefer_lower_id(graph: &StarGraph, parent: &mut Option<usize>, candidate: usize) {
+    let id_of = |idx: usize| graph.systems[idx].id;
+    if parent.is_some_and(|prev| id_of(candidate) < id_of(prev)) {
+        *parent = Some(candidate);
+    }
+}
+
+/// A* pathfinding where each gate jump costs the Euclidean distance between
+/// the two systems, so the total cost is the travelled distance. The straight
+/// line to the goal never exceeds the remaining travel distance, which keeps
+/// the heuristic admissible and the result optimal.
+pub fn shortest_distance_path(
+    graph: &StarGraph,
+    start: usize,
+    goal: usize,
+) -> Result<Path, PathError> {
+    astar(
+        graph,
+        start,
+        goal,
+        |from, to| Some(graph.systems[from].distance(&graph.systems[to])),
+        |idx| heuristic(graph, idx, goal),
+    )
+}
+
+/// A* core shared by the public search variants. `edge_cost(from, to)`
+/// returns the cost of a gate jump, or `None` to forbid the edge;
+/// `estimate(idx)` is the heuristic distance from `idx` to the goal.
+pub(crate) fn astar<C, H>(
+    graph: &StarGraph,
+    start: usize,
+    goal: usize,
+    edge_cost: C,
+    estimate: H,
+) -> Result<Path, PathError>
+where
+    C: Fn(usize, usize) -> Option<Scalar>,
+    H: Fn(usize) -> Scalar,
+{
+    astar_over(
+        graph,
+        start,
+        goal,
+        |current, out| {
+            out.extend(
+                graph.adjacency[current]
+                    .iter()
+                    .filter_map(|&next| Some((next, edge_cost(current, next)?, StepMode::Gate))),
+            )
+        },
+        estimate,
+    )
+}
+
+/// A* over an implicit graph: `neighbours(idx, out)` appends every
+/// `(next, cost, mode)` move available from `idx`. Used where moves do not come
+/// from the gate adjacency, e.g. range-limited jumps found via the k-d tree.
+///
+/// Ties are broken by system id so results do not depend on heap internals
+/// or adjacency order: equal keys pop the lower id first, and a system
+/// reached at equal cost from several systems keeps the lowest-id one as its
+/// predecessor (the move listed first when both come from the same system).
+///
+/// Scores live in arrays indexed by system. The binary heap has no
+/// decrease-key, so an improved system is pushed again and the outdated
+/// entries are skipped when popped; expanded systems are closed and only
+/// reopened if a cheaper route to them turns up, which an inconsistent
+/// heuristic can cause.
+pub(crate) fn astar_over<N, H>(
+    graph: &StarGraph,
+    start: usize,
+    goal: usize,
+    mut neighbours: N,
+    estimate: H,
+) -> Result<Path, PathError>
+where
+    N: FnMut(usize, &mut Vec<(usize, Scalar, StepMode)>),
+    H: Fn(usize) -> Scalar,
+{
+    check_endpoints(graph, start, goal)?;
+
     #[derive(Copy, Clone, Debug)]
     struct Node {
         idx: usize,
-        f_score: f32,
+        id: u32,
+        g_score: Scalar,
+        f_score: Scalar,
     }
 
     impl Eq for Node {}
 
     impl PartialEq for Node {
         fn eq(&self, other: &Self) -> bool {
-            self.f_score.eq(&other.f_score)
+            self.cmp(other) == Ordering::Equal
         }
     }
 
@@ -36,8 +448,9 @@ pub fn shortest_gate_path(graph: &StarGraph, start: usize, goal: usize) -> Optio
         fn cmp(&self, other: &Self) -> Ordering {
             other
                 .f_score
-                .partial_cmp(&self.f_score)
-                .unwrap_or(Ordering::Equal)
+                .total_cmp(&self.f_score)
+                .then(other.id.cmp(&self.id))
+                .then(other.idx.cmp(&self.idx))
         }
     }
 
@@ -47,72 +460,324 @@ pub fn shortest_gate_path(graph: &StarGraph, start: usize, goal: usize) -> Optio
         }
     }
 
+    let n = graph.len();
+    let mut came_from: Vec<Option<(usize, StepMode)>> = vec![None; n];
+    let mut g_score = vec![Scalar::INFINITY; n];
+    let mut closed = vec![false; n];
+    g_score[start] = 0.0;
     let mut open = BinaryHeap::new();
     open.push(Node {
         idx: start,
-        f_score: heuristic(graph, start, goal),
+        id: graph.systems[start].id,
+        g_score: 0.0,
+        f_score: estimate(start),
     });
+    let mut moves = Vec::new();
 
-    let mut came_from: HashMap<usize, usize> = HashMap::new();
-    let mut g_score: HashMap<usize, f32> = HashMap::new();
-    g_score.insert(start, 0.0);
-
-    while let Some(Node { idx: current, .. }) = open.pop() {
+    while let Some(Node {
+        idx: current,
+        g_score: current_g,
+        ..
+    }) = open.pop()
+    {
+        if closed[current] || current_g > g_score[current] {
+            continue;
+        }
+        closed[current] = true;
+        budget::spend()?;
+        stats::count_expanded();
         if current == goal {
-            return Some(reconstruct_path(&came_from, current));
+            stats::record_heuristic(estimate(start), current_g);
+            return Ok(reconstruct_path(graph, &came_from, &g_score, current));
         }
 
-        let current_g = *g_score.get(&current).unwrap_or(&f32::INFINITY);
-
-        for &neighbor in &graph.adjacency[current] {
-            let tentative_g = current_g + 1.0; // one gate jump
-            if tentative_g < *g_score.get(&neighbor).unwrap_or(&f32::INFINITY) {
-                came_from.insert(neighbor, current);
-                g_score.insert(neighbor, tentative_g);
-                let f = tentative_g + heuristic(graph, neighbor, goal);
+        moves.clear();
+        neighbours(current, &mut moves);
+        for &(neighbor, step_cost, mode) in &moves {
+            let tentative_g = current_g + step_cost;
+            if tentative_g == g_score[neighbor] && step_cost > 0.0 {
+                // Only the predecessor changes, so nothing needs requeueing.
+                // Zero-cost moves are skipped so predecessors cannot cycle.
+                let id_of = |idx: usize| graph.systems[idx].id;
+                if came_from[neighbor].is_some_and(|(prev, _)| id_of(current) < id_of(prev)) {
+                    came_from[neighbor] = Some((current, mode));
+                }
+            } else if tentative_g < g_score[neighbor] {
+                came_from[neighbor] = Some((current, mode));
+                g_score[neighbor] = tentative_g;
+                closed[neighbor] = false;
                 open.push(Node {
                     idx: neighbor,
-                    f_score: f,
+                    id: graph.systems[neighbor].id,
+                    g_score: tentative_g,
+                    f_score: tentative_g + estimate(neighbor),
                 });
             }
         }
+        stats::record_frontier(open.len());
     }
 
-    None
+    Err(PathError::NoRoute)
 }
 
-fn heuristic(graph: &StarGraph, from: usize, to: usize) -> f32 {
-    let a = &graph.systems[from];
-    let b = &graph.systems[to];
-    a.distance(b)
+/// Bidirectional A* over the gate graph with the same cost model and
+/// heuristic as `shortest_gate_path`. A forward search from `start` and a
+/// backward search over incoming gates from `goal` expand alternately and
+/// stop once no shorter meeting point can exist. Ties follow the
+/// `astar_over` rule: equal keys pop the lower id first, a system keeps the
+/// lowest-id neighbour among equally cheap ones, and so does the meeting
+/// point.
+pub fn bidirectional_gate_path(
+    graph: &StarGraph,
+    start: usize,
+    goal: usize,
+) -> Result<Path, PathError> {
+    bidirectional_astar(
+        graph,
+        start,
+        goal,
+        |_, _| 1.0,
+        |a, b| jump_heuristic(graph, a, b),
+    )
+}
+
+#[derive(Copy, Clone, Debug)]
+struct Frontier {
+    idx: usize,
+    id: u32,
+    key: Scalar,
 }
 
-fn reconstruct_path(came_from: &HashMap<usize, usize>, mut current: usize) -> Vec<PathStep> {
-    let mut total_path = vec![current];
-    while let Some(&prev) = came_from.get(&current) {
+impl Eq for Frontier {}
+
+impl PartialEq for Frontier {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Ord for Frontier {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other
+            .key
+            .total_cmp(&self.key)
+            .then(other.id.cmp(&self.id))
+            .then(other.idx.cmp(&self.idx))
+    }
+}
+
+impl PartialOrd for Frontier {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// One direction of a bidirectional search.
+struct Side {
+    g: Vec<Scalar>,
+    parent: Vec<Option<usize>>,
+    open: BinaryHeap<Frontier>,
+}
+
+impl Side {
+    fn new(graph: &StarGraph, root: usize, key: Scalar) -> Self {
+        let mut g = vec![Scalar::INFINITY; graph.len()];
+        g[root] = 0.0;
+        Side {
+            g,
+            parent: vec![None; graph.len()],
+            open: BinaryHeap::from([Frontier {
+                idx: root,
+                id: graph.systems[root].id,
+                key,
+            }]),
+        }
+    }
+
+    fn top_key(&self) -> Scalar {
+        self.open.peek().map_or(Scalar::INFINITY, |f| f.key)
+    }
+}
+
+/// Bidirectional A* using the average of the forward and backward
+/// heuristics as potential (Ikeda et al., 1994), which makes both searches
+/// share the same reduced edge costs. With `h(a, b)` a consistent distance
+/// estimate, the search stops with an optimal path once the two smallest
+/// open keys sum to at least the best meeting cost found.
+fn bidirectional_astar<C, H>(
+    graph: &StarGraph,
+    start: usize,
+    goal: usize,
+    edge_cost: C,
+    h: H,
+) -> Result<Path, PathError>
+where
+    C: Fn(usize, usize) -> Scalar,
+    H: Fn(usize, usize) -> Scalar,
+{
+    check_endpoints(graph, start, goal)?;
+
+    let potential = |v: usize| (h(v, goal) - h(start, v)) / 2.0;
+    let mut forward = Side::new(graph, start, potential(start));
+    let mut backward = Side::new(graph, goal, -potential(goal));
+    let mut best = Scalar::INFINITY;
+    let mut meeting = None;
+
+    while forward.top_key() + backward.top_key() < best {
+        let expand_forward = forward.top_key() <= backward.top_key();
+        let (side, other, sign) = if expand_forward {
+            (&mut forward, &backward, 1.0)
+        } else {
+            (&mut backward, &forward, -1.0)
+        };
+        let Some(Frontier {
+            idx: current, key, ..
+        }) = side.open.pop()
+        else {
+            break;
+        };
+        if key > side.g[current] + sign * potential(current) {
+            continue; // stale entry
+        }
+        budget::spend()?;
+        stats::count_expanded();
+
+        let neighbours = if expand_forward {
+            &graph.adjacency[current]
+        } else {
+            graph.incoming(current)
+        };
+        for &next in neighbours {
+            let step = if expand_forward {
+                edge_cost(current, next)
+            } else {
+                edge_cost(next, current)
+            };
+            let tentative = side.g[current] + step;
+            if tentative == side.g[next] && step > 0.0 {
+                prefer_lower_id(graph, &mut side.parent[next], current);
+                continue;
+            }
+            if tentative > side.g[next] {
+                continue;
+            }
+            side.g[next] = tentative;
+            side.parent[next] = Some(current);
+            side.open.push(Frontier {
+                idx: next,
+                id: graph.systems[next].id,
+                key: tentative + sign * potential(next),
+            });
+            let through = tentative + other.g[next];
+            if through < best {
+                best = through;
+                meeting = Some(next);
+            } else if through == best && through.is_finite() {
+                prefer_lower_id(graph, &mut meeting, next);
+            }
+        }
+        stats::record_frontier(forward.open.len() + backward.open.len());
+    }
+
+    let meeting = meeting.ok_or(PathError::NoRoute)?;
+    stats::record_heuristic(h(start, goal), best);
+    let mut systems = vec![meeting];
+    let mut current = meeting;
+    while let Some(prev) = forward.parent[current] {
+        systems.push(prev);
         current = prev;
-        total_path.push(current);
     }
-    total_path.reverse();
+    systems.reverse();
+    current = meeting;
+    while let Some(next) = backward.parent[current] {
+        systems.push(next);
+        current = next;
+    }
 
-    let mut result = Vec::with_capacity(total_path.len());
-    let mut cost = 0.0_f32;
-    for (i, idx) in total_path.iter().enumerate() {
+    let mut cost: Scalar = 0.0;
+    let mut result = Vec::with_capacity(systems.len());
+    for (i, &idx) in systems.iter().enumerate() {
         if i > 0 {
-            cost += 1.0;
+            cost += edge_cost(systems[i - 1], idx);
         }
-        result.push(PathStep {
-            system_index: *idx,
+        let prev = i.checked_sub(1).map(|p| systems[p]);
+        result.push(PathStep::new(
+            graph,
+            prev,
+            idx,
             cost,
-        });
+            prev.map(|_| StepMode::Gate),
+        ));
     }
+    Ok(result)
+}
 
-    result
+fn heuristic(graph: &StarGraph, from: usize, to: usize) -> Scalar {
+    let a = &graph.systems[from];
+    let b = &graph.systems[to];
+    a.distance(b)
+}
+
+/// Lower bound on the gate jumps between two systems: raw distance would
+/// overestimate unit jump costs by the typical gate length and make A*
+/// return suboptimal routes.
+fn jump_heuristic(graph: &StarGraph, from: usize, to: usize) -> Scalar {
+    let longest = graph.max_gate_length();
+    if longest > 0.0 {
+        heuristic(graph, from, to) / longest
+    } else {
+        0.0
+    }
+}
+
+/// Unit-cost gate steps along `systems`.
+pub(crate) fn gate_route(graph: &StarGraph, systems: &[usize]) -> Path {
+    systems
+        .iter()
+        .enumerate()
+        .map(|(i, &system_index)| {
+            let prev = i.checked_sub(1).map(|p| systems[p]);
+            PathStep::new(
+                graph,
+                prev,
+                system_index,
+                i as Scalar,
+                prev.map(|_| StepMode::Gate),
+            )
+        })
+        .collect()
+}
+
+fn reconstruct_path(
+    graph: &StarGraph,
+    came_from: &[Option<(usize, StepMode)>],
+    g_score: &[Scalar],
+    mut current: usize,
+) -> Vec<PathStep> {
+    let mut total_path = vec![(current, None)];
+    while let Some((prev, mode)) = came_from[current] {
+        total_path.last_mut().unwrap().1 = Some(mode);
+        current = prev;
+        total_path.push((current, None));
+    }
+    total_path.reverse();
+
+    let mut prev = None;
+    total_path
+        .into_iter()
+        .map(|(idx, mode)| {
+            let cost = g_score[idx];
+            let step = PathStep::new(graph, prev, idx, cost, mode);
+            prev = Some(idx);
+            step
+        })
+        .collect()
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::engine::graph_from;
     use crate::System;
 
     #[test]
@@ -147,5 +812,285 @@ mod tests {
             .collect();
         assert_eq!(ids, vec![1, 2, 3]);
         assert!((path.last().unwrap().cost - 2.0).abs() < 1e-5);
+        let legs: Vec<Scalar> = path.iter().map(|p| p.leg_distance).collect();
+        assert_eq!(legs, vec![0.0, 1.0, 1.0]);
+    }
+
+    /// Square A-B-D / A-C-D.
+    fn square() -> StarGraph {
+        let adjacency = vec![vec![1, 2], vec![0, 3], vec![0, 3], vec![1, 2]];
+        graph_from(&[[0.0; 3]; 4], adjacency)
+    }
+
+    fn via(path: Path) -> usize {
+        path[1].system_index
+    }
+
+    #[test]
+    fn safest_preference_detours_around_risk() {
+        let graph = square();
+        let risk = [0.0, 5.0, 0.5, 0.0];
+        let safest = shortest_path(&graph, 0, 3, RoutePreference::Safest, Some(&risk)).unwrap();
+        assert_eq!(via(safest), 2);
+        let jumps = shortest_path(&graph, 0, 3, RoutePreference::Safest, None).unwrap();
+        assert_eq!(jumps.last().unwrap().cost, 2.0);
+    }
+
+    #[test]
+    fn custom_cost_models_steer_the_route() {
+        // A toll on entering C, and a model forbidding the gate into B.
+        let graph = square();
+        let toll = |_: usize, to: usize| if to == 2 { 3.0 } else { 1.0 };
+        let tolled = shortest_gate_path_with(&graph, 0, 3, &toll).unwrap();
+        assert_eq!(via(tolled), 1);
+        let closed: &dyn CostFn =
+            &|_: usize, to: usize| if to == 1 { Scalar::INFINITY } else { 1.0 };
+        assert_eq!(
+            via(shortest_gate_path_with(&graph, 0, 3, closed).unwrap()),
+            2
+        );
+    }
+
+    #[test]
+    fn jump_limit_is_enforced() {
+        // A -> B -> C
+        let graph = crate::engine::demo_graph();
+        assert_eq!(shortest_gate_path_within(&graph, 0, 2, 2).unwrap().len(), 3);
+        assert_eq!(
+            shortest_gate_path_within(&graph, 0, 2, 1).unwrap_err(),
+            PathError::ExceedsLimit {
+                jumps: 2,
+                max_jumps: 1
+            }
+        );
+        let mut cut = graph.clone();
+        cut.adjacency[0] = vec![1];
+        cut.rebuild_indices();
+        assert_eq!(
+            shortest_gate_path_within(&cut, 0, 3, 10).unwrap_err(),
+            PathError::NoRoute
+        );
+    }
+
+    #[test]
+    fn search_failures_are_typed() {
+        let graph = crate::engine::demo_graph();
+        assert_eq!(shortest_gate_path(&graph, 1, 1), Err(PathError::SameSystem));
+        assert_eq!(trivial_path(&graph, 1).len(), 1);
+        assert_eq!(
+            bidirectional_gate_path(&graph, 0, 7),
+            Err(PathError::IndexOutOfRange { index: 7, len: 4 })
+        );
+    }
+
+    #[test]
+    fn searches_report_a_spent_budget() {
+        let graph = crate::engine::demo_graph();
+        let budget = crate::budget::SearchBudget {
+            max_expansions: Some(1),
+            time_budget_ms: None,
+        };
+        let mut inner = None;
+        let outer = budget.run(|| inner = Some(shortest_distance_path(&graph, 3, 2)));
+        assert!(outer.is_err());
+        assert!(matches!(inner, Some(Err(PathError::BudgetExceeded(_)))));
+    }
+
+    #[test]
+    fn batch_matches_single_searches() {
+        let mut graph = crate::engine::demo_graph();
+        graph.adjacency[0] = vec![1]; // D is now a dead end no one reaches
+        graph.rebuild_indices();
+        let pairs = [(0, 2), (2, 0), (0, 1), (0, 0), (1, 3), (1, 9)];
+        let batch = shortest_gate_paths_batch(&graph, &pairs);
+        let indices = |p: &Path| p.iter().map(|s| s.system_index).collect::<Vec<_>>();
+        for (&(start, goal), result) in pairs.iter().zip(&batch) {
+            let single = shortest_gate_path_within(&graph, start, goal, usize::MAX);
+            assert_eq!(result.as_ref().map(indices), single.as_ref().map(indices));
+        }
+    }
+
+    #[test]
+    fn batch_reports_a_spent_budget() {
+        let graph = crate::engine::demo_graph();
+        let budget = crate::budget::SearchBudget {
+            max_expansions: Some(1),
+            time_budget_ms: None,
+        };
+        let mut batch = Vec::new();
+        let outer = budget.run(|| batch = shortest_gate_paths_batch(&graph, &[(0, 2), (3, 2)]));
+        assert!(outer.is_err());
+        assert!(batch
+            .iter()
+            .all(|result| matches!(result, Err(PathError::BudgetExceeded(_)))));
+    }
+
+    #[test]
+    fn bidirectional_matches_unidirectional_on_grid() {
+        // 6x6 grid with unit spacing, so straight-line distance never
+        // overestimates the remaining jumps.
+        let width = 6;
+        let mut positions = Vec::new();
+        let mut adjacency = Vec::new();
+        for y in 0..width {
+            for x in 0..width {
+                positions.push([x as Scalar, y as Scalar, 0.0]);
+                let mut neighbours = Vec::new();
+                if x > 0 {
+                    neighbours.push(y * width + x - 1);
+                }
+                if x + 1 < width {
+                    neighbours.push(y * width + x + 1);
+                }
+                if y > 0 {
+                    neighbours.push((y - 1) * width + x);
+                }
+                if y + 1 < width {
+                    neighbours.push((y + 1) * width + x);
+                }
+                adjacency.push(neighbours);
+            }
+        }
+        let graph = graph_from(&positions, adjacency);
+
+        assert_eq!(
+            bidirectional_gate_path(&graph, 7, 7),
+            shortest_gate_path(&graph, 7, 7)
+        );
+        for (start, goal) in [(0, 35), (5, 30), (7, 8), (14, 3)] {
+            let uni = shortest_gate_path(&graph, start, goal).expect("path");
+            let bi = bidirectional_gate_path(&graph, start, goal).expect("path");
+            assert_eq!(bi.len(), uni.len(), "{start} -> {goal}");
+            assert_eq!(bi.first().unwrap().system_index, start);
+            assert_eq!(bi.last().unwrap().system_index, goal);
+            for pair in bi.windows(2) {
+                assert!(graph.adjacency[pair[0].system_index].contains(&pair[1].system_index));
+            }
+        }
+    }
+
+    #[test]
+    fn jump_heuristic_keeps_fewest_jumps_optimal() {
+        // Two jumps via F, far behind the start, or three jumps straight
+        // towards the goal. Raw distance would rate F hopeless.
+        let positions = [0.0, 40.0, 70.0, 100.0, -100.0].map(|x| [x, 0.0, 0.0]);
+        let adjacency = vec![vec![1, 4], vec![2], vec![3], vec![], vec![3]];
+        let graph = graph_from(&positions, adjacency);
+        assert_eq!(graph.max_gate_length(), 200.0);
+
+        let path = shortest_gate_path(&graph, 0, 3).expect("path");
+        let indices: Vec<usize> = path.iter().map(|s| s.system_index).collect();
+        assert_eq!(indices, vec![0, 4, 3]);
+        let path = bidirectional_gate_path(&graph, 0, 3).expect("path");
+        assert_eq!(path.len(), 3);
+    }
+
+    #[test]
+    fn equal_cost_routes_prefer_lower_ids() {
+        // Two 2-jump routes from S to T through mirror-image systems, with
+        // the higher id listed first in every adjacency list.
+        let systems = [
+            (10, [0.0, 0.0, 0.0]),
+            (30, [1.0, 1.0, 0.0]),
+            (20, [1.0, -1.0, 0.0]),
+            (40, [2.0, 0.0, 0.0]),
+        ]
+        .into_iter()
+        .map(|(id, pos)| System {
+            id,
+            name: format!("S{id}"),
+            pos,
+        })
+        .collect();
+        let adjacency = vec![vec![1, 2], vec![3], vec![3], vec![]];
+        let graph = StarGraph::new(systems, adjacency);
+
+        let batch = shortest_gate_paths_batch(&graph, &[(0, 3)]).remove(0);
+        for path in [
+            shortest_gate_path(&graph, 0, 3),
+            shortest_distance_path(&graph, 0, 3),
+            shortest_gate_path_within(&graph, 0, 3, 2),
+            bidirectional_gate_path(&graph, 0, 3),
+            batch,
+        ] {
+            let ids: Vec<u32> = path
+                .expect("path")
+                .iter()
+                .map(|s| graph.systems[s.system_index].id)
+                .collect();
+            assert_eq!(ids, vec![10, 20, 40]);
+        }
+    }
+
+    #[test]
+    fn systems_are_expanded_at_most_once() {
+        // A complete graph along a line: every system is first reached by an
+        // expensive direct hop and later improved many times over.
+        let n = 40;
+        let positions: Vec<[Scalar; 3]> = (0..n).map(|i| [(i * i) as Scalar, 0.0, 0.0]).collect();
+        let adjacency = (0..n)
+            .map(|i| (0..n).filter(|&j| j != i).collect())
+            .collect();
+        let graph = graph_from(&positions, adjacency);
+
+        let recorder = stats::StatsRecorder::start();
+        let path = astar_over(
+            &graph,
+            0,
+            n - 1,
+            |idx, out| {
+                for &next in &graph.adjacency[idx] {
+                    let d = graph.systems[idx].distance(&graph.systems[next]);
+                    out.push((next, d, StepMode::Gate));
+                }
+            },
+            |_| 0.0,
+        )
+        .expect("path");
+        assert!(recorder.finish().nodes_expanded <= n as u64);
+        assert_eq!(path.last().unwrap().cost, ((n - 1) * (n - 1)) as Scalar);
+    }
+
+    #[test]
+    fn distance_path_prefers_shorter_travel() {
+        // A -> B -> C takes two jumps but detours 50 units away;
+        // A -> D -> E -> C takes three jumps along a straight line.
+        let systems = vec![
+            System {
+                id: 1,
+                name: "A".into(),
+                pos: [0.0, 0.0, 0.0],
+            },
+            System {
+                id: 2,
+                name: "B".into(),
+                pos: [0.0, 50.0, 0.0],
+            },
+            System {
+                id: 3,
+                name: "C".into(),
+                pos: [4.0, 0.0, 0.0],
+            },
+            System {
+                id: 4,
+                name: "D".into(),
+                pos: [1.0, 0.0, 0.0],
+            },
+            System {
+                id: 5,
+                name: "E".into(),
+                pos: [3.0, 0.0, 0.0],
+            },
+        ];
+        let adjacency = vec![vec![1, 3], vec![2], vec![], vec![4], vec![2]];
+        let graph = StarGraph::new(systems, adjacency);
+
+        let path = shortest_distance_path(&graph, 0, 2).expect("path");
+        let ids: Vec<u32> = path
+            .iter()
+            .map(|p| graph.systems[p.system_index].id)
+            .collect();
+        assert_eq!(ids, vec![1, 4, 5, 3]);
+        assert!((path.last().unwrap().cost - 4.0).abs() < 1e-5);
     }
 }
@@ -0,0 +1,158 @@
+use crate::graph::components::StronglyConnected;
+use crate::graph::graph::StarGraph;
+
+/// Most components with gates for which `Reachability::build` stores
+/// bitmaps; one bit per pair of them keeps this at 8 MiB. Systems without
+/// any gate do not count.
+pub const MAX_REACHABILITY_COMPONENTS: usize = 8_192;
+
+/// Row of a component without gates, which reaches only itself.
+const NO_ROW: u32 = u32::MAX;
+
+/// Per-component bitmaps of the components reachable through gates, i.e.
+/// the transitive closure of the condensed gate graph. Answers whether any
+/// route exists between two systems with one bit test, so candidates in
+/// stranded pockets can be filtered without a search. Systems without any
+/// gate get no row, so maps with many of them stay within the limit.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Reachability {
+    /// row_of[c] is the bitmap row of component `c`, or `NO_ROW`.
+    row_of: Vec<u32>,
+    words_per_row: usize,
+    /// Row `r` reaches row `s` when bit `s` of row `r` is set.
+    bits: Vec<u64>,
+}
+
+impl Reachability {
+    /// Builds the bitmaps for the components of `graph`, or `None` when
+    /// more than `MAX_REACHABILITY_COMPONENTS` of them have gates.
+    pub fn build(graph: &StarGraph, scc: &StronglyConnected) -> Option<Self> {
+        let mut members = vec![Vec::new(); scc.len()];
+        for (v, &c) in scc.components().iter().enumerate() {
+            members[c as usize].push(v);
+        }
+        // Rows follow component order, so gates still only lead to
+        // lower-numbered rows.
+        let mut row_of = vec![NO_ROW; scc.len()];
+        let mut rows = 0;
+        for (c, systems) in members.iter().enumerate() {
+            let gated = systems
+                .iter()
+                .any(|&v| !graph.adjacency[v].is_empty() || !graph.incoming(v).is_empty());
+            if gated {
+                row_of[c] = rows;
+                rows += 1;
+            }
+        }
+        let rows = rows as usize;
+        if rows > MAX_REACHABILITY_COMPONENTS {
+            return None;
+        }
+        let words = rows.div_ceil(64);
+        let mut bits = vec![0; rows * words];
+        // Lower rows are complete by the time a higher one is filled in.
+        for (c, systems) in members.iter().enumerate() {
+            if row_of[c] == NO_ROW {
+                continue;
+            }
+            let r = row_of[c] as usize;
+            bits[r * words + r / 64] |= 1 << (r % 64);
+            for &v in systems {
+                for &w in &graph.adjacency[v] {
+                    let s = row_of[scc.component_of(w) as usize] as usize;
+                    if s != r {
+                        let (lower, row) = bits.split_at_mut(r * words);
+                        for (bit, &other) in row[..words].iter_mut().zip(&lower[s * words..]) {
+                            *bit |= other;
+                        }
+                    }
+                }
+            }
+        }
+        Some(Reachability {
+            row_of,
+            words_per_row: words,
+            bits,
+        })
+    }
+
+    /// Whether any system of component `from` has a route to component `to`.
+    pub fn reaches(&self, from: u32, to: u32) -> bool {
+        if from == to {
+            return true;
+        }
+        let (r, s) = (self.row_of[from as usize], self.row_of[to as usize]);
+        if r == NO_ROW || s == NO_ROW {
+            return false;
+        }
+        let (r, s) = (r as usize, s as usize);
+        self.bits[r * self.words_per_row + s / 64] >> (s % 64) & 1 == 1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::graph_from;
+
+    /// A one-way chain of `n` systems, each its own component.
+    fn chain(n: usize) -> StarGraph {
+        let adjacency = (0..n)
+            .map(|i| if i + 1 < n { vec![i + 1] } else { vec![] })
+            .collect();
+        graph_from(&vec![[0.0; 3]; n], adjacency)
+    }
+
+    #[test]
+    fn bitmaps_hold_the_transitive_closure() {
+        // Two-way pair 0<->1, one-way 1 -> 2 -> 3, isolated 4, one-way 5 -> 0.
+        let adjacency = vec![vec![1], vec![0, 2], vec![3], vec![], vec![], vec![0]];
+        let graph = graph_from(&[[0.0; 3]; 6], adjacency);
+        let scc = StronglyConnected::build(&graph);
+        let reach = Reachability::build(&graph, &scc).unwrap();
+        let reaches = |a: usize, b: usize| reach.reaches(scc.component_of(a), scc.component_of(b));
+        assert!(reaches(5, 3));
+        assert!(reaches(1, 0));
+        assert!(!reaches(3, 2));
+        assert!(!reaches(0, 5));
+        assert!(!reaches(0, 4));
+        assert!(reaches(4, 4));
+        // `may_reach` alone cannot rule out the isolated system.
+        assert!((0..6).any(|v| scc.may_reach(v, 4)));
+    }
+
+    #[test]
+    fn rows_span_several_words() {
+        let graph = chain(130);
+        let scc = StronglyConnected::build(&graph);
+        let reach = Reachability::build(&graph, &scc).unwrap();
+        let reaches = |a: usize, b: usize| reach.reaches(scc.component_of(a), scc.component_of(b));
+        assert!(reaches(0, 129));
+        assert!(reaches(70, 100));
+        assert!(!reaches(129, 0));
+        assert!(!reaches(100, 70));
+    }
+
+    #[test]
+    fn too_many_components_store_no_bitmaps() {
+        let graph = chain(MAX_REACHABILITY_COMPONENTS + 1);
+        let scc = StronglyConnected::build(&graph);
+        assert_eq!(Reachability::build(&graph, &scc), None);
+    }
+
+    #[test]
+    fn systems_without_gates_do_not_count_towards_the_limit() {
+        // A pair 0 -> 1 and more isolated systems than the limit.
+        let n = MAX_REACHABILITY_COMPONENTS + 10;
+        let mut adjacency = vec![Vec::new(); n];
+        adjacency[0].push(1);
+        let graph = graph_from(&vec![[0.0; 3]; n], adjacency);
+        let scc = StronglyConnected::build(&graph);
+        let reach = Reachability::build(&graph, &scc).unwrap();
+        let reaches = |a: usize, b: usize| reach.reaches(scc.component_of(a), scc.component_of(b));
+        assert!(reaches(0, 1));
+        assert!(!reaches(1, 0));
+        assert!(reaches(5, 5));
+        assert!(!reaches(0, 5) && !reaches(5, 1));
+    }
+}
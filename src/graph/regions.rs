@@ -0,0 +1,287 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use thiserror::Error;
+
+use crate::graph::graph::StarGraph;
+use crate::graph::pathfinder::{astar, shortest_gate_path, CostFn, JumpCost, Path, PathError};
+use crate::Scalar;
+
+/// Default number of border crossings kept per ordered pair of adjacent regions.
+pub const DEFAULT_CROSSINGS_PER_PAIR: usize = 4;
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum RegionError {
+    #[error("region assignment covers {assigned} systems but the graph has {systems}")]
+    AssignmentLength { systems: usize, assigned: usize },
+}
+
+/// (score, from_idx, to_idx) of a candidate border crossing; lower is better.
+type ScoredCrossing = (Scalar, usize, usize);
+
+/// Precomputed border crossings between adjacent regions, used as a
+/// lightweight two-level routing scheme: long searches first pick a route
+/// through the region graph, then only expand systems inside those regions
+/// and only cross borders through the best known gates.
+#[derive(Clone, Debug)]
+pub struct RegionShortcuts {
+    /// region_of[i] is the region id of graph.systems[i]
+    region_of: Vec<u32>,
+    /// neighbours of each region in the region graph, sorted
+    neighbours: HashMap<u32, Vec<u32>>,
+    /// best gate edges (from_idx, to_idx) for each ordered adjacent region pair
+    crossings: HashMap<(u32, u32), Vec<(usize, usize)>>,
+}
+
+impl RegionShortcuts {
+    /// Builds the table from a per-system region assignment, keeping at most
+    /// `crossings_per_pair` crossings per ordered region pair. Crossings whose
+    /// endpoints sit closest to their regions' centroids are preferred since
+    /// they lead into the interior rather than along the border.
+    pub fn build(
+        graph: &StarGraph,
+        region_of: Vec<u32>,
+        crossings_per_pair: usize,
+    ) -> Result<Self, RegionError> {
+        if region_of.len() != graph.len() {
+            return Err(RegionError::AssignmentLength {
+                systems: graph.len(),
+                assigned: region_of.len(),
+            });
+        }
+        let centroids = region_centroids(graph, &region_of);
+
+        let mut candidates: HashMap<(u32, u32), Vec<ScoredCrossing>> = HashMap::new();
+        for (from, neighbours) in graph.adjacency.iter().enumerate() {
+            for &to in neighbours {
+                let (a, b) = (region_of[from], region_of[to]);
+                if a == b {
+                    continue;
+                }
+                let score = graph.systems[from].distance_to_point(centroids[&a])
+                    + graph.systems[to].distance_to_point(centroids[&b]);
+                candidates
+                    .entry((a, b))
+                    .or_default()
+                    .push((score, from, to));
+            }
+        }
+
+        let mut neighbours: HashMap<u32, Vec<u32>> = HashMap::new();
+        let mut crossings = HashMap::with_capacity(candidates.len());
+        for ((a, b), mut edges) in candidates {
+            edges.sort_by(|x, y| x.0.total_cmp(&y.0));
+            edges.truncate(crossings_per_pair.max(1));
+            neighbours.entry(a).or_default().push(b);
+            crossings.insert((a, b), edges.into_iter().map(|(_, f, t)| (f, t)).collect());
+        }
+        for list in neighbours.values_mut() {
+            list.sort_unstable();
+        }
+
+        Ok(RegionShortcuts {
+            region_of,
+            neighbours,
+            crossings,
+        })
+    }
+
+    pub fn region_of(&self, index: usize) -> u32 {
+        self.region_of[index]
+    }
+
+    /// Stored border crossings leading from region `from` into region `to`.
+    pub fn crossings(&self, from: u32, to: u32) -> &[(usize, usize)] {
+        self.crossings
+            .get(&(from, to))
+            .map(Vec::as_slice)
+            .unwrap_or(&[])
+    }
+
+    /// Fewest-borders route through the region graph, inclusive of both ends.
+    pub fn region_route(&self, from: u32, to: u32) -> Option<Vec<u32>> {
+        let mut came_from: HashMap<u32, u32> = HashMap::new();
+        let mut queue = VecDeque::from([from]);
+        let mut seen = HashSet::from([from]);
+        while let Some(region) = queue.pop_front() {
+            if region == to {
+                let mut route = vec![to];
+                let mut current = to;
+                while let Some(&prev) = came_from.get(&current) {
+                    route.push(prev);
+                    current = prev;
+                }
+                route.reverse();
+                return Some(route);
+            }
+            for &next in self.neighbours.get(&region).into_iter().flatten() {
+                if seen.insert(next) {
+                    came_from.insert(next, region);
+                    queue.push_back(next);
+                }
+            }
+        }
+        None
+    }
+}
+
+/// Gate path search seeded by the region shortcut table. The search is
+/// confined to the regions on the region-level route and may only cross
+/// borders via stored crossings, so the route is the fewest jumps within
+/// that corridor, not necessarily across the whole map. If the corridor
+/// holds no path the full unrestricted search is used instead, so results
+/// are never worse than "no path".
+pub fn shortest_gate_path_via_regions(
+    graph: &StarGraph,
+    shortcuts: &RegionShortcuts,
+    start: usize,
+    goal: usize,
+) -> Result<Path, PathError> {
+    let (from, to) = (shortcuts.region_of(start), shortcuts.region_of(goal));
+    if from == to {
+        return shortest_gate_path(graph, start, goal);
+    }
+    let route = shortcuts.region_route(from, to).ok_or(PathError::NoRoute)?;
+
+    let corridor: HashSet<u32> = route.iter().copied().collect();
+    let allowed_crossings: HashSet<(usize, usize)> = route
+        .windows(2)
+        .flat_map(|pair| shortcuts.crossings(pair[0], pair[1]).iter().copied())
+        .collect();
+    let allowed = |a: usize, b: usize| {
+        let (ra, rb) = (shortcuts.region_of(a), shortcuts.region_of(b));
+        if ra == rb {
+            corridor.contains(&ra)
+        } else {
+            allowed_crossings.contains(&(a, b))
+        }
+    };
+    let seeded = astar(
+        graph,
+        start,
+        goal,
+        |a, b| allowed(a, b).then(|| JumpCost.cost(a, b)),
+        |idx| JumpCost.estimate(graph, idx, goal),
+    );
+    seeded.or_else(|_| shortest_gate_path(graph, start, goal))
+}
+
+fn region_centroids(graph: &StarGraph, region_of: &[u32]) -> HashMap<u32, [Scalar; 3]> {
+    let mut sums: HashMap<u32, ([Scalar; 3], usize)> = HashMap::new();
+    for (system, &region) in graph.systems.iter().zip(region_of) {
+        let entry = sums.entry(region).or_insert(([0.0; 3], 0));
+        for axis in 0..3 {
+            entry.0[axis] += system.pos[axis];
+        }
+        entry.1 += 1;
+    }
+    sums.into_iter()
+        .map(|(region, (sum, count))| {
+            let n = count as Scalar;
+            (region, [sum[0] / n, sum[1] / n, sum[2] / n])
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::graph_from;
+    use crate::stats::StatsRecorder;
+
+    /// Two regions, {0, 1, 3} and {2, 4, 5}, joined by gates 1->2 and 3->4
+    /// (and back).
+    fn two_regions() -> (StarGraph, RegionShortcuts) {
+        let adjacency = vec![
+            vec![1, 3],
+            vec![0, 2],
+            vec![1, 5],
+            vec![0, 4],
+            vec![3, 5],
+            vec![2, 4],
+        ];
+        let graph = graph_from(&[[0.0; 3]; 6], adjacency);
+        let shortcuts = RegionShortcuts::build(&graph, vec![1, 1, 2, 1, 2, 2], 1).unwrap();
+        (graph, shortcuts)
+    }
+
+    /// Unit-spaced systems at `cells` with two-way gates between grid
+    /// neighbours, and the region of each.
+    fn grid(cells: &[((i32, i32), u32)]) -> (StarGraph, Vec<u32>) {
+        let index: HashMap<(i32, i32), usize> = cells
+            .iter()
+            .enumerate()
+            .map(|(i, &(c, _))| (c, i))
+            .collect();
+        let adjacency = cells
+            .iter()
+            .map(|&((x, y), _)| {
+                [(x - 1, y), (x + 1, y), (x, y - 1), (x, y + 1)]
+                    .iter()
+                    .filter_map(|c| index.get(c).copied())
+                    .collect()
+            })
+            .collect();
+        let positions: Vec<[Scalar; 3]> = cells
+            .iter()
+            .map(|&((x, y), _)| [x as Scalar, y as Scalar, 0.0])
+            .collect();
+        let regions = cells.iter().map(|&(_, r)| r).collect();
+        (graph_from(&positions, adjacency), regions)
+    }
+
+    #[test]
+    fn region_routes_cross_at_the_border_gates() {
+        let (_, shortcuts) = two_regions();
+        assert_eq!(shortcuts.region_route(1, 2), Some(vec![1, 2]));
+        assert_eq!(shortcuts.crossings(1, 2).len(), 1);
+    }
+
+    #[test]
+    fn region_seeded_path_skips_dead_end_regions() {
+        // Start region 1 is the column x = 0 and goal region 3 the column
+        // x = 7. Region 4, the block between them, is a dead end open only
+        // to region 1; region 2 is the way round through y = 6.
+        let mut cells = Vec::new();
+        for y in 0..5 {
+            cells.push(((0, y), 1));
+            cells.push(((7, y), 3));
+            cells.extend((1..6).map(|x| ((x, y), 4)));
+        }
+        cells.push(((0, 5), 2));
+        cells.push(((7, 5), 2));
+        cells.extend((0..8).map(|x| ((x, 6), 2)));
+        let (graph, regions) = grid(&cells);
+        let shortcuts = RegionShortcuts::build(&graph, regions, 2).unwrap();
+        let (start, goal) = (2 * 7, 2 * 7 + 1); // (0, 2) and (7, 2)
+
+        let recorder = StatsRecorder::start();
+        let full = shortest_gate_path(&graph, start, goal).expect("path");
+        let full_expanded = recorder.finish().nodes_expanded;
+        let recorder = StatsRecorder::start();
+        let seeded = shortest_gate_path_via_regions(&graph, &shortcuts, start, goal).expect("path");
+        let seeded_expanded = recorder.finish().nodes_expanded;
+
+        assert_eq!(seeded.len(), full.len());
+        assert_eq!(seeded.len() - 1, 15);
+        assert!(
+            2 * seeded_expanded <= full_expanded,
+            "{seeded_expanded} vs {full_expanded}"
+        );
+    }
+
+    #[test]
+    fn bad_assignments_and_positions_do_not_panic() {
+        let (graph, _) = two_regions();
+        assert_eq!(
+            RegionShortcuts::build(&graph, vec![1, 2], 1).unwrap_err(),
+            RegionError::AssignmentLength {
+                systems: 6,
+                assigned: 2
+            }
+        );
+        let mut nan = graph.clone();
+        nan.systems[1].pos[0] = Scalar::NAN;
+        let shortcuts = RegionShortcuts::build(&nan, vec![1, 1, 2, 1, 2, 2], 1).unwrap();
+        assert_eq!(shortcuts.crossings(1, 2).len(), 1);
+    }
+}
@@ -0,0 +1,302 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+
+use serde::Serialize;
+
+use crate::graph::graph::StarGraph;
+use crate::graph::pathfinder::{
+    shortest_gate_path, trivial_path, Path, PathError, PathStep, StepMode,
+};
+use crate::Scalar;
+
+/// Removes immediate backtracks (`A, B, A` becomes `A`) and consecutive
+/// repeats (`A, A` becomes `A`), leaving longer loops intact.
+pub fn strip_backtracks<T: Copy + PartialEq>(route: &[T]) -> Vec<T> {
+    let mut result: Vec<T> = Vec::with_capacity(route.len());
+    for &system in route {
+        if result.last() == Some(&system) {
+            continue;
+        }
+        if result.len() >= 2 && result[result.len() - 2] == system {
+            result.pop();
+            continue;
+        }
+        result.push(system);
+    }
+    result
+}
+
+/// Collapses every revisit of a system by cutting out the loop between the
+/// two visits, so each system appears at most once. Backtracks are a special
+/// case of such a loop.
+pub fn canonicalize_route<T: Copy + Eq + Hash>(route: &[T]) -> Vec<T> {
+    let mut result: Vec<T> = Vec::with_capacity(route.len());
+    let mut position: HashMap<T, usize> = HashMap::new();
+    for &system in route {
+        if let Some(&first) = position.get(&system) {
+            for removed in result.drain(first + 1..) {
+                position.remove(&removed);
+            }
+            continue;
+        }
+        position.insert(system, result.len());
+        result.push(system);
+    }
+    result
+}
+
+/// Direction-independent key for a route: the canonical form, reversed if
+/// that makes it lexicographically smaller. Equal keys mean equal routes.
+pub fn undirected_route_key<T: Copy + Eq + Hash + Ord>(route: &[T]) -> Vec<T> {
+    let forward = canonicalize_route(route);
+    let mut backward = forward.clone();
+    backward.reverse();
+    forward.min(backward)
+}
+
+/// Returns true if both routes visit the same systems in the same order after
+/// canonicalization, travelled in either direction.
+pub fn routes_equal_undirected<T: Copy + Eq + Hash + Ord>(a: &[T], b: &[T]) -> bool {
+    undirected_route_key(a) == undirected_route_key(b)
+}
+
+/// Splits a route into consecutive chunks of at most `max_waypoints`
+/// systems for clients that cap how many waypoints can be set at once. Each
+/// chunk starts where the previous one ended, so the boundary system appears
+/// in both. A limit below 2 cannot make progress with overlap and yields one
+/// system per chunk.
+pub fn chunk_route<T: Clone>(route: &[T], max_waypoints: usize) -> Vec<Vec<T>> {
+    if max_waypoints < 2 {
+        return route.iter().map(|t| vec![t.clone()]).collect();
+    }
+    if route.len() <= max_waypoints {
+        return vec![route.to_vec()];
+    }
+    let mut chunks = Vec::new();
+    let mut start = 0;
+    while start + 1 < route.len() {
+        let end = (start + max_waypoints).min(route.len());
+        chunks.push(route[start..end].to_vec());
+        start = end - 1;
+    }
+    chunks
+}
+
+/// Cost of a route in one dataset version.
+#[derive(Clone, Copy, Debug, Serialize, PartialEq)]
+pub struct RouteCost {
+    pub jumps: usize,
+    pub distance: Scalar,
+}
+
+/// How a saved route fares against a newer dataset version.
+#[derive(Clone, Debug, Serialize, PartialEq)]
+pub struct RouteStability {
+    /// True if every system and gate of the route still exists.
+    pub valid: bool,
+    /// Cost in the old dataset, or `None` if the route was not valid there.
+    pub old_cost: Option<RouteCost>,
+    /// Cost in the new dataset, or `None` if the route is broken.
+    pub new_cost: Option<RouteCost>,
+    /// Position in the route of the first system that is missing or cannot
+    /// be reached from its predecessor.
+    pub first_break: Option<usize>,
+    /// For broken routes: the intact prefix followed by a fresh gate path to
+    /// the destination, as system ids.
+    pub repaired: Option<Vec<u32>>,
+    pub repaired_cost: Option<RouteCost>,
+}
+
+/// Re-evaluates a route of system ids, saved against `old`, on the `new`
+/// dataset version and repairs it from the first break if possible.
+pub fn evaluate_route(old: &StarGraph, new: &StarGraph, route: &[u32]) -> RouteStability {
+    let old_cost = route_cost(old, route).ok();
+    let (new_cost, first_break) = match route_cost(new, route) {
+        Ok(cost) => (Some(cost), None),
+        Err(position) => (None, Some(position)),
+    };
+
+    let repaired = first_break.and_then(|position| repair_route(new, route, position));
+    let repaired_cost = repaired
+        .as_deref()
+        .and_then(|ids| route_cost(new, ids).ok());
+    RouteStability {
+        valid: first_break.is_none(),
+        old_cost,
+        new_cost,
+        first_break,
+        repaired,
+        repaired_cost,
+    }
+}
+
+/// Cost of following `route` through `graph`, or the position of the first
+/// system that is unknown or not gate-connected to its predecessor.
+pub fn route_cost(graph: &StarGraph, route: &[u32]) -> Result<RouteCost, usize> {
+    let mut cost = RouteCost {
+        jumps: 0,
+        distance: 0.0,
+    };
+    let mut previous: Option<usize> = None;
+    for (position, &id) in route.iter().enumerate() {
+        let idx = graph.index_of_id(id).ok_or(position)?;
+        if let Some(prev) = previous {
+            if !graph.adjacency[prev].contains(&idx) {
+                return Err(position);
+            }
+            cost.jumps += 1;
+            cost.distance += graph.systems[prev].distance(&graph.systems[idx]);
+        }
+        previous = Some(idx);
+    }
+    Ok(cost)
+}
+
+/// Where a pilot stands on a route and what is left of it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RouteProgress {
+    /// Position in the route of the pilot's system, or `None` if they have
+    /// left the route.
+    pub position: Option<usize>,
+    /// True if `remaining` is a fresh gate path because the pilot left the
+    /// route or its rest no longer exists.
+    pub rerouted: bool,
+    /// From the pilot's system to the destination; costs count jumps.
+    pub remaining: Path,
+}
+
+/// Progress of a pilot in system `current` along `route`, a list of system
+/// ids. A system the route visits twice counts as its first visit. The rest
+/// of the route is followed while every system and gate of it exists;
+/// otherwise the pilot gets the fewest-jumps gate path to the destination.
+pub fn route_progress(
+    graph: &StarGraph,
+    route: &[u32],
+    current: usize,
+) -> Result<RouteProgress, PathError> {
+    let len = graph.systems.len();
+    if current >= len {
+        return Err(PathError::IndexOutOfRange {
+            index: current,
+            len,
+        });
+    }
+    let destination = route
+        .last()
+        .and_then(|&id| graph.index_of_id(id))
+        .ok_or(PathError::NoRoute)?;
+    let position = route.iter().position(|&id| id == graph.systems[current].id);
+    if let Some(position) = position {
+        let rest = &route[position..];
+        if route_cost(graph, rest).is_ok() {
+            let mut prev = None;
+            let remaining = rest
+                .iter()
+                .enumerate()
+                .map(|(jumps, &id)| {
+                    let idx = graph.index_of_id(id).expect("route_cost checked every id");
+                    let mode = prev.map(|_| StepMode::Gate);
+                    let step = PathStep::new(graph, prev, idx, jumps as Scalar, mode);
+                    prev = Some(idx);
+                    step
+                })
+                .collect();
+            return Ok(RouteProgress {
+                position: Some(position),
+                rerouted: false,
+                remaining,
+            });
+        }
+    }
+    let remaining = match shortest_gate_path(graph, current, destination) {
+        Ok(path) => path,
+        Err(PathError::SameSystem) => trivial_path(graph, current),
+        Err(err) => return Err(err),
+    };
+    Ok(RouteProgress {
+        position,
+        rerouted: true,
+        remaining,
+    })
+}
+
+fn repair_route(graph: &StarGraph, route: &[u32], first_break: usize) -> Option<Vec<u32>> {
+    let destination = graph.index_of_id(*route.last()?)?;
+    // The system just before the break is the last one known to be intact.
+    let resume_at = first_break.checked_sub(1)?;
+    let resume = graph.index_of_id(route[resume_at])?;
+    let detour = match shortest_gate_path(graph, resume, destination) {
+        Ok(detour) => detour,
+        Err(PathError::SameSystem) => trivial_path(graph, resume),
+        Err(_) => return None,
+    };
+
+    let mut repaired = route[..resume_at].to_vec();
+    repaired.extend(
+        detour
+            .iter()
+            .map(|step| graph.systems[step.system_index].id),
+    );
+    Some(repaired)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::demo_graph;
+
+    #[test]
+    fn canonicalization_and_comparison() {
+        assert_eq!(strip_backtracks(&[1, 2, 2, 3, 2, 4]), vec![1, 2, 4]);
+        assert_eq!(strip_backtracks(&[1, 2, 3, 1]), vec![1, 2, 3, 1]);
+        assert_eq!(canonicalize_route(&[1, 2, 3, 4, 2, 5]), vec![1, 2, 5]);
+        assert!(routes_equal_undirected(&[1, 2, 3, 2, 4], &[4, 2, 1]));
+        assert!(!routes_equal_undirected(&[1, 2, 4], &[1, 3, 4]));
+    }
+
+    #[test]
+    fn chunks_share_boundary_systems() {
+        let chunks = chunk_route(&[1, 2, 3, 4, 5, 6], 3);
+        assert_eq!(chunks, vec![vec![1, 2, 3], vec![3, 4, 5], vec![5, 6]]);
+        assert_eq!(chunk_route(&[1, 2], 3), vec![vec![1, 2]]);
+    }
+
+    #[test]
+    fn broken_route_is_repaired() {
+        let old = demo_graph();
+        // New version drops the direct D -> A gate but adds D -> C.
+        let mut new = old.clone();
+        new.adjacency[3] = vec![2];
+        new.adjacency[2].push(3);
+        new.rebuild_indices();
+
+        let intact = evaluate_route(&old, &new, &[1, 2, 3]);
+        assert!(intact.valid);
+        assert_eq!(intact.new_cost.map(|c| c.jumps), Some(2));
+
+        let broken = evaluate_route(&old, &new, &[4, 1, 2]);
+        assert!(!broken.valid);
+        assert_eq!(broken.old_cost.map(|c| c.jumps), Some(2));
+        assert_eq!(broken.first_break, Some(1));
+        assert_eq!(broken.repaired, Some(vec![4, 3, 2]));
+    }
+
+    #[test]
+    fn progress_follows_the_route_or_reroutes() {
+        let graph = demo_graph();
+        let on_route = route_progress(&graph, &[4, 1, 2, 3], 1).unwrap();
+        assert_eq!((on_route.position, on_route.rerouted), (Some(2), false));
+        let rest: Vec<usize> = on_route.remaining.iter().map(|s| s.system_index).collect();
+        assert_eq!(rest, vec![1, 2]);
+        assert_eq!(on_route.remaining[1].cost, 1.0);
+
+        // The pilot strayed to D: a fresh path from there.
+        let strayed = route_progress(&graph, &[1, 2, 3], 3).unwrap();
+        assert_eq!((strayed.position, strayed.rerouted), (None, true));
+        assert_eq!(strayed.remaining.len(), 4);
+
+        let arrived = route_progress(&graph, &[1, 2, 3], 2).unwrap();
+        assert_eq!(arrived.remaining.len(), 1);
+        assert_eq!(route_progress(&graph, &[], 0), Err(PathError::NoRoute));
+    }
+}
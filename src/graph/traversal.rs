@@ -0,0 +1,135 @@
+use std::collections::{HashSet, VecDeque};
+
+use crate::graph::graph::StarGraph;
+use crate::stats;
+
+/// Breadth-first search from `origin` returning up to `n` systems not in
+/// `visited`, closest first by gate jumps, as `(index, jumps)`. Visited
+/// systems are still travelled through; they are just not reported. The
+/// origin itself is never reported.
+pub fn nearest_unvisited_by_jumps(
+    graph: &StarGraph,
+    origin: usize,
+    visited: &HashSet<usize>,
+    n: usize,
+) -> Vec<(usize, usize)> {
+    nearest_by_jumps_where(graph, origin, n, |idx| !visited.contains(&idx))
+}
+
+/// Up to `n` hub systems, those with more than `degree_threshold` gates,
+/// closest first by gate jumps from `origin`, as `(index, jumps)`. The
+/// origin is reported at zero jumps when it is itself a hub.
+pub fn nearest_hubs_by_jumps(
+    graph: &StarGraph,
+    origin: usize,
+    degree_threshold: usize,
+    n: usize,
+) -> Vec<(usize, usize)> {
+    let is_hub = |idx: usize| graph.adjacency[idx].len() > degree_threshold;
+    if n == 0 {
+        return Vec::new();
+    }
+    if !is_hub(origin) {
+        return nearest_by_jumps_where(graph, origin, n, is_hub);
+    }
+    let mut results = vec![(origin, 0)];
+    results.extend(nearest_by_jumps_where(graph, origin, n - 1, is_hub));
+    results
+}
+
+/// Every system reachable from `origin` in at most `max_jumps` gate jumps,
+/// grouped by distance: `rings[k]` holds the systems exactly `k` jumps away
+/// (ring 0 is the origin), each ring in ascending index order.
+pub fn systems_within_jumps(graph: &StarGraph, origin: usize, max_jumps: usize) -> Vec<Vec<usize>> {
+    let mut seen = vec![false; graph.len()];
+    seen[origin] = true;
+    let mut rings = vec![vec![origin]];
+    while rings.len() <= max_jumps {
+        let mut next_ring = Vec::new();
+        for &current in &rings[rings.len() - 1] {
+            stats::count_expanded();
+            for &next in &graph.adjacency[current] {
+                if !seen[next] {
+                    seen[next] = true;
+                    next_ring.push(next);
+                }
+            }
+        }
+        if next_ring.is_empty() {
+            break;
+        }
+        next_ring.sort_unstable();
+        rings.push(next_ring);
+    }
+    rings
+}
+
+/// Breadth-first search from `origin` for the first `n` other systems that
+/// satisfy `report`; non-matching systems are still travelled through.
+fn nearest_by_jumps_where<F>(
+    graph: &StarGraph,
+    origin: usize,
+    n: usize,
+    report: F,
+) -> Vec<(usize, usize)>
+where
+    F: Fn(usize) -> bool,
+{
+    let mut results = Vec::new();
+    if n == 0 {
+        return results;
+    }
+    let mut seen = vec![false; graph.len()];
+    seen[origin] = true;
+    let mut queue = VecDeque::from([(origin, 0usize)]);
+    while let Some((current, jumps)) = queue.pop_front() {
+        stats::count_expanded();
+        for &next in &graph.adjacency[current] {
+            if seen[next] {
+                continue;
+            }
+            seen[next] = true;
+            if report(next) {
+                results.push((next, jumps + 1));
+                if results.len() == n {
+                    return results;
+                }
+            }
+            queue.push_back((next, jumps + 1));
+        }
+    }
+    results
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::demo_graph;
+
+    #[test]
+    fn unvisited_search_passes_through_visited_systems() {
+        // A -> B, D; B -> A, C
+        let graph = demo_graph();
+        let visited = HashSet::from([1]);
+        let found = nearest_unvisited_by_jumps(&graph, 0, &visited, 5);
+        assert_eq!(found, vec![(3, 1), (2, 2)]);
+    }
+
+    #[test]
+    fn hubs_are_found_by_jumps() {
+        // A and B have two gates each; C and D have one.
+        let graph = demo_graph();
+        assert_eq!(nearest_hubs_by_jumps(&graph, 2, 1, 5), vec![(1, 1), (0, 2)]);
+        assert_eq!(nearest_hubs_by_jumps(&graph, 0, 1, 1), vec![(0, 0)]);
+    }
+
+    #[test]
+    fn rings_group_systems_by_jumps() {
+        let graph = demo_graph();
+        assert_eq!(
+            systems_within_jumps(&graph, 0, 5),
+            vec![vec![0], vec![1, 3], vec![2]]
+        );
+        assert_eq!(systems_within_jumps(&graph, 2, 1), vec![vec![2], vec![1]]);
+    }
+}
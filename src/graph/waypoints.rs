@@ -0,0 +1,265 @@
+use crate::graph::graph::StarGraph;
+use crate::graph::pathfinder::{shortest_gate_paths_batch, PathError, PathStep};
+use crate::Scalar;
+
+/// Largest number of waypoints ordered exactly; Held–Karp needs
+/// O(2^n · n²) time and O(2^n · n) memory.
+pub const HELD_KARP_LIMIT: usize = 12;
+
+/// A visiting order for a set of waypoints and the gate route through them.
+#[derive(Clone, Debug)]
+pub struct WaypointPlan {
+    /// Waypoint indices in visiting order.
+    pub order: Vec<usize>,
+    /// The stitched route from the origin through every waypoint; costs are
+    /// jumps from the origin.
+    pub route: Vec<PathStep>,
+}
+
+/// Orders the must-visit `waypoints` to minimise the total gate jumps of a
+/// route that starts at `origin` and ends at whichever waypoint is visited
+/// last. Up to `HELD_KARP_LIMIT` waypoints the order is optimal; beyond that
+/// a nearest-neighbour tour is improved with 2-opt moves. Duplicates and the
+/// origin itself are ignored. Fails with `PathError::NoRoute` when a
+/// waypoint is unreachable, or with the leg search's error.
+pub fn plan_waypoint_route(
+    graph: &StarGraph,
+    origin: usize,
+    waypoints: &[usize],
+) -> Result<WaypointPlan, PathError> {
+    plan_tour(graph, origin, waypoints, false)
+}
+
+/// Like `plan_waypoint_route`, but the route returns to `origin` after the
+/// last waypoint and the order minimises the jumps of the whole loop, as
+/// for a patrol or a logistics round trip. Fails with `PathError::NoRoute`
+/// when some waypoint cannot be reached or cannot get back.
+pub fn plan_patrol_cycle(
+    graph: &StarGraph,
+    origin: usize,
+    waypoints: &[usize],
+) -> Result<WaypointPlan, PathError> {
+    plan_tour(graph, origin, waypoints, true)
+}
+
+fn plan_tour(
+    graph: &StarGraph,
+    origin: usize,
+    waypoints: &[usize],
+    closed: bool,
+) -> Result<WaypointPlan, PathError> {
+    let mut stops = vec![origin];
+    for &w in waypoints {
+        if !stops.contains(&w) {
+            stops.push(w);
+        }
+    }
+    let n = stops.len();
+    let legs_wanted: Vec<(usize, usize)> = (0..n)
+        .flat_map(|i| {
+            (0..n)
+                .filter(move |&j| j != i && (closed || j != 0))
+                .map(move |j| (i, j))
+        })
+        .collect();
+    let pairs: Vec<(usize, usize)> = legs_wanted
+        .iter()
+        .map(|&(i, j)| (stops[i], stops[j]))
+        .collect();
+    let mut legs = vec![vec![None; n]; n];
+    for (&(i, j), path) in legs_wanted
+        .iter()
+        .zip(shortest_gate_paths_batch(graph, &pairs))
+    {
+        legs[i][j] = match path {
+            Ok(path) => Some(path),
+            Err(PathError::NoRoute) => None,
+            Err(err) => return Err(err),
+        };
+    }
+    let jumps = |i: usize, j: usize| legs[i][j].as_ref().map(|p| p.len() - 1);
+    if (1..n).any(|j| jumps(0, j).is_none() || (closed && jumps(j, 0).is_none())) {
+        return Err(PathError::NoRoute);
+    }
+    // Every waypoint is reachable from the origin, but not necessarily from
+    // each other on a directed map; such legs are simply never chosen.
+    let cost = |i: usize, j: usize| jumps(i, j).unwrap_or(usize::MAX / 4);
+
+    let order = if n - 1 <= HELD_KARP_LIMIT {
+        held_karp(n, &cost, closed)
+    } else {
+        two_opt(nearest_neighbour(n, &cost), &cost, closed)
+    };
+    if tour_cost(&order, &cost, closed) >= usize::MAX / 4 {
+        return Err(PathError::NoRoute);
+    }
+
+    let mut route = vec![PathStep::new(graph, None, origin, 0.0, None)];
+    let mut prev = 0;
+    let returning = (closed && n > 1).then_some(0);
+    for &stop in order.iter().chain(&returning) {
+        let offset = route.len() - 1;
+        let leg = legs[prev][stop].as_ref().ok_or(PathError::NoRoute)?;
+        route.extend(leg.iter().skip(1).map(|step| PathStep {
+            cost: step.cost + offset as Scalar,
+            ..step.clone()
+        }));
+        prev = stop;
+    }
+    Ok(WaypointPlan {
+        order: order.into_iter().map(|i| stops[i]).collect(),
+        route,
+    })
+}
+
+/// Jumps of the tour from stop 0 through `order`, and back to stop 0 when
+/// `closed`.
+fn tour_cost(order: &[usize], cost: &dyn Fn(usize, usize) -> usize, closed: bool) -> usize {
+    let mut prev = 0;
+    let open = order.iter().fold(0usize, |sum, &stop| {
+        let sum = sum.saturating_add(cost(prev, stop));
+        prev = stop;
+        sum
+    });
+    match order.last() {
+        Some(&last) if closed => open.saturating_add(cost(last, 0)),
+        _ => open,
+    }
+}
+
+/// Optimal tour from stop 0 through stops `1..n`, returning to stop 0 when
+/// `closed`.
+fn held_karp(n: usize, cost: &dyn Fn(usize, usize) -> usize, closed: bool) -> Vec<usize> {
+    let m = n - 1;
+    if m == 0 {
+        return Vec::new();
+    }
+    let full = 1usize << m;
+    // best[set][last]: cheapest route from 0 covering `set`, ending at
+    // waypoint `last` (bit `last` of `set`).
+    let mut best = vec![vec![usize::MAX; m]; full];
+    let mut parent = vec![vec![usize::MAX; m]; full];
+    for last in 0..m {
+        best[1 << last][last] = cost(0, last + 1);
+    }
+    for set in 1..full {
+        for last in 0..m {
+            let here = best[set][last];
+            if set & (1 << last) == 0 || here == usize::MAX {
+                continue;
+            }
+            for next in (0..m).filter(|&next| set & (1 << next) == 0) {
+                let candidate = here.saturating_add(cost(last + 1, next + 1));
+                let slot = &mut best[set | 1 << next][next];
+                if candidate < *slot {
+                    *slot = candidate;
+                    parent[set | 1 << next][next] = last;
+                }
+            }
+        }
+    }
+    let closing = |l: usize| if closed { cost(l + 1, 0) } else { 0 };
+    let mut last = (0..m)
+        .min_by_key(|&l| best[full - 1][l].saturating_add(closing(l)))
+        .unwrap_or(0);
+    let mut set = full - 1;
+    let mut order = Vec::with_capacity(m);
+    loop {
+        order.push(last + 1);
+        let prev = parent[set][last];
+        set &= !(1 << last);
+        if prev == usize::MAX {
+            break;
+        }
+        last = prev;
+    }
+    order.reverse();
+    order
+}
+
+fn nearest_neighbour(n: usize, cost: &dyn Fn(usize, usize) -> usize) -> Vec<usize> {
+    let mut remaining: Vec<usize> = (1..n).collect();
+    let mut order = Vec::with_capacity(n - 1);
+    let mut current = 0;
+    while let Some(pos) = (0..remaining.len()).min_by_key(|&p| cost(current, remaining[p])) {
+        current = remaining.swap_remove(pos);
+        order.push(current);
+    }
+    order
+}
+
+/// Reverses segments of the tour while that shortens it.
+fn two_opt(
+    mut order: Vec<usize>,
+    cost: &dyn Fn(usize, usize) -> usize,
+    closed: bool,
+) -> Vec<usize> {
+    let total = |order: &[usize]| tour_cost(order, cost, closed);
+    let mut best = total(&order);
+    let mut improved = true;
+    while improved {
+        improved = false;
+        for i in 0..order.len() {
+            for j in i + 1..order.len() {
+                order[i..=j].reverse();
+                let candidate = total(&order);
+                if candidate < best {
+                    best = candidate;
+                    improved = true;
+                } else {
+                    order[i..=j].reverse();
+                }
+            }
+        }
+    }
+    order
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::graph_from;
+
+    /// A line 0-1-2-3-4-5-6.
+    fn line() -> StarGraph {
+        let positions: Vec<[Scalar; 3]> = (0..7).map(|i| [i as Scalar, 0.0, 0.0]).collect();
+        let adjacency = (0..7usize)
+            .map(|i| {
+                [i.checked_sub(1), (i < 6).then_some(i + 1)]
+                    .into_iter()
+                    .flatten()
+                    .collect()
+            })
+            .collect();
+        graph_from(&positions, adjacency)
+    }
+
+    #[test]
+    fn waypoints_are_visited_in_the_cheapest_order() {
+        // From the middle, heading to one end first and then sweeping to the
+        // other costs 3 + 6 jumps, the minimum.
+        let plan = plan_waypoint_route(&line(), 3, &[6, 2, 0, 4, 2]).unwrap();
+        assert_eq!(plan.route.first().unwrap().system_index, 3);
+        assert_eq!(plan.route.len() - 1, 9);
+        assert_eq!(plan.route.last().unwrap().cost, 9.0);
+        let mut visited = plan.order.clone();
+        visited.sort_unstable();
+        assert_eq!(visited, vec![0, 2, 4, 6]);
+    }
+
+    #[test]
+    fn patrols_return_to_the_origin() {
+        // From the middle covering both ends: 3 + 6 + 3 jumps.
+        let patrol = plan_patrol_cycle(&line(), 3, &[6, 0]).unwrap();
+        assert_eq!(patrol.route.len() - 1, 12);
+        assert_eq!(patrol.route.last().unwrap().system_index, 3);
+    }
+
+    #[test]
+    fn tour_heuristics_straighten_crossed_tours() {
+        let cost = |a: usize, b: usize| a.abs_diff(b);
+        assert_eq!(two_opt(vec![2, 1, 3, 4], &cost, false), vec![1, 2, 3, 4]);
+        assert_eq!(nearest_neighbour(4, &cost), vec![1, 2, 3]);
+        assert_eq!(held_karp(4, &cost, true).len(), 3);
+    }
+}
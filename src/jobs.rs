@@ -0,0 +1,401 @@
+//! Background jobs for analyses too slow for one invocation. Submitting a
+//! request queues it and returns a job id at once; `job_status` reports
+//! where it stands and, once done, its result. Results stay cached, so
+//! resubmitting an identical request returns the same job. A job still
+//! running when the dataset is replaced runs again on the new one.
+
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Instant;
+
+use serde::Serialize;
+use serde_json::Value;
+use thiserror::Error;
+use tokio::sync::Notify;
+
+use crate::api::{handle_request, EngineReply, EngineRequest};
+use crate::engine::Engine;
+use crate::progress::Progress;
+
+/// Default number of jobs kept, queued, running and finished together.
+pub const DEFAULT_MAX_JOBS: usize = 64;
+
+pub type JobId = u64;
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum JobError {
+    #[error("background jobs are disabled")]
+    Disabled,
+    #[error("too many unfinished jobs (limit {limit}); retry later")]
+    Full { limit: usize },
+}
+
+/// Where a job stands, as reported to clients.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(tag = "state", rename_all = "snake_case")]
+pub enum JobState {
+    /// Waiting behind `position` other jobs.
+    Queued {
+        position: usize,
+    },
+    Running {
+        elapsed_ms: u64,
+        /// Fraction done, from 0 to 1, for analyses that report it.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        progress: Option<f32>,
+    },
+    /// The reply, exactly as the request would have returned it inline.
+    Done {
+        result: Value,
+    },
+    Failed {
+        message: String,
+    },
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct JobStatus {
+    pub job_id: JobId,
+    #[serde(flatten)]
+    pub state: JobState,
+}
+
+#[derive(Debug)]
+enum Stage {
+    Queued(EngineRequest),
+    Running {
+        started: Instant,
+        /// `Jobs::generation` when the job was taken.
+        generation: u64,
+        progress: Arc<Progress>,
+    },
+    Done(Value),
+    Failed(String),
+}
+
+#[derive(Debug)]
+struct Job {
+    /// Serialized request, for finding identical submissions.
+    key: String,
+    stage: Stage,
+}
+
+#[derive(Debug, Default)]
+struct Jobs {
+    next_id: JobId,
+    /// Bumped by `forget_results`; results of jobs taken in an earlier
+    /// generation describe a replaced dataset.
+    generation: u64,
+    entries: BTreeMap<JobId, Job>,
+    by_key: HashMap<String, JobId>,
+    queue: VecDeque<JobId>,
+}
+
+/// Queue and result store of background jobs. It runs nothing itself: the
+/// host takes jobs with `next` (or `run_next`) and reports back with
+/// `finish` or `fail`. Finished jobs are evicted oldest first once
+/// `max_jobs` are kept.
+#[derive(Debug)]
+pub struct JobQueue {
+    max_jobs: usize,
+    jobs: Mutex<Jobs>,
+    queued: Notify,
+}
+
+impl Default for JobQueue {
+    fn default() -> Self {
+        JobQueue::new(DEFAULT_MAX_JOBS)
+    }
+}
+
+impl JobQueue {
+    /// A queue keeping at most `max_jobs` jobs; 0 disables submissions.
+    pub fn new(max_jobs: usize) -> Self {
+        JobQueue {
+            max_jobs,
+            jobs: Mutex::default(),
+            queued: Notify::new(),
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Jobs> {
+        self.jobs.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Queues `request`, or returns the job already holding an identical
+    /// one unless that job failed.
+    pub fn submit(&self, request: EngineRequest) -> Result<JobStatus, JobError> {
+        if self.max_jobs == 0 {
+            return Err(JobError::Disabled);
+        }
+        let key = serde_json::to_string(&request).unwrap_or_default();
+        let mut jobs = self.lock();
+        if let Some(&id) = jobs.by_key.get(&key) {
+            if !matches!(jobs.entries[&id].stage, Stage::Failed(_)) {
+                return Ok(jobs.status(id).expect("indexed jobs exist"));
+            }
+            jobs.remove(id);
+        }
+        if jobs.entries.len() >= self.max_jobs {
+            let finished = jobs
+                .entries
+                .iter()
+                .find(|(_, job)| matches!(job.stage, Stage::Done(_) | Stage::Failed(_)))
+                .map(|(&id, _)| id);
+            match finished {
+                Some(id) => jobs.remove(id),
+                None => {
+                    return Err(JobError::Full {
+                        limit: self.max_jobs,
+                    })
+                }
+            }
+        }
+        let id = jobs.next_id;
+        jobs.next_id += 1;
+        jobs.by_key.insert(key.clone(), id);
+        jobs.entries.insert(
+            id,
+            Job {
+                key,
+                stage: Stage::Queued(request),
+            },
+        );
+        jobs.queue.push_back(id);
+        let status = jobs.status(id).expect("just inserted");
+        drop(jobs);
+        self.queued.notify_one();
+        Ok(status)
+    }
+
+    /// Current state of job `id`; `None` once it has been evicted.
+    pub fn status(&self, id: JobId) -> Option<JobStatus> {
+        self.lock().status(id)
+    }
+
+    /// Marks the oldest queued job as running and hands out its request.
+    pub fn take_next(&self) -> Option<(JobId, EngineRequest)> {
+        let mut jobs = self.lock();
+        let id = jobs.queue.pop_front()?;
+        let running = Stage::Running {
+            started: Instant::now(),
+            generation: jobs.generation,
+            progress: Arc::default(),
+        };
+        let job = jobs.entries.get_mut(&id).expect("queued jobs exist");
+        match std::mem::replace(&mut job.stage, running) {
+            Stage::Queued(request) => Some((id, request)),
+            _ => unreachable!("only queued jobs are in the queue"),
+        }
+    }
+
+    /// Runs `work` for the running job `id`, so the progress it reports
+    /// shows in `job_status`.
+    pub fn track<T>(&self, id: JobId, work: impl FnOnce() -> T) -> T {
+        let progress = match self.lock().entries.get(&id).map(|job| &job.stage) {
+            Some(Stage::Running { progress, .. }) => Arc::clone(progress),
+            _ => Arc::default(),
+        };
+        progress.run(work)
+    }
+
+    /// Waits for a queued job and takes it.
+    pub async fn next(&self) -> (JobId, EngineRequest) {
+        loop {
+            if let Some(job) = self.take_next() {
+                return job;
+            }
+            self.queued.notified().await;
+        }
+    }
+
+    pub fn finish(&self, id: JobId, reply: &EngineReply) {
+        let stage = match serde_json::to_value(reply) {
+            Ok(result) => Stage::Done(result),
+            Err(err) => Stage::Failed(format!("Failed to store the result: {err}")),
+        };
+        self.set_stage(id, stage);
+    }
+
+    pub fn fail(&self, id: JobId, message: impl Into<String>) {
+        self.set_stage(id, Stage::Failed(message.into()));
+    }
+
+    /// Settles a running job, unless it was taken before the last
+    /// `forget_results`: its outcome then describes the replaced dataset, so
+    /// it is dropped and the job queued again at the front.
+    fn set_stage(&self, id: JobId, stage: Stage) {
+        let mut jobs = self.lock();
+        let current = jobs.generation;
+        let Some(job) = jobs.entries.get_mut(&id) else {
+            return;
+        };
+        match &job.stage {
+            Stage::Running { generation, .. } if *generation != current => {
+                job.stage = match serde_json::from_str(&job.key) {
+                    Ok(request) => Stage::Queued(request),
+                    Err(err) => Stage::Failed(format!("Failed to requeue the job: {err}")),
+                };
+                if matches!(job.stage, Stage::Queued(_)) {
+                    jobs.queue.push_front(id);
+                    drop(jobs);
+                    self.queued.notify_one();
+                }
+            }
+            _ => job.stage = stage,
+        }
+    }
+
+    /// Runs the oldest queued job on the calling thread; `false` when none
+    /// was queued.
+    pub fn run_next(&self, engine: &Engine) -> bool {
+        let Some((id, request)) = self.take_next() else {
+            return false;
+        };
+        let ran = self.track(id, || {
+            catch_unwind(AssertUnwindSafe(|| handle_request(engine, request)))
+        });
+        match ran {
+            Ok(reply) => self.finish(id, &reply),
+            Err(_) => self.fail(id, "Query failed"),
+        }
+        true
+    }
+
+    /// Drops finished results, e.g. once they describe a replaced dataset,
+    /// along with those of jobs still running.
+    pub fn forget_results(&self) {
+        let mut jobs = self.lock();
+        jobs.generation += 1;
+        let finished: Vec<JobId> = jobs
+            .entries
+            .iter()
+            .filter(|(_, job)| matches!(job.stage, Stage::Done(_) | Stage::Failed(_)))
+            .map(|(&id, _)| id)
+            .collect();
+        for id in finished {
+            jobs.remove(id);
+        }
+    }
+}
+
+impl Jobs {
+    fn status(&self, id: JobId) -> Option<JobStatus> {
+        let state = match &self.entries.get(&id)?.stage {
+            Stage::Queued(_) => JobState::Queued {
+                position: self.queue.iter().position(|&q| q == id).unwrap_or(0),
+            },
+            Stage::Running {
+                started, progress, ..
+            } => JobState::Running {
+                elapsed_ms: started.elapsed().as_millis() as u64,
+                progress: progress.fraction(),
+            },
+            Stage::Done(result) => JobState::Done {
+                result: result.clone(),
+            },
+            Stage::Failed(message) => JobState::Failed {
+                message: message.clone(),
+            },
+        };
+        Some(JobStatus { job_id: id, state })
+    }
+
+    fn remove(&mut self, id: JobId) {
+        if let Some(job) = self.entries.remove(&id) {
+            self.by_key.remove(&job.key);
+        }
+        self.queue.retain(|&q| q != id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::demo_graph;
+
+    #[test]
+    fn jobs_run_once_and_evict_oldest_results() {
+        let engine = Engine::new(demo_graph(), None);
+        let jobs = JobQueue::new(2);
+        let request = |json: &str| serde_json::from_str::<EngineRequest>(json).unwrap();
+        let sweep = r#"{"kind":"sweep","center":[0.0,0.0,0.0],"radius":3.0}"#;
+        let first = jobs.submit(request(sweep)).unwrap();
+        assert_eq!(first.state, JobState::Queued { position: 0 });
+        assert_eq!(jobs.submit(request(sweep)).unwrap().job_id, first.job_id);
+        let path = r#"{"kind":"path","start_id":1,"end_id":3}"#;
+        let second = jobs.submit(request(path)).unwrap();
+        assert_eq!(second.state, JobState::Queued { position: 1 });
+        let nearest = r#"{"kind":"nearest","system_name":"A","radius":1.0,"count":1}"#;
+        assert_eq!(
+            jobs.submit(request(nearest)),
+            Err(JobError::Full { limit: 2 })
+        );
+
+        assert!(jobs.run_next(&engine));
+        match jobs.status(first.job_id).unwrap().state {
+            JobState::Done { result } => assert_eq!(result["kind"], "sweep"),
+            other => panic!("expected a result, got {other:?}"),
+        }
+        // The finished sweep makes room for a third job.
+        let third = jobs.submit(request(nearest)).unwrap();
+        assert!(jobs.status(first.job_id).is_none());
+        assert!(jobs.run_next(&engine) && jobs.run_next(&engine));
+        assert!(!jobs.run_next(&engine));
+        assert!(matches!(
+            jobs.status(third.job_id).unwrap().state,
+            JobState::Done { .. }
+        ));
+        assert_eq!(
+            JobQueue::new(0).submit(request(path)),
+            Err(JobError::Disabled)
+        );
+    }
+
+    #[test]
+    fn running_jobs_report_progress() {
+        let jobs = JobQueue::new(2);
+        let json = r#"{"kind":"jump_distances","pairs":[[1,3],[4,3]]}"#;
+        let job = jobs.submit(serde_json::from_str(json).unwrap()).unwrap();
+        let (id, _) = jobs.take_next().unwrap();
+        let running = |progress| JobState::Running {
+            elapsed_ms: 0,
+            progress,
+        };
+        let without_time = |mut status: JobStatus| {
+            if let JobState::Running { elapsed_ms, .. } = &mut status.state {
+                *elapsed_ms = 0;
+            }
+            status.state
+        };
+        assert_eq!(without_time(jobs.status(id).unwrap()), running(None));
+        let seen = jobs.track(id, || {
+            crate::progress::report(1, 4);
+            jobs.status(id).unwrap()
+        });
+        assert_eq!(without_time(seen), running(Some(0.25)));
+        assert_eq!(job.job_id, id);
+    }
+
+    #[test]
+    fn results_from_a_replaced_dataset_are_run_again() {
+        let engine = Engine::new(demo_graph(), None);
+        let jobs = JobQueue::new(2);
+        let json = r#"{"kind":"path","start_id":1,"end_id":3}"#;
+        let job = jobs.submit(serde_json::from_str(json).unwrap()).unwrap();
+        let (id, request) = jobs.take_next().unwrap();
+        // The dataset is swapped while the job runs on the old one.
+        jobs.forget_results();
+        jobs.finish(id, &handle_request(&engine, request));
+        assert_eq!(
+            jobs.status(job.job_id).unwrap().state,
+            JobState::Queued { position: 0 }
+        );
+        assert!(jobs.run_next(&engine));
+        assert!(matches!(
+            jobs.status(job.job_id).unwrap().state,
+            JobState::Done { .. }
+        ));
+    }
+}
@@ -1,27 +1,63 @@
+// Conversions between `Scalar` and `f64` are no-ops in `f64` builds.
+#![cfg_attr(feature = "f64", allow(clippy::unnecessary_cast))]
+
+pub mod api;
+pub mod budget;
+pub mod config;
 pub mod data;
+pub mod encoding;
+pub mod engine;
+pub mod export;
 pub mod graph;
+pub mod jobs;
+pub mod limiter;
+pub mod poi;
+pub mod progress;
+#[cfg(feature = "recording")]
+pub mod recording;
+pub mod refresh;
+pub mod render;
+mod rng;
 pub mod spatial;
+pub mod stats;
 pub mod sweep;
+pub mod tags;
+pub mod units;
+pub mod validate;
 
 use serde::{Deserialize, Serialize};
 
+/// Scalar type of positions and of the distances between them: `f32`, or
+/// `f64` with the `f64` feature. The source data is `f64`, and far from the
+/// origin `f32` keeps too little of it apart; `f64` doubles index memory.
+#[cfg(not(feature = "f64"))]
+pub type Scalar = f32;
+#[cfg(feature = "f64")]
+pub type Scalar = f64;
+
+/// Unsigned integer as wide as `Scalar`, holding its bit patterns.
+#[cfg(not(feature = "f64"))]
+pub type ScalarBits = u32;
+#[cfg(feature = "f64")]
+pub type ScalarBits = u64;
+
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
 pub struct System {
     pub id: u32,
     pub name: String,
-    /// Position in 3D space (e.g. light-years, already transformed)
-    pub pos: [f32; 3],
+    /// Position in 3D space, in the dataset's `units::CoordinateFrame`
+    pub pos: [Scalar; 3],
 }
 
 impl System {
-    pub fn distance(&self, other: &System) -> f32 {
+    pub fn distance(&self, other: &System) -> Scalar {
         let dx = self.pos[0] - other.pos[0];
         let dy = self.pos[1] - other.pos[1];
         let dz = self.pos[2] - other.pos[2];
         (dx * dx + dy * dy + dz * dz).sqrt()
     }
 
-    pub fn distance_to_point(&self, p: [f32; 3]) -> f32 {
+    pub fn distance_to_point(&self, p: [Scalar; 3]) -> Scalar {
         let dx = self.pos[0] - p[0];
         let dy = self.pos[1] - p[1];
         let dz = self.pos[2] - p[2];
@@ -0,0 +1,113 @@
+use std::str::FromStr;
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+/// Default number of heavy queries allowed to run at once.
+pub const DEFAULT_MAX_HEAVY_QUERIES: usize = 2;
+
+/// What happens to a heavy query when all slots are taken.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OverflowPolicy {
+    /// Wait for a slot to free up.
+    #[default]
+    Queue,
+    /// Reject the query immediately.
+    Shed,
+}
+
+impl FromStr for OverflowPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "queue" => Ok(OverflowPolicy::Queue),
+            "shed" => Ok(OverflowPolicy::Shed),
+            other => Err(format!("unknown overflow policy {other}")),
+        }
+    }
+}
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum LimitError {
+    #[error("too many heavy queries in flight (limit {limit}); retry later")]
+    Saturated { limit: usize },
+}
+
+/// Caps how many expensive queries (sweeps and similar) run concurrently so
+/// they cannot starve latency-sensitive traffic on a shared deployment.
+#[derive(Clone, Debug)]
+pub struct HeavyQueryLimiter {
+    semaphore: Arc<Semaphore>,
+    limit: usize,
+    policy: OverflowPolicy,
+}
+
+impl HeavyQueryLimiter {
+    pub fn new(limit: usize, policy: OverflowPolicy) -> Self {
+        let limit = limit.max(1);
+        HeavyQueryLimiter {
+            semaphore: Arc::new(Semaphore::new(limit)),
+            limit,
+            policy,
+        }
+    }
+
+    pub fn limit(&self) -> usize {
+        self.limit
+    }
+
+    pub fn policy(&self) -> OverflowPolicy {
+        self.policy
+    }
+
+    /// Obtains a slot according to the overflow policy. The slot is released
+    /// when the returned permit is dropped.
+    pub async fn acquire(&self) -> Result<OwnedSemaphorePermit, LimitError> {
+        let saturated = LimitError::Saturated { limit: self.limit };
+        match self.policy {
+            OverflowPolicy::Queue => self
+                .semaphore
+                .clone()
+                .acquire_owned()
+                .await
+                .map_err(|_| saturated),
+            OverflowPolicy::Shed => self
+                .semaphore
+                .clone()
+                .try_acquire_owned()
+                .map_err(|_| saturated),
+        }
+    }
+
+    /// Waits for a slot whatever the policy: background jobs have no caller
+    /// to turn away.
+    pub async fn acquire_queued(&self) -> OwnedSemaphorePermit {
+        self.semaphore
+            .clone()
+            .acquire_owned()
+            .await
+            .expect("the semaphore is never closed")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn shed_policy_rejects_when_full() {
+        let limiter = HeavyQueryLimiter::new(1, OverflowPolicy::Shed);
+        let permit = limiter.acquire().await.expect("first slot");
+        assert_eq!(
+            limiter.acquire().await.unwrap_err(),
+            LimitError::Saturated { limit: 1 }
+        );
+        drop(permit);
+        assert!(limiter.acquire().await.is_ok());
+        assert_eq!("SHED".parse(), Ok(OverflowPolicy::Shed));
+    }
+}
@@ -1,291 +1,187 @@
-use std::env;
+use std::sync::{Arc, PoisonError, RwLock};
+use std::time::Duration;
 
 use lambda_runtime::{service_fn, Error, LambdaEvent};
-use once_cell::sync::Lazy;
-use serde::{Deserialize, Serialize};
-use starmap_engine::data::{read_graph_from_file, DataError};
-use starmap_engine::graph::graph::StarGraph;
-use starmap_engine::graph::pathfinder::shortest_gate_path;
-use starmap_engine::spatial::kd_tree::KDTree;
-use starmap_engine::sweep::sweep::greedy_sweep_within_radius;
-use starmap_engine::System;
-
-static GRAPH: Lazy<StarGraph> = Lazy::new(load_or_sample_graph);
-
-static GRAPH_KD: Lazy<KDTree> = Lazy::new(|| {
-    let pts: Vec<[f32; 3]> = GRAPH.systems.iter().map(|s| s.pos).collect();
-    KDTree::build(&pts)
-});
-
-fn load_or_sample_graph() -> StarGraph {
-    match load_graph_from_env() {
-        Ok(Some(graph)) => graph,
-        Ok(None) => {
-            log::info!("STARMAP_DATASET not set; using built-in demo graph");
-            sample_graph()
-        }
-        Err(err) => {
-            log::warn!("Failed to load dataset from STARMAP_DATASET: {err}");
-            sample_graph()
-        }
-    }
+use once_cell::sync::OnceCell;
+use starmap_engine::api::{handle_request, EngineReply, EngineRequest, EngineResponse};
+use starmap_engine::config::EngineConfig;
+use starmap_engine::data::DataError;
+use starmap_engine::encoding::{encode_reply, WireReply};
+use starmap_engine::engine::Engine;
+use starmap_engine::limiter::HeavyQueryLimiter;
+use starmap_engine::refresh::{refresh, swap_metric, DatasetVersion};
+
+/// Process-wide state, built once from the validated `EngineConfig`. The
+/// engine is replaced whole when a newer dataset is swapped in.
+struct Runtime {
+    engine: RwLock<Arc<Engine>>,
+    limiter: HeavyQueryLimiter,
+    #[cfg(feature = "recording")]
+    recorder: Option<starmap_engine::recording::Recorder>,
 }
 
-fn load_graph_from_env() -> Result<Option<StarGraph>, DataError> {
-    if let Ok(path) = env::var("STARMAP_DATASET") {
-        log::info!("Loading dataset from {path}");
-        let graph = read_graph_from_file(path)?;
-        Ok(Some(graph))
-    } else {
-        Ok(None)
+static RUNTIME: OnceCell<Runtime> = OnceCell::new();
+
+impl Runtime {
+    fn from_config(config: EngineConfig) -> Result<Self, DataError> {
+        let limiter = HeavyQueryLimiter::new(config.max_heavy_queries, config.heavy_overflow);
+        #[cfg(feature = "recording")]
+        let recorder = config.record_path.as_deref().and_then(|path| {
+            starmap_engine::recording::Recorder::open(path)
+                .map_err(|err| log::warn!("Failed to open recording file {path}: {err}"))
+                .ok()
+        });
+        Ok(Runtime {
+            engine: RwLock::new(Arc::new(Engine::from_config(config)?)),
+            limiter,
+            #[cfg(feature = "recording")]
+            recorder,
+        })
     }
-}
-
-fn sample_graph() -> StarGraph {
-    // Tiny demo graph; replace with real starmap index in production.
-    let systems = vec![
-        System {
-            id: 1,
-            name: "A".into(),
-            pos: [0.0, 0.0, 0.0],
-        },
-        System {
-            id: 2,
-            name: "B".into(),
-            pos: [1.0, 0.0, 0.0],
-        },
-        System {
-            id: 3,
-            name: "C".into(),
-            pos: [2.0, 0.0, 0.0],
-        },
-        System {
-            id: 4,
-            name: "D".into(),
-            pos: [0.0, 2.0, 0.0],
-        },
-    ];
-    let adjacency = vec![
-        vec![1, 3], // A -> B, D
-        vec![0, 2], // B -> A, C
-        vec![1],    // C -> B
-        vec![0],    // D -> A
-    ];
-    StarGraph::new(systems, adjacency)
-}
-
-#[derive(Debug, Deserialize)]
-#[serde(untagged)]
-enum LocationInput {
-    Coordinates {
-        #[serde(alias = "origin", alias = "center")]
-        coords: [f32; 3],
-    },
-    System {
-        system_name: String,
-    },
-}
-
-#[derive(Debug, Deserialize)]
-#[serde(tag = "kind", rename_all = "snake_case")]
-enum EngineRequest {
-    Nearest {
-        #[serde(flatten)]
-        location: LocationInput,
-        radius: f32,
-        count: usize,
-    },
-    Path {
-        start_id: u32,
-        end_id: u32,
-    },
-    Sweep {
-        #[serde(flatten)]
-        location: LocationInput,
-        radius: f32,
-    },
-}
 
-#[derive(Debug, Serialize)]
-#[serde(tag = "kind", rename_all = "snake_case")]
-enum EngineResponse {
-    Nearest {
-        systems: Vec<NearestResult>,
-    },
-    Path {
-        systems: Vec<PathResult>,
-    },
-    Sweep {
-        systems: Vec<SweepResult>,
-        total_distance: f32,
-    },
-    Error {
-        message: String,
-    },
+    /// The current engine; requests keep the one they started with.
+    fn engine(&self) -> Arc<Engine> {
+        self.engine
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
 }
 
-#[derive(Debug, Serialize)]
-struct NearestResult {
-    id: u32,
-    name: String,
-    distance: f32,
+fn runtime() -> &'static Runtime {
+    RUNTIME
+        .get()
+        .expect("runtime is initialised before serving")
 }
 
-#[derive(Debug, Serialize)]
-struct PathResult {
-    id: u32,
-    name: String,
-    cumulative_cost: f32,
+/// Runs light requests inline; heavy ones take a limiter slot and run on the
+/// blocking pool so they cannot stall the runtime.
+async fn dispatch(request: EngineRequest) -> EngineReply {
+    let engine = runtime().engine();
+    if !request.is_heavy() {
+        return handle_request(&engine, request);
+    }
+    let permit = match runtime().limiter.acquire().await {
+        Ok(permit) => permit,
+        Err(err) => return EngineResponse::error(err.to_string()).into(),
+    };
+    tokio::task::spawn_blocking(move || {
+        let _permit = permit;
+        handle_request(&engine, request)
+    })
+    .await
+    .unwrap_or_else(|err| EngineResponse::error(format!("Query failed: {err}")).into())
 }
 
-#[derive(Debug, Serialize)]
-struct SweepResult {
-    id: u32,
-    name: String,
+/// Runs submitted jobs one at a time against the current engine, each
+/// holding a heavy-query slot like an inline heavy request.
+async fn run_jobs() {
+    let jobs = runtime().engine().jobs.clone();
+    loop {
+        let (id, request) = jobs.next().await;
+        let permit = runtime().limiter.acquire_queued().await;
+        let engine = runtime().engine();
+        let tracker = jobs.clone();
+        let ran = tokio::task::spawn_blocking(move || {
+            let _permit = permit;
+            tracker.track(id, || handle_request(&engine, request))
+        })
+        .await;
+        match ran {
+            Ok(reply) => jobs.finish(id, &reply),
+            Err(err) => jobs.fail(id, format!("Query failed: {err}")),
+        }
+    }
 }
 
-async fn handler(event: LambdaEvent<EngineRequest>) -> Result<EngineResponse, Error> {
-    let req = event.payload;
-    match req {
-        EngineRequest::Nearest {
-            location,
-            radius,
-            count,
-        } => {
-            let origin = match resolve_location(&GRAPH, location) {
-                Ok(point) => point,
-                Err(msg) => return Ok(EngineResponse::Error { message: msg }),
-            };
-            let kd = &*GRAPH_KD;
-            let nn = kd.nearest_n_within_radius(origin, radius, count);
-            let systems = nn
-                .into_iter()
-                .map(|(idx, d)| {
-                    let s = &GRAPH.systems[idx];
-                    NearestResult {
-                        id: s.id,
-                        name: s.name.clone(),
-                        distance: d,
+/// Checks the configured dataset every `interval` and swaps in newer
+/// versions that pass their self-check, printing a `swap_metric` line.
+async fn refresh_dataset(interval: Duration) {
+    // Remote datasets are checked over blocking HTTP.
+    let dataset = runtime().engine().config.dataset.clone();
+    let mut seen = tokio::task::spawn_blocking(move || {
+        dataset.and_then(|dataset| DatasetVersion::of(&dataset).ok())
+    })
+    .await
+    .ok()
+    .flatten();
+    let mut ticks = tokio::time::interval(interval);
+    // The first tick completes immediately.
+    ticks.tick().await;
+    loop {
+        ticks.tick().await;
+        let current = runtime().engine();
+        let mut next = seen.clone();
+        let checked = tokio::task::spawn_blocking(move || {
+            let result = refresh(&current, &mut next);
+            (next, result)
+        })
+        .await;
+        match checked {
+            Ok((next, result)) => {
+                seen = next;
+                match result {
+                    Ok(Some(fresh)) => {
+                        println!("{}", swap_metric(&fresh));
+                        log::info!("Swapped in a newer dataset");
+                        *runtime()
+                            .engine
+                            .write()
+                            .unwrap_or_else(PoisonError::into_inner) = Arc::new(fresh);
                     }
-                })
-                .collect();
-            Ok(EngineResponse::Nearest { systems })
-        }
-        EngineRequest::Path { start_id, end_id } => {
-            let g = &*GRAPH;
-            let Some(start) = g.index_of_id(start_id) else {
-                return Ok(EngineResponse::Error {
-                    message: format!("Unknown start_id {}", start_id),
-                });
-            };
-            let Some(goal) = g.index_of_id(end_id) else {
-                return Ok(EngineResponse::Error {
-                    message: format!("Unknown end_id {}", end_id),
-                });
-            };
-            if let Some(path) = shortest_gate_path(g, start, goal) {
-                let systems = path
-                    .into_iter()
-                    .map(|step| {
-                        let s = &g.systems[step.system_index];
-                        PathResult {
-                            id: s.id,
-                            name: s.name.clone(),
-                            cumulative_cost: step.cost,
-                        }
-                    })
-                    .collect();
-                Ok(EngineResponse::Path { systems })
-            } else {
-                Ok(EngineResponse::Error {
-                    message: "No path found".into(),
-                })
+                    Ok(None) => {}
+                    Err(err) => log::warn!("Dataset refresh skipped: {err}"),
+                }
             }
-        }
-        EngineRequest::Sweep { location, radius } => {
-            let center = match resolve_location(&GRAPH, location) {
-                Ok(point) => point,
-                Err(msg) => return Ok(EngineResponse::Error { message: msg }),
-            };
-            let g = &*GRAPH;
-            let (indices, total_distance) = greedy_sweep_within_radius(g, center, radius);
-            let systems = indices
-                .into_iter()
-                .map(|idx| {
-                    let s = &g.systems[idx];
-                    SweepResult {
-                        id: s.id,
-                        name: s.name.clone(),
-                    }
-                })
-                .collect();
-            Ok(EngineResponse::Sweep {
-                systems,
-                total_distance,
-            })
+            Err(err) => log::warn!("Dataset refresh failed: {err}"),
         }
     }
 }
 
-fn resolve_location(graph: &StarGraph, location: LocationInput) -> Result<[f32; 3], String> {
-    match location {
-        LocationInput::Coordinates { coords } => Ok(coords),
-        LocationInput::System { system_name } => {
-            let Some(index) = graph.index_of_name(&system_name) else {
-                return Err(format!("Unknown system_name {system_name}"));
-            };
-            Ok(graph.systems[index].pos)
-        }
-    }
+async fn handler(event: LambdaEvent<EngineRequest>) -> Result<WireReply, Error> {
+    let encoding = event.payload.options.encoding;
+    let reply = record_and_dispatch(event.payload).await;
+    Ok(encode_reply(reply, encoding))
 }
 
-#[tokio::main]
-async fn main() -> Result<(), Error> {
-    env_logger::init();
-    let func = service_fn(handler);
-    lambda_runtime::run(func).await
+#[cfg(not(feature = "recording"))]
+async fn record_and_dispatch(request: EngineRequest) -> EngineReply {
+    dispatch(request).await
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-
-    #[test]
-    fn location_from_coordinates() {
-        let json = r#"{"kind":"nearest","origin":[1.0,2.0,3.0],"radius":1.0,"count":1}"#;
-        let req: EngineRequest = serde_json::from_str(json).expect("parse");
-        match req {
-            EngineRequest::Nearest { location, .. } => {
-                let coords = resolve_location(&sample_graph(), location).expect("coords");
-                assert_eq!(coords, [1.0, 2.0, 3.0]);
-            }
-            _ => panic!("expected nearest"),
-        }
+#[cfg(feature = "recording")]
+async fn record_and_dispatch(payload: EngineRequest) -> EngineReply {
+    let Some(recorder) = runtime().recorder.as_ref() else {
+        return dispatch(payload).await;
+    };
+    let request = starmap_engine::recording::encode_request(&payload);
+    let started = std::time::Instant::now();
+    let reply = dispatch(payload).await;
+    if let Err(err) = recorder.record(request, &reply.response, started.elapsed()) {
+        log::warn!("Failed to record request: {err}");
     }
+    reply
+}
 
-    #[test]
-    fn location_from_system_name() {
-        let json = r#"{"kind":"sweep","system_name":"B","radius":10.0}"#;
-        let req: EngineRequest = serde_json::from_str(json).expect("parse");
-        match req {
-            EngineRequest::Sweep { location, .. } => {
-                let coords = resolve_location(&sample_graph(), location).expect("coords");
-                assert_eq!(coords, [1.0, 0.0, 0.0]);
-            }
-            _ => panic!("expected sweep"),
-        }
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    env_logger::init();
+    let config = EngineConfig::from_env()?;
+    // Loading may download the dataset over blocking HTTP.
+    let runtime =
+        tokio::task::block_in_place(|| RUNTIME.get_or_try_init(|| Runtime::from_config(config)))?;
+    let engine = runtime.engine();
+    let report = engine.startup_check();
+    if report.passed {
+        log::info!("Dataset self-check passed ({} checks)", report.checks.len());
+    } else {
+        log::warn!("Dataset self-check failed: {report:?}");
     }
-
-    #[test]
-    fn location_unknown_system_errors() {
-        let json = r#"{"kind":"nearest","system_name":"Z","radius":5.0,"count":1}"#;
-        let req: EngineRequest = serde_json::from_str(json).expect("parse");
-        match req {
-            EngineRequest::Nearest { location, .. } => {
-                let err = resolve_location(&sample_graph(), location).expect_err("missing");
-                assert!(err.contains("Unknown system_name"));
-            }
-            _ => panic!("expected nearest"),
-        }
+    let interval = engine.config.refresh_interval_secs;
+    if interval > 0 && engine.config.dataset.is_some() {
+        tokio::spawn(refresh_dataset(Duration::from_secs(interval)));
     }
+    tokio::spawn(run_jobs());
+    let func = service_fn(handler);
+    lambda_runtime::run(func).await
 }
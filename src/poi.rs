@@ -0,0 +1,255 @@
+use std::cell::RefCell;
+use std::collections::{BTreeSet, HashMap};
+use std::fs;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::data::DataError;
+use crate::spatial::corridor::corridor_hits;
+use crate::Scalar;
+
+/// A user-defined point of interest. POIs live outside the immutable system
+/// dataset and can be added or removed at any time.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct Poi {
+    pub id: u64,
+    pub label: String,
+    #[serde(default)]
+    pub tags: BTreeSet<String>,
+    pub pos: [Scalar; 3],
+}
+
+impl Poi {
+    pub fn has_tag(&self, tag: &str) -> bool {
+        self.tags.contains(tag)
+    }
+
+    fn distance_to_point(&self, p: [Scalar; 3]) -> Scalar {
+        let dx = self.pos[0] - p[0];
+        let dy = self.pos[1] - p[1];
+        let dz = self.pos[2] - p[2];
+        (dx * dx + dy * dy + dz * dz).sqrt()
+    }
+}
+
+type Cell = [i64; 3];
+
+/// POI collection indexed by a uniform grid of cubic cells, which supports
+/// cheap inserts and removals unlike the bulk-built KD tree.
+#[derive(Clone, Debug)]
+pub struct PoiLayer {
+    cell_size: Scalar,
+    next_id: u64,
+    pois: HashMap<u64, Poi>,
+    cells: HashMap<Cell, Vec<u64>>,
+}
+
+impl PoiLayer {
+    /// `cell_size` should be around the typical query radius.
+    pub fn new(cell_size: Scalar) -> Self {
+        assert!(cell_size > 0.0, "cell_size must be positive");
+        PoiLayer {
+            cell_size,
+            next_id: 1,
+            pois: HashMap::new(),
+            cells: HashMap::new(),
+        }
+    }
+
+    /// Loads POIs from a JSON list of `{"label", "tags", "pos"}` objects,
+    /// numbering them from 1 in file order.
+    pub fn load<P: AsRef<Path>>(path: P, cell_size: Scalar) -> Result<Self, DataError> {
+        #[derive(Deserialize)]
+        struct Entry {
+            label: String,
+            #[serde(default)]
+            tags: BTreeSet<String>,
+            pos: [Scalar; 3],
+        }
+
+        let entries: Vec<Entry> = serde_json::from_slice(&fs::read(path)?)?;
+        let mut layer = PoiLayer::new(cell_size);
+        for entry in entries {
+            layer.insert(entry.label, entry.tags, entry.pos);
+        }
+        Ok(layer)
+    }
+
+    pub fn len(&self) -> usize {
+        self.pois.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pois.is_empty()
+    }
+
+    pub fn get(&self, id: u64) -> Option<&Poi> {
+        self.pois.get(&id)
+    }
+
+    /// Adds a POI and returns its newly assigned id.
+    pub fn insert(
+        &mut self,
+        label: impl Into<String>,
+        tags: BTreeSet<String>,
+        pos: [Scalar; 3],
+    ) -> u64 {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.cells.entry(self.cell_of(pos)).or_default().push(id);
+        self.pois.insert(
+            id,
+            Poi {
+                id,
+                label: label.into(),
+                tags,
+                pos,
+            },
+        );
+        id
+    }
+
+    pub fn remove(&mut self, id: u64) -> Option<Poi> {
+        let poi = self.pois.remove(&id)?;
+        let cell = self.cell_of(poi.pos);
+        if let Some(ids) = self.cells.get_mut(&cell) {
+            ids.retain(|&other| other != id);
+            if ids.is_empty() {
+                self.cells.remove(&cell);
+            }
+        }
+        Some(poi)
+    }
+
+    /// Up to `n` POIs within `radius` of `target`, optionally restricted to
+    /// those carrying `tag`, sorted ascending by distance.
+    pub fn nearest(
+        &self,
+        target: [Scalar; 3],
+        radius: Scalar,
+        n: usize,
+        tag: Option<&str>,
+    ) -> Vec<(&Poi, Scalar)> {
+        let lo = [target[0] - radius, target[1] - radius, target[2] - radius];
+        let hi = [target[0] + radius, target[1] + radius, target[2] + radius];
+        let mut results: Vec<(&Poi, Scalar)> = self
+            .candidates(lo, hi)
+            .filter(|poi| tag.is_none_or(|t| poi.has_tag(t)))
+            .map(|poi| (poi, poi.distance_to_point(target)))
+            .filter(|&(_, d)| d <= radius)
+            .collect();
+        results.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap().then(a.0.id.cmp(&b.0.id)));
+        results.truncate(n);
+        results
+    }
+
+    /// POIs within `max_distance` of the polyline through `waypoints` as
+    /// `(poi, distance, leg)`, where `leg` is the closest segment
+    /// (`waypoints[leg]` to `waypoints[leg + 1]`) and `distance` the distance
+    /// to it. Ordered along the route like `spatial::corridor::within_corridor`.
+    pub fn along_route(
+        &self,
+        waypoints: &[[Scalar; 3]],
+        max_distance: Scalar,
+    ) -> Vec<(&Poi, Scalar, usize)> {
+        // Corridor hits refer to points by index, so candidates are numbered
+        // as they turn up.
+        let found: RefCell<(Vec<&Poi>, HashMap<u64, usize>)> = RefCell::default();
+        let near_segment = |a: [Scalar; 3], b: [Scalar; 3]| {
+            let lo = [0, 1, 2].map(|i| a[i].min(b[i]) - max_distance);
+            let hi = [0, 1, 2].map(|i| a[i].max(b[i]) + max_distance);
+            let (pois, slots) = &mut *found.borrow_mut();
+            self.candidates(lo, hi)
+                .map(|poi| {
+                    *slots.entry(poi.id).or_insert_with(|| {
+                        pois.push(poi);
+                        pois.len() - 1
+                    })
+                })
+                .collect::<Vec<usize>>()
+        };
+        let hits = corridor_hits(waypoints, max_distance, near_segment, |i| {
+            found.borrow().0[i].pos
+        });
+        let pois = found.into_inner().0;
+        hits.into_iter()
+            .map(|hit| (pois[hit.index], hit.distance, hit.segment))
+            .collect()
+    }
+
+    /// POIs in cells overlapping the box `[lo, hi]`. Falls back to a full
+    /// scan when the box spans more cells than there are POIs.
+    fn candidates(&self, lo: [Scalar; 3], hi: [Scalar; 3]) -> Box<dyn Iterator<Item = &Poi> + '_> {
+        let (min, max) = (self.cell_of(lo), self.cell_of(hi));
+        let span: f64 = (0..3)
+            .map(|i| max[i] as f64 - min[i] as f64 + 1.0)
+            .product();
+        if span > self.pois.len() as f64 {
+            return Box::new(self.pois.values());
+        }
+        let mut ids = Vec::new();
+        for x in min[0]..=max[0] {
+            for y in min[1]..=max[1] {
+                for z in min[2]..=max[2] {
+                    if let Some(cell) = self.cells.get(&[x, y, z]) {
+                        ids.extend_from_slice(cell);
+                    }
+                }
+            }
+        }
+        Box::new(ids.into_iter().filter_map(|id| self.pois.get(&id)))
+    }
+
+    fn cell_of(&self, p: [Scalar; 3]) -> Cell {
+        p.map(|v| (v / self.cell_size).floor() as i64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn layer_supports_updates_and_queries() {
+        let mut layer = PoiLayer::new(5.0);
+        let depot = layer.insert(
+            "Depot",
+            BTreeSet::from(["friendly".to_string()]),
+            [1.0, 0.0, 0.0],
+        );
+        let wreck = layer.insert("Wreck", BTreeSet::new(), [3.0, 0.0, 0.0]);
+        let far = layer.insert("Beacon", BTreeSet::new(), [50.0, 2.0, 0.0]);
+
+        let near: Vec<u64> = layer
+            .nearest([0.0; 3], 10.0, 5, None)
+            .iter()
+            .map(|p| p.0.id)
+            .collect();
+        assert_eq!(near, vec![depot, wreck]);
+        let tagged = layer.nearest([0.0; 3], 10.0, 5, Some("friendly"));
+        assert_eq!(tagged.len(), 1);
+
+        let route = [[0.0, 0.0, 0.0], [100.0, 0.0, 0.0]];
+        let along: Vec<u64> = layer
+            .along_route(&route, 2.5)
+            .iter()
+            .map(|p| p.0.id)
+            .collect();
+        assert_eq!(along, vec![depot, wreck, far]);
+        let back = [[100.0, 0.0, 0.0], [60.0, 0.0, 0.0], [0.0, 0.0, 0.0]];
+        let along: Vec<(u64, usize)> = layer
+            .along_route(&back, 2.5)
+            .iter()
+            .map(|p| (p.0.id, p.2))
+            .collect();
+        assert_eq!(along, vec![(far, 1), (wreck, 1), (depot, 1)]);
+
+        assert_eq!(
+            layer.remove(wreck).map(|p| p.label),
+            Some("Wreck".to_string())
+        );
+        assert_eq!(layer.nearest([0.0; 3], 10.0, 5, None).len(), 1);
+        assert_eq!(layer.len(), 2);
+    }
+}
@@ -0,0 +1,61 @@
+use std::cell::RefCell;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+
+/// Share of a background job's work done so far, written by the thread
+/// running it and read by `job_status`.
+#[derive(Debug)]
+pub struct Progress {
+    /// `f32` bits of the fraction; `u32::MAX` (a NaN) until first reported.
+    bits: AtomicU32,
+}
+
+impl Default for Progress {
+    fn default() -> Self {
+        Progress {
+            bits: AtomicU32::new(u32::MAX),
+        }
+    }
+}
+
+thread_local! {
+    static ACTIVE: RefCell<Option<Arc<Progress>>> = const { RefCell::new(None) };
+}
+
+impl Progress {
+    /// Fraction done, from 0 to 1; `None` when the work reports no progress.
+    pub fn fraction(&self) -> Option<f32> {
+        let bits = self.bits.load(Ordering::Relaxed);
+        (bits != u32::MAX).then(|| f32::from_bits(bits))
+    }
+
+    /// Runs `work` with its `report` calls on this thread going to `self`.
+    /// Like the `budget`, progress is thread-local.
+    pub fn run<T>(self: &Arc<Self>, work: impl FnOnce() -> T) -> T {
+        struct Reset(Option<Arc<Progress>>);
+        impl Drop for Reset {
+            fn drop(&mut self) {
+                ACTIVE.with(|a| *a.borrow_mut() = self.0.take());
+            }
+        }
+
+        let outer = ACTIVE.with(|a| a.borrow_mut().replace(Arc::clone(self)));
+        let _reset = Reset(outer);
+        work()
+    }
+}
+
+/// Reports `done` of `total` steps of the running job; does nothing outside
+/// `Progress::run`. Multi-part analyses report once per part.
+pub(crate) fn report(done: usize, total: usize) {
+    ACTIVE.with(|a| {
+        if let Some(progress) = a.borrow().as_ref() {
+            let fraction = if total == 0 {
+                1.0
+            } else {
+                (done as f32 / total as f32).min(1.0)
+            };
+            progress.bits.store(fraction.to_bits(), Ordering::Relaxed);
+        }
+    });
+}
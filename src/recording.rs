@@ -0,0 +1,118 @@
+//! Request/response recording for regression testing. Recorded traffic is
+//! stored as JSONL and can be re-run against a new build with the `replay`
+//! binary.
+
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::api::{EngineRequest, EngineResponse};
+
+#[derive(Debug, Error)]
+pub enum RecordingError {
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("JSON error on line {line}: {source}")]
+    Json {
+        line: usize,
+        #[source]
+        source: serde_json::Error,
+    },
+}
+
+/// One recorded request with the response it produced.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct Recording {
+    pub request: serde_json::Value,
+    pub response: serde_json::Value,
+    pub elapsed_micros: u64,
+}
+
+/// Appends recordings to a JSONL file. Safe to share between tasks.
+#[derive(Debug)]
+pub struct Recorder {
+    file: Mutex<File>,
+}
+
+impl Recorder {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, RecordingError> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Recorder {
+            file: Mutex::new(file),
+        })
+    }
+
+    /// The request is passed pre-encoded because handling consumes it.
+    pub fn record(
+        &self,
+        request: serde_json::Value,
+        response: &EngineResponse,
+        elapsed: Duration,
+    ) -> Result<(), RecordingError> {
+        let recording = Recording {
+            request,
+            response: serde_json::to_value(response)
+                .map_err(|source| RecordingError::Json { line: 0, source })?,
+            elapsed_micros: elapsed.as_micros() as u64,
+        };
+        let mut line = serde_json::to_vec(&recording)
+            .map_err(|source| RecordingError::Json { line: 0, source })?;
+        line.push(b'\n');
+        let mut file = self.file.lock().unwrap_or_else(|e| e.into_inner());
+        file.write_all(&line)?;
+        Ok(())
+    }
+}
+
+pub fn encode_request(request: &EngineRequest) -> serde_json::Value {
+    serde_json::to_value(request).unwrap_or(serde_json::Value::Null)
+}
+
+pub fn read_recordings<P: AsRef<Path>>(path: P) -> Result<Vec<Recording>, RecordingError> {
+    let reader = BufReader::new(File::open(path)?);
+    let mut recordings = Vec::new();
+    for (idx, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let recording = serde_json::from_str(&line).map_err(|source| RecordingError::Json {
+            line: idx + 1,
+            source,
+        })?;
+        recordings.push(recording);
+    }
+    Ok(recordings)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::handle_request;
+    use crate::engine::{demo_graph, Engine};
+
+    #[test]
+    fn recordings_round_trip_through_jsonl() {
+        let file = tempfile::NamedTempFile::new().expect("temp file");
+        let recorder = Recorder::open(file.path()).expect("open");
+        let engine = Engine::new(demo_graph(), None);
+
+        let request: EngineRequest =
+            serde_json::from_str(r#"{"kind":"path","start_id":1,"end_id":3}"#).unwrap();
+        let encoded = encode_request(&request);
+        let response = handle_request(&engine, request).response;
+        recorder
+            .record(encoded.clone(), &response, Duration::from_micros(5))
+            .expect("record");
+
+        let recordings = read_recordings(file.path()).expect("read");
+        assert_eq!(recordings.len(), 1);
+        assert_eq!(recordings[0].request, encoded);
+        assert_eq!(recordings[0].response["kind"], "path");
+    }
+}
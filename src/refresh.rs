@@ -0,0 +1,180 @@
+use std::fs;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde_json::{json, Value};
+use thiserror::Error;
+
+use crate::data::remote::{fetch_dataset, is_remote, remote_version, RemoteVersion};
+use crate::data::{metadata_path_for, DataError};
+use crate::engine::{Engine, SelfCheckReport};
+use crate::graph::cache::PathCache;
+
+/// Size and modification time of one file, the local stand-in for an ETag.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FileStamp {
+    len: u64,
+    modified: Option<SystemTime>,
+}
+
+impl FileStamp {
+    fn of(path: &Path) -> std::io::Result<Self> {
+        let meta = fs::metadata(path)?;
+        Ok(FileStamp {
+            len: meta.len(),
+            modified: meta.modified().ok(),
+        })
+    }
+}
+
+/// Identifies one version of a dataset bundle without reading it: a local
+/// one by its graph and metadata files, a remote one by its HTTP validator.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DatasetVersion {
+    Local {
+        dataset: FileStamp,
+        metadata: Option<FileStamp>,
+    },
+    Remote(RemoteVersion),
+}
+
+impl DatasetVersion {
+    /// Version of the dataset at a local path or an HTTP(S) URL.
+    pub fn of(dataset: &str) -> Result<Self, RefreshError> {
+        if is_remote(dataset) {
+            return remote_version(dataset)?
+                .map(DatasetVersion::Remote)
+                .ok_or_else(|| RefreshError::Unversioned(dataset.to_string()));
+        }
+        let path = Path::new(dataset);
+        Ok(DatasetVersion::Local {
+            dataset: FileStamp::of(path).map_err(DataError::from)?,
+            metadata: FileStamp::of(&metadata_path_for(path)).ok(),
+        })
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum RefreshError {
+    #[error("failed to load the new dataset: {0}")]
+    Data(#[from] DataError),
+    #[error("the new dataset failed its self-check: {0:?}")]
+    SelfCheck(SelfCheckReport),
+    #[error("{0} sends neither an ETag nor a Last-Modified header, so new versions cannot be told apart")]
+    Unversioned(String),
+}
+
+/// Checks the live dataset of `current` for a version other than `seen`,
+/// where `None` means any readable version is new. A new version is loaded
+/// and self-checked, and returned as an engine ready to swap in, keeping the
+/// config, snapshots, tags, POIs and job queue of `current`. `seen` moves on
+/// even when the new version is rejected, so a bad upload is retried only
+/// once it changes again. Engines without a configured dataset never refresh.
+/// A remote dataset is fetched with a conditional request, so an unchanged
+/// one is not downloaded again.
+pub fn refresh(
+    current: &Engine,
+    seen: &mut Option<DatasetVersion>,
+) -> Result<Option<Engine>, RefreshError> {
+    let Some(dataset) = &current.config.dataset else {
+        return Ok(None);
+    };
+    let strict = current.config.strict;
+    let mut fresh = if is_remote(dataset) {
+        let last = match seen {
+            Some(DatasetVersion::Remote(version)) => Some(version.clone()),
+            _ => None,
+        };
+        let Some(fetched) = fetch_dataset(dataset, last.as_ref())? else {
+            return Ok(None);
+        };
+        let version = fetched
+            .version
+            .clone()
+            .ok_or_else(|| RefreshError::Unversioned(dataset.clone()))?;
+        if last.as_ref() == Some(&version) {
+            return Ok(None);
+        }
+        *seen = Some(DatasetVersion::Remote(version));
+        Engine::load_with(&fetched.path, strict)?
+    } else {
+        let version = DatasetVersion::of(dataset)?;
+        if seen.as_ref() == Some(&version) {
+            return Ok(None);
+        }
+        *seen = Some(version);
+        Engine::load_with(dataset, strict)?
+    };
+    let report = fresh.self_check();
+    if !report.passed {
+        return Err(RefreshError::SelfCheck(report));
+    }
+    fresh.snapshots = current.snapshots.clone();
+    fresh.tags = current.tags.clone();
+    fresh.pois = current.pois.clone();
+    fresh.closed_gates = current.closed_gates.clone();
+    // Queued jobs carry over and run on the new dataset; results computed
+    // on the old one are dropped.
+    fresh.jobs = current.jobs.clone();
+    fresh.jobs.forget_results();
+    fresh.path_cache = PathCache::new(current.config.path_cache_size);
+    fresh.config = current.config.clone();
+    Ok(Some(fresh))
+}
+
+/// CloudWatch embedded-metric record of a dataset swap, printed as one
+/// stdout line so Lambda logs turn it into a `DatasetSwapped` metric.
+pub fn swap_metric(engine: &Engine) -> Value {
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis() as u64);
+    json!({
+        "_aws": {
+            "Timestamp": timestamp,
+            "CloudWatchMetrics": [{
+                "Namespace": "StarmapEngine",
+                "Dimensions": [[]],
+                "Metrics": [{ "Name": "DatasetSwapped", "Unit": "Count" }],
+            }],
+        },
+        "DatasetSwapped": 1,
+        "release_tag": engine.metadata.as_ref().map(|m| m.release_tag.as_str()),
+        "systems": engine.graph.len(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::EngineConfig;
+    use crate::data::write_graph_to_file;
+    use crate::engine::demo_graph;
+    use crate::graph::graph::StarGraph;
+
+    #[test]
+    fn newer_datasets_are_loaded_once() {
+        let dir = tempfile::tempdir().expect("temp dir");
+        let path = dir.path().join("starmap.bin");
+        write_graph_to_file(&demo_graph(), &path).unwrap();
+        let dataset = path.display().to_string();
+        let current = Engine::from_config(EngineConfig {
+            dataset: Some(dataset.clone()),
+            ..EngineConfig::default()
+        })
+        .unwrap();
+        let mut seen = Some(DatasetVersion::of(&dataset).unwrap());
+        assert!(refresh(&current, &mut seen).unwrap().is_none());
+
+        // Drop system D and its gate.
+        let demo = demo_graph();
+        let mut adjacency = demo.adjacency[..3].to_vec();
+        adjacency[0].retain(|&n| n != 3);
+        let smaller = StarGraph::new(demo.systems[..3].to_vec(), adjacency);
+        write_graph_to_file(&smaller, &path).unwrap();
+        let fresh = refresh(&current, &mut seen).unwrap().expect("new version");
+        assert_eq!(fresh.graph.len(), 3);
+        assert_eq!(fresh.config, current.config);
+        assert!(refresh(&fresh, &mut seen).unwrap().is_none());
+        assert_eq!(swap_metric(&fresh)["systems"], 3);
+    }
+}
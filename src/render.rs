@@ -0,0 +1,158 @@
+//! Plain-text map of a route for terminals: the route's systems are
+//! projected onto the two axes along which they spread the most, and drawn
+//! on a character grid together with any other systems in view.
+
+use crate::graph::graph::StarGraph;
+use crate::Scalar;
+
+/// Size of the character grid, in columns and rows.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RenderSize {
+    pub columns: usize,
+    pub rows: usize,
+}
+
+impl Default for RenderSize {
+    fn default() -> Self {
+        RenderSize {
+            columns: 72,
+            rows: 24,
+        }
+    }
+}
+
+const START: char = 'S';
+const END: char = 'E';
+const STOP: char = 'o';
+const LEG: char = '*';
+const OTHER: char = '.';
+
+/// Renders the route through `route` (system indices in travel order) as a
+/// framed grid followed by a legend of the projected axes. Other systems
+/// inside the route's bounding box show as `.`, legs as `*`, intermediate
+/// stops as `o`, and the endpoints as `S` and `E`.
+pub fn render_route(graph: &StarGraph, route: &[usize], size: RenderSize) -> String {
+    let (columns, rows) = (size.columns.max(2), size.rows.max(2));
+    let Some(&first) = route.first() else {
+        return String::new();
+    };
+    let mut min = graph.systems[first].pos;
+    let mut max = min;
+    for &idx in route {
+        for axis in 0..3 {
+            min[axis] = min[axis].min(graph.systems[idx].pos[axis]);
+            max[axis] = max[axis].max(graph.systems[idx].pos[axis]);
+        }
+    }
+    let mut axes = [0, 1, 2];
+    axes.sort_by(|&a, &b| (max[b] - min[b]).total_cmp(&(max[a] - min[a])));
+    let (u, v) = (axes[0], axes[1]);
+
+    // Rows grow downwards, so the second axis is flipped.
+    let cell = |pos: [Scalar; 3]| -> Option<(usize, usize)> {
+        let scale = |value: Scalar, axis: usize, cells: usize| {
+            let extent = max[axis] - min[axis];
+            if extent > 0.0 {
+                ((value - min[axis]) / extent * (cells - 1) as Scalar).round()
+            } else {
+                ((cells - 1) / 2) as Scalar
+            }
+        };
+        let (x, y) = (scale(pos[u], u, columns), scale(pos[v], v, rows));
+        let inside = |c: Scalar, cells: usize| (0.0..=(cells - 1) as Scalar).contains(&c);
+        (inside(x, columns) && inside(y, rows)).then(|| (x as usize, rows - 1 - y as usize))
+    };
+
+    let mut grid = vec![vec![' '; columns]; rows];
+    let inside_box =
+        |pos: [Scalar; 3]| (0..3).all(|axis| (min[axis]..=max[axis]).contains(&pos[axis]));
+    for system in &graph.systems {
+        if inside_box(system.pos) {
+            if let Some((x, y)) = cell(system.pos) {
+                grid[y][x] = OTHER;
+            }
+        }
+    }
+    for leg in route.windows(2) {
+        let from = cell(graph.systems[leg[0]].pos);
+        let to = cell(graph.systems[leg[1]].pos);
+        if let (Some(from), Some(to)) = (from, to) {
+            draw_line(&mut grid, from, to);
+        }
+    }
+    let last = route.len() - 1;
+    for (step, &idx) in route.iter().enumerate() {
+        if let Some((x, y)) = cell(graph.systems[idx].pos) {
+            grid[y][x] = match step {
+                0 => START,
+                s if s == last => END,
+                _ if grid[y][x] == START || grid[y][x] == END => grid[y][x],
+                _ => STOP,
+            };
+        }
+    }
+
+    let axis_name = |axis: usize| ["x", "y", "z"][axis];
+    let border = format!("+{}+\n", "-".repeat(columns));
+    let mut out = border.clone();
+    for row in &grid {
+        out.push('|');
+        out.extend(row.iter());
+        out.push_str("|\n");
+    }
+    out.push_str(&border);
+    out.push_str(&format!(
+        "{START} {} -> {END} {}, {} jumps; across: {} {:.1}..{:.1}, up: {} {:.1}..{:.1}\n",
+        graph.systems[first].name,
+        graph.systems[route[last]].name,
+        last,
+        axis_name(u),
+        min[u],
+        max[u],
+        axis_name(v),
+        min[v],
+        max[v],
+    ));
+    out
+}
+
+/// Marks the cells between two grid points, leaving the endpoints to the
+/// caller.
+fn draw_line(grid: &mut [Vec<char>], from: (usize, usize), to: (usize, usize)) {
+    let dx = to.0 as isize - from.0 as isize;
+    let dy = to.1 as isize - from.1 as isize;
+    let steps = dx.abs().max(dy.abs());
+    for i in 1..steps {
+        let x = from.0 as isize + (dx * i + dx.signum() * steps / 2) / steps;
+        let y = from.1 as isize + (dy * i + dy.signum() * steps / 2) / steps;
+        let cell = &mut grid[y as usize][x as usize];
+        if *cell == ' ' || *cell == OTHER {
+            *cell = LEG;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::demo_graph;
+
+    #[test]
+    fn route_endpoints_and_legs_are_drawn() {
+        let graph = demo_graph();
+        // D -> A -> B -> C spans x and y.
+        let size = RenderSize {
+            columns: 9,
+            rows: 5,
+        };
+        let map = render_route(&graph, &[3, 0, 1, 2], size);
+        let lines: Vec<&str> = map.lines().collect();
+        assert_eq!(lines.len(), size.rows + 3);
+        assert_eq!(lines[1], "|S        |");
+        assert_eq!(lines[3], "|*        |");
+        assert_eq!(lines[5], "|o***o***E|");
+        assert!(lines[6].starts_with("+---"));
+        assert!(lines[7].starts_with("S D -> E C, 3 jumps"));
+        assert_eq!(render_route(&graph, &[], size), "");
+    }
+}
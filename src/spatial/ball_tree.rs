@@ -0,0 +1,199 @@
+use crate::spatial::index::SpatialIndex;
+use crate::spatial::kd_tree::{dist2, Candidates};
+use crate::validate::is_searchable;
+use crate::Scalar;
+
+/// Most points kept in a leaf ball.
+const LEAF_SIZE: usize = 8;
+
+/// Marks a leaf in `Ball::left`.
+const LEAF: u32 = u32::MAX;
+
+/// A bounding sphere over `points[start..end]`. Inner balls have two
+/// children, at `left` and `left + 1`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Ball {
+    center: [Scalar; 3],
+    radius: Scalar,
+    start: u32,
+    end: u32,
+    left: u32,
+}
+
+/// Ball tree: a hierarchy of bounding spheres, each split in two at the
+/// median of its widest axis. Unlike k-d tree cells, a ball shrinks to fit
+/// its points, so searches skip the empty space around tight clusters that
+/// k-d splits would cut through.
+#[derive(Clone, Debug)]
+pub struct BallTree {
+    /// The points, reordered so each ball's points are contiguous.
+    points: Vec<[Scalar; 3]>,
+    /// Source index of each entry of `points`.
+    indices: Vec<u32>,
+    balls: Vec<Ball>,
+}
+
+impl BallTree {
+    pub fn build(points: &[[Scalar; 3]]) -> Self {
+        let mut order: Vec<u32> = (0..points.len())
+            .map(|i| u32::try_from(i).expect("fewer than 2^32 points"))
+            .collect();
+        let mut balls = Vec::new();
+        let mut pending = Vec::new();
+        if !points.is_empty() {
+            balls.push(bounding_ball(points, &order, 0, points.len()));
+            pending.push(0);
+        }
+        while let Some(id) = pending.pop() {
+            let Ball { start, end, .. } = balls[id];
+            let (start, end) = (start as usize, end as usize);
+            if end - start <= LEAF_SIZE {
+                continue;
+            }
+            let range = &mut order[start..end];
+            let axis = widest_axis(points, range);
+            let mid = range.len() / 2;
+            range.select_nth_unstable_by(mid, |&a, &b| {
+                points[a as usize][axis].total_cmp(&points[b as usize][axis])
+            });
+            let left = balls.len();
+            balls[id].left = left as u32;
+            balls.push(bounding_ball(points, &order, start, start + mid));
+            balls.push(bounding_ball(points, &order, start + mid, end));
+            pending.extend([left, left + 1]);
+        }
+        BallTree {
+            points: order.iter().map(|&i| points[i as usize]).collect(),
+            indices: order,
+            balls,
+        }
+    }
+
+    /// Visits balls nearest first, skipping those whose surface lies further
+    /// from `target` than the squared distance `visit` last returned.
+    fn walk<F>(&self, target: [Scalar; 3], mut visit: F)
+    where
+        F: FnMut(usize, Scalar) -> Scalar,
+    {
+        let mut limit2 = Scalar::INFINITY;
+        let gap2 = |ball: &Ball| {
+            let gap = (dist2(target, ball.center).sqrt() - ball.radius).max(0.0);
+            gap * gap
+        };
+        let mut stack = Vec::new();
+        if let Some(root) = self.balls.first() {
+            stack.push((0, gap2(root)));
+        }
+        while let Some((id, gap)) = stack.pop() {
+            if gap > limit2 {
+                continue;
+            }
+            let ball = &self.balls[id];
+            if ball.left == LEAF {
+                for slot in ball.start as usize..ball.end as usize {
+                    limit2 = visit(slot, dist2(self.points[slot], target));
+                }
+                continue;
+            }
+            let left = ball.left as usize;
+            let (a, b) = (gap2(&self.balls[left]), gap2(&self.balls[left + 1]));
+            if a <= b {
+                stack.extend([(left + 1, b), (left, a)]);
+            } else {
+                stack.extend([(left, a), (left + 1, b)]);
+            }
+        }
+    }
+}
+
+impl SpatialIndex for BallTree {
+    fn len(&self) -> usize {
+        self.points.len()
+    }
+
+    fn nearest_one(&self, target: [Scalar; 3]) -> Option<(usize, Scalar)> {
+        self.nearest_n(target, 1).pop()
+    }
+
+    fn nearest_n(&self, target: [Scalar; 3], n: usize) -> Vec<(usize, Scalar)> {
+        self.nearest_n_within_radius(target, Scalar::INFINITY, n)
+    }
+
+    fn nearest_n_within_radius(
+        &self,
+        target: [Scalar; 3],
+        radius: Scalar,
+        n: usize,
+    ) -> Vec<(usize, Scalar)> {
+        if n == 0 || !is_searchable(target, radius) {
+            return Vec::new();
+        }
+        let radius2 = radius * radius;
+        let mut best = Candidates::new(n);
+        self.walk(target, |slot, dist2| {
+            if dist2 <= radius2 {
+                best.offer(self.indices[slot] as usize, dist2);
+            }
+            best.worst().min(radius2)
+        });
+        best.into_sorted()
+    }
+}
+
+fn bounding_ball(points: &[[Scalar; 3]], order: &[u32], start: usize, end: usize) -> Ball {
+    let members = || order[start..end].iter().map(|&i| points[i as usize]);
+    let mut center = [0.0; 3];
+    for point in members() {
+        for axis in 0..3 {
+            center[axis] += point[axis];
+        }
+    }
+    let count = (end - start) as Scalar;
+    center = center.map(|sum| sum / count);
+    let radius = members()
+        .map(|point| dist2(point, center))
+        .fold(0.0, Scalar::max)
+        .sqrt();
+    Ball {
+        center,
+        radius,
+        start: start as u32,
+        end: end as u32,
+        left: LEAF,
+    }
+}
+
+fn widest_axis(points: &[[Scalar; 3]], order: &[u32]) -> usize {
+    let mut min = [Scalar::INFINITY; 3];
+    let mut max = [Scalar::NEG_INFINITY; 3];
+    for &i in order {
+        for axis in 0..3 {
+            min[axis] = min[axis].min(points[i as usize][axis]);
+            max[axis] = max[axis].max(points[i as usize][axis]);
+        }
+    }
+    (0..3)
+        .max_by(|&a, &b| (max[a] - min[a]).total_cmp(&(max[b] - min[b])))
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::spatial::index::{assert_agrees_with_kd, scattered_points};
+
+    #[test]
+    fn ball_tree_agrees_with_the_kd_tree_on_clusters() {
+        // Tight clusters far apart, the layout ball trees are meant for.
+        let points: Vec<[Scalar; 3]> = scattered_points(2_000, 3.0)
+            .into_iter()
+            .enumerate()
+            .map(|(i, [x, y, z])| {
+                let cluster = (i % 5) as Scalar * 1_000.0;
+                [cluster + x, y, cluster + z]
+            })
+            .collect();
+        let targets = [[0.0; 3], [2_001.0, 1.0, 2_000.5], [500.0, 0.0, 500.0]];
+        assert_agrees_with_kd(&BallTree::build(&points), &points, &targets, 2.0);
+    }
+}
@@ -0,0 +1,128 @@
+use std::collections::HashMap;
+
+use serde::Serialize;
+
+use crate::spatial::morton::MortonIndex;
+use crate::spatial::shapes::distance_to_segment;
+use crate::Scalar;
+
+/// A point near a route and where along the route it lies.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+pub struct CorridorHit {
+    pub index: usize,
+    /// Distance to the closest segment.
+    pub distance: Scalar,
+    /// The closest segment, `waypoints[segment]` to `waypoints[segment + 1]`.
+    pub segment: usize,
+}
+
+/// Every point of `index` (built over `points`) within `distance` of the
+/// polyline through `waypoints`, each once at its closest segment and in
+/// route order: by segment, then by position along it. A single waypoint
+/// gives the sphere around it.
+pub fn within_corridor(
+    index: &MortonIndex,
+    points: &[[Scalar; 3]],
+    waypoints: &[[Scalar; 3]],
+    distance: Scalar,
+) -> Vec<CorridorHit> {
+    corridor_hits(
+        waypoints,
+        distance,
+        |a, b| index.near_segment(a, b, distance),
+        |i| points[i],
+    )
+}
+
+/// `within_corridor` over any point index: `near_segment(a, b)` lists the
+/// indices of candidate points near the segment from `a` to `b` (a superset
+/// is fine), and `point_of` gives their positions.
+pub fn corridor_hits<I>(
+    waypoints: &[[Scalar; 3]],
+    distance: Scalar,
+    mut near_segment: impl FnMut([Scalar; 3], [Scalar; 3]) -> I,
+    point_of: impl Fn(usize) -> [Scalar; 3],
+) -> Vec<CorridorHit>
+where
+    I: IntoIterator<Item = usize>,
+{
+    let segments: Vec<([Scalar; 3], [Scalar; 3])> = match waypoints {
+        [] => Vec::new(),
+        [only] => vec![(*only, *only)],
+        _ => waypoints.windows(2).map(|w| (w[0], w[1])).collect(),
+    };
+    // (distance, segment, position along it) of each point's closest segment.
+    let mut closest: HashMap<usize, (Scalar, usize, Scalar)> = HashMap::new();
+    for (segment, &(a, b)) in segments.iter().enumerate() {
+        for i in near_segment(a, b) {
+            let point = point_of(i);
+            let d = distance_to_segment(point, a, b);
+            if d > distance {
+                continue;
+            }
+            let along = projection(point, a, b);
+            closest
+                .entry(i)
+                .and_modify(|best| {
+                    if d < best.0 {
+                        *best = (d, segment, along);
+                    }
+                })
+                .or_insert((d, segment, along));
+        }
+    }
+    let mut hits: Vec<(CorridorHit, Scalar)> = closest
+        .into_iter()
+        .map(|(index, (distance, segment, along))| {
+            let hit = CorridorHit {
+                index,
+                distance,
+                segment,
+            };
+            (hit, along)
+        })
+        .collect();
+    hits.sort_by(|(a, along_a), (b, along_b)| {
+        (a.segment, along_a, a.index)
+            .partial_cmp(&(b.segment, along_b, b.index))
+            .expect("positions are finite")
+    });
+    hits.into_iter().map(|(hit, _)| hit).collect()
+}
+
+/// Position of `point` projected onto the segment, from 0 at `a` to 1 at `b`.
+fn projection(point: [Scalar; 3], a: [Scalar; 3], b: [Scalar; 3]) -> Scalar {
+    let ab = [0, 1, 2].map(|axis| b[axis] - a[axis]);
+    let len2: Scalar = ab.iter().map(|c| c * c).sum();
+    if len2 == 0.0 {
+        return 0.0;
+    }
+    let t: Scalar = (0..3).map(|axis| (point[axis] - a[axis]) * ab[axis]).sum();
+    (t / len2).clamp(0.0, 1.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hits_follow_the_route_once_each() {
+        let points = [
+            [5.0, 0.5, 0.0],  // near the first leg
+            [10.0, 5.0, 0.0], // near the second leg
+            [1.0, -0.5, 0.0], // near the first leg, earlier
+            [10.5, 0.0, 0.0], // at the corner: closest to both legs
+            [5.0, 5.0, 0.0],  // too far from either
+        ];
+        let index = MortonIndex::build(&points);
+        let route = [[0.0, 0.0, 0.0], [10.0, 0.0, 0.0], [10.0, 10.0, 0.0]];
+        let hits = within_corridor(&index, &points, &route, 1.0);
+        let order: Vec<(usize, usize)> = hits.iter().map(|h| (h.index, h.segment)).collect();
+        assert_eq!(order, vec![(2, 0), (0, 0), (3, 0), (1, 1)]);
+        assert_eq!(hits[1].distance, 0.5);
+
+        let around = within_corridor(&index, &points, &route[..1], 1.2);
+        assert_eq!(around.iter().map(|h| h.index).collect::<Vec<_>>(), vec![2]);
+        assert!(within_corridor(&index, &points, &[], 1.0).is_empty());
+    }
+}
@@ -0,0 +1,243 @@
+use std::collections::HashMap;
+
+use thiserror::Error;
+
+use crate::spatial::index::SpatialIndex;
+use crate::spatial::kd_tree::{dist2, Candidates};
+use crate::spatial::morton::bounds;
+use crate::validate::is_searchable;
+use crate::Scalar;
+
+/// Points per cell aimed for when `GridHash::build` picks the cell size.
+const POINTS_PER_CELL: Scalar = 2.0;
+
+/// Largest cell coordinate magnitude; keeps ring arithmetic clear of `i32`
+/// overflow.
+const MAX_CELL_COORD: Scalar = (1 << 28) as Scalar;
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum GridHashError {
+    #[error("cell_size must be positive and finite")]
+    InvalidCellSize,
+    #[error("cell_size is too small for the extent of the points")]
+    TooFine,
+}
+
+type CellKey = [i32; 3];
+
+/// Uniform grid of cubic cells, hashed so only occupied cells take memory.
+/// A radius query reads just the cells the sphere overlaps, which beats any
+/// tree when stars are spread roughly evenly and the cell size is near the
+/// query radius; strongly clustered data is better served by the trees.
+#[derive(Clone, Debug)]
+pub struct GridHash {
+    cell_size: Scalar,
+    /// The points, reordered so each cell's points are contiguous.
+    points: Vec<[Scalar; 3]>,
+    /// Source index of each entry of `points`.
+    indices: Vec<u32>,
+    /// Range of `points` in each occupied cell.
+    cells: HashMap<CellKey, (u32, u32)>,
+    /// Lowest and highest occupied cell coordinates per axis.
+    extent: Option<(CellKey, CellKey)>,
+}
+
+impl GridHash {
+    /// Picks a cell size that puts about `POINTS_PER_CELL` points in each
+    /// cell of the bounding box, right for evenly spread points.
+    pub fn build(points: &[[Scalar; 3]]) -> Self {
+        let cell_size = bounds(points.iter().copied())
+            .map(|(min, max)| {
+                let widest = (0..3).map(|a| max[a] - min[a]).fold(0.0, Scalar::max);
+                let per_axis = (points.len() as Scalar / POINTS_PER_CELL).cbrt().ceil();
+                widest / per_axis.max(1.0)
+            })
+            .filter(|&size| size > 0.0)
+            .unwrap_or(1.0);
+        Self::with_cell_size(points, cell_size).expect("cell size fits the extent")
+    }
+
+    pub fn with_cell_size(
+        points: &[[Scalar; 3]],
+        cell_size: Scalar,
+    ) -> Result<Self, GridHashError> {
+        if !(cell_size.is_finite() && cell_size > 0.0) {
+            return Err(GridHashError::InvalidCellSize);
+        }
+        let mut keyed = Vec::with_capacity(points.len());
+        for (i, &p) in points.iter().enumerate() {
+            let coords = p.map(|v| (v / cell_size).floor());
+            if coords.iter().any(|c| c.abs() > MAX_CELL_COORD) {
+                return Err(GridHashError::TooFine);
+            }
+            let index = u32::try_from(i).expect("fewer than 2^32 points");
+            keyed.push((coords.map(|c| c as i32), index));
+        }
+        keyed.sort_unstable();
+        let mut cells = HashMap::new();
+        let mut start = 0;
+        for run in keyed.chunk_by(|a, b| a.0 == b.0) {
+            cells.insert(run[0].0, (start as u32, (start + run.len()) as u32));
+            start += run.len();
+        }
+        let extent = keyed.first().map(|&(first, _)| {
+            keyed.iter().fold((first, first), |(lo, hi), &(key, _)| {
+                (
+                    [0, 1, 2].map(|a| lo[a].min(key[a])),
+                    [0, 1, 2].map(|a| hi[a].max(key[a])),
+                )
+            })
+        });
+        Ok(GridHash {
+            cell_size,
+            points: keyed.iter().map(|&(_, i)| points[i as usize]).collect(),
+            indices: keyed.into_iter().map(|(_, i)| i).collect(),
+            cells,
+            extent,
+        })
+    }
+
+    pub fn cell_size(&self) -> Scalar {
+        self.cell_size
+    }
+
+    /// Number of occupied cells.
+    pub fn occupied_cells(&self) -> usize {
+        self.cells.len()
+    }
+
+    fn key_of(&self, p: [Scalar; 3]) -> CellKey {
+        p.map(|v| {
+            (v / self.cell_size)
+                .floor()
+                .clamp(-MAX_CELL_COORD, MAX_CELL_COORD) as i32
+        })
+    }
+
+    fn offer_cell(
+        &self,
+        key: CellKey,
+        target: [Scalar; 3],
+        radius2: Scalar,
+        best: &mut Candidates,
+    ) {
+        if let Some(&(start, end)) = self.cells.get(&key) {
+            for slot in start as usize..end as usize {
+                let d2 = dist2(self.points[slot], target);
+                if d2 <= radius2 {
+                    best.offer(self.indices[slot] as usize, d2);
+                }
+            }
+        }
+    }
+}
+
+impl SpatialIndex for GridHash {
+    fn len(&self) -> usize {
+        self.points.len()
+    }
+
+    fn nearest_one(&self, target: [Scalar; 3]) -> Option<(usize, Scalar)> {
+        self.nearest_n(target, 1).pop()
+    }
+
+    fn nearest_n(&self, target: [Scalar; 3], n: usize) -> Vec<(usize, Scalar)> {
+        self.nearest_n_within_radius(target, Scalar::INFINITY, n)
+    }
+
+    /// Reads cells in rings of growing Chebyshev distance around the
+    /// target's cell, until a ring cannot hold anything closer than the
+    /// results. Once the rings would visit more cells than are occupied,
+    /// the remaining occupied cells are scanned directly instead.
+    fn nearest_n_within_radius(
+        &self,
+        target: [Scalar; 3],
+        radius: Scalar,
+        n: usize,
+    ) -> Vec<(usize, Scalar)> {
+        let Some((lo, hi)) = self.extent else {
+            return Vec::new();
+        };
+        if n == 0 || !is_searchable(target, radius) {
+            return Vec::new();
+        }
+        let radius2 = radius * radius;
+        let center = self.key_of(target);
+        let last_ring = (0..3)
+            .map(|a| (center[a] - lo[a]).abs().max((hi[a] - center[a]).abs()))
+            .max()
+            .unwrap_or(0);
+        let mut best = Candidates::new(n);
+        let mut visited = 0usize;
+        for ring in 0..=last_ring {
+            // Every cell of the ring is at least `ring - 1` whole cells away.
+            let gap = (ring - 1).max(0) as Scalar * self.cell_size;
+            if gap * gap > best.worst().min(radius2) {
+                break;
+            }
+            let side = 2 * ring as usize + 1;
+            let cells = side.pow(3) - side.saturating_sub(2).pow(3);
+            if visited + cells > self.cells.len() {
+                for &key in self.cells.keys() {
+                    let ring_of = (0..3).map(|a| (key[a] - center[a]).abs()).max();
+                    if ring_of.unwrap_or(0) >= ring {
+                        self.offer_cell(key, target, radius2, &mut best);
+                    }
+                }
+                break;
+            }
+            visited += cells;
+            for dx in -ring..=ring {
+                for dy in -ring..=ring {
+                    let on_shell = dx.abs() == ring || dy.abs() == ring;
+                    let step = if on_shell { 1 } else { (2 * ring).max(1) };
+                    let mut dz = -ring;
+                    while dz <= ring {
+                        let key = [center[0] + dx, center[1] + dy, center[2] + dz];
+                        self.offer_cell(key, target, radius2, &mut best);
+                        dz += step;
+                    }
+                }
+            }
+        }
+        best.into_sorted()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::spatial::index::{assert_agrees_with_kd, scattered_points};
+
+    #[test]
+    fn grid_hash_agrees_with_the_kd_tree_at_any_cell_size() {
+        let points = scattered_points(2_000, 80.0);
+        let targets = [[0.0; 3], [40.0, 41.5, 39.0], [-300.0, 20.0, 500.0]];
+        for grid in [
+            GridHash::build(&points),
+            GridHash::with_cell_size(&points, 0.5).unwrap(),
+            GridHash::with_cell_size(&points, 500.0).unwrap(),
+        ] {
+            assert_agrees_with_kd(&grid, &points, &targets, 6.0);
+        }
+    }
+
+    #[test]
+    fn cell_sizes_are_validated() {
+        let points = scattered_points(2_000, 80.0);
+        assert_eq!(
+            GridHash::with_cell_size(&points, 500.0)
+                .unwrap()
+                .occupied_cells(),
+            1
+        );
+        assert_eq!(
+            GridHash::with_cell_size(&points, 0.0).err(),
+            Some(GridHashError::InvalidCellSize)
+        );
+        assert_eq!(
+            GridHash::with_cell_size(&points, 1e-12).err(),
+            Some(GridHashError::TooFine)
+        );
+    }
+}
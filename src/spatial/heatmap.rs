@@ -0,0 +1,306 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::Scalar;
+
+/// Most cells a heatmap may have along one axis.
+pub const MAX_HEATMAP_CELLS_PER_AXIS: u32 = 1_024;
+
+/// Most cells a density kernel may reach from a system along one axis; the
+/// kernel is cut off at three bandwidths.
+pub const MAX_KERNEL_REACH: u32 = 6;
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum HeatmapError {
+    #[error("cell_size must be positive and finite")]
+    InvalidCellSize,
+    #[error("more than {MAX_HEATMAP_CELLS_PER_AXIS} cells along an axis; use a larger cell_size")]
+    TooFine,
+    #[error("bandwidth must be positive and finite")]
+    InvalidBandwidth,
+    #[error("bandwidth spans more than {MAX_KERNEL_REACH} cells; use a smaller bandwidth or a larger cell_size")]
+    KernelTooWide,
+}
+
+/// Plane a heatmap is projected onto; the remaining axis is collapsed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Projection {
+    Xy,
+    Xz,
+    Yz,
+}
+
+impl Projection {
+    fn axes(self) -> &'static [usize] {
+        match self {
+            Projection::Xy => &[0, 1],
+            Projection::Xz => &[0, 2],
+            Projection::Yz => &[1, 2],
+        }
+    }
+}
+
+/// How the values of the systems in one cell combine.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Aggregate {
+    Sum,
+    Mean,
+}
+
+/// One non-empty cell.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct HeatCell {
+    /// Cell coordinates along each grid axis, counted from `origin`.
+    pub index: Vec<u32>,
+    pub systems: usize,
+    pub value: Scalar,
+}
+
+/// Values binned into a regular grid of cubic (or, projected, square)
+/// cells. Only non-empty cells are listed, sorted by index, so the result
+/// stays small for a sparse map.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct Heatmap {
+    /// Lowest corner of cell 0 along every axis, in the points' frame.
+    pub origin: [Scalar; 3],
+    /// Cells along each grid axis: three, or the two of the projection.
+    pub dims: Vec<u32>,
+    pub cells: Vec<HeatCell>,
+}
+
+/// Bins `(position, value)` samples into cells of `cell_size`, starting at
+/// the lowest corner of their bounding box.
+pub fn heatmap(
+    samples: &[([Scalar; 3], Scalar)],
+    cell_size: Scalar,
+    projection: Option<Projection>,
+    aggregate: Aggregate,
+) -> Result<Heatmap, HeatmapError> {
+    let axes = projection.map_or(&[0, 1, 2][..], Projection::axes);
+    let Some((origin, dims)) = layout(samples.iter().map(|&(pos, _)| pos), cell_size, axes)? else {
+        return Ok(Heatmap::empty(axes));
+    };
+    let cell_of = |value: Scalar, axis: usize| ((value - origin[axis]) / cell_size) as u32;
+
+    let mut cells: BTreeMap<Vec<u32>, (usize, Scalar)> = BTreeMap::new();
+    for &(pos, value) in samples {
+        let index = axes.iter().map(|&axis| cell_of(pos[axis], axis)).collect();
+        let cell = cells.entry(index).or_default();
+        cell.0 += 1;
+        cell.1 += value;
+    }
+    let cells = cells
+        .into_iter()
+        .map(|(index, (systems, sum))| HeatCell {
+            index,
+            systems,
+            value: match aggregate {
+                Aggregate::Sum => sum,
+                Aggregate::Mean => sum / systems as Scalar,
+            },
+        })
+        .collect();
+    Ok(Heatmap {
+        origin,
+        dims,
+        cells,
+    })
+}
+
+/// Gaussian kernel density estimate of `points`, in points per unit volume
+/// (per unit area when projected), at the centre of every cell within three
+/// `bandwidth`s of a point. Unlike plain counts it varies smoothly, and
+/// cells next to a point are listed even when empty; `systems` still counts
+/// the points inside each cell. The grid is laid out as by `heatmap`.
+pub fn density(
+    points: &[[Scalar; 3]],
+    cell_size: Scalar,
+    projection: Option<Projection>,
+    bandwidth: Scalar,
+) -> Result<Heatmap, HeatmapError> {
+    if !(bandwidth.is_finite() && bandwidth > 0.0) {
+        return Err(HeatmapError::InvalidBandwidth);
+    }
+    let axes = projection.map_or(&[0, 1, 2][..], Projection::axes);
+    let Some((origin, dims)) = layout(points.iter().copied(), cell_size, axes)? else {
+        return Ok(Heatmap::empty(axes));
+    };
+    let reach = (3.0 * bandwidth / cell_size).ceil();
+    if reach > MAX_KERNEL_REACH as Scalar {
+        return Err(HeatmapError::KernelTooWide);
+    }
+    let reach = reach as i64;
+    let h = bandwidth as f64;
+    let norm = (std::f64::consts::TAU.sqrt() * h)
+        .powi(axes.len() as i32)
+        .recip();
+
+    let mut cells: BTreeMap<Vec<u32>, (usize, f64)> = BTreeMap::new();
+    for p in points {
+        let home: Vec<i64> = axes
+            .iter()
+            .map(|&axis| ((p[axis] - origin[axis]) / cell_size) as i64)
+            .collect();
+        cells
+            .entry(home.iter().map(|&c| c as u32).collect())
+            .or_default()
+            .0 += 1;
+        // Every cell of the window around `home`, clipped to the grid.
+        let lo: Vec<i64> = home.iter().map(|&c| (c - reach).max(0)).collect();
+        let hi: Vec<i64> = home
+            .iter()
+            .zip(&dims)
+            .map(|(&c, &dim)| (c + reach).min(dim as i64 - 1))
+            .collect();
+        let mut index = lo.clone();
+        loop {
+            let r2: f64 = axes
+                .iter()
+                .zip(&index)
+                .map(|(&axis, &c)| {
+                    let center = origin[axis] as f64 + (c as f64 + 0.5) * cell_size as f64;
+                    (center - p[axis] as f64).powi(2)
+                })
+                .sum();
+            let key = index.iter().map(|&c| c as u32).collect();
+            cells.entry(key).or_default().1 += norm * (-r2 / (2.0 * h * h)).exp();
+            let Some(axis) = (0..index.len()).rev().find(|&a| index[a] < hi[a]) else {
+                break;
+            };
+            index[axis] += 1;
+            index[axis + 1..].copy_from_slice(&lo[axis + 1..]);
+        }
+    }
+    Ok(Heatmap {
+        origin,
+        dims,
+        cells: cells
+            .into_iter()
+            .map(|(index, (systems, value))| HeatCell {
+                index,
+                systems,
+                value: value as Scalar,
+            })
+            .collect(),
+    })
+}
+
+impl Heatmap {
+    fn empty(axes: &[usize]) -> Self {
+        Heatmap {
+            origin: [0.0; 3],
+            dims: vec![0; axes.len()],
+            cells: Vec::new(),
+        }
+    }
+}
+
+/// Lowest corner and per-axis cell counts of a grid.
+type Layout = ([Scalar; 3], Vec<u32>);
+
+/// Origin and per-axis cell counts of the grid of `cell_size` cells from the
+/// lowest corner of the positions' bounding box; `None` without positions.
+fn layout(
+    positions: impl Iterator<Item = [Scalar; 3]>,
+    cell_size: Scalar,
+    axes: &[usize],
+) -> Result<Option<Layout>, HeatmapError> {
+    if !(cell_size.is_finite() && cell_size > 0.0) {
+        return Err(HeatmapError::InvalidCellSize);
+    }
+    let mut origin = [Scalar::INFINITY; 3];
+    let mut max = [Scalar::NEG_INFINITY; 3];
+    let mut any = false;
+    for pos in positions {
+        any = true;
+        for axis in 0..3 {
+            origin[axis] = origin[axis].min(pos[axis]);
+            max[axis] = max[axis].max(pos[axis]);
+        }
+    }
+    if !any {
+        return Ok(None);
+    }
+    let dims = axes
+        .iter()
+        .map(|&axis| {
+            let extent = (max[axis] - origin[axis]) / cell_size;
+            if extent >= MAX_HEATMAP_CELLS_PER_AXIS as Scalar {
+                Err(HeatmapError::TooFine)
+            } else {
+                Ok(extent as u32 + 1)
+            }
+        })
+        .collect::<Result<_, _>>()?;
+    Ok(Some((origin, dims)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn samples_bin_into_sparse_cells() {
+        let samples = [
+            ([0.0, 0.0, 0.0], 2.0),
+            ([0.5, 0.9, 5.0], 4.0),
+            ([2.5, 0.0, 0.0], 1.0),
+        ];
+        let grid = heatmap(&samples, 1.0, None, Aggregate::Sum).unwrap();
+        assert_eq!(grid.dims, vec![3, 1, 6]);
+        assert_eq!(grid.cells.len(), 3);
+
+        // Projected onto x/y, the first two share a cell.
+        let flat = heatmap(&samples, 1.0, Some(Projection::Xy), Aggregate::Mean).unwrap();
+        assert_eq!(flat.dims, vec![3, 1]);
+        let first = &flat.cells[0];
+        assert_eq!((first.index.as_slice(), first.systems), (&[0, 0][..], 2));
+        assert_eq!(first.value, 3.0);
+        assert_eq!(flat.cells[1].index, vec![2, 0]);
+
+        assert_eq!(
+            heatmap(&samples, 0.0, None, Aggregate::Sum),
+            Err(HeatmapError::InvalidCellSize)
+        );
+        assert_eq!(
+            heatmap(&samples, 1e-3, None, Aggregate::Sum),
+            Err(HeatmapError::TooFine)
+        );
+    }
+
+    #[test]
+    fn density_spreads_each_system_over_nearby_cells() {
+        // Corner systems fix the grid at 11 x 11; the kernel around the
+        // middle one reaches two cells each way and does not overlap theirs.
+        let points = [[0.0, 0.0, 0.0], [5.2, 5.2, 3.0], [10.0, 10.0, 0.0]];
+        let grid = density(&points, 1.0, Some(Projection::Xy), 0.5).unwrap();
+        assert_eq!(grid.dims, vec![11, 11]);
+        let middle: Vec<&HeatCell> = grid
+            .cells
+            .iter()
+            .filter(|c| c.index.iter().all(|&i| (3..=7).contains(&i)))
+            .collect();
+        assert_eq!(middle.len(), 25);
+        let peak = middle
+            .iter()
+            .max_by(|a, b| a.value.total_cmp(&b.value))
+            .unwrap();
+        assert_eq!((peak.index.as_slice(), peak.systems), (&[5, 5][..], 1));
+        // One system's worth of density over unit cells.
+        let mass: Scalar = middle.iter().map(|c| c.value).sum();
+        assert!((mass - 1.0).abs() < 0.05, "{mass}");
+
+        assert_eq!(
+            density(&points, 1.0, None, 0.0),
+            Err(HeatmapError::InvalidBandwidth)
+        );
+        assert_eq!(
+            density(&points, 1.0, None, 3.0),
+            Err(HeatmapError::KernelTooWide)
+        );
+        assert!(density(&[], 1.0, None, 1.0).unwrap().cells.is_empty());
+    }
+}
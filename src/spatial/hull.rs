@@ -0,0 +1,223 @@
+use std::collections::HashSet;
+
+use serde::Serialize;
+use thiserror::Error;
+
+use crate::Scalar;
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum HullError {
+    #[error("a convex hull needs at least 4 points, got {0}")]
+    TooFewPoints(usize),
+    #[error("the points lie on a single plane and enclose no volume")]
+    Flat,
+}
+
+/// Convex hull of a point set: the smallest convex polyhedron containing
+/// every point, e.g. the territory spanned by a region's systems.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct ConvexHull {
+    /// Indices of the points on the hull's corners, ascending.
+    pub vertices: Vec<usize>,
+    /// Triangles as point indices, counter-clockwise seen from outside.
+    pub faces: Vec<[usize; 3]>,
+    pub volume: f64,
+    pub area: f64,
+}
+
+/// A hull triangle with its outward unit normal and plane offset.
+struct Face {
+    corners: [usize; 3],
+    normal: [f64; 3],
+    offset: f64,
+}
+
+/// Incremental convex hull: starting from a tetrahedron of extreme points,
+/// each point outside the hull so far replaces the faces it can see with a
+/// fan from the horizon around them to itself. Runs in f64; points within
+/// a tiny tolerance of the hull count as inside, so near-coplanar faces are
+/// not split into slivers.
+pub fn convex_hull(points: &[[Scalar; 3]]) -> Result<ConvexHull, HullError> {
+    if points.len() < 4 {
+        return Err(HullError::TooFewPoints(points.len()));
+    }
+    let p: Vec<[f64; 3]> = points.iter().map(|q| q.map(f64::from)).collect();
+    let extent = (0..3)
+        .map(|axis| {
+            let (lo, hi) = p
+                .iter()
+                .fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), q| {
+                    (lo.min(q[axis]), hi.max(q[axis]))
+                });
+            hi - lo
+        })
+        .fold(0.0, f64::max);
+    let eps = extent * 1e-9;
+
+    let seed = initial_tetrahedron(&p, eps).ok_or(HullError::Flat)?;
+    let inside = [0, 1, 2].map(|axis| seed.iter().map(|&i| p[i][axis]).sum::<f64>() / 4.0);
+    let face = |corners: [usize; 3]| -> Face {
+        let [a, b, c] = corners.map(|i| p[i]);
+        let n = normalize(cross(sub(b, a), sub(c, a)));
+        let offset = dot(n, a);
+        // Orient outwards, away from the interior point.
+        if dot(n, inside) > offset {
+            Face {
+                corners: [corners[0], corners[2], corners[1]],
+                normal: n.map(|v| -v),
+                offset: -offset,
+            }
+        } else {
+            Face {
+                corners,
+                normal: n,
+                offset,
+            }
+        }
+    };
+    let [s0, s1, s2, s3] = seed;
+    let mut faces = vec![
+        face([s0, s1, s2]),
+        face([s0, s1, s3]),
+        face([s0, s2, s3]),
+        face([s1, s2, s3]),
+    ];
+
+    for (i, &q) in p.iter().enumerate() {
+        if seed.contains(&i) {
+            continue;
+        }
+        let (visible, kept): (Vec<Face>, Vec<Face>) = faces
+            .into_iter()
+            .partition(|f| dot(f.normal, q) - f.offset > eps);
+        faces = kept;
+        if visible.is_empty() {
+            continue;
+        }
+        let edges: HashSet<(usize, usize)> = visible
+            .iter()
+            .flat_map(|f| {
+                let [a, b, c] = f.corners;
+                [(a, b), (b, c), (c, a)]
+            })
+            .collect();
+        // Horizon edges border exactly one visible face; keeping their
+        // direction keeps the new faces facing outwards.
+        let mut horizon: Vec<(usize, usize)> = edges
+            .iter()
+            .copied()
+            .filter(|&(a, b)| !edges.contains(&(b, a)))
+            .collect();
+        horizon.sort_unstable();
+        faces.extend(horizon.into_iter().map(|(a, b)| face([a, b, i])));
+    }
+
+    let mut vertices: Vec<usize> = faces.iter().flat_map(|f| f.corners).collect();
+    vertices.sort_unstable();
+    vertices.dedup();
+    let mut volume = 0.0;
+    let mut area = 0.0;
+    for f in &faces {
+        let [a, b, c] = f.corners.map(|i| p[i]);
+        let twice_area = cross(sub(b, a), sub(c, a));
+        area += length(twice_area) / 2.0;
+        volume += dot(sub(a, inside), twice_area) / 6.0;
+    }
+    Ok(ConvexHull {
+        vertices,
+        faces: faces.into_iter().map(|f| f.corners).collect(),
+        volume,
+        area,
+    })
+}
+
+/// Four points spanning a proper tetrahedron, or `None` when every point
+/// lies on one plane.
+fn initial_tetrahedron(p: &[[f64; 3]], eps: f64) -> Option<[usize; 4]> {
+    let farthest = |score: &dyn Fn([f64; 3]) -> f64| {
+        (0..p.len())
+            .map(|i| (i, score(p[i])))
+            .fold((0, f64::NEG_INFINITY), |best, (i, s)| {
+                if s > best.1 {
+                    (i, s)
+                } else {
+                    best
+                }
+            })
+    };
+    let a = farthest(&|q| -q[0]).0;
+    let (b, ab) = farthest(&|q| length(sub(q, p[a])));
+    if ab <= eps {
+        return None;
+    }
+    let dir = normalize(sub(p[b], p[a]));
+    let (c, off_line) = farthest(&|q| length(cross(dir, sub(q, p[a]))));
+    if off_line <= eps {
+        return None;
+    }
+    let n = normalize(cross(sub(p[b], p[a]), sub(p[c], p[a])));
+    let (d, off_plane) = farthest(&|q| dot(n, sub(q, p[a])).abs());
+    if off_plane <= eps {
+        return None;
+    }
+    Some([a, b, c, d])
+}
+
+fn sub(a: [f64; 3], b: [f64; 3]) -> [f64; 3] {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+fn dot(a: [f64; 3], b: [f64; 3]) -> f64 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn cross(a: [f64; 3], b: [f64; 3]) -> [f64; 3] {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+fn length(a: [f64; 3]) -> f64 {
+    dot(a, a).sqrt()
+}
+
+fn normalize(a: [f64; 3]) -> [f64; 3] {
+    let len = length(a);
+    a.map(|v| v / len)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cube_hull_keeps_the_corners_and_measures_the_cube() {
+        // Corners of a 2-wide cube, plus interior and face points.
+        let mut points: Vec<[Scalar; 3]> = (0..8)
+            .map(|i| [0, 1, 2].map(|axis| if i & (1 << axis) != 0 { 2.0 } else { 0.0 }))
+            .collect();
+        points.extend([[1.0; 3], [0.5, 1.5, 1.0], [1.0, 1.0, 2.0], [2.0, 0.5, 0.5]]);
+        let hull = convex_hull(&points).unwrap();
+        assert_eq!(hull.vertices, (0..8).collect::<Vec<_>>());
+        assert_eq!(hull.faces.len(), 12);
+        assert!((hull.volume - 8.0).abs() < 1e-9);
+        assert!((hull.area - 24.0).abs() < 1e-9);
+        // Every face points away from the centre.
+        for [a, b, c] in hull.faces {
+            let [a, b, c] = [a, b, c].map(|i| points[i].map(f64::from));
+            let outward = dot(cross(sub(b, a), sub(c, a)), sub(a, [1.0; 3]));
+            assert!(outward > 0.0);
+        }
+
+        assert_eq!(convex_hull(&points[..3]), Err(HullError::TooFewPoints(3)));
+        let flat = [
+            [0.0, 0.0, 1.0],
+            [1.0, 0.0, 1.0],
+            [0.0, 1.0, 1.0],
+            [1.0, 1.0, 1.0],
+        ];
+        assert_eq!(convex_hull(&flat), Err(HullError::Flat));
+    }
+}
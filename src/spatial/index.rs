@@ -0,0 +1,183 @@
+use serde::{Deserialize, Serialize};
+
+use crate::spatial::ball_tree::BallTree;
+use crate::spatial::grid_hash::GridHash;
+use crate::spatial::kd_tree::KDTree;
+use crate::spatial::morton::MortonIndex;
+use crate::spatial::octree::Octree;
+use crate::spatial::rtree::RTree;
+use crate::Scalar;
+
+/// Point queries shared by the spatial index structures, so analyses can run
+/// over whichever suits the data. Results are `(index, distance)` pairs,
+/// closest first, with indices into the points the index was built from.
+/// Zero, negative and non-finite inputs behave as described in `validate`.
+pub trait SpatialIndex {
+    /// Number of indexed points.
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn nearest_one(&self, target: [Scalar; 3]) -> Option<(usize, Scalar)>;
+
+    /// The `n` points closest to `target`, however far away.
+    fn nearest_n(&self, target: [Scalar; 3], n: usize) -> Vec<(usize, Scalar)>;
+
+    /// Up to `n` of the points closest to `target` within `radius`.
+    fn nearest_n_within_radius(
+        &self,
+        target: [Scalar; 3],
+        radius: Scalar,
+        n: usize,
+    ) -> Vec<(usize, Scalar)>;
+}
+
+impl SpatialIndex for KDTree {
+    fn len(&self) -> usize {
+        KDTree::len(self)
+    }
+
+    fn nearest_one(&self, target: [Scalar; 3]) -> Option<(usize, Scalar)> {
+        KDTree::nearest_one(self, target)
+    }
+
+    fn nearest_n(&self, target: [Scalar; 3], n: usize) -> Vec<(usize, Scalar)> {
+        KDTree::nearest_n(self, target, n)
+    }
+
+    fn nearest_n_within_radius(
+        &self,
+        target: [Scalar; 3],
+        radius: Scalar,
+        n: usize,
+    ) -> Vec<(usize, Scalar)> {
+        KDTree::nearest_n_within_radius(self, target, radius, n)
+    }
+}
+
+/// The available `SpatialIndex` structures.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SpatialIndexKind {
+    /// Axis-aligned median splits; the general-purpose choice.
+    #[default]
+    KdTree,
+    /// Nested bounding spheres, which keep pruning well where points are
+    /// tightly clustered with large empty space between clusters.
+    BallTree,
+    /// Packed bounding boxes; strongest on box and region queries.
+    RTree,
+    /// Recursive octants with the default `OctreeConfig`; adapts its depth
+    /// to local density.
+    Octree,
+    /// Hashed uniform grid with an automatic cell size; fastest for radius
+    /// queries over evenly spread points.
+    GridHash,
+    /// Points sorted by Z-order code; flat, so it loads without a rebuild.
+    Morton,
+}
+
+impl SpatialIndexKind {
+    pub fn build(self, points: &[[Scalar; 3]]) -> Box<dyn SpatialIndex + Send + Sync> {
+        match self {
+            SpatialIndexKind::KdTree => Box::new(KDTree::build(points)),
+            SpatialIndexKind::BallTree => Box::new(BallTree::build(points)),
+            SpatialIndexKind::RTree => Box::new(RTree::build(points)),
+            SpatialIndexKind::Octree => Box::new(Octree::build(points)),
+            SpatialIndexKind::GridHash => Box::new(GridHash::build(points)),
+            SpatialIndexKind::Morton => Box::new(MortonIndex::build(points)),
+        }
+    }
+}
+
+/// Deterministic points scattered over `[0, extent)` on each axis.
+#[cfg(test)]
+pub(crate) fn scattered_points(count: usize, extent: Scalar) -> Vec<[Scalar; 3]> {
+    (0..count)
+        .map(|i| {
+            let i = i as Scalar;
+            [(i * 7.3) % extent, (i * 13.7) % extent, (i * 3.1) % extent]
+        })
+        .collect()
+}
+
+/// Asserts that `index`, built over `points`, answers the shared queries
+/// around each of `targets` with the same distances as a `KDTree`; ties may
+/// come in a different order.
+#[cfg(test)]
+pub(crate) fn assert_agrees_with_kd(
+    index: &dyn SpatialIndex,
+    points: &[[Scalar; 3]],
+    targets: &[[Scalar; 3]],
+    radius: Scalar,
+) {
+    use crate::spatial::kd_tree::dist2;
+
+    let kd = KDTree::build(points);
+    let distances = |found: Vec<(usize, Scalar)>| -> Vec<Scalar> {
+        found.into_iter().map(|(_, d)| d).collect()
+    };
+    assert_eq!(index.len(), points.len());
+    for &target in targets {
+        assert_eq!(
+            distances(index.nearest_n(target, 10)),
+            distances(kd.nearest_n(target, 10)),
+            "nearest_n around {target:?}"
+        );
+        assert_eq!(
+            distances(index.nearest_n_within_radius(target, radius, usize::MAX)),
+            distances(kd.nearest_n_within_radius(target, radius, usize::MAX)),
+            "within {radius} of {target:?}"
+        );
+        let (i, d) = index.nearest_one(target).expect("points to search");
+        assert_eq!(d, dist2(points[i], target).sqrt());
+        assert_eq!(Some(d), kd.nearest_one(target).map(|(_, d)| d));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::spatial::mst::euclidean_mst;
+
+    const KINDS: [SpatialIndexKind; 6] = [
+        SpatialIndexKind::KdTree,
+        SpatialIndexKind::BallTree,
+        SpatialIndexKind::RTree,
+        SpatialIndexKind::Octree,
+        SpatialIndexKind::GridHash,
+        SpatialIndexKind::Morton,
+    ];
+
+    #[test]
+    fn every_kind_handles_empty_and_single_point_sets() {
+        for kind in KINDS {
+            let empty = kind.build(&[]);
+            assert!(
+                empty.is_empty() && empty.nearest_one([0.0; 3]).is_none(),
+                "{kind:?}"
+            );
+            let single = kind.build(&[[1.0, 2.0, 3.0]]);
+            assert_eq!(
+                single.nearest_one([0.0; 3]).map(|(i, _)| i),
+                Some(0),
+                "{kind:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn analyses_run_over_any_index_kind() {
+        let points = scattered_points(150, 40.0);
+        let over_kd = euclidean_mst(&points, &KDTree::build(&points), 4);
+        for kind in KINDS {
+            assert_eq!(
+                euclidean_mst(&points, &*kind.build(&points), 4),
+                over_kd,
+                "{kind:?}"
+            );
+        }
+    }
+}
@@ -1,109 +1,706 @@
+use std::collections::BinaryHeap;
+
 use serde::{Deserialize, Serialize};
 
-/// Node in a 3D k-d tree.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+use crate::spatial::metric::{Euclidean, Metric};
+use crate::spatial::shapes::{Cone, Slab};
+use crate::stats;
+use crate::validate::is_searchable;
+use crate::{Scalar, ScalarBits};
+
+/// Subtrees with fewer points than this are partitioned on the current
+/// thread by the `parallel` build.
+#[cfg(feature = "parallel")]
+const PARALLEL_BUILD_THRESHOLD: usize = 8_192;
+
+/// Index marking a removed node, which stays in place to keep its split.
+const TOMBSTONE: u32 = u32::MAX;
+
+/// Inserted and removed points both add work to every search, so the tree
+/// is rebuilt once together they exceed an eighth of it, or this many.
+const MIN_REBUILD_THRESHOLD: usize = 64;
+
+/// Version of `PortableKDTree` written by this build.
+pub const KD_FORMAT_VERSION: u32 = 1;
+
+/// Node of a 3D k-d tree; its children are implicit in its position.
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct KDNode {
-    pub point: [f32; 3],
-    pub index: usize,
-    pub axis: usize,
-    pub left: Option<Box<KDNode>>,
-    pub right: Option<Box<KDNode>>,
+    pub point: [Scalar; 3],
+    /// Index of the point in the slice the tree was built from.
+    pub index: u32,
 }
 
 /// Simple 3D k-d tree supporting N-nearest-within-radius queries.
+///
+/// Nodes live in one flat array in implicit layout: the subtree over
+/// `nodes[start..end]` has its root in the middle, at
+/// `start + (end - start) / 2`, splits on axis `depth % 3`, and keeps its
+/// left subtree before the root and its right subtree after. With no child
+/// links nodes are small and contiguous, and the tree serializes as a plain
+/// list. Serialized through `PortableKDTree`, never in this layout.
+///
+/// Points can be inserted and removed after the build without rebuilding
+/// each time: inserted points wait in a list searched linearly, and removed
+/// ones are tombstoned in place. The tree rebuilds itself once these add up.
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(into = "PortableKDTree", try_from = "PortableKDTree")]
 pub struct KDTree {
-    pub root: Option<Box<KDNode>>,
+    nodes: Vec<KDNode>,
+    /// Points inserted since the last build.
+    inserted: Vec<KDNode>,
+    /// Tombstoned nodes.
+    removed: usize,
 }
 
 impl KDTree {
-    pub fn build(points: &[[f32; 3]]) -> Self {
+    /// Partitions each range of points around its median on the range's
+    /// axis (a linear-time selection, not a sort). With the `parallel`
+    /// feature, large subtrees are partitioned on rayon's thread pool; the
+    /// tree is the same either way.
+    pub fn build(points: &[[Scalar; 3]]) -> Self {
         let mut indices: Vec<usize> = (0..points.len()).collect();
-        let root = Self::build_recursive(points, &mut indices, 0);
-        KDTree { root }
+        #[cfg(feature = "parallel")]
+        partition_parallel(points, &mut indices, 0);
+        #[cfg(not(feature = "parallel"))]
+        partition(points, &mut indices, 0);
+        let nodes = indices
+            .into_iter()
+            .map(|index| KDNode {
+                point: points[index],
+                index: u32::try_from(index).expect("fewer than 2^32 points"),
+            })
+            .collect();
+        KDTree::from_nodes(nodes)
     }
 
-    fn build_recursive(
-        points: &[[f32; 3]],
-        idx: &mut [usize],
-        depth: usize,
-    ) -> Option<Box<KDNode>> {
-        use core::cmp::Ordering;
+    fn from_nodes(nodes: Vec<KDNode>) -> Self {
+        KDTree {
+            nodes,
+            inserted: Vec::new(),
+            removed: 0,
+        }
+    }
 
-        if idx.is_empty() {
-            return None;
+    /// Number of points in the tree.
+    pub fn len(&self) -> usize {
+        self.nodes.len() - self.removed + self.inserted.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Adds `point` under `index`, which should not already be in the tree.
+    pub fn insert(&mut self, index: usize, point: [Scalar; 3]) {
+        let index = u32::try_from(index)
+            .ok()
+            .filter(|&i| i != TOMBSTONE)
+            .expect("fewer than 2^32 - 1 points");
+        self.inserted.push(KDNode { point, index });
+        self.rebuild_if_degraded();
+    }
+
+    /// Removes the point stored under `index` at `point`; `false` if there
+    /// is none.
+    pub fn remove(&mut self, index: usize, point: [Scalar; 3]) -> bool {
+        let is_it = |node: &KDNode| node.index as usize == index && node.point == point;
+        if let Some(i) = self.inserted.iter().position(is_it) {
+            self.inserted.swap_remove(i);
+            return true;
         }
+        // Equal coordinates may sit on either side of a split, so both
+        // sides are searched when `point` lies on the plane.
+        let mut stack = vec![(0, self.nodes.len(), 0)];
+        while let Some((start, end, depth)) = stack.pop() {
+            if start == end {
+                continue;
+            }
+            let mid = start + (end - start) / 2;
+            if is_it(&self.nodes[mid]) {
+                self.nodes[mid].index = TOMBSTONE;
+                self.removed += 1;
+                self.rebuild_if_degraded();
+                return true;
+            }
+            let axis = depth % 3;
+            let split = self.nodes[mid].point[axis];
+            if point[axis] <= split {
+                stack.push((start, mid, depth + 1));
+            }
+            if point[axis] >= split {
+                stack.push((mid + 1, end, depth + 1));
+            }
+        }
+        false
+    }
 
-        let axis = depth % 3;
-        idx.sort_by(|&a, &b| {
-            points[a][axis]
-                .partial_cmp(&points[b][axis])
-                .unwrap_or(Ordering::Equal)
-        });
-        let mid = idx.len() / 2;
-        let median = idx[mid];
+    fn rebuild_if_degraded(&mut self) {
+        let changes = self.inserted.len() + self.removed;
+        if changes > (self.nodes.len() / 8).max(MIN_REBUILD_THRESHOLD) {
+            self.rebuild();
+        }
+    }
+
+    /// Builds a balanced tree over the current points, keeping their
+    /// indices.
+    fn rebuild(&mut self) {
+        let live: Vec<KDNode> = self
+            .nodes
+            .iter()
+            .filter(|node| node.index != TOMBSTONE)
+            .chain(&self.inserted)
+            .copied()
+            .collect();
+        let points: Vec<[Scalar; 3]> = live.iter().map(|node| node.point).collect();
+        let mut tree = KDTree::build(&points);
+        for node in &mut tree.nodes {
+            node.index = live[node.index as usize].index;
+        }
+        *self = tree;
+    }
 
-        Some(Box::new(KDNode {
-            point: points[median],
-            index: median,
-            axis,
-            left: Self::build_recursive(points, &mut idx[..mid], depth + 1),
-            right: Self::build_recursive(points, &mut idx[mid + 1..], depth + 1),
-        }))
+    /// Depth-first walk with an explicit stack. Each node's child on the
+    /// target's side of the split is visited first. `visit` handles a node
+    /// and returns the squared distance beyond which nothing is wanted any
+    /// more; a subtree is skipped when its split plane lies further from
+    /// `target` than that.
+    fn walk<F>(&self, target: [Scalar; 3], visit: F)
+    where
+        F: FnMut(&KDNode) -> Scalar,
+    {
+        self.walk_with(target, &Euclidean, visit);
     }
 
-    /// Returns up to `n` nearest neighbours within the given radius of the target point.
+    /// `walk` under any metric: `visit` returns a `raw` limit and split
+    /// planes are compared through `Metric::axis_raw`.
+    fn walk_with<M, F>(&self, target: [Scalar; 3], metric: &M, mut visit: F)
+    where
+        M: Metric,
+        F: FnMut(&KDNode) -> Scalar,
+    {
+        let mut limit2 = Scalar::INFINITY;
+        for node in &self.inserted {
+            stats::count_kd_visit();
+            limit2 = visit(node);
+        }
+        // (subtree range, depth, squared distance from `target` to the
+        // subtree's side of its parent's split plane)
+        let mut stack = vec![(0, self.nodes.len(), 0, 0.0)];
+        while let Some((start, end, depth, gap2)) = stack.pop() {
+            if start == end || gap2 > limit2 {
+                continue;
+            }
+            let mid = start + (end - start) / 2;
+            let node = &self.nodes[mid];
+            stats::count_kd_visit();
+            if node.index != TOMBSTONE {
+                limit2 = visit(node);
+            }
+
+            let axis = depth % 3;
+            let delta = target[axis] - node.point[axis];
+            let (near, far) = if delta < 0.0 {
+                ((start, mid), (mid + 1, end))
+            } else {
+                ((mid + 1, end), (start, mid))
+            };
+            stack.push((far.0, far.1, depth + 1, metric.axis_raw(axis, delta)));
+            stack.push((near.0, near.1, depth + 1, gap2));
+        }
+    }
+
+    /// Returns up to `n` nearest neighbours within the given radius of the
+    /// target point. See `validate` for zero, negative and non-finite inputs.
     pub fn nearest_n_within_radius(
         &self,
-        target: [f32; 3],
-        radius: f32,
+        target: [Scalar; 3],
+        radius: Scalar,
         n: usize,
-    ) -> Vec<(usize, f32)> {
-        let mut results = Vec::new();
+    ) -> Vec<(usize, Scalar)> {
+        self.nearest_n_within_radius_filtered(target, radius, n, |_| true)
+    }
+
+    /// Like `nearest_n_within_radius`, but only points whose index passes
+    /// `keep` are returned, e.g. to exclude already visited systems.
+    pub fn nearest_n_within_radius_filtered<F>(
+        &self,
+        target: [Scalar; 3],
+        radius: Scalar,
+        n: usize,
+        keep: F,
+    ) -> Vec<(usize, Scalar)>
+    where
+        F: Fn(usize) -> bool,
+    {
+        self.search(target, radius, n, &Euclidean, keep)
+    }
+
+    /// `nearest_n_within_radius` under another metric, with `radius` and
+    /// the returned distances in its units.
+    pub fn nearest_n_within_radius_by<M: Metric>(
+        &self,
+        target: [Scalar; 3],
+        radius: Scalar,
+        n: usize,
+        metric: &M,
+    ) -> Vec<(usize, Scalar)> {
+        self.search(target, radius, n, metric, |_| true)
+    }
+
+    fn search<M, F>(
+        &self,
+        target: [Scalar; 3],
+        radius: Scalar,
+        n: usize,
+        metric: &M,
+        keep: F,
+    ) -> Vec<(usize, Scalar)>
+    where
+        M: Metric,
+        F: Fn(usize) -> bool,
+    {
+        if n == 0 || !is_searchable(target, radius) {
+            return Vec::new();
+        }
+        // Only the `n` best are kept, and once there are `n` the search
+        // radius shrinks to the worst of them.
+        let limit = metric.raw_of(radius);
+        let mut best = Candidates::new(n);
+        self.walk_with(target, metric, |node| {
+            let raw = metric.raw(node.point, target);
+            if raw <= limit && keep(node.index as usize) {
+                best.offer(node.index as usize, raw);
+            }
+            best.worst().min(limit)
+        });
+        best.into_sorted_by(|raw| metric.distance(raw))
+    }
+
+    /// Points within `radius` of `target` as `(index, distance)`, found one
+    /// at a time as the iterator is advanced, in no particular order. Stops
+    /// searching when the caller stops, e.g. after the first match of a
+    /// predicate with `find`.
+    pub fn iter_within_radius(&self, target: [Scalar; 3], radius: Scalar) -> WithinRadius<'_> {
+        let searchable = is_searchable(target, radius);
+        WithinRadius {
+            tree: self,
+            target,
+            radius2: radius * radius,
+            inserted: if searchable { 0 } else { self.inserted.len() },
+            stack: if searchable {
+                vec![(0, self.nodes.len(), 0, 0.0)]
+            } else {
+                Vec::new()
+            },
+        }
+    }
+
+    /// Number of points within `radius` of `target`, found without
+    /// collecting them: density checks over every system allocate nothing.
+    pub fn count_within_radius(&self, target: [Scalar; 3], radius: Scalar) -> usize {
+        if !is_searchable(target, radius) {
+            return 0;
+        }
         let radius2 = radius * radius;
-        self.search_recursive(&self.root, target, radius2, &mut results);
-        // sort ascending by distance
-        results.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap());
-        results.truncate(n);
-        results
+        let mut count = 0;
+        self.walk(target, |node| {
+            if dist2(node.point, target) <= radius2 {
+                count += 1;
+            }
+            radius2
+        });
+        count
+    }
+
+    /// Returns the closest point to `target` and its distance, if any: the
+    /// "which system am I closest to" fast path, with no allocation or
+    /// sorting. Non-finite targets have no nearest point.
+    pub fn nearest_one(&self, target: [Scalar; 3]) -> Option<(usize, Scalar)> {
+        if !is_searchable(target, 0.0) {
+            return None;
+        }
+        let mut best: Option<(usize, Scalar)> = None;
+        self.walk(target, |node| {
+            let dist2 = dist2(node.point, target);
+            match best {
+                Some((_, b)) if b <= dist2 => b,
+                _ => {
+                    best = Some((node.index as usize, dist2));
+                    dist2
+                }
+            }
+        });
+        best.map(|(index, dist2)| (index, dist2.sqrt()))
     }
 
-    #[allow(clippy::only_used_in_recursion)]
-    fn search_recursive(
+    /// Returns the `n` points closest to `target` however far away they are,
+    /// sorted ascending by distance. Subtrees are skipped once they cannot
+    /// beat the `n`-th best candidate so far, so no radius guess is needed.
+    pub fn nearest_n(&self, target: [Scalar; 3], n: usize) -> Vec<(usize, Scalar)> {
+        self.nearest_n_by(target, n, &Euclidean)
+    }
+
+    /// `nearest_n` under another metric, e.g. `SquaredEuclidean` to rank
+    /// without square roots.
+    pub fn nearest_n_by<M: Metric>(
         &self,
-        node: &Option<Box<KDNode>>,
-        target: [f32; 3],
-        radius2: f32,
-        results: &mut Vec<(usize, f32)>,
-    ) {
-        if let Some(noderef) = node {
-            let dx = noderef.point[0] - target[0];
-            let dy = noderef.point[1] - target[1];
-            let dz = noderef.point[2] - target[2];
-            let dist2 = dx * dx + dy * dy + dz * dz;
-            if dist2 <= radius2 {
-                results.push((noderef.index, dist2.sqrt()));
-            }
-
-            let axis = noderef.axis;
-            let delta = target[axis] - noderef.point[axis];
-            let (first, second) = if delta < 0.0 {
-                (&noderef.left, &noderef.right)
+        target: [Scalar; 3],
+        n: usize,
+        metric: &M,
+    ) -> Vec<(usize, Scalar)> {
+        self.search(target, Scalar::INFINITY, n, metric, |_| true)
+    }
+
+    /// Returns every point inside the cone, sorted ascending by distance from the apex.
+    pub fn within_cone(&self, cone: &Cone) -> Vec<(usize, Scalar)> {
+        let mut results = Vec::new();
+        // The cone fits inside the sphere of radius `max_range` around the
+        // apex, so the usual radius pruning applies.
+        let range2 = cone.max_range * cone.max_range;
+        self.walk(cone.apex, |node| {
+            if cone.contains(node.point) {
+                results.push((node.index as usize, dist2(node.point, cone.apex).sqrt()));
+            }
+            range2
+        });
+        results.sort_by(|a, b| a.1.total_cmp(&b.1));
+        results
+    }
+
+    /// Returns the `n` points of the cone closest to its apex, sorted
+    /// ascending by distance: what lies ahead within `max_range`. The search
+    /// narrows to the `n`-th best like `nearest_n_within_radius`.
+    pub fn nearest_n_in_cone(&self, cone: &Cone, n: usize) -> Vec<(usize, Scalar)> {
+        if n == 0 || !is_searchable(cone.apex, cone.max_range) {
+            return Vec::new();
+        }
+        let range2 = cone.max_range * cone.max_range;
+        let mut best = Candidates::new(n);
+        self.walk(cone.apex, |node| {
+            if cone.contains(node.point) {
+                best.offer(node.index as usize, dist2(node.point, cone.apex));
+            }
+            best.worst().min(range2)
+        });
+        best.into_sorted()
+    }
+
+    /// Returns the indices of every point inside the slab, in ascending index order.
+    pub fn within_slab(&self, slab: &Slab) -> Vec<usize> {
+        let mut results: Vec<usize> = self
+            .inserted
+            .iter()
+            .filter(|node| slab.contains(node.point))
+            .map(|node| node.index as usize)
+            .collect();
+        // (subtree range, depth, bounding box of the subtree)
+        let mut stack = vec![(
+            0,
+            self.nodes.len(),
+            0,
+            [Scalar::NEG_INFINITY; 3],
+            [Scalar::INFINITY; 3],
+        )];
+        while let Some((start, end, depth, min, max)) = stack.pop() {
+            if start == end {
+                continue;
+            }
+            stats::count_kd_visit();
+            if !slab.intersects_box(min, max) {
+                continue;
+            }
+            let mid = start + (end - start) / 2;
+            let node = &self.nodes[mid];
+            if node.index != TOMBSTONE && slab.contains(node.point) {
+                results.push(node.index as usize);
+            }
+
+            // Children hold points on either side of the split plane, so each
+            // inherits the parent's bounds clipped at the split coordinate.
+            let axis = depth % 3;
+            let mut right_min = min;
+            right_min[axis] = node.point[axis];
+            stack.push((mid + 1, end, depth + 1, right_min, max));
+            let mut left_max = max;
+            left_max[axis] = node.point[axis];
+            stack.push((start, mid, depth + 1, min, left_max));
+        }
+        results.sort_unstable();
+        results
+    }
+}
+
+/// Arranges `indices` into the implicit layout of a subtree rooted at
+/// `depth`, working through an explicit list of ranges rather than
+/// recursing.
+fn partition(points: &[[Scalar; 3]], indices: &mut [usize], depth: usize) {
+    let mut pending = vec![(0, indices.len(), depth)];
+    while let Some((start, end, depth)) = pending.pop() {
+        if end - start < 2 {
+            continue;
+        }
+        let mid = start + (end - start) / 2;
+        select_median(points, &mut indices[start..end], mid - start, depth % 3);
+        pending.push((mid + 1, end, depth + 1));
+        pending.push((start, mid, depth + 1));
+    }
+}
+
+/// `partition`, handing both halves of large subtrees to rayon. Recursion
+/// depth is logarithmic, since each level halves the points.
+#[cfg(feature = "parallel")]
+fn partition_parallel(points: &[[Scalar; 3]], indices: &mut [usize], depth: usize) {
+    if indices.len() < PARALLEL_BUILD_THRESHOLD {
+        return partition(points, indices, depth);
+    }
+    let mid = indices.len() / 2;
+    select_median(points, indices, mid, depth % 3);
+    let (left, rest) = indices.split_at_mut(mid);
+    rayon::join(
+        || partition_parallel(points, left, depth + 1),
+        || partition_parallel(points, &mut rest[1..], depth + 1),
+    );
+}
+
+/// Moves the point with rank `mid` on `axis` to `indices[mid]`, smaller
+/// ones before it and larger ones after.
+fn select_median(points: &[[Scalar; 3]], indices: &mut [usize], mid: usize, axis: usize) {
+    indices.select_nth_unstable_by(mid, |&a, &b| points[a][axis].total_cmp(&points[b][axis]));
+}
+
+/// Lazy radius search of `KDTree::iter_within_radius`: the walk of `walk`,
+/// paused after each match.
+#[derive(Clone, Debug)]
+pub struct WithinRadius<'a> {
+    tree: &'a KDTree,
+    target: [Scalar; 3],
+    radius2: Scalar,
+    /// Next entry of `tree.inserted` to check.
+    inserted: usize,
+    /// Pending subtrees, as in `walk`.
+    stack: Vec<(usize, usize, usize, Scalar)>,
+}
+
+impl Iterator for WithinRadius<'_> {
+    type Item = (usize, Scalar);
+
+    fn next(&mut self) -> Option<(usize, Scalar)> {
+        let within = |node: &KDNode| {
+            let d2 = dist2(node.point, self.target);
+            (d2 <= self.radius2).then(|| (node.index as usize, d2.sqrt()))
+        };
+        while let Some(node) = self.tree.inserted.get(self.inserted) {
+            self.inserted += 1;
+            stats::count_kd_visit();
+            if let Some(found) = within(node) {
+                return Some(found);
+            }
+        }
+        while let Some((start, end, depth, gap2)) = self.stack.pop() {
+            if start == end || gap2 > self.radius2 {
+                continue;
+            }
+            let mid = start + (end - start) / 2;
+            let node = &self.tree.nodes[mid];
+            stats::count_kd_visit();
+            let axis = depth % 3;
+            let delta = self.target[axis] - node.point[axis];
+            let (near, far) = if delta < 0.0 {
+                ((start, mid), (mid + 1, end))
             } else {
-                (&noderef.right, &noderef.left)
+                ((mid + 1, end), (start, mid))
             };
+            self.stack.push((far.0, far.1, depth + 1, delta * delta));
+            self.stack.push((near.0, near.1, depth + 1, gap2));
+            if node.index != TOMBSTONE {
+                if let Some(found) = within(node) {
+                    return Some(found);
+                }
+            }
+        }
+        None
+    }
+}
+
+pub(crate) fn dist2(a: [Scalar; 3], b: [Scalar; 3]) -> Scalar {
+    let dx = a[0] - b[0];
+    let dy = a[1] - b[1];
+    let dz = a[2] - b[2];
+    dx * dx + dy * dy + dz * dz
+}
+
+/// On-disk form of a `KDTree`, independent of the in-memory layout: the
+/// nodes' points, source indices and split axes as parallel arrays, in the
+/// order of the writing build's layout. A reader whose layout orders or
+/// splits nodes differently rebuilds the tree from the points instead, so
+/// persisted trees keep loading across layout changes.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PortableKDTree {
+    pub version: u32,
+    pub points: Vec<[Scalar; 3]>,
+    pub indices: Vec<u32>,
+    pub axes: Vec<u8>,
+}
+
+impl From<KDTree> for PortableKDTree {
+    fn from(mut tree: KDTree) -> Self {
+        if tree.removed > 0 || !tree.inserted.is_empty() {
+            tree.rebuild();
+        }
+        let axes = tree.axes();
+        PortableKDTree {
+            version: KD_FORMAT_VERSION,
+            points: tree.nodes.iter().map(|node| node.point).collect(),
+            indices: tree.nodes.iter().map(|node| node.index).collect(),
+            axes,
+        }
+    }
+}
+
+impl TryFrom<PortableKDTree> for KDTree {
+    type Error = String;
+
+    fn try_from(portable: PortableKDTree) -> Result<Self, Self::Error> {
+        let PortableKDTree {
+            version,
+            points,
+            indices,
+            axes,
+        } = portable;
+        if version > KD_FORMAT_VERSION {
+            return Err(format!(
+                "k-d tree format {version} is newer than {KD_FORMAT_VERSION}"
+            ));
+        }
+        if indices.len() != points.len() || axes.len() != points.len() {
+            return Err(format!(
+                "k-d tree arrays differ in length: {} points, {} indices, {} axes",
+                points.len(),
+                indices.len(),
+                axes.len()
+            ));
+        }
+        let nodes = points
+            .iter()
+            .zip(&indices)
+            .map(|(&point, &index)| KDNode { point, index })
+            .collect();
+        let tree = KDTree::from_nodes(nodes);
+        if tree.axes() == axes && tree.is_partitioned() {
+            return Ok(tree);
+        }
+        // Another layout: rebuild, keeping the stored source indices.
+        let mut rebuilt = KDTree::build(&points);
+        for node in &mut rebuilt.nodes {
+            node.index = indices[node.index as usize];
+        }
+        Ok(rebuilt)
+    }
+}
 
-            self.search_recursive(first, target, radius2, results);
-            if delta * delta <= radius2 {
-                self.search_recursive(second, target, radius2, results);
+impl KDTree {
+    /// Split axis of every node, in node order.
+    fn axes(&self) -> Vec<u8> {
+        let mut axes = vec![0; self.nodes.len()];
+        let mut pending = vec![(0, self.nodes.len(), 0)];
+        while let Some((start, end, depth)) = pending.pop() {
+            if start == end {
+                continue;
+            }
+            let mid = start + (end - start) / 2;
+            axes[mid] = (depth % 3) as u8;
+            pending.push((start, mid, depth + 1));
+            pending.push((mid + 1, end, depth + 1));
+        }
+        axes
+    }
+
+    /// Whether every node splits its subtree correctly, so searches over
+    /// the nodes as laid out find every point.
+    fn is_partitioned(&self) -> bool {
+        let mut pending = vec![(0, self.nodes.len(), 0)];
+        while let Some((start, end, depth)) = pending.pop() {
+            if start == end {
+                continue;
+            }
+            let mid = start + (end - start) / 2;
+            let axis = depth % 3;
+            let split = self.nodes[mid].point[axis];
+            let left = self.nodes[start..mid].iter();
+            let right = self.nodes[mid + 1..end].iter();
+            if left.map(|n| n.point[axis]).any(|v| v > split)
+                || right.map(|n| n.point[axis]).any(|v| v < split)
+            {
+                return false;
+            }
+            pending.push((start, mid, depth + 1));
+            pending.push((mid + 1, end, depth + 1));
+        }
+        true
+    }
+}
+
+/// The `capacity` closest points seen so far, as a max-heap on squared
+/// distance so the worst one is evicted first.
+pub(crate) struct Candidates {
+    capacity: usize,
+    /// `(squared distance bits, index)`; bits of non-negative floats order
+    /// like the floats.
+    heap: BinaryHeap<(ScalarBits, usize)>,
+}
+
+impl Candidates {
+    pub(crate) fn new(capacity: usize) -> Self {
+        Candidates {
+            capacity,
+            heap: BinaryHeap::with_capacity(capacity.min(1024) + 1),
+        }
+    }
+
+    /// Squared distance a point must beat to be kept.
+    pub(crate) fn worst(&self) -> Scalar {
+        match self.heap.peek() {
+            Some(&(bits, _)) if self.heap.len() >= self.capacity => Scalar::from_bits(bits),
+            _ => Scalar::INFINITY,
+        }
+    }
+
+    pub(crate) fn offer(&mut self, index: usize, dist2: Scalar) {
+        if dist2 < self.worst() {
+            self.heap.push((dist2.to_bits(), index));
+            if self.heap.len() > self.capacity {
+                self.heap.pop();
             }
         }
     }
+
+    /// `(index, distance)` pairs, closest first.
+    pub(crate) fn into_sorted(self) -> Vec<(usize, Scalar)> {
+        self.into_sorted_by(Scalar::sqrt)
+    }
+
+    /// Like `into_sorted`, with `distance` turning each kept value into the
+    /// reported distance.
+    pub(crate) fn into_sorted_by(
+        self,
+        distance: impl Fn(Scalar) -> Scalar,
+    ) -> Vec<(usize, Scalar)> {
+        self.heap
+            .into_sorted_vec()
+            .into_iter()
+            .map(|(bits, index)| (index, distance(Scalar::from_bits(bits))))
+            .collect()
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::KDTree;
+    use super::{dist2, KDNode, KDTree, PortableKDTree, KD_FORMAT_VERSION};
+    use crate::spatial::metric::{Euclidean, MetricError, SquaredEuclidean, Weighted};
+    use crate::spatial::shapes::{Cone, Slab};
+    use crate::stats::StatsRecorder;
+    use crate::Scalar;
 
     #[test]
     fn nearest_n_within_radius_basic() {
@@ -119,4 +716,274 @@ mod tests {
         assert_eq!(res[0].0, 0);
         assert_eq!(res[1].0, 1);
     }
+
+    #[test]
+    fn wide_radius_searches_prune_against_the_worst_kept_point() {
+        let pts: Vec<[Scalar; 3]> = (0..20_000)
+            .map(|i| {
+                let i = i as Scalar;
+                [(i * 7.3) % 200.0, (i * 13.7) % 200.0, (i * 3.1) % 200.0]
+            })
+            .collect();
+        let kd = KDTree::build(&pts);
+        let recorder = StatsRecorder::start();
+        let found = kd.nearest_n_within_radius([100.0; 3], 1_000.0, 5);
+        assert!(recorder.finish().kd_nodes_visited < 1_000);
+        let dists = |r: &[(usize, Scalar)]| r.iter().map(|&(_, d)| d).collect::<Vec<_>>();
+        assert_eq!(dists(&found), dists(&kd.nearest_n([100.0; 3], 5)));
+    }
+
+    #[test]
+    fn nearest_n_needs_no_radius() {
+        let pts: Vec<[Scalar; 3]> = (0..300)
+            .map(|i| {
+                let i = i as Scalar;
+                [(i * 7.3) % 90.0, (i * 13.7) % 90.0, (i * 3.1) % 90.0]
+            })
+            .collect();
+        let kd = KDTree::build(&pts);
+        for target in [[0.0; 3], [45.0, 45.0, 45.0], [500.0, -20.0, 3.0]] {
+            let scan = kd.nearest_n_within_radius(target, Scalar::INFINITY, 7);
+            let found = kd.nearest_n(target, 7);
+            let dists = |r: &[(usize, Scalar)]| r.iter().map(|&(_, d)| d).collect::<Vec<_>>();
+            assert_eq!(dists(&found), dists(&scan));
+            assert_eq!(kd.nearest_one(target).map(|(_, d)| d), Some(found[0].1));
+        }
+        assert_eq!(kd.nearest_one([0.0, Scalar::INFINITY, 0.0]), None);
+        assert_eq!(kd.nearest_n([0.0; 3], 1000).len(), pts.len());
+        assert!(kd.nearest_n([Scalar::NAN, 0.0, 0.0], 3).is_empty());
+    }
+
+    #[test]
+    fn flat_layout_handles_large_collinear_sets() {
+        // 200k points on a line: the implicit layout stays balanced however
+        // the points lie, and each node takes 16 bytes (32 with f64).
+        let node_len = if cfg!(feature = "f64") { 32 } else { 16 };
+        assert_eq!(std::mem::size_of::<KDNode>(), node_len);
+        let n = 200_000;
+        let pts: Vec<[Scalar; 3]> = (0..n).map(|i| [i as Scalar, 0.0, 0.0]).collect();
+        let kd = KDTree::build(&pts);
+        let target = [n as Scalar, 0.0, 0.0];
+        assert_eq!(kd.nearest_one(target), Some((n - 1, 1.0)));
+        assert_eq!(kd.nearest_n(target, 2)[1].0, n - 2);
+        assert_eq!(kd.nearest_n_within_radius(target, 2.5, 5).len(), 2);
+        let slab = Slab::new([1.0, 0.0, 0.0], -0.5, 0.5);
+        assert_eq!(kd.within_slab(&slab), vec![0]);
+    }
+
+    #[test]
+    fn median_partitions_keep_duplicate_coordinates_findable() {
+        // Heavy ties on every axis put equal keys on both sides of splits.
+        let pts: Vec<[Scalar; 3]> = (0..2_000)
+            .map(|i| [(i % 5) as Scalar, (i % 3) as Scalar, (i % 7) as Scalar])
+            .collect();
+        let kd = KDTree::build(&pts);
+        for target in [[2.0, 1.0, 3.0], [0.0; 3], [4.5, 2.5, 6.5]] {
+            let mut found: Vec<usize> = kd
+                .nearest_n_within_radius(target, 1.1, usize::MAX)
+                .into_iter()
+                .map(|(i, _)| i)
+                .collect();
+            found.sort_unstable();
+            let expected: Vec<usize> = (0..pts.len())
+                .filter(|&i| {
+                    let d2: Scalar = (0..3).map(|a| (pts[i][a] - target[a]).powi(2)).sum();
+                    d2 <= 1.1 * 1.1
+                })
+                .collect();
+            assert_eq!(kd.count_within_radius(target, 1.1), expected.len());
+            assert_eq!(found, expected);
+        }
+        assert_eq!(kd.count_within_radius([0.0; 3], Scalar::NAN), 0);
+    }
+
+    #[test]
+    fn lazy_radius_queries_stop_when_the_caller_does() {
+        let pts: Vec<[Scalar; 3]> = (0..5_000)
+            .map(|i| {
+                let i = i as Scalar;
+                [(i * 7.3) % 50.0, (i * 13.7) % 50.0, (i * 3.1) % 50.0]
+            })
+            .collect();
+        let kd = KDTree::build(&pts);
+        let target = [25.0; 3];
+        let mut lazy: Vec<(usize, Scalar)> = kd.iter_within_radius(target, 6.0).collect();
+        lazy.sort_by(|a, b| a.1.total_cmp(&b.1).then(a.0.cmp(&b.0)));
+        let mut eager = kd.nearest_n_within_radius(target, 6.0, usize::MAX);
+        eager.sort_by(|a, b| a.1.total_cmp(&b.1).then(a.0.cmp(&b.0)));
+        assert_eq!(lazy, eager);
+
+        let recorder = StatsRecorder::start();
+        let first = kd.iter_within_radius(target, 6.0).next();
+        let visited = recorder.finish().kd_nodes_visited;
+        assert!(first.is_some());
+        assert!(visited < lazy.len() as u64, "{visited} nodes for one match");
+        assert_eq!(kd.iter_within_radius([Scalar::NAN; 3], 6.0).count(), 0);
+    }
+
+    #[test]
+    fn queries_follow_the_given_metric() {
+        let pts = [
+            [3.0, 0.0, 0.0],
+            [0.0, 0.0, 2.0],
+            [0.0, 1.0, 0.0],
+            [2.0, 2.0, 2.0],
+        ];
+        let mut kd = KDTree::build(&pts);
+        kd.insert(4, [0.0, 0.0, 0.5]);
+        assert_eq!(
+            kd.nearest_n_by([0.0; 3], 2, &SquaredEuclidean),
+            vec![(4, 0.25), (2, 1.0)]
+        );
+        assert_eq!(
+            kd.nearest_n_by([0.0; 3], 5, &Euclidean),
+            kd.nearest_n([0.0; 3], 5)
+        );
+
+        // Weighting z makes leaving the plane cost ten times as much.
+        let flat = Weighted::new([1.0, 1.0, 100.0]).unwrap();
+        let order: Vec<usize> = kd
+            .nearest_n_by([0.0; 3], 5, &flat)
+            .into_iter()
+            .map(|(i, _)| i)
+            .collect();
+        assert_eq!(order, vec![2, 0, 4, 1, 3]);
+        let within = kd.nearest_n_within_radius_by([0.0; 3], 5.0, usize::MAX, &flat);
+        assert_eq!(within, vec![(2, 1.0), (0, 3.0), (4, 5.0)]);
+        assert_eq!(
+            Weighted::new([1.0, -1.0, 1.0]),
+            Err(MetricError::InvalidWeights([1.0, -1.0, 1.0]))
+        );
+    }
+
+    #[test]
+    fn inserts_and_removals_apply_without_a_full_rebuild() {
+        let mut pts: Vec<[Scalar; 3]> = (0..1_000)
+            .map(|i| {
+                [
+                    (i % 10) as Scalar,
+                    (i / 10 % 10) as Scalar,
+                    (i / 100) as Scalar,
+                ]
+            })
+            .collect();
+        let mut kd = KDTree::build(&pts);
+        let mut live = vec![true; pts.len()];
+        for i in (0..pts.len()).step_by(50) {
+            assert!(kd.remove(i, pts[i]));
+            live[i] = false;
+        }
+        assert!(!kd.remove(0, pts[0]));
+        for i in 0..30 {
+            let point = [4.5, 4.5, i as Scalar * 0.3];
+            kd.insert(pts.len(), point);
+            pts.push(point);
+            live.push(true);
+        }
+        // Below the rebuild threshold, so the original nodes remain.
+        assert_eq!(kd.nodes.len(), 1_000);
+        assert_eq!(kd.len(), 1_010);
+        assert!(kd.remove(1_000, pts[1_000]));
+        live[1_000] = false;
+
+        let brute = |kd: &KDTree, live: &[bool], target: [Scalar; 3]| {
+            let mut found: Vec<usize> = kd
+                .nearest_n_within_radius(target, 1.5, usize::MAX)
+                .into_iter()
+                .map(|(i, _)| i)
+                .collect();
+            found.sort_unstable();
+            let expected: Vec<usize> = (0..pts.len())
+                .filter(|&i| live[i] && dist2(pts[i], target) <= 1.5 * 1.5)
+                .collect();
+            assert_eq!(kd.count_within_radius(target, 1.5), expected.len());
+            assert_eq!(found, expected);
+        };
+        for target in [[4.0, 4.0, 0.0], [0.0; 3], [5.0, 5.0, 5.0]] {
+            brute(&kd, &live, target);
+        }
+        assert_eq!(kd.nearest_one([0.0; 3]).unwrap().0, 1);
+
+        // Enough changes trigger a rebuild over the live points.
+        for i in (1..pts.len()).step_by(7) {
+            assert_eq!(kd.remove(i, pts[i]), live[i]);
+            live[i] = false;
+        }
+        assert!(kd.nodes.len() < 1_000);
+        assert_eq!(kd.len(), live.iter().filter(|&&l| l).count());
+        brute(&kd, &live, [4.0, 4.0, 0.0]);
+        let loaded: KDTree = bincode::deserialize(&bincode::serialize(&kd).unwrap()).unwrap();
+        assert_eq!(loaded.len(), kd.len());
+    }
+
+    #[test]
+    fn portable_form_survives_layout_changes() {
+        let pts: Vec<[Scalar; 3]> = (0..500)
+            .map(|i| {
+                let i = i as Scalar;
+                [(i * 7.3) % 50.0, (i * 13.7) % 50.0, (i * 3.1) % 50.0]
+            })
+            .collect();
+        let kd = KDTree::build(&pts);
+        let bytes = bincode::serialize(&kd).unwrap();
+        let portable: PortableKDTree = bincode::deserialize(&bytes).unwrap();
+        assert_eq!(portable.version, KD_FORMAT_VERSION);
+        let loaded: KDTree = bincode::deserialize(&bytes).unwrap();
+        let target = [25.0, 10.0, 40.0];
+        assert_eq!(loaded.nearest_n(target, 9), kd.nearest_n(target, 9));
+
+        // Nodes from some other layout, here in source order, are rebuilt.
+        let foreign = PortableKDTree {
+            version: KD_FORMAT_VERSION,
+            points: pts.clone(),
+            indices: (0..pts.len() as u32).collect(),
+            axes: vec![0; pts.len()],
+        };
+        let rebuilt = KDTree::try_from(foreign.clone()).unwrap();
+        assert_eq!(rebuilt.nearest_n(target, 9), kd.nearest_n(target, 9));
+        let newer = PortableKDTree {
+            version: KD_FORMAT_VERSION + 1,
+            ..foreign
+        };
+        assert!(KDTree::try_from(newer).is_err());
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn parallel_build_matches_the_sequential_one() {
+        let pts: Vec<[Scalar; 3]> = (0..50_000)
+            .map(|i| {
+                let i = i as Scalar;
+                [(i * 7.3) % 900.0, (i * 13.7) % 900.0, (i * 3.1) % 900.0]
+            })
+            .collect();
+        let mut sequential: Vec<usize> = (0..pts.len()).collect();
+        super::partition(&pts, &mut sequential, 0);
+        let parallel = KDTree::build(&pts);
+        let order: Vec<usize> = parallel.nodes.iter().map(|n| n.index as usize).collect();
+        assert_eq!(order, sequential);
+    }
+
+    #[test]
+    fn cone_and_slab_queries() {
+        let pts = [
+            [0.0, 0.0, 0.0],
+            [3.0, 0.5, 0.0],
+            [0.0, 3.0, 0.0],
+            [-3.0, 0.0, 0.0],
+            [6.0, 0.0, 4.0],
+        ];
+        let kd = KDTree::build(&pts);
+
+        let cone = Cone::new([0.0, 0.0, 0.0], [1.0, 0.0, 0.0], 0.4, 5.0);
+        let hits: Vec<usize> = kd.within_cone(&cone).iter().map(|h| h.0).collect();
+        assert_eq!(hits, vec![0, 1]);
+        let wide = Cone::new([-1.0, 0.0, 0.0], [1.0, 0.0, 0.0], 1.0, 20.0);
+        let ahead: Vec<usize> = kd.nearest_n_in_cone(&wide, 2).iter().map(|h| h.0).collect();
+        assert_eq!(ahead, vec![0, 1]);
+        assert_eq!(kd.nearest_n_in_cone(&wide, 10).len(), 3);
+
+        let slab = Slab::new([0.0, 0.0, 1.0], -1.0, 1.0);
+        assert_eq!(kd.within_slab(&slab), vec![0, 1, 2, 3]);
+    }
 }
@@ -0,0 +1,193 @@
+use serde::{Deserialize, Serialize};
+
+use crate::rng::SplitMix64;
+use crate::spatial::positions::Positions;
+use crate::Scalar;
+
+/// Parameters for `kmeans`.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct KMeansConfig {
+    /// Clusters wanted; fewer are returned when there are fewer distinct
+    /// points.
+    pub k: usize,
+    /// Upper bound on assignment rounds after seeding; at least one runs.
+    pub max_iterations: usize,
+    pub seed: u64,
+}
+
+impl Default for KMeansConfig {
+    fn default() -> Self {
+        KMeansConfig {
+            k: 8,
+            max_iterations: 100,
+            seed: 0,
+        }
+    }
+}
+
+/// Result of `kmeans`.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Clustering {
+    pub centroids: Vec<[Scalar; 3]>,
+    /// assignments[i] is the cluster of points[i], an index into `centroids`;
+    /// empty when there are no centroids (no points, or `k` of 0).
+    pub assignments: Vec<u32>,
+    /// Assignment rounds run; fewer than `max_iterations` when converged.
+    pub iterations: usize,
+    /// Sum of squared distances from each point to its centroid.
+    pub inertia: f64,
+}
+
+/// Groups `points` into `config.k` clusters of nearby points, e.g. operating
+/// theaters or staging areas. Centroids are seeded with k-means++ (Arthur &
+/// Vassilvitskii, 2007), each further seed picked with probability
+/// proportional to its squared distance from the seeds so far, then refined
+/// by Lloyd's algorithm until no point changes cluster. The same seed always
+/// gives the same clustering.
+pub fn kmeans(points: &[[Scalar; 3]], config: &KMeansConfig) -> Clustering {
+    let positions = Positions::new(points);
+    let mut centroids = seed_centroids(&positions, config.k, config.seed);
+    if centroids.is_empty() {
+        return Clustering {
+            centroids,
+            assignments: Vec::new(),
+            iterations: 0,
+            inertia: 0.0,
+        };
+    }
+    let mut assignments = vec![0u32; points.len()];
+    let mut best = vec![Scalar::INFINITY; points.len()];
+    let mut closest = vec![0u32; points.len()];
+    let mut dist2 = Vec::with_capacity(points.len());
+    let mut iterations = 0;
+    while iterations < config.max_iterations.max(1) {
+        iterations += 1;
+        best.fill(Scalar::INFINITY);
+        for (cluster, &centroid) in centroids.iter().enumerate() {
+            positions.squared_distances_to(centroid, &mut dist2);
+            for ((best, closest), &d2) in best.iter_mut().zip(&mut closest).zip(&dist2) {
+                if d2 < *best {
+                    *best = d2;
+                    *closest = cluster as u32;
+                }
+            }
+        }
+        if closest == assignments && iterations > 1 {
+            break;
+        }
+        assignments.copy_from_slice(&closest);
+        // Means in f64 so large clusters far from the origin stay exact;
+        // an emptied cluster keeps its centroid.
+        let mut sums = vec![([0.0f64; 3], 0usize); centroids.len()];
+        for (p, &cluster) in points.iter().zip(&assignments) {
+            let (sum, count) = &mut sums[cluster as usize];
+            for axis in 0..3 {
+                sum[axis] += p[axis] as f64;
+            }
+            *count += 1;
+        }
+        for (centroid, (sum, count)) in centroids.iter_mut().zip(sums) {
+            if count > 0 {
+                *centroid = sum.map(|s| (s / count as f64) as Scalar);
+            }
+        }
+    }
+    let inertia = points
+        .iter()
+        .zip(&assignments)
+        .map(|(p, &cluster)| {
+            let c = centroids[cluster as usize];
+            (0..3)
+                .map(|axis| (p[axis] as f64 - c[axis] as f64).powi(2))
+                .sum::<f64>()
+        })
+        .sum();
+    Clustering {
+        centroids,
+        assignments,
+        iterations,
+        inertia,
+    }
+}
+
+/// k-means++ seeding. Stops early once every point coincides with a seed.
+fn seed_centroids(positions: &Positions, k: usize, seed: u64) -> Vec<[Scalar; 3]> {
+    let mut centroids = Vec::with_capacity(k);
+    if positions.is_empty() || k == 0 {
+        return centroids;
+    }
+    let mut rng = SplitMix64::new(seed);
+    centroids.push(positions.get(rng.next_index(positions.len())));
+    // Squared distance from each point to its nearest seed.
+    let mut nearest = Vec::new();
+    positions.squared_distances_to(centroids[0], &mut nearest);
+    let mut dist2 = Vec::with_capacity(positions.len());
+    while centroids.len() < k {
+        let total: f64 = nearest.iter().map(|&d| d as f64).sum();
+        if total <= 0.0 {
+            break;
+        }
+        let mut remaining = rng.next_f64() * total;
+        let mut pick = nearest.iter().rposition(|&d| d > 0.0).expect("total > 0");
+        for (i, &d) in nearest.iter().enumerate() {
+            remaining -= d as f64;
+            if remaining < 0.0 && d > 0.0 {
+                pick = i;
+                break;
+            }
+        }
+        let centroid = positions.get(pick);
+        centroids.push(centroid);
+        positions.squared_distances_to(centroid, &mut dist2);
+        for (n, &d) in nearest.iter_mut().zip(&dist2) {
+            *n = n.min(d);
+        }
+    }
+    centroids
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn separated_blobs_become_one_cluster_each() {
+        let centers = [[0.0, 0.0, 0.0], [100.0, 0.0, 0.0], [0.0, 100.0, 50.0]];
+        let points: Vec<[Scalar; 3]> = (0..300)
+            .map(|i| {
+                let c = centers[i % 3];
+                let j = i as Scalar;
+                [
+                    c[0] + (j * 7.3) % 5.0,
+                    c[1] + (j * 13.7) % 5.0,
+                    c[2] + (j * 3.1) % 5.0,
+                ]
+            })
+            .collect();
+        let config = KMeansConfig {
+            k: 3,
+            ..KMeansConfig::default()
+        };
+        let clustering = kmeans(&points, &config);
+        assert_eq!(clustering.centroids.len(), 3);
+        for (blob, center) in centers.iter().enumerate() {
+            let cluster = clustering.assignments[blob];
+            assert!((blob..points.len())
+                .step_by(3)
+                .all(|i| clustering.assignments[i] == cluster));
+            let c = clustering.centroids[cluster as usize];
+            assert!((0..3).all(|a| (c[a] - center[a] - 2.5).abs() < 0.5));
+        }
+        assert!(clustering.iterations < config.max_iterations);
+        assert_eq!(kmeans(&points, &config), clustering);
+
+        // Two distinct points cannot make more than two clusters.
+        let pairs = [[1.0; 3], [1.0; 3], [2.0; 3]];
+        let few = kmeans(&pairs, &config);
+        assert_eq!(few.centroids.len(), 2);
+        assert_eq!(few.inertia, 0.0);
+        assert!(kmeans(&[], &config).centroids.is_empty());
+        let none = kmeans(&pairs, &KMeansConfig { k: 0, ..config });
+        assert!(none.centroids.is_empty() && none.assignments.is_empty());
+    }
+}
@@ -0,0 +1,125 @@
+use serde::Serialize;
+
+use crate::spatial::index::SpatialIndex;
+use crate::spatial::neighbours::k_nearest;
+use crate::Scalar;
+
+/// Directed k-nearest-neighbour graph: every point linked to its `k` closest
+/// other points, optionally only those within `max_range`. Jump-drive style
+/// routing takes it as adjacency (`StarGraph::new(systems, graph.adjacency())`)
+/// and clustering as a sparse similarity graph (`symmetric`).
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct KnnGraph {
+    pub k: usize,
+    pub max_range: Option<Scalar>,
+    /// `(neighbour, distance)` per point, closest first.
+    pub neighbours: Vec<Vec<(u32, Scalar)>>,
+}
+
+impl KnnGraph {
+    /// `spatial` must be built over `points` in order. Points with fewer than `k`
+    /// others in range get only those.
+    pub fn build(
+        points: &[[Scalar; 3]],
+        spatial: &(impl SpatialIndex + ?Sized),
+        k: usize,
+        max_range: Option<Scalar>,
+    ) -> Self {
+        let neighbours = (0..points.len())
+            .map(|i| {
+                let found = match max_range {
+                    None => k_nearest(points, spatial, i, k),
+                    Some(range) => {
+                        let mut found = spatial.nearest_n_within_radius(points[i], range, k + 1);
+                        found.retain(|&(j, _)| j != i);
+                        found.truncate(k);
+                        found
+                    }
+                };
+                found.into_iter().map(|(j, d)| (j as u32, d)).collect()
+            })
+            .collect();
+        KnnGraph {
+            k,
+            max_range,
+            neighbours,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.neighbours.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.neighbours.is_empty()
+    }
+
+    /// Neighbour indices of every point, in the adjacency-list form of
+    /// `StarGraph`.
+    pub fn adjacency(&self) -> Vec<Vec<usize>> {
+        self.neighbours
+            .iter()
+            .map(|list| list.iter().map(|&(j, _)| j as usize).collect())
+            .collect()
+    }
+
+    /// Undirected adjacency: `a` and `b` are linked when either is among
+    /// the other's nearest. Lists are sorted by index.
+    pub fn symmetric(&self) -> Vec<Vec<usize>> {
+        let mut adjacency = self.adjacency();
+        for (a, list) in self.neighbours.iter().enumerate() {
+            for &(b, _) in list {
+                adjacency[b as usize].push(a);
+            }
+        }
+        for list in &mut adjacency {
+            list.sort_unstable();
+            list.dedup();
+        }
+        adjacency
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::spatial::kd_tree::KDTree;
+
+    #[test]
+    fn neighbours_match_a_scan_within_range() {
+        let points: Vec<[Scalar; 3]> = (0..300)
+            .map(|i| {
+                let i = i as Scalar;
+                [(i * 7.3) % 60.0, (i * 13.7) % 60.0, (i * 3.1) % 60.0]
+            })
+            .collect();
+        let kd = KDTree::build(&points);
+        let dist = |a: usize, b: usize| {
+            (0..3)
+                .map(|axis| (points[a][axis] - points[b][axis]).powi(2))
+                .sum::<Scalar>()
+                .sqrt()
+        };
+        for max_range in [None, Some(6.0)] {
+            let graph = KnnGraph::build(&points, &kd, 4, max_range);
+            assert_eq!(graph.len(), points.len());
+            for (a, list) in graph.neighbours.iter().enumerate() {
+                let mut scan: Vec<Scalar> = (0..points.len())
+                    .filter(|&b| b != a)
+                    .map(|b| dist(a, b))
+                    .filter(|&d| max_range.is_none_or(|r| d <= r))
+                    .collect();
+                scan.sort_by(Scalar::total_cmp);
+                scan.truncate(4);
+                let found: Vec<Scalar> = list.iter().map(|&(_, d)| d).collect();
+                assert_eq!(found, scan);
+            }
+        }
+
+        // A point left out by its neighbour's list still links back.
+        let line = [[0.0; 3], [1.0, 0.0, 0.0], [3.0, 0.0, 0.0]];
+        let graph = KnnGraph::build(&line, &KDTree::build(&line), 1, None);
+        assert_eq!(graph.adjacency(), vec![vec![1], vec![0], vec![1]]);
+        assert_eq!(graph.symmetric(), vec![vec![1], vec![0, 2], vec![1]]);
+    }
+}
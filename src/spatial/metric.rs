@@ -0,0 +1,115 @@
+use thiserror::Error;
+
+use crate::spatial::kd_tree::dist2;
+use crate::Scalar;
+
+#[derive(Debug, Error, PartialEq)]
+pub enum MetricError {
+    #[error("axis weights must be zero or positive and finite, got {0:?}")]
+    InvalidWeights([Scalar; 3]),
+}
+
+/// Distance measure for the k-d tree queries. Searches rank and prune on
+/// `raw` values, which only need to grow with the distance, and convert to
+/// the reported distance once per result.
+pub trait Metric {
+    /// Comparable form of the distance between `a` and `b`.
+    fn raw(&self, a: [Scalar; 3], b: [Scalar; 3]) -> Scalar;
+
+    /// Least `raw` value between two points `delta` apart along `axis`,
+    /// whatever their other coordinates: how far away a split plane is.
+    fn axis_raw(&self, axis: usize, delta: Scalar) -> Scalar;
+
+    /// Reported distance of a `raw` value.
+    fn distance(&self, raw: Scalar) -> Scalar;
+
+    /// `raw` value of a distance, for radius bounds.
+    fn raw_of(&self, distance: Scalar) -> Scalar;
+}
+
+/// Straight-line distance; what every query uses unless told otherwise.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Euclidean;
+
+impl Metric for Euclidean {
+    fn raw(&self, a: [Scalar; 3], b: [Scalar; 3]) -> Scalar {
+        dist2(a, b)
+    }
+
+    fn axis_raw(&self, _axis: usize, delta: Scalar) -> Scalar {
+        delta * delta
+    }
+
+    fn distance(&self, raw: Scalar) -> Scalar {
+        raw.sqrt()
+    }
+
+    fn raw_of(&self, distance: Scalar) -> Scalar {
+        distance * distance
+    }
+}
+
+/// Squared straight-line distance, reported as is: the same ranking as
+/// `Euclidean` without a square root per result. Radii are squared too.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct SquaredEuclidean;
+
+impl Metric for SquaredEuclidean {
+    fn raw(&self, a: [Scalar; 3], b: [Scalar; 3]) -> Scalar {
+        dist2(a, b)
+    }
+
+    fn axis_raw(&self, _axis: usize, delta: Scalar) -> Scalar {
+        delta * delta
+    }
+
+    fn distance(&self, raw: Scalar) -> Scalar {
+        raw
+    }
+
+    fn raw_of(&self, distance: Scalar) -> Scalar {
+        distance
+    }
+}
+
+/// Euclidean distance with each axis' squared difference scaled by its
+/// weight, e.g. to make leaving the galactic plane cost more than moving
+/// within it. A weight of 0 ignores the axis.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Weighted {
+    weights: [Scalar; 3],
+}
+
+impl Weighted {
+    pub fn new(weights: [Scalar; 3]) -> Result<Self, MetricError> {
+        if weights.iter().all(|w| w.is_finite() && *w >= 0.0) {
+            Ok(Weighted { weights })
+        } else {
+            Err(MetricError::InvalidWeights(weights))
+        }
+    }
+
+    pub fn weights(&self) -> [Scalar; 3] {
+        self.weights
+    }
+}
+
+impl Metric for Weighted {
+    fn raw(&self, a: [Scalar; 3], b: [Scalar; 3]) -> Scalar {
+        (0..3)
+            .map(|axis| self.weights[axis] * (a[axis] - b[axis]).powi(2))
+            .sum()
+    }
+
+    fn axis_raw(&self, axis: usize, delta: Scalar) -> Scalar {
+        self.weights[axis] * delta * delta
+    }
+
+    fn distance(&self, raw: Scalar) -> Scalar {
+        raw.sqrt()
+    }
+
+    fn raw_of(&self, distance: Scalar) -> Scalar {
+        distance * distance
+    }
+}
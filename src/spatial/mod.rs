@@ -1 +1,19 @@
+pub mod ball_tree;
+pub mod corridor;
+pub mod grid_hash;
+pub mod heatmap;
+pub mod hull;
+pub mod index;
 pub mod kd_tree;
+pub mod kmeans;
+pub mod knn_graph;
+pub mod metric;
+pub mod morton;
+pub mod mst;
+pub mod neighbours;
+pub mod octree;
+pub mod positions;
+pub mod rtree;
+pub mod safe_corridor;
+pub mod shapes;
+pub mod voids;
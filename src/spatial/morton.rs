@@ -0,0 +1,417 @@
+use thiserror::Error;
+
+use crate::spatial::index::SpatialIndex;
+use crate::spatial::kd_tree::{dist2, Candidates};
+use crate::spatial::shapes::distance_to_segment;
+use crate::validate::is_searchable;
+use crate::Scalar;
+
+/// Bits kept per axis; three axes fill 63 bits of the code.
+pub const MORTON_BITS: u32 = 21;
+
+/// Z-order (Morton) code of `pos` within the box `min..=max`: each
+/// coordinate is quantized to `MORTON_BITS` bits and the bits interleaved,
+/// so points close in space tend to get close codes.
+pub fn morton_code(pos: [Scalar; 3], min: [Scalar; 3], max: [Scalar; 3]) -> u64 {
+    interleave(quantize(pos, min, max))
+}
+
+/// Prefix of `MortonIndex::to_bytes` output.
+pub const MORTON_MAGIC: &[u8; 4] = b"SMZ1";
+
+/// Bytes of one stored coordinate.
+const SCALAR_LEN: usize = std::mem::size_of::<Scalar>();
+
+/// Bytes of the `to_bytes` header: magic, point count, `min` and `max`.
+const HEADER_LEN: usize = 4 + 4 + 6 * SCALAR_LEN;
+
+/// Bytes per point: code, source index and position.
+const POINT_LEN: usize = 8 + 4 + 3 * SCALAR_LEN;
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum MortonLayoutError {
+    #[error("not a Morton index")]
+    BadMagic,
+    #[error("Morton index of {expected} bytes holds {actual}")]
+    Length { expected: usize, actual: usize },
+}
+
+/// Points sorted by Morton code, answering box queries by binary search over
+/// a few code intervals instead of a tree walk. Box lookups return a cheap
+/// superset of the true answer, meant as a prefilter ahead of an exact test
+/// for bulk spatial joins and route corridors. The index is nothing but flat
+/// arrays, so `from_bytes` restores it without sorting or building anything.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MortonIndex {
+    min: [Scalar; 3],
+    max: [Scalar; 3],
+    /// Morton codes, ascending.
+    codes: Vec<u64>,
+    /// Source index of the point with each code.
+    indices: Vec<u32>,
+    /// The points, in code order.
+    points: Vec<[Scalar; 3]>,
+}
+
+impl MortonIndex {
+    pub fn build(points: &[[Scalar; 3]]) -> Self {
+        let (min, max) = bounds(points.iter().copied()).unwrap_or_default();
+        let mut entries: Vec<(u64, u32)> = points
+            .iter()
+            .enumerate()
+            .map(|(i, &p)| {
+                let index = u32::try_from(i).expect("fewer than 2^32 points");
+                (morton_code(p, min, max), index)
+            })
+            .collect();
+        entries.sort_unstable();
+        MortonIndex {
+            min,
+            max,
+            codes: entries.iter().map(|&(code, _)| code).collect(),
+            points: entries.iter().map(|&(_, i)| points[i as usize]).collect(),
+            indices: entries.into_iter().map(|(_, i)| i).collect(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.codes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.codes.is_empty()
+    }
+
+    /// Little-endian layout: `MORTON_MAGIC`, the point count as `u32`,
+    /// `min` and `max`, then the codes, source indices and points as flat
+    /// arrays.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(HEADER_LEN + self.len() * POINT_LEN);
+        bytes.extend(MORTON_MAGIC);
+        bytes.extend((self.len() as u32).to_le_bytes());
+        for v in self.min.iter().chain(&self.max) {
+            bytes.extend(v.to_le_bytes());
+        }
+        for code in &self.codes {
+            bytes.extend(code.to_le_bytes());
+        }
+        for index in &self.indices {
+            bytes.extend(index.to_le_bytes());
+        }
+        for v in self.points.iter().flatten() {
+            bytes.extend(v.to_le_bytes());
+        }
+        bytes
+    }
+
+    /// Reads the layout of `to_bytes`, checking only its length.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, MortonLayoutError> {
+        let rest = bytes
+            .strip_prefix(MORTON_MAGIC)
+            .ok_or(MortonLayoutError::BadMagic)?;
+        let length_error = |expected| MortonLayoutError::Length {
+            expected,
+            actual: bytes.len(),
+        };
+        let (count, rest) = rest
+            .split_first_chunk::<4>()
+            .ok_or_else(|| length_error(HEADER_LEN))?;
+        let count = u32::from_le_bytes(*count) as usize;
+        let expected = HEADER_LEN + count * POINT_LEN;
+        if bytes.len() != expected {
+            return Err(length_error(expected));
+        }
+        let (bounds, rest) = rest.split_at(6 * SCALAR_LEN);
+        let (codes, rest) = rest.split_at(count * 8);
+        let (indices, points) = rest.split_at(count * 4);
+        let floats = |chunk: &[u8]| -> Vec<Scalar> {
+            chunk
+                .chunks_exact(SCALAR_LEN)
+                .map(|b| Scalar::from_le_bytes(b.try_into().expect("one coordinate")))
+                .collect()
+        };
+        let bounds = floats(bounds);
+        Ok(MortonIndex {
+            min: [bounds[0], bounds[1], bounds[2]],
+            max: [bounds[3], bounds[4], bounds[5]],
+            codes: codes
+                .chunks_exact(8)
+                .map(|b| u64::from_le_bytes(b.try_into().expect("8-byte chunk")))
+                .collect(),
+            indices: indices
+                .chunks_exact(4)
+                .map(|b| u32::from_le_bytes(b.try_into().expect("4-byte chunk")))
+                .collect(),
+            points: floats(points)
+                .chunks_exact(3)
+                .map(|p| [p[0], p[1], p[2]])
+                .collect(),
+        })
+    }
+
+    /// Every point inside the box `lo..=hi`, plus some points near it.
+    pub fn candidates_in_box(&self, lo: [Scalar; 3], hi: [Scalar; 3]) -> Vec<usize> {
+        self.slots_in_box(lo, hi)
+            .map(|slot| self.indices[slot] as usize)
+            .collect()
+    }
+
+    /// Slots of `points` covering `candidates_in_box`.
+    fn slots_in_box(&self, lo: [Scalar; 3], hi: [Scalar; 3]) -> impl Iterator<Item = usize> + '_ {
+        let mut ranges = Vec::new();
+        if !self.is_empty() && (0..3).all(|a| lo[a] <= self.max[a] && hi[a] >= self.min[a]) {
+            let qlo = quantize(lo, self.min, self.max);
+            let qhi = quantize(hi, self.min, self.max);
+            let extent = (0..3).map(|a| qhi[a] - qlo[a] + 1).max().unwrap_or(1);
+            collect_ranges(qlo, qhi, extent, [0; 3], 0, &mut ranges);
+        }
+        ranges.into_iter().flat_map(|(first, last)| {
+            let start = self.codes.partition_point(|&code| code < first);
+            let end = self.codes.partition_point(|&code| code <= last);
+            start..end
+        })
+    }
+
+    /// Indices of the points inside the box `lo..=hi`.
+    pub fn within_box(&self, lo: [Scalar; 3], hi: [Scalar; 3]) -> Vec<usize> {
+        self.slots_in_box(lo, hi)
+            .filter(|&slot| (0..3).all(|a| (lo[a]..=hi[a]).contains(&self.points[slot][a])))
+            .map(|slot| self.indices[slot] as usize)
+            .collect()
+    }
+
+    /// Indices of the points within `distance` of the segment from `a` to `b`.
+    pub fn near_segment(&self, a: [Scalar; 3], b: [Scalar; 3], distance: Scalar) -> Vec<usize> {
+        let lo = [0, 1, 2].map(|axis| a[axis].min(b[axis]) - distance);
+        let hi = [0, 1, 2].map(|axis| a[axis].max(b[axis]) + distance);
+        self.slots_in_box(lo, hi)
+            .filter(|&slot| distance_to_segment(self.points[slot], a, b) <= distance)
+            .map(|slot| self.indices[slot] as usize)
+            .collect()
+    }
+
+    /// Spatial join: every `(query, point)` pair of indices closer than
+    /// `radius`, grouped by query.
+    pub fn join_within(&self, queries: &[[Scalar; 3]], radius: Scalar) -> Vec<(usize, usize)> {
+        let radius2 = radius * radius;
+        let mut pairs = Vec::new();
+        for (q, &center) in queries.iter().enumerate() {
+            let lo = center.map(|c| c - radius);
+            let hi = center.map(|c| c + radius);
+            for slot in self.slots_in_box(lo, hi) {
+                if dist2(self.points[slot], center) <= radius2 {
+                    pairs.push((q, self.indices[slot] as usize));
+                }
+            }
+        }
+        pairs
+    }
+}
+
+impl SpatialIndex for MortonIndex {
+    fn len(&self) -> usize {
+        MortonIndex::len(self)
+    }
+
+    fn nearest_one(&self, target: [Scalar; 3]) -> Option<(usize, Scalar)> {
+        self.nearest_n(target, 1).pop()
+    }
+
+    fn nearest_n(&self, target: [Scalar; 3], n: usize) -> Vec<(usize, Scalar)> {
+        self.nearest_n_within_radius(target, Scalar::INFINITY, n)
+    }
+
+    /// Box lookups around `target`, doubling the box until it holds `n`
+    /// points inside its inscribed sphere or covers every point.
+    fn nearest_n_within_radius(
+        &self,
+        target: [Scalar; 3],
+        radius: Scalar,
+        n: usize,
+    ) -> Vec<(usize, Scalar)> {
+        if n == 0 || !is_searchable(target, radius) || self.is_empty() {
+            return Vec::new();
+        }
+        let widest = (0..3)
+            .map(|a| self.max[a] - self.min[a])
+            .fold(0.0, Scalar::max);
+        // Farthest any point can be from `target`, which ends the doubling.
+        let reach = (0..3)
+            .map(|a| {
+                let far = (target[a] - self.min[a])
+                    .abs()
+                    .max((target[a] - self.max[a]).abs());
+                far * far
+            })
+            .sum::<Scalar>()
+            .sqrt();
+        let mut half = (widest / (self.len() as Scalar).cbrt()).max(Scalar::MIN_POSITIVE);
+        loop {
+            let half_width = half.min(radius);
+            let lo = target.map(|c| c - half_width);
+            let hi = target.map(|c| c + half_width);
+            let limit2 = half_width * half_width;
+            let mut best = Candidates::new(n);
+            for slot in self.slots_in_box(lo, hi) {
+                let d2 = dist2(self.points[slot], target);
+                if d2 <= limit2 {
+                    best.offer(self.indices[slot] as usize, d2);
+                }
+            }
+            let found = best.into_sorted();
+            if found.len() == n || half >= radius || half >= reach {
+                return found;
+            }
+            half *= 2.0;
+        }
+    }
+}
+
+/// Appends, in ascending order, code intervals covering the quantized box
+/// `lo..=hi` by descending the implicit octree. Cells straddling the box
+/// edge are split until they are a quarter of the box's extent, then taken
+/// whole, which bounds the number of intervals at a small cost in excess
+/// candidates.
+fn collect_ranges(
+    lo: [u64; 3],
+    hi: [u64; 3],
+    extent: u64,
+    cell: [u64; 3],
+    level: u32,
+    ranges: &mut Vec<(u64, u64)>,
+) {
+    let shift = MORTON_BITS - level;
+    let size = 1u64 << shift;
+    let cell_lo = cell.map(|c| c * size);
+    let cell_hi = cell_lo.map(|c| c + size - 1);
+    if (0..3).any(|a| cell_hi[a] < lo[a] || cell_lo[a] > hi[a]) {
+        return;
+    }
+    let inside = (0..3).all(|a| cell_lo[a] >= lo[a] && cell_hi[a] <= hi[a]);
+    if inside || level == MORTON_BITS || size * 4 <= extent {
+        let first = interleave(cell) << (3 * shift);
+        let last = first + ((1u64 << (3 * shift)) - 1);
+        match ranges.last_mut() {
+            Some((_, prev_last)) if *prev_last + 1 == first => *prev_last = last,
+            _ => ranges.push((first, last)),
+        }
+        return;
+    }
+    for child in 0..8u64 {
+        let child_cell = [0, 1, 2].map(|a| cell[a] * 2 + ((child >> a) & 1));
+        collect_ranges(lo, hi, extent, child_cell, level + 1, ranges);
+    }
+}
+
+/// Cell coordinates of `pos` on the `MORTON_BITS`-bit grid over `min..=max`.
+fn quantize(pos: [Scalar; 3], min: [Scalar; 3], max: [Scalar; 3]) -> [u64; 3] {
+    let cells = ((1u64 << MORTON_BITS) - 1) as Scalar;
+    [0, 1, 2].map(|axis| {
+        let extent = max[axis] - min[axis];
+        let t = if extent > 0.0 {
+            ((pos[axis] - min[axis]) / extent).clamp(0.0, 1.0)
+        } else {
+            0.0
+        };
+        (t * cells) as u64
+    })
+}
+
+fn interleave(cell: [u64; 3]) -> u64 {
+    spread_bits(cell[0]) | spread_bits(cell[1]) << 1 | spread_bits(cell[2]) << 2
+}
+
+/// Bounding box of `points`, or `None` when empty.
+pub fn bounds(points: impl IntoIterator<Item = [Scalar; 3]>) -> Option<([Scalar; 3], [Scalar; 3])> {
+    let mut points = points.into_iter();
+    let first = points.next()?;
+    Some(points.fold((first, first), |(mut min, mut max), p| {
+        for axis in 0..3 {
+            min[axis] = min[axis].min(p[axis]);
+            max[axis] = max[axis].max(p[axis]);
+        }
+        (min, max)
+    }))
+}
+
+/// Inserts two zero bits between each of the low 21 bits of `v`.
+fn spread_bits(v: u64) -> u64 {
+    let mut x = v & 0x1f_ffff;
+    x = (x | x << 32) & 0x1f_0000_0000_ffff;
+    x = (x | x << 16) & 0x1f_0000_ff00_00ff;
+    x = (x | x << 8) & 0x100f_00f0_0f00_f00f;
+    x = (x | x << 4) & 0x10c3_0c30_c30c_30c3;
+    x = (x | x << 2) & 0x1249_2492_4924_9249;
+    x
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::spatial::index::{assert_agrees_with_kd, scattered_points};
+
+    #[test]
+    fn codes_interleave_axes() {
+        let min = [0.0; 3];
+        let max = [1.0; 3];
+        assert_eq!(morton_code([0.0; 3], min, max), 0);
+        assert_eq!(morton_code([1.0, 1.0, 1.0], min, max), (1u64 << 63) - 1);
+        // The highest bit of x lands below the highest bits of y and z.
+        let x = morton_code([1.0, 0.0, 0.0], min, max);
+        let z = morton_code([0.0, 0.0, 1.0], min, max);
+        assert!(x < z);
+        assert_eq!(x & z, 0);
+    }
+
+    #[test]
+    fn box_queries_match_a_scan() {
+        let points = scattered_points(1000, 100.0);
+        let index = MortonIndex::build(&points);
+        let (lo, hi) = ([20.0, 35.0, 0.0], [45.0, 60.0, 50.0]);
+        let mut expected: Vec<usize> = (0..points.len())
+            .filter(|&i| (0..3).all(|a| (lo[a]..=hi[a]).contains(&points[i][a])))
+            .collect();
+        let mut found = index.within_box(lo, hi);
+        found.sort_unstable();
+        expected.sort_unstable();
+        assert!(!expected.is_empty());
+        assert_eq!(found, expected);
+        assert!(index.candidates_in_box(lo, hi).len() < points.len());
+
+        let pairs = index.join_within(&[[50.0; 3]], 10.0);
+        let scan = points
+            .iter()
+            .filter(|p| p.iter().map(|c| (c - 50.0).powi(2)).sum::<Scalar>() <= 100.0)
+            .count();
+        assert_eq!(pairs.len(), scan);
+    }
+
+    #[test]
+    fn flat_layout_round_trips() {
+        let index = MortonIndex::build(&scattered_points(1000, 100.0));
+        let bytes = index.to_bytes();
+        assert_eq!(MortonIndex::from_bytes(&bytes).unwrap(), index);
+        assert_eq!(
+            MortonIndex::from_bytes(&bytes[..bytes.len() - 1]),
+            Err(MortonLayoutError::Length {
+                expected: bytes.len(),
+                actual: bytes.len() - 1
+            })
+        );
+        assert_eq!(
+            MortonIndex::from_bytes(b"nope"),
+            Err(MortonLayoutError::BadMagic)
+        );
+        assert!(MortonIndex::from_bytes(&MortonIndex::build(&[]).to_bytes())
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn loaded_index_agrees_with_the_kd_tree() {
+        let points = scattered_points(1000, 100.0);
+        let loaded = MortonIndex::from_bytes(&MortonIndex::build(&points).to_bytes()).unwrap();
+        let targets = [[0.0; 3], [50.0, 20.0, 75.0], [400.0, -90.0, 10.0]];
+        assert_agrees_with_kd(&loaded, &points, &targets, 8.0);
+    }
+}
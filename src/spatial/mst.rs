@@ -0,0 +1,161 @@
+use serde::Serialize;
+
+use crate::spatial::index::SpatialIndex;
+use crate::spatial::neighbours::k_nearest;
+use crate::Scalar;
+
+/// Neighbours per system offered as candidate edges by default.
+pub const DEFAULT_MST_NEIGHBOURS: usize = 8;
+
+/// One edge of a spanning tree, between point indices `a < b`.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+pub struct TreeEdge {
+    pub a: usize,
+    pub b: usize,
+    pub length: Scalar,
+}
+
+/// Minimum spanning forest of a point set, with edges in the order Kruskal's
+/// algorithm accepted them (shortest first).
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct SpanningTree {
+    pub edges: Vec<TreeEdge>,
+    pub total_length: Scalar,
+    /// Connected pieces; 1 when the candidate graph spans every point.
+    pub components: usize,
+}
+
+/// Euclidean minimum spanning tree over the graph joining every point to its
+/// `k` nearest neighbours. That graph contains the true Euclidean MST for
+/// all but pathological layouts once `k` is around 8, at a fraction of the
+/// cost of the complete graph. Clusters separated by more than their
+/// neighbours' spread can stay apart, in which case the result is a forest
+/// (`components > 1`) and a larger `k` joins them.
+pub fn euclidean_mst(
+    points: &[[Scalar; 3]],
+    spatial: &(impl SpatialIndex + ?Sized),
+    k: usize,
+) -> SpanningTree {
+    let mut candidates: Vec<TreeEdge> = (0..points.len())
+        .flat_map(|a| {
+            k_nearest(points, spatial, a, k)
+                .into_iter()
+                .map(move |(b, length)| TreeEdge {
+                    a: a.min(b),
+                    b: a.max(b),
+                    length,
+                })
+        })
+        .collect();
+    candidates.sort_by(|x, y| {
+        x.length
+            .total_cmp(&y.length)
+            .then((x.a, x.b).cmp(&(y.a, y.b)))
+    });
+    candidates.dedup_by_key(|e| (e.a, e.b));
+
+    let mut sets = DisjointSets::new(points.len());
+    let mut tree = SpanningTree {
+        components: points.len(),
+        ..SpanningTree::default()
+    };
+    for edge in candidates {
+        if sets.union(edge.a, edge.b) {
+            tree.total_length += edge.length;
+            tree.components -= 1;
+            tree.edges.push(edge);
+        }
+    }
+    tree
+}
+
+/// Union–find with path halving and union by size.
+struct DisjointSets {
+    parent: Vec<usize>,
+    size: Vec<usize>,
+}
+
+impl DisjointSets {
+    fn new(n: usize) -> Self {
+        DisjointSets {
+            parent: (0..n).collect(),
+            size: vec![1; n],
+        }
+    }
+
+    fn find(&mut self, mut v: usize) -> usize {
+        while self.parent[v] != v {
+            self.parent[v] = self.parent[self.parent[v]];
+            v = self.parent[v];
+        }
+        v
+    }
+
+    /// Joins the sets of `a` and `b`; `false` if they were already one.
+    fn union(&mut self, a: usize, b: usize) -> bool {
+        let (mut a, mut b) = (self.find(a), self.find(b));
+        if a == b {
+            return false;
+        }
+        if self.size[a] < self.size[b] {
+            std::mem::swap(&mut a, &mut b);
+        }
+        self.parent[b] = a;
+        self.size[a] += self.size[b];
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::spatial::kd_tree::KDTree;
+
+    #[test]
+    fn tree_matches_prim_on_the_complete_graph() {
+        let points: Vec<[Scalar; 3]> = (0..150)
+            .map(|i| {
+                let i = i as Scalar;
+                [(i * 7.3) % 40.0, (i * 13.7) % 40.0, (i * 3.1) % 40.0]
+            })
+            .collect();
+        let kd = KDTree::build(&points);
+        let tree = euclidean_mst(&points, &kd, DEFAULT_MST_NEIGHBOURS);
+        assert_eq!(tree.components, 1);
+        assert_eq!(tree.edges.len(), points.len() - 1);
+
+        // Prim's algorithm over every pair.
+        let dist = |a: usize, b: usize| {
+            (0..3)
+                .map(|axis| (points[a][axis] - points[b][axis]).powi(2))
+                .sum::<Scalar>()
+                .sqrt()
+        };
+        let mut best: Vec<Scalar> = (0..points.len()).map(|v| dist(0, v)).collect();
+        let mut in_tree = vec![false; points.len()];
+        in_tree[0] = true;
+        let mut total = 0.0;
+        for _ in 1..points.len() {
+            let v = (0..points.len())
+                .filter(|&v| !in_tree[v])
+                .min_by(|&a, &b| best[a].total_cmp(&best[b]))
+                .unwrap();
+            in_tree[v] = true;
+            total += best[v];
+            for (w, b) in best.iter_mut().enumerate() {
+                *b = b.min(dist(v, w));
+            }
+        }
+        assert!((tree.total_length - total).abs() < 1e-2 * total);
+
+        // Two distant pairs with one neighbour each stay a forest.
+        let apart = [
+            [0.0; 3],
+            [1.0, 0.0, 0.0],
+            [100.0, 0.0, 0.0],
+            [101.0, 0.0, 0.0],
+        ];
+        let forest = euclidean_mst(&apart, &KDTree::build(&apart), 1);
+        assert_eq!((forest.components, forest.edges.len()), (2, 2));
+    }
+}
@@ -0,0 +1,108 @@
+use serde::{Deserialize, Serialize};
+
+use crate::spatial::index::SpatialIndex;
+use crate::Scalar;
+
+/// Neighbour ranks recorded by default, 1 being the closest other system.
+pub const NEIGHBOUR_RANKS: [usize; 3] = [1, 5, 10];
+
+/// Distance from every system to its k-th nearest other system for a few
+/// ranks k: a cheap local density measure for features such as adaptive
+/// sweep radii. Computed offline and stored in the dataset metadata.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct NeighbourDistances {
+    pub ranks: Vec<usize>,
+    /// Indexed like the dataset's systems, then like `ranks`; `None` where
+    /// the dataset has too few other systems.
+    pub systems: Vec<Vec<Option<Scalar>>>,
+}
+
+impl NeighbourDistances {
+    pub fn compute(
+        points: &[[Scalar; 3]],
+        spatial: &(impl SpatialIndex + ?Sized),
+        ranks: &[usize],
+    ) -> Self {
+        NeighbourDistances {
+            ranks: ranks.to_vec(),
+            systems: (0..points.len())
+                .map(|i| neighbour_distances(points, spatial, i, ranks))
+                .collect(),
+        }
+    }
+
+    /// The stored distances of system `index`, if this table covers it.
+    pub fn get(&self, index: usize) -> Option<&[Option<Scalar>]> {
+        self.systems.get(index).map(Vec::as_slice)
+    }
+}
+
+/// Distances from `points[index]` to its neighbours of the given `ranks`.
+pub fn neighbour_distances(
+    points: &[[Scalar; 3]],
+    spatial: &(impl SpatialIndex + ?Sized),
+    index: usize,
+    ranks: &[usize],
+) -> Vec<Option<Scalar>> {
+    let deepest = ranks.iter().copied().max().unwrap_or(0);
+    let others = k_nearest(points, spatial, index, deepest);
+    ranks
+        .iter()
+        .map(|&rank| {
+            rank.checked_sub(1)
+                .and_then(|k| others.get(k))
+                .map(|&(_, d)| d)
+        })
+        .collect()
+}
+
+/// The `k` nearest other points of `points[index]`, closest first.
+pub(crate) fn k_nearest(
+    points: &[[Scalar; 3]],
+    spatial: &(impl SpatialIndex + ?Sized),
+    index: usize,
+    k: usize,
+) -> Vec<(usize, Scalar)> {
+    let mut found = spatial.nearest_n(points[index], k + 1);
+    found.retain(|&(i, _)| i != index);
+    found.truncate(k);
+    found
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::spatial::kd_tree::KDTree;
+
+    #[test]
+    fn ranks_match_a_sorted_scan() {
+        let points: Vec<[Scalar; 3]> = (0..200)
+            .map(|i| {
+                let i = i as Scalar;
+                [(i * 7.3) % 50.0, (i * 13.7) % 50.0, (i * 3.1) % 50.0]
+            })
+            .collect();
+        let kd = KDTree::build(&points);
+        let table = NeighbourDistances::compute(&points, &kd, &NEIGHBOUR_RANKS);
+        for (i, p) in points.iter().enumerate() {
+            let mut scan: Vec<Scalar> = points
+                .iter()
+                .enumerate()
+                .filter(|&(j, _)| j != i)
+                .map(|(_, q)| (0..3).map(|a| (p[a] - q[a]).powi(2)).sum::<Scalar>().sqrt())
+                .collect();
+            scan.sort_by(Scalar::total_cmp);
+            let expected: Vec<Option<Scalar>> =
+                NEIGHBOUR_RANKS.iter().map(|&r| Some(scan[r - 1])).collect();
+            assert_eq!(table.get(i).unwrap(), expected.as_slice());
+        }
+
+        // Two points: only the closest neighbour exists.
+        let pair = [[0.0; 3], [3.0, 4.0, 0.0]];
+        let kd = KDTree::build(&pair);
+        assert_eq!(
+            neighbour_distances(&pair, &kd, 1, &NEIGHBOUR_RANKS),
+            vec![Some(5.0), None, None]
+        );
+    }
+}
@@ -0,0 +1,258 @@
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::spatial::index::SpatialIndex;
+use crate::spatial::kd_tree::{dist2, Candidates};
+use crate::spatial::morton::bounds;
+use crate::validate::is_searchable;
+use crate::Scalar;
+
+/// How finely an octree subdivides.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OctreeConfig {
+    /// Levels below the root; cells at this depth stay leaves however full.
+    pub max_depth: u32,
+    /// Most points a cell holds before it is split.
+    pub leaf_size: usize,
+}
+
+impl Default for OctreeConfig {
+    fn default() -> Self {
+        OctreeConfig {
+            max_depth: 16,
+            leaf_size: 8,
+        }
+    }
+}
+
+/// An octree cell over `points[start..end]`, with the tight bounding box of
+/// those points. Inner cells have their non-empty children at
+/// `children.0..children.1`; leaves have an empty range.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Cell {
+    min: [Scalar; 3],
+    max: [Scalar; 3],
+    start: u32,
+    end: u32,
+    children: (u32, u32),
+}
+
+/// Octree: space split into eight equal octants, recursively, wherever a
+/// cell holds more than `leaf_size` points. Dense regions of the map get
+/// deep, fine cells and sparse ones stay coarse, without the median splits
+/// of the k-d tree.
+#[derive(Clone, Debug)]
+pub struct Octree {
+    /// The points, reordered so each cell's points are contiguous.
+    points: Vec<[Scalar; 3]>,
+    /// Source index of each entry of `points`.
+    indices: Vec<u32>,
+    cells: Vec<Cell>,
+}
+
+impl Octree {
+    pub fn build(points: &[[Scalar; 3]]) -> Self {
+        Self::with_config(points, OctreeConfig::default())
+    }
+
+    pub fn with_config(points: &[[Scalar; 3]], config: OctreeConfig) -> Self {
+        let mut order: Vec<u32> = (0..points.len())
+            .map(|i| u32::try_from(i).expect("fewer than 2^32 points"))
+            .collect();
+        let mut cells = Vec::new();
+        // (cell, cube center, cube half-width, depth)
+        let mut pending = Vec::new();
+        if let Some((min, max)) = bounds(points.iter().copied()) {
+            let half = (0..3).map(|a| max[a] - min[a]).fold(0.0, Scalar::max) / 2.0;
+            cells.push(Cell {
+                min,
+                max,
+                start: 0,
+                end: points.len() as u32,
+                children: (0, 0),
+            });
+            pending.push((0, [0, 1, 2].map(|a| min[a] + half), half, 0));
+        }
+        while let Some((id, center, half, depth)) = pending.pop() {
+            let Cell {
+                min,
+                max,
+                start,
+                end,
+                ..
+            } = cells[id];
+            let (start, end) = (start as usize, end as usize);
+            // Identical points cannot be told apart by splitting.
+            if end - start <= config.leaf_size || depth >= config.max_depth || min == max {
+                continue;
+            }
+            let octant = |i: u32| {
+                let p = points[i as usize];
+                (0..3)
+                    .filter(|&a| p[a] >= center[a])
+                    .map(|a| 1 << a)
+                    .sum::<usize>()
+            };
+            order[start..end].sort_unstable_by_key(|&i| octant(i));
+            let first = cells.len();
+            let quarter = half / 2.0;
+            let mut run = start;
+            while run < end {
+                let o = octant(order[run]);
+                let stop = run + order[run..end].partition_point(|&i| octant(i) == o);
+                let (min, max) = bounds(order[run..stop].iter().map(|&i| points[i as usize]))
+                    .expect("runs are not empty");
+                let child_center = [0, 1, 2].map(|a| {
+                    if o & (1 << a) != 0 {
+                        center[a] + quarter
+                    } else {
+                        center[a] - quarter
+                    }
+                });
+                pending.push((cells.len(), child_center, quarter, depth + 1));
+                cells.push(Cell {
+                    min,
+                    max,
+                    start: run as u32,
+                    end: stop as u32,
+                    children: (0, 0),
+                });
+                run = stop;
+            }
+            cells[id].children = (first as u32, cells.len() as u32);
+        }
+        Octree {
+            points: order.iter().map(|&i| points[i as usize]).collect(),
+            indices: order,
+            cells,
+        }
+    }
+
+    /// Depth of the deepest cell; 0 for a single leaf or an empty tree.
+    pub fn depth(&self) -> u32 {
+        let mut deepest = 0;
+        let mut stack: Vec<(usize, u32)> = (!self.cells.is_empty())
+            .then_some((0, 0))
+            .into_iter()
+            .collect();
+        while let Some((id, depth)) = stack.pop() {
+            deepest = deepest.max(depth);
+            let (first, last) = self.cells[id].children;
+            stack.extend((first as usize..last as usize).map(|child| (child, depth + 1)));
+        }
+        deepest
+    }
+}
+
+impl SpatialIndex for Octree {
+    fn len(&self) -> usize {
+        self.points.len()
+    }
+
+    fn nearest_one(&self, target: [Scalar; 3]) -> Option<(usize, Scalar)> {
+        self.nearest_n(target, 1).pop()
+    }
+
+    fn nearest_n(&self, target: [Scalar; 3], n: usize) -> Vec<(usize, Scalar)> {
+        self.nearest_n_within_radius(target, Scalar::INFINITY, n)
+    }
+
+    /// Best-first: cells are opened in order of their boxes' distance from
+    /// `target`, until the closest remaining box cannot beat the results.
+    fn nearest_n_within_radius(
+        &self,
+        target: [Scalar; 3],
+        radius: Scalar,
+        n: usize,
+    ) -> Vec<(usize, Scalar)> {
+        if n == 0 || !is_searchable(target, radius) || self.cells.is_empty() {
+            return Vec::new();
+        }
+        let radius2 = radius * radius;
+        let box_dist2 = |cell: &Cell| {
+            (0..3)
+                .map(|a| {
+                    (cell.min[a] - target[a])
+                        .max(target[a] - cell.max[a])
+                        .max(0.0)
+                })
+                .map(|gap| gap * gap)
+                .sum::<Scalar>()
+        };
+        let mut best = Candidates::new(n);
+        // Min-heap on squared distance bits, via `Reverse`.
+        let mut open = BinaryHeap::new();
+        open.push(Reverse((box_dist2(&self.cells[0]).to_bits(), 0)));
+        while let Some(Reverse((bits, id))) = open.pop() {
+            if Scalar::from_bits(bits) > best.worst().min(radius2) {
+                break;
+            }
+            let cell = &self.cells[id];
+            let (first, last) = (cell.children.0 as usize, cell.children.1 as usize);
+            if first == last {
+                for slot in cell.start as usize..cell.end as usize {
+                    let d2 = dist2(self.points[slot], target);
+                    if d2 <= radius2 {
+                        best.offer(self.indices[slot] as usize, d2);
+                    }
+                }
+            } else {
+                for child in first..last {
+                    let d2 = box_dist2(&self.cells[child]);
+                    open.push(Reverse((d2.to_bits(), child)));
+                }
+            }
+        }
+        best.into_sorted()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::spatial::index::{assert_agrees_with_kd, scattered_points};
+
+    /// A dense core inside a sparse halo.
+    fn core_and_halo() -> Vec<[Scalar; 3]> {
+        let core = scattered_points(3_000, 5.0);
+        let halo = scattered_points(3_000, 500.0);
+        (0..3_000)
+            .map(|i| if i % 4 == 0 { halo[i] } else { core[i] })
+            .collect()
+    }
+
+    const SHALLOW: OctreeConfig = OctreeConfig {
+        max_depth: 2,
+        leaf_size: 4,
+    };
+
+    #[test]
+    fn octree_agrees_with_the_kd_tree() {
+        let points = core_and_halo();
+        let targets = [[0.0; 3], [2.5, 2.5, 2.5], [400.0, -20.0, 250.0]];
+        assert_agrees_with_kd(&Octree::build(&points), &points, &targets, 3.0);
+        assert_agrees_with_kd(
+            &Octree::with_config(&points, SHALLOW),
+            &points,
+            &targets,
+            3.0,
+        );
+    }
+
+    #[test]
+    fn depth_adapts_to_density_within_the_limit() {
+        let points = core_and_halo();
+        assert!(Octree::build(&points).depth() > 2);
+        assert_eq!(Octree::with_config(&points, SHALLOW).depth(), 2);
+    }
+
+    #[test]
+    fn duplicates_stop_splitting() {
+        // Rather than recursing to `max_depth`.
+        let same = Octree::build(&[[1.0; 3]; 20]);
+        assert_eq!(same.depth(), 0);
+        assert_eq!(same.nearest_n([0.0; 3], 3).len(), 3);
+    }
+}
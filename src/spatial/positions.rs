@@ -0,0 +1,126 @@
+use crate::Scalar;
+
+/// Positions stored as structure-of-arrays: every x, then every y, then
+/// every z. Distances from one point to all of them then run as a single
+/// pass over three contiguous slices, which the compiler turns into packed
+/// SIMD arithmetic on stable Rust without any intrinsics.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Positions {
+    xs: Vec<Scalar>,
+    ys: Vec<Scalar>,
+    zs: Vec<Scalar>,
+}
+
+impl Positions {
+    pub fn new(points: &[[Scalar; 3]]) -> Self {
+        Self::from_iter_exact(points.iter().copied(), points.len())
+    }
+
+    fn from_iter_exact(points: impl Iterator<Item = [Scalar; 3]>, len: usize) -> Self {
+        let mut positions = Positions {
+            xs: Vec::with_capacity(len),
+            ys: Vec::with_capacity(len),
+            zs: Vec::with_capacity(len),
+        };
+        for p in points {
+            positions.xs.push(p[0]);
+            positions.ys.push(p[1]);
+            positions.zs.push(p[2]);
+        }
+        positions
+    }
+
+    pub fn len(&self) -> usize {
+        self.xs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.xs.is_empty()
+    }
+
+    pub fn get(&self, index: usize) -> [Scalar; 3] {
+        [self.xs[index], self.ys[index], self.zs[index]]
+    }
+
+    /// Removes the point at `index`, keeping the others in order.
+    pub fn remove(&mut self, index: usize) -> [Scalar; 3] {
+        [
+            self.xs.remove(index),
+            self.ys.remove(index),
+            self.zs.remove(index),
+        ]
+    }
+
+    /// Fills `out` with the squared distance from `target` to each point,
+    /// in order.
+    pub fn squared_distances_to(&self, target: [Scalar; 3], out: &mut Vec<Scalar>) {
+        out.clear();
+        out.resize(self.len(), 0.0);
+        // Zipped slices leave no bounds checks and no dependency between
+        // iterations, so each step handles a full vector of points.
+        let points = self.xs.iter().zip(&self.ys).zip(&self.zs);
+        for (d2, ((&x, &y), &z)) in out.iter_mut().zip(points) {
+            let dx = x - target[0];
+            let dy = y - target[1];
+            let dz = z - target[2];
+            *d2 = dx * dx + dy * dy + dz * dz;
+        }
+    }
+
+    /// Fills `out` with the distance from `target` to each point, in order;
+    /// equal to `System::distance_to_point` bit for bit.
+    pub fn distances_to(&self, target: [Scalar; 3], out: &mut Vec<Scalar>) {
+        self.squared_distances_to(target, out);
+        for d in out.iter_mut() {
+            *d = d.sqrt();
+        }
+    }
+}
+
+impl FromIterator<[Scalar; 3]> for Positions {
+    fn from_iter<I: IntoIterator<Item = [Scalar; 3]>>(points: I) -> Self {
+        let points = points.into_iter();
+        let len = points.size_hint().0;
+        Self::from_iter_exact(points, len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::System;
+
+    #[test]
+    fn batch_distances_match_the_scalar_ones() {
+        let points: Vec<[Scalar; 3]> = (0..37)
+            .map(|i| {
+                let i = i as Scalar;
+                [(i * 7.3) % 11.0, -(i * 13.7) % 5.0, i * 1.0e3]
+            })
+            .collect();
+        let mut positions: Positions = points.iter().copied().collect();
+        let target = [1.5, -2.0, 40.0];
+        let mut out = Vec::new();
+        positions.distances_to(target, &mut out);
+        let expected: Vec<Scalar> = points
+            .iter()
+            .map(|&pos| {
+                let system = System {
+                    id: 0,
+                    name: String::new(),
+                    pos,
+                };
+                system.distance_to_point(target)
+            })
+            .collect();
+        assert_eq!(out, expected);
+
+        assert_eq!(positions.remove(3), points[3]);
+        assert_eq!(positions.get(3), points[4]);
+        positions.squared_distances_to(target, &mut out);
+        assert_eq!(out.len(), 36);
+        assert_eq!(out[3].sqrt(), expected[4]);
+        Positions::default().distances_to(target, &mut out);
+        assert!(out.is_empty());
+    }
+}
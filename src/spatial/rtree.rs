@@ -0,0 +1,263 @@
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+
+use crate::spatial::index::SpatialIndex;
+use crate::spatial::kd_tree::{dist2, Candidates};
+use crate::spatial::morton::bounds;
+use crate::spatial::shapes::Slab;
+use crate::validate::is_searchable;
+use crate::Scalar;
+
+/// Most entries per node.
+const NODE_CAPACITY: usize = 16;
+
+/// A bounding box over entries `start..end` of the level below: points for
+/// leaves, nodes otherwise.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct RNode {
+    min: [Scalar; 3],
+    max: [Scalar; 3],
+    start: u32,
+    end: u32,
+}
+
+/// R-tree bulk-loaded with Sort-Tile-Recursive packing: nodes are full and
+/// their boxes barely overlap, which suits box and region queries that the
+/// k-d tree only answers by testing one point per node. Static; rebuild it
+/// to change the points.
+#[derive(Clone, Debug)]
+pub struct RTree {
+    /// The points, reordered so each leaf's points are contiguous.
+    points: Vec<[Scalar; 3]>,
+    /// Source index of each entry of `points`.
+    indices: Vec<u32>,
+    /// Leaves first, then each level up to the root, which is last.
+    nodes: Vec<RNode>,
+    leaves: usize,
+}
+
+impl RTree {
+    pub fn build(points: &[[Scalar; 3]]) -> Self {
+        let order = tile(points, (0..points.len()).collect());
+        let mut tree = RTree {
+            points: order.iter().flatten().map(|&i| points[i]).collect(),
+            indices: order
+                .iter()
+                .flatten()
+                .map(|&i| u32::try_from(i).expect("fewer than 2^32 points"))
+                .collect(),
+            nodes: Vec::new(),
+            leaves: order.len(),
+        };
+        let mut level = Vec::with_capacity(order.len());
+        let mut start = 0;
+        for group in &order {
+            let (min, max) = bounds(tree.points[start..start + group.len()].iter().copied())
+                .expect("groups are not empty");
+            level.push(RNode {
+                min,
+                max,
+                start: start as u32,
+                end: (start + group.len()) as u32,
+            });
+            start += group.len();
+        }
+        // Each pass tiles one level into parents and appends it in the
+        // parents' order, so every parent's children are contiguous.
+        while level.len() > 1 {
+            let centers: Vec<[Scalar; 3]> = level
+                .iter()
+                .map(|n| [0, 1, 2].map(|a| (n.min[a] + n.max[a]) / 2.0))
+                .collect();
+            let mut parents = Vec::new();
+            for group in tile(&centers, (0..level.len()).collect()) {
+                let first = tree.nodes.len();
+                tree.nodes.extend(group.iter().map(|&i| level[i]));
+                let children = &tree.nodes[first..];
+                let min = [0, 1, 2].map(|a| {
+                    children
+                        .iter()
+                        .map(|n| n.min[a])
+                        .fold(Scalar::MAX, Scalar::min)
+                });
+                let max = [0, 1, 2].map(|a| {
+                    children
+                        .iter()
+                        .map(|n| n.max[a])
+                        .fold(Scalar::MIN, Scalar::max)
+                });
+                parents.push(RNode {
+                    min,
+                    max,
+                    start: first as u32,
+                    end: tree.nodes.len() as u32,
+                });
+            }
+            level = parents;
+        }
+        tree.nodes.extend(level);
+        tree
+    }
+
+    /// Indices of the points inside the box `lo..=hi`, ascending.
+    pub fn within_box(&self, lo: [Scalar; 3], hi: [Scalar; 3]) -> Vec<usize> {
+        self.within_region(
+            |min, max| (0..3).all(|a| min[a] <= hi[a] && max[a] >= lo[a]),
+            |p| (0..3).all(|a| (lo[a]..=hi[a]).contains(&p[a])),
+        )
+    }
+
+    /// Indices of the points inside the slab, ascending.
+    pub fn within_slab(&self, slab: &Slab) -> Vec<usize> {
+        self.within_region(
+            |min, max| slab.intersects_box(min, max),
+            |p| slab.contains(p),
+        )
+    }
+
+    /// Indices of the points of an arbitrary region, ascending. Nodes whose
+    /// box fails `intersects` are skipped; points are kept when `contains`
+    /// holds.
+    pub fn within_region<I, C>(&self, intersects: I, contains: C) -> Vec<usize>
+    where
+        I: Fn([Scalar; 3], [Scalar; 3]) -> bool,
+        C: Fn([Scalar; 3]) -> bool,
+    {
+        let mut results = Vec::new();
+        let mut stack: Vec<usize> = self.nodes.len().checked_sub(1).into_iter().collect();
+        while let Some(id) = stack.pop() {
+            let node = self.nodes[id];
+            if !intersects(node.min, node.max) {
+                continue;
+            }
+            let range = node.start as usize..node.end as usize;
+            if id < self.leaves {
+                results.extend(
+                    range
+                        .filter(|&slot| contains(self.points[slot]))
+                        .map(|slot| self.indices[slot] as usize),
+                );
+            } else {
+                stack.extend(range);
+            }
+        }
+        results.sort_unstable();
+        results
+    }
+}
+
+impl SpatialIndex for RTree {
+    fn len(&self) -> usize {
+        self.points.len()
+    }
+
+    fn nearest_one(&self, target: [Scalar; 3]) -> Option<(usize, Scalar)> {
+        self.nearest_n(target, 1).pop()
+    }
+
+    fn nearest_n(&self, target: [Scalar; 3], n: usize) -> Vec<(usize, Scalar)> {
+        self.nearest_n_within_radius(target, Scalar::INFINITY, n)
+    }
+
+    /// Best-first: nodes are opened in order of their boxes' distance from
+    /// `target`, until the closest remaining box cannot beat the results.
+    fn nearest_n_within_radius(
+        &self,
+        target: [Scalar; 3],
+        radius: Scalar,
+        n: usize,
+    ) -> Vec<(usize, Scalar)> {
+        if n == 0 || !is_searchable(target, radius) || self.nodes.is_empty() {
+            return Vec::new();
+        }
+        let radius2 = radius * radius;
+        let box_dist2 = |node: &RNode| {
+            (0..3)
+                .map(|a| {
+                    (node.min[a] - target[a])
+                        .max(target[a] - node.max[a])
+                        .max(0.0)
+                })
+                .map(|gap| gap * gap)
+                .sum::<Scalar>()
+        };
+        let mut best = Candidates::new(n);
+        // Min-heap on squared distance bits, via `Reverse`.
+        let mut open = BinaryHeap::new();
+        let root = self.nodes.len() - 1;
+        open.push(Reverse((box_dist2(&self.nodes[root]).to_bits(), root)));
+        while let Some(Reverse((bits, id))) = open.pop() {
+            if Scalar::from_bits(bits) > best.worst().min(radius2) {
+                break;
+            }
+            let node = &self.nodes[id];
+            let range = node.start as usize..node.end as usize;
+            if id < self.leaves {
+                for slot in range {
+                    let d2 = dist2(self.points[slot], target);
+                    if d2 <= radius2 {
+                        best.offer(self.indices[slot] as usize, d2);
+                    }
+                }
+            } else {
+                for child in range {
+                    let d2 = box_dist2(&self.nodes[child]);
+                    open.push(Reverse((d2.to_bits(), child)));
+                }
+            }
+        }
+        best.into_sorted()
+    }
+}
+
+/// Sort-Tile-Recursive grouping of `entries` (indices into `centers`) into
+/// runs of at most `NODE_CAPACITY`: slabs along x, strips along y within
+/// each slab, then runs along z within each strip.
+fn tile(centers: &[[Scalar; 3]], mut entries: Vec<usize>) -> Vec<Vec<usize>> {
+    let groups = entries.len().div_ceil(NODE_CAPACITY);
+    let per_axis = (groups as f64).cbrt().ceil().max(1.0) as usize;
+    let by =
+        |axis: usize| move |a: &usize, b: &usize| centers[*a][axis].total_cmp(&centers[*b][axis]);
+    let mut result = Vec::with_capacity(groups);
+    entries.sort_by(by(0));
+    for slab in entries.chunks_mut(NODE_CAPACITY * per_axis * per_axis) {
+        slab.sort_by(by(1));
+        for strip in slab.chunks_mut(NODE_CAPACITY * per_axis) {
+            strip.sort_by(by(2));
+            result.extend(strip.chunks(NODE_CAPACITY).map(<[usize]>::to_vec));
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::spatial::index::{assert_agrees_with_kd, scattered_points};
+    use crate::spatial::kd_tree::KDTree;
+
+    #[test]
+    fn rtree_agrees_with_the_kd_tree() {
+        let points = scattered_points(3_000, 90.0);
+        let targets = [[0.0; 3], [45.0, 45.0, 45.0], [200.0, -5.0, 30.0]];
+        assert_agrees_with_kd(&RTree::build(&points), &points, &targets, 5.0);
+    }
+
+    #[test]
+    fn region_queries_match_a_scan() {
+        let points = scattered_points(3_000, 90.0);
+        let tree = RTree::build(&points);
+        let (lo, hi) = ([10.0, 20.0, 0.0], [30.0, 45.0, 60.0]);
+        let expected: Vec<usize> = (0..points.len())
+            .filter(|&i| (0..3).all(|a| (lo[a]..=hi[a]).contains(&points[i][a])))
+            .collect();
+        assert_eq!(tree.within_box(lo, hi), expected);
+        assert!(RTree::build(&[]).within_box(lo, hi).is_empty());
+
+        let slab = Slab::centered_at([45.0; 3], [1.0, 1.0, 0.0], 4.0);
+        assert_eq!(
+            tree.within_slab(&slab),
+            KDTree::build(&points).within_slab(&slab)
+        );
+    }
+}
@@ -0,0 +1,185 @@
+use crate::spatial::kd_tree::KDTree;
+use crate::spatial::morton::MortonIndex;
+use crate::spatial::shapes::distance_to_segment;
+use crate::Scalar;
+
+/// Detours tried around each blocking system, evenly spaced around the leg.
+const DETOUR_DIRECTIONS: usize = 8;
+/// Only the shortest few clear detours are explored, which keeps the search
+/// from growing exponentially in `max_depth` too quickly.
+const DETOURS_TRIED: usize = 3;
+/// How far past the margin a detour waypoint is placed.
+const DETOUR_CLEARANCE: Scalar = 1.5;
+
+/// Plans a polyline from `start` to `end` through free space that stays at
+/// least `margin` away from every point in `danger`, for cloaked travel
+/// where only proximity to hostile systems matters. Waypoints are arbitrary
+/// coordinates, not systems, and include both ends.
+///
+/// Each leg that passes too close to a dangerous system is split at a
+/// detour waypoint beside it, choosing the shortest clear detour, down to
+/// `max_depth` levels. Returns `None` if either end lies inside the margin
+/// or no corridor is found within that depth.
+pub fn plan_safe_corridor(
+    start: [Scalar; 3],
+    end: [Scalar; 3],
+    danger: &[[Scalar; 3]],
+    margin: Scalar,
+    max_depth: usize,
+) -> Option<Vec<[Scalar; 3]>> {
+    let planner = Planner {
+        kd: KDTree::build(danger),
+        morton: MortonIndex::build(danger),
+        danger,
+        margin,
+    };
+    if !planner.is_clear(start) || !planner.is_clear(end) {
+        return None;
+    }
+    let mut waypoints = vec![start];
+    planner.leg(start, end, max_depth, &mut waypoints)?;
+    Some(waypoints)
+}
+
+struct Planner<'a> {
+    kd: KDTree,
+    /// Long legs are checked through box prefilters rather than a sphere
+    /// around the whole leg.
+    morton: MortonIndex,
+    danger: &'a [[Scalar; 3]],
+    margin: Scalar,
+}
+
+impl Planner<'_> {
+    fn is_clear(&self, point: [Scalar; 3]) -> bool {
+        self.kd
+            .nearest_one(point)
+            .is_none_or(|(_, dist)| dist >= self.margin)
+    }
+
+    /// The dangerous point that comes closest to the leg, if any is inside
+    /// the margin.
+    fn worst_violation(&self, a: [Scalar; 3], b: [Scalar; 3]) -> Option<[Scalar; 3]> {
+        self.morton
+            .near_segment(a, b, self.margin)
+            .into_iter()
+            .map(|i| (i, distance_to_segment(self.danger[i], a, b)))
+            .filter(|&(_, d)| d < self.margin)
+            .min_by(|x, y| x.1.total_cmp(&y.1))
+            .map(|(i, _)| self.danger[i])
+    }
+
+    /// Appends the waypoints after `a` up to and including `b`.
+    fn leg(
+        &self,
+        a: [Scalar; 3],
+        b: [Scalar; 3],
+        depth: usize,
+        out: &mut Vec<[Scalar; 3]>,
+    ) -> Option<()> {
+        let Some(blocker) = self.worst_violation(a, b) else {
+            out.push(b);
+            return Some(());
+        };
+        if depth == 0 {
+            return None;
+        }
+
+        // Two unit vectors perpendicular to the leg, the first pointing from
+        // the leg towards the blocker when they are not aligned.
+        let axis = normalize(sub(b, a))?;
+        let away = sub(blocker, a);
+        let mut u = sub(away, scale(axis, dot(away, axis)));
+        if length(u) < 1e-6 {
+            u = if axis[0].abs() < 0.9 {
+                cross(axis, [1.0, 0.0, 0.0])
+            } else {
+                cross(axis, [0.0, 1.0, 0.0])
+            };
+        }
+        let u = normalize(u)?;
+        let v = cross(axis, u);
+
+        let offset = self.margin * DETOUR_CLEARANCE;
+        let mut detours: Vec<[Scalar; 3]> = (0..DETOUR_DIRECTIONS)
+            .map(|k| {
+                let angle =
+                    std::f64::consts::TAU as Scalar * k as Scalar / DETOUR_DIRECTIONS as Scalar;
+                let dir = add(scale(u, angle.cos()), scale(v, angle.sin()));
+                add(blocker, scale(dir, offset))
+            })
+            .filter(|&w| self.is_clear(w))
+            .collect();
+        detours.sort_by(|&w1, &w2| {
+            let len = |w| length(sub(w, a)) + length(sub(b, w));
+            len(w1).total_cmp(&len(w2))
+        });
+        for w in detours.into_iter().take(DETOURS_TRIED) {
+            let mark = out.len();
+            if self.leg(a, w, depth - 1, out).is_some() && self.leg(w, b, depth - 1, out).is_some()
+            {
+                return Some(());
+            }
+            out.truncate(mark);
+        }
+        None
+    }
+}
+
+fn add(a: [Scalar; 3], b: [Scalar; 3]) -> [Scalar; 3] {
+    [a[0] + b[0], a[1] + b[1], a[2] + b[2]]
+}
+
+fn sub(a: [Scalar; 3], b: [Scalar; 3]) -> [Scalar; 3] {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+fn scale(a: [Scalar; 3], s: Scalar) -> [Scalar; 3] {
+    [a[0] * s, a[1] * s, a[2] * s]
+}
+
+fn dot(a: [Scalar; 3], b: [Scalar; 3]) -> Scalar {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn cross(a: [Scalar; 3], b: [Scalar; 3]) -> [Scalar; 3] {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+fn length(a: [Scalar; 3]) -> Scalar {
+    dot(a, a).sqrt()
+}
+
+fn normalize(a: [Scalar; 3]) -> Option<[Scalar; 3]> {
+    let len = length(a);
+    (len > 0.0).then(|| scale(a, 1.0 / len))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn corridor_bends_around_danger() {
+        let danger = [[5.0, 0.0, 0.0], [5.0, 3.0, 0.0], [12.0, 0.0, 0.0]];
+        let path = plan_safe_corridor([0.0; 3], [20.0, 0.0, 0.0], &danger, 1.0, 6).unwrap();
+        assert_eq!(path.first(), Some(&[0.0; 3]));
+        assert_eq!(path.last(), Some(&[20.0, 0.0, 0.0]));
+        assert!(path.len() > 2);
+        for leg in path.windows(2) {
+            for &d in &danger {
+                assert!(distance_to_segment(d, leg[0], leg[1]) >= 1.0 - 1e-4);
+            }
+        }
+
+        // An end inside the margin cannot be reached.
+        assert_eq!(
+            plan_safe_corridor([0.0; 3], [5.5, 0.0, 0.0], &danger, 1.0, 6),
+            None
+        );
+    }
+}
@@ -0,0 +1,156 @@
+use serde::{Deserialize, Serialize};
+
+use crate::Scalar;
+
+/// Cone with its tip at `apex`, opening along `direction` by `half_angle`
+/// radians and truncated at `max_range` from the apex.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Cone {
+    pub apex: [Scalar; 3],
+    pub direction: [Scalar; 3],
+    pub half_angle: Scalar,
+    pub max_range: Scalar,
+}
+
+impl Cone {
+    pub fn new(
+        apex: [Scalar; 3],
+        direction: [Scalar; 3],
+        half_angle: Scalar,
+        max_range: Scalar,
+    ) -> Self {
+        Cone {
+            apex,
+            direction: normalize(direction),
+            half_angle,
+            max_range,
+        }
+    }
+
+    /// Returns true if `point` lies inside the cone. The apex itself is
+    /// always contained.
+    pub fn contains(&self, point: [Scalar; 3]) -> bool {
+        let offset = sub(point, self.apex);
+        let dist = length(offset);
+        if dist > self.max_range {
+            return false;
+        }
+        if dist == 0.0 {
+            return true;
+        }
+        let cos_angle = dot(offset, self.direction) / dist;
+        cos_angle >= self.half_angle.cos()
+    }
+}
+
+/// Region between two parallel planes sharing `normal`. A point `p` is inside
+/// when `min_offset <= dot(normal, p) <= max_offset`, with `normal` stored as a
+/// unit vector.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Slab {
+    pub normal: [Scalar; 3],
+    pub min_offset: Scalar,
+    pub max_offset: Scalar,
+}
+
+impl Slab {
+    pub fn new(normal: [Scalar; 3], min_offset: Scalar, max_offset: Scalar) -> Self {
+        Slab {
+            normal: normalize(normal),
+            min_offset: min_offset.min(max_offset),
+            max_offset: min_offset.max(max_offset),
+        }
+    }
+
+    /// Slab perpendicular to `normal` through `point` with the given total thickness.
+    pub fn centered_at(point: [Scalar; 3], normal: [Scalar; 3], thickness: Scalar) -> Self {
+        let unit = normalize(normal);
+        let center = dot(unit, point);
+        let half = thickness.abs() / 2.0;
+        Slab::new(unit, center - half, center + half)
+    }
+
+    pub fn offset_of(&self, point: [Scalar; 3]) -> Scalar {
+        dot(self.normal, point)
+    }
+
+    pub fn contains(&self, point: [Scalar; 3]) -> bool {
+        let offset = self.offset_of(point);
+        offset >= self.min_offset && offset <= self.max_offset
+    }
+
+    /// Returns true if any point of the axis-aligned box `[min, max]` could lie
+    /// inside the slab. Unbounded box sides are allowed.
+    pub fn intersects_box(&self, min: [Scalar; 3], max: [Scalar; 3]) -> bool {
+        let mut lo: Scalar = 0.0;
+        let mut hi: Scalar = 0.0;
+        for axis in 0..3 {
+            let n = self.normal[axis];
+            if n > 0.0 {
+                lo += n * min[axis];
+                hi += n * max[axis];
+            } else if n < 0.0 {
+                lo += n * max[axis];
+                hi += n * min[axis];
+            }
+        }
+        hi >= self.min_offset && lo <= self.max_offset
+    }
+}
+
+/// Shortest distance from `point` to the segment between `a` and `b`.
+pub fn distance_to_segment(point: [Scalar; 3], a: [Scalar; 3], b: [Scalar; 3]) -> Scalar {
+    let ab = sub(b, a);
+    let ap = sub(point, a);
+    let len2 = dot(ab, ab);
+    let t = if len2 == 0.0 {
+        0.0
+    } else {
+        (dot(ap, ab) / len2).clamp(0.0, 1.0)
+    };
+    let closest = [a[0] + ab[0] * t, a[1] + ab[1] * t, a[2] + ab[2] * t];
+    length(sub(point, closest))
+}
+
+fn sub(a: [Scalar; 3], b: [Scalar; 3]) -> [Scalar; 3] {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+fn dot(a: [Scalar; 3], b: [Scalar; 3]) -> Scalar {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn length(a: [Scalar; 3]) -> Scalar {
+    dot(a, a).sqrt()
+}
+
+fn normalize(a: [Scalar; 3]) -> [Scalar; 3] {
+    let len = length(a);
+    if len == 0.0 {
+        return a;
+    }
+    [a[0] / len, a[1] / len, a[2] / len]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cone_and_slab_membership() {
+        let cone = Cone::new([0.0, 0.0, 0.0], [2.0, 0.0, 0.0], 0.5, 10.0);
+        assert!(cone.contains([5.0, 1.0, 0.0]));
+        assert!(!cone.contains([0.0, 5.0, 0.0]));
+        assert!(!cone.contains([11.0, 0.0, 0.0]));
+
+        let slab = Slab::centered_at([0.0, 0.0, 1.0], [0.0, 0.0, 1.0], 2.0);
+        assert!(slab.contains([10.0, -3.0, 1.5]));
+        assert!(!slab.contains([0.0, 0.0, 2.5]));
+        assert!(!slab.intersects_box([0.0, 0.0, 3.0], [1.0, 1.0, Scalar::INFINITY]));
+
+        let a = [0.0, 0.0, 0.0];
+        let b = [10.0, 0.0, 0.0];
+        assert_eq!(distance_to_segment([5.0, 3.0, 0.0], a, b), 3.0);
+        assert_eq!(distance_to_segment([-4.0, 3.0, 0.0], a, b), 5.0);
+    }
+}
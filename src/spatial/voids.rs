@@ -0,0 +1,145 @@
+use serde::Serialize;
+
+use crate::spatial::kd_tree::KDTree;
+use crate::Scalar;
+
+/// Local refinement steps per candidate void.
+const REFINE_STEPS: usize = 24;
+
+/// An empty ball inside the map: no system lies closer to `center` than
+/// `radius`.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Void {
+    pub center: [Scalar; 3],
+    pub radius: Scalar,
+    /// Index of a system on the void's boundary.
+    pub nearest: usize,
+}
+
+/// Finds up to `count` of the largest empty balls inside the bounding box of
+/// `points`, largest first. Candidate centres start on a `resolution`³ grid
+/// and climb towards the spot farthest from any system; balls are kept inside
+/// the box so the open space around the map does not count. Results do not
+/// contain each other's centres.
+///
+/// Unusually large voids in a dense region often mean coordinates were
+/// ingested with the wrong scale or axis order.
+pub fn find_voids(
+    points: &[[Scalar; 3]],
+    kd: &KDTree,
+    count: usize,
+    resolution: usize,
+) -> Vec<Void> {
+    if points.is_empty() || count == 0 {
+        return Vec::new();
+    }
+    let mut min = [Scalar::INFINITY; 3];
+    let mut max = [Scalar::NEG_INFINITY; 3];
+    for p in points {
+        for axis in 0..3 {
+            min[axis] = min[axis].min(p[axis]);
+            max[axis] = max[axis].max(p[axis]);
+        }
+    }
+    let resolution = resolution.max(1);
+    let cell = [0, 1, 2].map(|axis| (max[axis] - min[axis]) / resolution as Scalar);
+    let clearance = |c: [Scalar; 3]| -> Option<(usize, Scalar)> {
+        let (nearest, dist) = kd.nearest_one(c)?;
+        let wall = (0..3)
+            .map(|axis| (c[axis] - min[axis]).min(max[axis] - c[axis]))
+            .fold(Scalar::INFINITY, Scalar::min);
+        Some((nearest, dist.min(wall)))
+    };
+
+    let mut candidates = Vec::new();
+    for i in 0..resolution {
+        for j in 0..resolution {
+            for k in 0..resolution {
+                let mut center = [i, j, k].map(|n| n as Scalar + 0.5);
+                for axis in 0..3 {
+                    center[axis] = min[axis] + center[axis] * cell[axis];
+                }
+                let Some((mut nearest, mut radius)) = clearance(center) else {
+                    continue;
+                };
+                // Coordinate-wise hill climb with a shrinking step.
+                let mut step = cell.iter().copied().fold(0.0, Scalar::max) / 2.0;
+                for _ in 0..REFINE_STEPS {
+                    let mut improved = false;
+                    for axis in 0..3 {
+                        for sign in [-1.0, 1.0] {
+                            let mut next = center;
+                            next[axis] += sign * step;
+                            if let Some((n, r)) = clearance(next) {
+                                if r > radius {
+                                    (center, nearest, radius) = (next, n, r);
+                                    improved = true;
+                                }
+                            }
+                        }
+                    }
+                    if !improved {
+                        step /= 2.0;
+                    }
+                }
+                if radius > 0.0 {
+                    candidates.push(Void {
+                        center,
+                        radius,
+                        nearest,
+                    });
+                }
+            }
+        }
+    }
+
+    candidates.sort_by(|a, b| b.radius.total_cmp(&a.radius));
+    let mut voids: Vec<Void> = Vec::new();
+    for candidate in candidates {
+        let overlaps = voids
+            .iter()
+            .any(|v| distance(v.center, candidate.center) < v.radius.max(candidate.radius));
+        if !overlaps {
+            voids.push(candidate);
+            if voids.len() == count {
+                break;
+            }
+        }
+    }
+    voids
+}
+
+fn distance(a: [Scalar; 3], b: [Scalar; 3]) -> Scalar {
+    let dx = a[0] - b[0];
+    let dy = a[1] - b[1];
+    let dz = a[2] - b[2];
+    (dx * dx + dy * dy + dz * dz).sqrt()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_the_hole_in_a_filled_cube() {
+        // Points on a 0..=10 lattice with everything within 3 of the middle
+        // removed.
+        let mut points = Vec::new();
+        for x in 0..=10 {
+            for y in 0..=10 {
+                for z in 0..=10 {
+                    let p = [x as Scalar, y as Scalar, z as Scalar];
+                    if distance(p, [5.0, 5.0, 5.0]) > 3.0 {
+                        points.push(p);
+                    }
+                }
+            }
+        }
+        let kd = KDTree::build(&points);
+        let voids = find_voids(&points, &kd, 2, 4);
+        let largest = &voids[0];
+        assert!(distance(largest.center, [5.0, 5.0, 5.0]) < 0.5);
+        assert!(largest.radius > 3.0);
+        assert!(voids[1..].iter().all(|v| v.radius < 1.0));
+    }
+}
@@ -0,0 +1,115 @@
+use std::cell::Cell;
+use std::time::Instant;
+
+use serde::Serialize;
+
+use crate::Scalar;
+
+thread_local! {
+    static NODES_EXPANDED: Cell<u64> = const { Cell::new(0) };
+    static KD_NODES_VISITED: Cell<u64> = const { Cell::new(0) };
+    static CACHE_HIT: Cell<Option<bool>> = const { Cell::new(None) };
+    static FRONTIER_PEAK: Cell<u64> = const { Cell::new(0) };
+    static HEURISTIC: Cell<Option<(Scalar, Scalar)>> = const { Cell::new(None) };
+}
+
+/// Counts one node taken off a graph search frontier.
+pub(crate) fn count_expanded() {
+    NODES_EXPANDED.with(|c| c.set(c.get() + 1));
+}
+
+/// Counts one k-d tree node visited by a spatial query.
+pub(crate) fn count_kd_visit() {
+    KD_NODES_VISITED.with(|c| c.set(c.get() + 1));
+}
+
+/// Records the current size of a graph search frontier, keeping the peak.
+pub(crate) fn record_frontier(len: usize) {
+    FRONTIER_PEAK.with(|c| c.set(c.get().max(len as u64)));
+}
+
+/// Records a search's heuristic estimate at the start against the cost of
+/// the route it found. The last search of a query wins.
+pub(crate) fn record_heuristic(estimate: Scalar, cost: Scalar) {
+    HEURISTIC.with(|c| c.set(Some((estimate, cost))));
+}
+
+/// Records whether a cache answered the current query.
+pub(crate) fn record_cache_hit(hit: bool) {
+    CACHE_HIT.with(|c| c.set(Some(hit)));
+}
+
+/// Wall time spent in one phase of a query.
+#[derive(Clone, Debug, Serialize, PartialEq)]
+pub struct PhaseTime {
+    pub phase: &'static str,
+    pub micros: u64,
+}
+
+/// Execution metrics attached to a response when `debug_stats` is set.
+#[derive(Clone, Debug, Default, Serialize, PartialEq)]
+pub struct QueryStats {
+    pub nodes_expanded: u64,
+    pub kd_nodes_visited: u64,
+    /// Largest number of entries a graph search frontier held at once.
+    pub frontier_peak: u64,
+    /// Start-to-goal heuristic estimate over the cost of the route found:
+    /// 1 is a perfect estimate, lower values mean more wasted expansions.
+    /// `None` without a heuristic search or for a zero-cost route.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub heuristic_tightness: Option<Scalar>,
+    /// Whether the answer came from a cache; `None` when none was consulted.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cache_hit: Option<bool>,
+    pub phases: Vec<PhaseTime>,
+    pub total_micros: u64,
+}
+
+/// Collects `QueryStats` for work done on the current thread. Requests are
+/// handled synchronously on one thread, so the counters are thread-local and
+/// cost a plain increment when nobody is watching.
+pub struct StatsRecorder {
+    started: Instant,
+    phase_started: Instant,
+    stats: QueryStats,
+}
+
+impl StatsRecorder {
+    /// Resets the thread's counters and starts the clock.
+    pub fn start() -> Self {
+        NODES_EXPANDED.with(|c| c.set(0));
+        KD_NODES_VISITED.with(|c| c.set(0));
+        CACHE_HIT.with(|c| c.set(None));
+        FRONTIER_PEAK.with(|c| c.set(0));
+        HEURISTIC.with(|c| c.set(None));
+        let now = Instant::now();
+        StatsRecorder {
+            started: now,
+            phase_started: now,
+            stats: QueryStats::default(),
+        }
+    }
+
+    /// Closes the current phase under `phase` and starts the next one.
+    pub fn phase(&mut self, phase: &'static str) {
+        let now = Instant::now();
+        self.stats.phases.push(PhaseTime {
+            phase,
+            micros: (now - self.phase_started).as_micros() as u64,
+        });
+        self.phase_started = now;
+    }
+
+    pub fn finish(mut self) -> QueryStats {
+        self.stats.nodes_expanded = NODES_EXPANDED.with(Cell::get);
+        self.stats.kd_nodes_visited = KD_NODES_VISITED.with(Cell::get);
+        self.stats.frontier_peak = FRONTIER_PEAK.with(Cell::get);
+        self.stats.heuristic_tightness = HEURISTIC
+            .with(Cell::get)
+            .filter(|&(_, cost)| cost > 0.0)
+            .map(|(estimate, cost)| estimate / cost);
+        self.stats.cache_hit = CACHE_HIT.with(Cell::get);
+        self.stats.total_micros = self.started.elapsed().as_micros() as u64;
+        self.stats
+    }
+}
@@ -1,4 +1,11 @@
+use std::collections::HashSet;
+
+use serde::{Deserialize, Serialize};
+
 use crate::graph::graph::StarGraph;
+use crate::spatial::positions::Positions;
+use crate::validate::is_searchable;
+use crate::Scalar;
 
 /// Greedy sweep: starting from the closest node to `center` within `radius`,
 /// repeatedly visit the nearest unvisited node within that radius.
@@ -6,62 +13,291 @@ use crate::graph::graph::StarGraph;
 /// Returns (ordered_indices, total_distance).
 pub fn greedy_sweep_within_radius(
     graph: &StarGraph,
-    center: [f32; 3],
-    radius: f32,
-) -> (Vec<usize>, f32) {
-    let mut candidates: Vec<usize> = graph
-        .systems
+    center: [Scalar; 3],
+    radius: Scalar,
+) -> (Vec<usize>, Scalar) {
+    let (path, total_distance, _) = greedy_sweep_avoiding(graph, center, radius, &HashSet::new());
+    (path, total_distance)
+}
+
+/// Greedy sweep that leaves out the systems in `avoid`, e.g. hostile or
+/// depleted ones.
+///
+/// Returns (ordered_indices, total_distance, avoided_indices), where the
+/// avoided indices are those inside the radius that were skipped on request.
+/// A NaN or negative radius, or a non-finite center, matches nothing.
+pub fn greedy_sweep_avoiding(
+    graph: &StarGraph,
+    center: [Scalar; 3],
+    radius: Scalar,
+    avoid: &HashSet<usize>,
+) -> (Vec<usize>, Scalar, Vec<usize>) {
+    sweep_with_objective(graph, center, radius, avoid, SweepObjective::TotalDistance)
+}
+
+/// What a sweep's visiting order optimizes.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SweepObjective {
+    /// Short total distance: greedy nearest neighbour.
+    #[default]
+    TotalDistance,
+    /// Short longest single leg, for ships whose jump or scan range caps
+    /// each leg (bottleneck TSP heuristic).
+    LongestLeg,
+}
+
+/// Most systems whose interior order is refined with 2-opt moves when the
+/// last system is pinned; each pass is quadratic in the tour length.
+const PINNED_TWO_OPT_LIMIT: usize = 1_000;
+
+/// Systems a sweep must start or end at, e.g. the current location and a
+/// pickup point. Pinned systems are visited even when they lie outside the
+/// radius or are avoided.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SweepEnds {
+    pub first: Option<usize>,
+    pub last: Option<usize>,
+}
+
+/// Sweep like `greedy_sweep_avoiding`, ordering the systems for `objective`.
+/// Both orders start at the system closest to `center`.
+pub fn sweep_with_objective(
+    graph: &StarGraph,
+    center: [Scalar; 3],
+    radius: Scalar,
+    avoid: &HashSet<usize>,
+    objective: SweepObjective,
+) -> (Vec<usize>, Scalar, Vec<usize>) {
+    sweep_with_ends(
+        graph,
+        center,
+        radius,
+        avoid,
+        objective,
+        SweepEnds::default(),
+    )
+}
+
+/// Sweep like `sweep_with_objective` that starts and ends at the systems
+/// pinned in `ends`, ordering only the systems in between.
+pub fn sweep_with_ends(
+    graph: &StarGraph,
+    center: [Scalar; 3],
+    radius: Scalar,
+    avoid: &HashSet<usize>,
+    objective: SweepObjective,
+    ends: SweepEnds,
+) -> (Vec<usize>, Scalar, Vec<usize>) {
+    if !is_searchable(center, radius) {
+        return (Vec::new(), 0.0, Vec::new());
+    }
+    let mut dist2 = Vec::new();
+    graph.positions().squared_distances_to(center, &mut dist2);
+    let (mut candidates, avoided): (Vec<usize>, Vec<usize>) = (0..dist2.len())
+        .filter(|&idx| dist2[idx] <= radius * radius)
+        .partition(|idx| !avoid.contains(idx));
+    let pinned = |idx: &usize| Some(*idx) == ends.first || Some(*idx) == ends.last;
+    let avoided: Vec<usize> = avoided.into_iter().filter(|idx| !pinned(idx)).collect();
+    candidates.retain(|idx| !pinned(idx));
+
+    if candidates.is_empty() && ends == SweepEnds::default() {
+        return (Vec::new(), 0.0, avoided);
+    }
+
+    // Start at candidate closest to center
+    candidates.sort_by(|&a, &b| dist2[a].sqrt().total_cmp(&dist2[b].sqrt()));
+    if let Some(first) = ends.first {
+        candidates.insert(0, first);
+    }
+    let (path, total_distance) = order_tour(graph, candidates, objective, ends.last);
+    (path, total_distance, avoided)
+}
+
+/// Orders exactly the systems in `systems` into a tour for `objective`,
+/// e.g. "visit these 14 systems", with no radius involved. The tour starts
+/// at `ends.first`, or the first listed system when that is unpinned, and
+/// ends at `ends.last` when pinned; pinned systems need not be listed.
+/// Repeated systems are visited once.
+///
+/// Returns (ordered_indices, total_distance).
+pub fn sweep_systems(
+    graph: &StarGraph,
+    systems: &[usize],
+    objective: SweepObjective,
+    ends: SweepEnds,
+) -> (Vec<usize>, Scalar) {
+    let mut seen = HashSet::new();
+    let mut candidates: Vec<usize> = systems
         .iter()
-        .enumerate()
-        .filter_map(|(idx, s)| {
-            let dx = s.pos[0] - center[0];
-            let dy = s.pos[1] - center[1];
-            let dz = s.pos[2] - center[2];
-            let dist2 = dx * dx + dy * dy + dz * dz;
-            if dist2 <= radius * radius {
-                Some(idx)
-            } else {
-                None
-            }
-        })
+        .copied()
+        .filter(|&idx| Some(idx) != ends.first && Some(idx) != ends.last && seen.insert(idx))
         .collect();
+    if let Some(first) = ends.first {
+        candidates.insert(0, first);
+    }
+    order_tour(graph, candidates, objective, ends.last)
+}
 
-    if candidates.is_empty() {
-        return (Vec::new(), 0.0);
+/// Orders `candidates` from `candidates[0]`, appending `last` when pinned.
+fn order_tour(
+    graph: &StarGraph,
+    candidates: Vec<usize>,
+    objective: SweepObjective,
+    last: Option<usize>,
+) -> (Vec<usize>, Scalar) {
+    let with_last = |mut path: Vec<usize>| {
+        path.extend(last);
+        path
+    };
+    let mut path = match objective {
+        SweepObjective::TotalDistance => with_last(nearest_neighbour_order(graph, candidates)),
+        SweepObjective::LongestLeg => {
+            let greedy = with_last(nearest_neighbour_order(graph, candidates.clone()));
+            let tree = with_last(tree_order(graph, candidates));
+            if longest_leg(graph, &tree) < longest_leg(graph, &greedy) {
+                tree
+            } else {
+                greedy
+            }
+        }
+    };
+    // Greedy orders ignore where the tour has to end, so straighten the
+    // interior out towards the pinned last system.
+    if objective == SweepObjective::TotalDistance
+        && last.is_some()
+        && path.len() <= PINNED_TWO_OPT_LIMIT
+    {
+        two_opt_interior(graph, &mut path);
     }
+    let total_distance = path
+        .windows(2)
+        .map(|w| graph.systems[w[0]].distance(&graph.systems[w[1]]))
+        .sum();
+    (path, total_distance)
+}
 
-    // Start at candidate closest to center
-    candidates.sort_by(|&a, &b| {
-        let da = graph.systems[a].distance_to_point(center);
-        let db = graph.systems[b].distance_to_point(center);
-        da.partial_cmp(&db).unwrap()
-    });
+/// Length of the longest leg of a sweep order.
+pub fn longest_leg(graph: &StarGraph, path: &[usize]) -> Scalar {
+    path.windows(2)
+        .map(|w| graph.systems[w[0]].distance(&graph.systems[w[1]]))
+        .fold(0.0, Scalar::max)
+}
 
-    let mut path = Vec::new();
-    let mut total_distance = 0.0_f32;
+/// Reverses stretches strictly between the first and last system while that
+/// shortens the tour, keeping both ends in place.
+fn two_opt_interior(graph: &StarGraph, path: &mut [usize]) {
+    let dist = |a: usize, b: usize| graph.systems[a].distance(&graph.systems[b]);
+    let mut improved = true;
+    while improved {
+        improved = false;
+        for i in 1..path.len().saturating_sub(2) {
+            for j in i + 1..path.len() - 1 {
+                // Reversing path[i..=j] swaps legs (i-1, i) and (j, j+1).
+                let before = dist(path[i - 1], path[i]) + dist(path[j], path[j + 1]);
+                let after = dist(path[i - 1], path[j]) + dist(path[i], path[j + 1]);
+                if after < before - 1e-6 * before {
+                    path[i..=j].reverse();
+                    improved = true;
+                }
+            }
+        }
+    }
+}
 
-    let mut current = candidates.remove(0);
-    path.push(current);
+/// From `candidates[0]`, repeatedly visits the nearest unvisited candidate.
+fn nearest_neighbour_order(graph: &StarGraph, mut candidates: Vec<usize>) -> Vec<usize> {
+    let mut path = Vec::with_capacity(candidates.len());
+    if candidates.is_empty() {
+        return path;
+    }
+    let mut positions: Positions = candidates
+        .iter()
+        .map(|&idx| graph.systems[idx].pos)
+        .collect();
+    let mut here = positions.remove(0);
+    path.push(candidates.remove(0));
 
+    let mut distances = Vec::with_capacity(candidates.len());
     while !candidates.is_empty() {
-        let (next_idx, next_pos) = candidates
+        positions.distances_to(here, &mut distances);
+        let (next_idx, _) = distances
             .iter()
             .enumerate()
-            .map(|(i, &idx)| (i, graph.systems[idx].distance(&graph.systems[current])))
-            .min_by(|a, b| a.1.partial_cmp(&b.1).unwrap())
+            .min_by(|a, b| a.1.total_cmp(b.1))
             .unwrap();
 
-        total_distance += next_pos;
-        current = candidates.remove(next_idx);
-        path.push(current);
+        here = positions.remove(next_idx);
+        path.push(candidates.remove(next_idx));
     }
+    path
+}
 
-    (path, total_distance)
+/// Walks the minimum spanning tree of the candidates from `candidates[0]`,
+/// listing systems at even depth on the way down and at odd depth on the
+/// way back up. Consecutive systems are then at most three tree edges
+/// apart, so no leg exceeds three times the longest tree edge, itself a
+/// lower bound on the best possible longest leg.
+fn tree_order(graph: &StarGraph, candidates: Vec<usize>) -> Vec<usize> {
+    let n = candidates.len();
+    if n == 0 {
+        return Vec::new();
+    }
+    let dist =
+        |a: usize, b: usize| graph.systems[candidates[a]].distance(&graph.systems[candidates[b]]);
+
+    // Prim's algorithm on the complete graph, O(n²).
+    let mut children = vec![Vec::new(); n];
+    let mut in_tree = vec![false; n];
+    let mut best = vec![(Scalar::INFINITY, 0); n];
+    in_tree[0] = true;
+    for (i, slot) in best.iter_mut().enumerate().skip(1) {
+        *slot = (dist(0, i), 0);
+    }
+    for _ in 1..n {
+        let Some(next) = (0..n)
+            .filter(|&i| !in_tree[i])
+            .min_by(|&a, &b| best[a].0.total_cmp(&best[b].0))
+        else {
+            break;
+        };
+        in_tree[next] = true;
+        children[best[next].1].push(next);
+        for i in 0..n {
+            if !in_tree[i] {
+                let d = dist(next, i);
+                if d < best[i].0 {
+                    best[i] = (d, next);
+                }
+            }
+        }
+    }
+
+    let mut order = Vec::with_capacity(n);
+    // (node, depth, entered)
+    let mut stack = vec![(0, 0usize, false)];
+    while let Some((node, depth, entered)) = stack.pop() {
+        if entered {
+            if depth % 2 == 1 {
+                order.push(candidates[node]);
+            }
+            continue;
+        }
+        if depth % 2 == 0 {
+            order.push(candidates[node]);
+        }
+        stack.push((node, depth, true));
+        for &child in children[node].iter().rev() {
+            stack.push((child, depth + 1, false));
+        }
+    }
+    order
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::engine::graph_from;
     use crate::System;
 
     #[test]
@@ -97,4 +333,106 @@ mod tests {
         assert!(ids.contains(&1) && ids.contains(&2) && ids.contains(&3));
         assert!(dist > 0.0);
     }
+
+    #[test]
+    fn longest_leg_objective_avoids_long_hops() {
+        // Greedy nearest neighbour from 0 runs out along the line and then
+        // has to jump all the way back to the stragglers near the start.
+        let positions = [0.0, 1.0, 2.0, 3.0, 4.0, 5.0, -1.5].map(|x| [x, 0.0, 0.0]);
+        let graph = graph_from(&positions, vec![Vec::new(); positions.len()]);
+        let avoid = HashSet::new();
+        let center = [0.4, 0.0, 0.0];
+
+        let (greedy, _, _) =
+            sweep_with_objective(&graph, center, 10.0, &avoid, SweepObjective::TotalDistance);
+        let (tight, total, _) =
+            sweep_with_objective(&graph, center, 10.0, &avoid, SweepObjective::LongestLeg);
+        assert_eq!(longest_leg(&graph, &greedy), 6.5);
+        assert!(longest_leg(&graph, &tight) <= 3.0);
+        assert_eq!(tight[0], 0);
+        let mut visited = tight.clone();
+        visited.sort_unstable();
+        assert_eq!(visited, (0..positions.len()).collect::<Vec<_>>());
+        assert!(total > 0.0);
+    }
+
+    /// Nine systems on a line, no gates.
+    fn nine_in_a_line() -> StarGraph {
+        let positions: Vec<[Scalar; 3]> = (0..9).map(|i| [i as Scalar, 0.0, 0.0]).collect();
+        graph_from(&positions, vec![Vec::new(); 9])
+    }
+
+    /// Start in the middle and finish at one end.
+    const MIDDLE_TO_END: SweepEnds = SweepEnds {
+        first: Some(4),
+        last: Some(8),
+    };
+
+    #[test]
+    fn pinned_ends_frame_the_tour() {
+        let graph = nine_in_a_line();
+        let avoid = HashSet::from([8]);
+        let (path, total, avoided) = sweep_with_ends(
+            &graph,
+            [4.0, 0.0, 0.0],
+            3.5,
+            &avoid,
+            SweepObjective::TotalDistance,
+            MIDDLE_TO_END,
+        );
+        // 8 lies outside the radius and is avoided, but pinned all the same.
+        assert_eq!(path.first(), Some(&4));
+        assert_eq!(path.last(), Some(&8));
+        assert!(avoided.is_empty());
+        let mut visited = path.clone();
+        visited.sort_unstable();
+        assert_eq!(visited, (1..=8).collect::<Vec<_>>());
+        // Out to 1 first, then straight across to 8.
+        assert_eq!(total, 3.0 + 7.0);
+    }
+
+    #[test]
+    fn pinned_ends_hold_for_the_longest_leg_objective() {
+        let graph = nine_in_a_line();
+        let (tight, _, _) = sweep_with_ends(
+            &graph,
+            [4.0, 0.0, 0.0],
+            3.5,
+            &HashSet::new(),
+            SweepObjective::LongestLeg,
+            MIDDLE_TO_END,
+        );
+        assert_eq!((tight[0], *tight.last().unwrap()), (4, 8));
+    }
+
+    #[test]
+    fn explicit_system_lists_are_toured_from_the_start() {
+        let graph = crate::engine::demo_graph();
+        // C and D, plus a repeat, from B: C is one away, D then √8 further.
+        let ends = SweepEnds {
+            first: Some(1),
+            last: None,
+        };
+        let (path, total) = sweep_systems(&graph, &[3, 2, 3], SweepObjective::TotalDistance, ends);
+        assert_eq!(path, vec![1, 2, 3]);
+        assert!((total - (1.0 + Scalar::sqrt(8.0))).abs() < 1e-5);
+        // Unpinned, the tour starts at the first listed system.
+        let (path, _) = sweep_systems(
+            &graph,
+            &[3, 0],
+            SweepObjective::LongestLeg,
+            SweepEnds::default(),
+        );
+        assert_eq!(path, vec![3, 0]);
+    }
+
+    #[test]
+    fn sweep_skips_avoided_systems() {
+        let graph = crate::engine::demo_graph();
+        let avoid = HashSet::from([1, 3]);
+        let (path, _, avoided) = greedy_sweep_avoiding(&graph, [0.0, 0.0, 0.0], 1.5, &avoid);
+        // D (index 3) is outside the radius, so only B counts as avoided.
+        assert_eq!(path, vec![0]);
+        assert_eq!(avoided, vec![1]);
+    }
 }
@@ -0,0 +1,91 @@
+use std::collections::{BTreeSet, HashMap};
+use std::fs;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::data::DataError;
+
+/// Operator-defined string tags per system id, kept outside the immutable
+/// dataset like `poi::PoiLayer`. Loaded from an overlay file and changed at
+/// runtime by `set_tags` requests.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct SystemTags {
+    by_id: HashMap<u32, BTreeSet<String>>,
+}
+
+impl SystemTags {
+    /// Reads a JSON overlay mapping system ids to tag lists, e.g.
+    /// `{"30000001": ["friendly", "staging"]}`.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, DataError> {
+        let by_id = serde_json::from_slice(&fs::read(path)?)?;
+        Ok(SystemTags { by_id })
+    }
+
+    pub fn get(&self, id: u32) -> Option<&BTreeSet<String>> {
+        self.by_id.get(&id)
+    }
+
+    /// Replaces the tags of system `id`; an empty set removes them.
+    pub fn set(&mut self, id: u32, tags: BTreeSet<String>) {
+        if tags.is_empty() {
+            self.by_id.remove(&id);
+        } else {
+            self.by_id.insert(id, tags);
+        }
+    }
+
+    pub fn has(&self, id: u32, tag: &str) -> bool {
+        self.by_id.get(&id).is_some_and(|tags| tags.contains(tag))
+    }
+
+    /// Whether system `id` satisfies every predicate of `filter`.
+    pub fn matches(&self, id: u32, filter: &TagFilter) -> bool {
+        filter
+            .0
+            .iter()
+            .all(|predicate| match predicate.strip_prefix('!') {
+                Some(tag) => !self.has(id, tag),
+                None => self.has(id, predicate),
+            })
+    }
+}
+
+/// Tag predicates a system must all satisfy: `"friendly"` requires the
+/// tag, `"!hostile"` forbids it. Empty filters match every system.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct TagFilter(pub Vec<String>);
+
+impl TagFilter {
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn predicates_require_and_forbid_tags() {
+        let mut file = tempfile::NamedTempFile::new().expect("temp file");
+        std::io::Write::write_all(
+            &mut file,
+            br#"{"1": ["friendly"], "2": ["friendly", "hostile"]}"#,
+        )
+        .unwrap();
+        let mut tags = SystemTags::load(file.path()).unwrap();
+        let friendly = TagFilter(vec!["friendly".into(), "!hostile".into()]);
+        assert!(tags.matches(1, &friendly));
+        assert!(!tags.matches(2, &friendly));
+        assert!(!tags.matches(3, &friendly));
+        assert!(tags.matches(3, &TagFilter::default()));
+
+        tags.set(2, BTreeSet::from(["friendly".to_string()]));
+        assert!(tags.matches(2, &friendly));
+        tags.set(1, BTreeSet::new());
+        assert_eq!(tags.get(1), None);
+    }
+}
@@ -0,0 +1,84 @@
+use serde::{Deserialize, Serialize};
+
+use crate::Scalar;
+
+/// Meters in one light-year (IAU: Julian year times the speed of light).
+pub const METERS_PER_LIGHT_YEAR: f64 = 9_460_730_472_580_800.0;
+/// Meters in one astronomical unit (IAU 2012 Resolution B2).
+pub const METERS_PER_AU: f64 = 149_597_870_700.0;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DistanceUnit {
+    #[default]
+    #[serde(rename = "meters", alias = "m")]
+    Meters,
+    #[serde(rename = "ly", alias = "light_years")]
+    LightYears,
+    #[serde(rename = "au")]
+    Au,
+}
+
+impl DistanceUnit {
+    pub fn meters_per_unit(self) -> f64 {
+        match self {
+            DistanceUnit::Meters => 1.0,
+            DistanceUnit::LightYears => METERS_PER_LIGHT_YEAR,
+            DistanceUnit::Au => METERS_PER_AU,
+        }
+    }
+
+    /// Converts a length expressed in `self` into `target` units.
+    pub fn convert(self, value: Scalar, target: DistanceUnit) -> Scalar {
+        (value as f64 * self.meters_per_unit() / target.meters_per_unit()) as Scalar
+    }
+}
+
+/// Describes how positions are expressed: `position = (raw - origin) / unit`,
+/// where `raw` is in meters in the source dataset's frame and `origin` is the
+/// frame's origin in that same raw frame.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct CoordinateFrame {
+    #[serde(default)]
+    pub units: DistanceUnit,
+    #[serde(default)]
+    pub origin: [f64; 3],
+}
+
+impl CoordinateFrame {
+    /// Converts a position expressed in `self` into `target`.
+    pub fn convert_point(&self, point: [Scalar; 3], target: &CoordinateFrame) -> [Scalar; 3] {
+        let from_scale = self.units.meters_per_unit();
+        let to_scale = target.units.meters_per_unit();
+        let mut out: [Scalar; 3] = [0.0; 3];
+        for axis in 0..3 {
+            let raw = point[axis] as f64 * from_scale + self.origin[axis];
+            out[axis] = ((raw - target.origin[axis]) / to_scale) as Scalar;
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frame_conversion_recenters_and_rescales() {
+        let raw = CoordinateFrame::default();
+        let recentered = CoordinateFrame {
+            units: DistanceUnit::LightYears,
+            origin: [METERS_PER_LIGHT_YEAR, 0.0, 0.0],
+        };
+        let p = raw.convert_point(
+            [(3.0 * METERS_PER_LIGHT_YEAR) as Scalar, 0.0, 0.0],
+            &recentered,
+        );
+        assert!((p[0] - 2.0).abs() < 1e-4);
+        let back = recentered.convert_point(p, &raw);
+        assert!((back[0] as f64 / METERS_PER_LIGHT_YEAR - 3.0).abs() < 1e-4);
+        assert!((DistanceUnit::Au.convert(1.0, DistanceUnit::Meters) - 1.495_978_7e11).abs() < 1e5);
+
+        let parsed: CoordinateFrame = serde_json::from_str(r#"{"units":"ly"}"#).unwrap();
+        assert_eq!(parsed.units, DistanceUnit::LightYears);
+    }
+}
@@ -0,0 +1,167 @@
+//! Checks on the numeric inputs of spatial queries (`nearest`, `sweep`,
+//! `unvisited`, `midpoint` and the k-d tree behind them). Degenerate inputs
+//! have one defined meaning everywhere:
+//!
+//! - A radius of 0 is an inclusive bound: only systems exactly at the query
+//!   point match. An infinite radius means unlimited.
+//! - A count of 0 asks for nothing and gets an empty list, not an error.
+//! - An empty dataset answers every spatial query with an empty result.
+//! - NaN or infinite coordinates, and NaN or negative radii, are rejected
+//!   with an `InputError` by the request layer. The underlying index and
+//!   sweep functions never panic on them and return nothing instead.
+
+use thiserror::Error;
+
+use crate::Scalar;
+
+/// Why the input of a spatial query was rejected.
+#[derive(Clone, Debug, Error, PartialEq)]
+pub enum InputError {
+    #[error("{name} must have finite coordinates, got {value:?}")]
+    NonFinitePoint {
+        name: &'static str,
+        value: [Scalar; 3],
+    },
+    #[error("{name} must be zero or positive, got {value}")]
+    InvalidRadius { name: &'static str, value: Scalar },
+}
+
+/// Accepts points whose three coordinates are finite.
+pub fn check_point(name: &'static str, value: [Scalar; 3]) -> Result<[Scalar; 3], InputError> {
+    if value.iter().all(|c| c.is_finite()) {
+        Ok(value)
+    } else {
+        Err(InputError::NonFinitePoint { name, value })
+    }
+}
+
+/// Accepts zero, positive and infinite radii.
+pub fn check_radius(name: &'static str, value: Scalar) -> Result<Scalar, InputError> {
+    if value >= 0.0 {
+        Ok(value)
+    } else {
+        Err(InputError::InvalidRadius { name, value })
+    }
+}
+
+/// True when both `check_point` and `check_radius` would accept the input,
+/// for functions that answer bad input with no results.
+pub(crate) fn is_searchable(point: [Scalar; 3], radius: Scalar) -> bool {
+    point.iter().all(|c| c.is_finite()) && radius >= 0.0
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use crate::api::{handle_request, EngineResponse};
+    use crate::engine::{demo_graph, Engine};
+    use crate::graph::graph::StarGraph;
+    use crate::Scalar;
+
+    /// Expected outcome of a request: the number of systems returned, or
+    /// an error mentioning the given text.
+    enum Expect {
+        Found(usize),
+        Rejected(&'static str),
+    }
+
+    #[test]
+    fn degenerate_inputs_have_defined_results() {
+        use Expect::*;
+        let demo = Engine::new(demo_graph(), None);
+        let empty = Engine::new(StarGraph::new(Vec::new(), Vec::new()), None);
+        let at_a = [0.0, 0.0, 0.0];
+        let mut cases = vec![
+            // (engine, kind, origin, radius, count, expected)
+            (&demo, "nearest", json!(at_a), json!(1.5), 3, Found(2)),
+            (&demo, "nearest", json!(at_a), json!(0.0), 3, Found(1)),
+            (
+                &demo,
+                "nearest",
+                json!([0.5, 0.0, 0.0]),
+                json!(0.0),
+                3,
+                Found(0),
+            ),
+            (&demo, "nearest", json!(at_a), json!(1.5), 0, Found(0)),
+            (
+                &demo,
+                "nearest",
+                json!(at_a),
+                json!(-1.0),
+                3,
+                Rejected("radius"),
+            ),
+            (&demo, "nearest", json!(at_a), json!(1e39), 9, Found(4)),
+            (&empty, "nearest", json!(at_a), json!(5.0), 3, Found(0)),
+            (&empty, "nearest", json!(at_a), json!(0.0), 0, Found(0)),
+            (&demo, "sweep", json!(at_a), json!(1.5), 0, Found(2)),
+            (&demo, "sweep", json!(at_a), json!(0.0), 0, Found(1)),
+            (
+                &demo,
+                "sweep",
+                json!(at_a),
+                json!(-0.5),
+                0,
+                Rejected("radius"),
+            ),
+            (&empty, "sweep", json!(at_a), json!(5.0), 0, Found(0)),
+        ];
+        // JSON has no NaN or infinity; 1e39 overflows f32 to infinity, while
+        // every JSON number fits an f64.
+        if !cfg!(feature = "f64") {
+            cases.extend([
+                (
+                    &demo,
+                    "nearest",
+                    json!([1e39, 0.0, 0.0]),
+                    json!(1.0),
+                    3,
+                    Rejected("origin"),
+                ),
+                (
+                    &demo,
+                    "sweep",
+                    json!([0.0, -1e39, 0.0]),
+                    json!(1.0),
+                    0,
+                    Rejected("center"),
+                ),
+            ]);
+        }
+        for (i, (engine, kind, origin, radius, count, expected)) in cases.into_iter().enumerate() {
+            let request = json!({
+                "kind": kind,
+                "origin": origin,
+                "radius": radius,
+                "count": count,
+            });
+            let request = serde_json::from_value(request).unwrap();
+            let response = handle_request(engine, request).response;
+            match (response, expected) {
+                (EngineResponse::Nearest { systems, .. }, Found(n)) => {
+                    assert_eq!(systems.len(), n, "case {i}")
+                }
+                (EngineResponse::Sweep { systems, .. }, Found(n)) => {
+                    assert_eq!(systems.len(), n, "case {i}")
+                }
+                (EngineResponse::Error { message }, Rejected(field)) => {
+                    assert!(message.contains(field), "case {i}: {message}")
+                }
+                (other, _) => panic!("case {i}: unexpected {other:?}"),
+            }
+        }
+
+        // The raw index answers bad input with nothing rather than panicking.
+        assert!(demo
+            .kd
+            .nearest_n_within_radius([Scalar::NAN; 3], 10.0, 3)
+            .is_empty());
+        assert!(demo
+            .kd
+            .nearest_n_within_radius(at_a, Scalar::NAN, 3)
+            .is_empty());
+        assert!(demo.kd.nearest_n_within_radius(at_a, -1.0, 3).is_empty());
+    }
+}
@@ -1,8 +1,14 @@
+use std::path::Path;
+
+use rusqlite::{params, Connection};
+use starmap_engine::data::build::{build_bundle, build_graph_from_sqlite, ReleaseSource};
+use starmap_engine::data::bundle::serialize_bundle;
 use starmap_engine::data::{deserialize_graph, serialize_graph};
 use starmap_engine::graph::graph::StarGraph;
 use starmap_engine::graph::pathfinder::shortest_gate_path;
 use starmap_engine::spatial::kd_tree::KDTree;
 use starmap_engine::sweep::sweep::greedy_sweep_within_radius;
+use starmap_engine::Scalar;
 use starmap_engine::System;
 
 #[test]
@@ -28,7 +34,7 @@ fn integration_end_to_end_small_graph() {
     let graph = StarGraph::new(systems.clone(), adjacency);
 
     // KD-tree nearest
-    let pts: Vec<[f32; 3]> = systems.iter().map(|s| s.pos).collect();
+    let pts: Vec<[Scalar; 3]> = systems.iter().map(|s| s.pos).collect();
     let kd = KDTree::build(&pts);
     let nn = kd.nearest_n_within_radius([0.0, 0.0, 0.0], 2.0, 3);
     assert!(!nn.is_empty());
@@ -67,3 +73,82 @@ fn dataset_round_trip_serialization() {
     assert_eq!(restored.systems[0].name, "Alpha");
     assert_eq!(restored.adjacency[0], vec![1]);
 }
+
+/// Writes a release-style SQLite database holding `systems` and `jumps` in
+/// the order given.
+fn write_release_db(path: &Path, systems: &[(i64, &str, [f64; 3])], jumps: &[(i64, i64)]) {
+    let conn = Connection::open(path).expect("open");
+    conn.execute_batch(
+        "CREATE TABLE SolarSystems (solarSystemId INTEGER, name TEXT, centerX REAL, centerY REAL, centerZ REAL);
+         CREATE TABLE Jumps (fromSystemId INTEGER, toSystemId INTEGER);",
+    )
+    .expect("schema");
+    for (id, name, [x, y, z]) in systems {
+        conn.execute(
+            "INSERT INTO SolarSystems VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![id, name, x, y, z],
+        )
+        .expect("system");
+    }
+    for (from, to) in jumps {
+        conn.execute("INSERT INTO Jumps VALUES (?1, ?2)", params![from, to])
+            .expect("jump");
+    }
+}
+
+#[test]
+fn rebuilding_a_release_is_byte_identical() {
+    std::env::set_var("SOURCE_DATE_EPOCH", "1700000000");
+    let systems = [
+        (30000001, "Alpha", [0.0, 0.0, 0.0]),
+        (30000002, "Beta", [4.0e15, 0.0, 0.0]),
+        (30000003, "Gamma", [4.0e15, 3.0e15, 0.0]),
+        (30000004, "Delta", [-2.0e15, 1.0e15, 5.0e14]),
+        (30000005, "Epsilon", [1.0e15, -6.0e15, 2.0e15]),
+    ];
+    let jumps = [
+        (30000001, 30000002),
+        (30000002, 30000001),
+        (30000002, 30000003),
+        (30000003, 30000002),
+        (30000001, 30000004),
+        (30000004, 30000001),
+        (30000003, 30000005),
+        (30000005, 30000003),
+        (30000005, 30000003),
+    ];
+    let reversed_systems: Vec<_> = systems.iter().rev().copied().collect();
+    let reversed_jumps: Vec<_> = jumps.iter().rev().copied().collect();
+
+    let dir = tempfile::tempdir().expect("tempdir");
+    let source = ReleaseSource {
+        release_tag: "v1".into(),
+        asset_name: "static_data.db".into(),
+        asset_url: "https://example.invalid/static_data.db".into(),
+        published_at: None,
+    };
+    let build = |name: &str, systems: &[(i64, &str, [f64; 3])], jumps: &[(i64, i64)]| {
+        let path = dir.path().join(name);
+        write_release_db(&path, systems, jumps);
+        let (graph, edges) = build_graph_from_sqlite(&path).expect("graph");
+        let graph_bytes = serialize_graph(&graph).expect("serialize graph");
+        let bundle = build_bundle(graph, edges, &source).expect("bundle");
+        (
+            graph_bytes,
+            serialize_bundle(&bundle).expect("serialize bundle"),
+            bundle,
+        )
+    };
+
+    let (graph_a, bundle_a, built) = build("a.db", &systems, &jumps);
+    let (graph_b, bundle_b, _) = build("b.db", &reversed_systems, &reversed_jumps);
+    assert_eq!(graph_a, graph_b);
+    assert_eq!(bundle_a, bundle_b);
+
+    let metadata = built.metadata.expect("metadata");
+    assert_eq!(metadata.generated_at_epoch, 1_700_000_000);
+    assert_eq!(metadata.systems, 5);
+    // The duplicate jump is dropped.
+    assert_eq!(metadata.directed_edges, 8);
+    assert!(!metadata.canaries.is_empty());
+}